@@ -41,6 +41,7 @@ fn main() -> Result<(), std::io::Error> {
         r: args.r,
         min_span: args.min_span,
         sketch: false,
+        ..ShmmrSpec::default()
     };
     let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
     sdb.load_seqs_from_fastx(filepath)?;
@@ -140,6 +140,7 @@ fn generate_bed_graph_from_fastx_files(args: &CmdOptions) {
         r: args.r,
         min_span: args.min_span,
         sketch: false,
+        ..ShmmrSpec::default()
     };
     let mut sdb0 = SeqIndexDB::new();
     let input_files = BufReader::new(
@@ -43,6 +43,7 @@ fn main() {
         r: args.r,
         min_span: args.min_span,
         sketch: false,
+        ..pgr_db::shmmrutils::ShmmrSpec::default()
     };
     let mut sdb = SeqIndexDB::new();
     let input_files = BufReader::new(
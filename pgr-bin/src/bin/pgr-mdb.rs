@@ -9,6 +9,8 @@ use pgr_db::agc_io::AGCFile;
 #[cfg(feature = "with_agc")]
 use pgr_db::shmmrutils::ShmmrSpec;
 
+use pgr_db::shmmrutils::DEFAULT_REDUCTION_LEVELS;
+
 #[cfg(feature = "with_agc")]
 use std::fs::File;
 
@@ -35,12 +37,18 @@ struct CmdOptions {
     /// sparse minimizer (shimmer) reduction factor
     #[clap(long, short, default_value_t = 4)]
     r: u32,
+    /// number of the reductions by `r`, more levels give the sparser shimmers
+    #[clap(long, default_value_t = DEFAULT_REDUCTION_LEVELS)]
+    levels: u32,
     /// min span for neighboring minimiers
     #[clap(long, short, default_value_t = 64)]
     min_span: u32,
     /// using sketch k-mer than minimizer
     #[clap(short, long)]
     sketch: bool,
+    /// target number of shimmers per kb, replacing the selection by `w` and `r`
+    #[clap(long)]
+    density: Option<u32>,
     /// set to use agc prefecting feature (more memory usage but faster, useful for agcfile with many small contigs)
     #[clap(short, long)]
     prefetching: bool,
@@ -94,6 +102,9 @@ fn main() {
         r: args.r,
         min_span: args.min_span,
         sketch: args.sketch,
+        density: args.density,
+        levels: args.levels,
+        ..ShmmrSpec::default()
     };
 
     #[cfg(feature = "with_agc")]
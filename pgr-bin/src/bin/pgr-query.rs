@@ -1,5 +1,6 @@
 const VERSION_STRING: &str = env!("VERSION_STRING");
 use clap::{self, CommandFactory, Parser};
+use pgr_db::aln::Strand;
 use pgr_db::ext::{get_fastx_reader, GZFastaReader, SeqIndexDB};
 use pgr_db::fasta_io::SeqRec;
 use rayon::prelude::*;
@@ -170,7 +171,7 @@ fn main() -> Result<(), std::io::Error> {
                         if aln.len() > 2 {
                             aln_lens.push(aln.len());
                             for hp in &aln {
-                                if hp.0 .2 == hp.1 .2 {
+                                if Strand::of(hp) == Strand::Forward {
                                     f_count += 1;
                                 } else {
                                     r_count += 1;
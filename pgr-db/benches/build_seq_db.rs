@@ -0,0 +1,31 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pgr_db::seq_db::{CompactSeqDB, SHMMRSPEC};
+
+/// build a DB from a fastx file with and without aligning the fragments, the file is
+/// `PGR_BENCH_FASTX` if it is set, e.g. a large file to measure the build time on
+fn bench_build_seq_db(c: &mut Criterion) {
+    let filepath = std::env::var("PGR_BENCH_FASTX")
+        .unwrap_or_else(|_| "test/test_data/test_seqs.fa".to_string());
+    let mut group = c.benchmark_group("build_seq_db");
+    group.sample_size(10);
+    [true, false]
+        .into_iter()
+        .for_each(|compress_with_alignment| {
+            group.bench_with_input(
+                BenchmarkId::new("compress_with_alignment", compress_with_alignment),
+                &filepath,
+                |b, filepath| {
+                    b.iter(|| {
+                        let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+                        sdb.compress_with_alignment = compress_with_alignment;
+                        sdb.load_seqs_from_fastx(filepath.clone()).unwrap();
+                        sdb
+                    })
+                },
+            );
+        });
+    group.finish();
+}
+
+criterion_group!(benches, bench_build_seq_db);
+criterion_main!(benches);
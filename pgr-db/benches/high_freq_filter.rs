@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pgr_db::seq_db::{CompactSeqDB, SHMMRSPEC};
+
+fn random_bases(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            b"ACGT"[((state >> 33) % 4) as usize]
+        })
+        .collect()
+}
+
+/// the sequences of unique spacers between the copies of a few repeat units, every sequence
+/// carries all copies, so the shimmer pairs of the units are shared by hundreds of fragments
+fn repeat_rich_seqs() -> Vec<(u32, Option<String>, String, Vec<u8>)> {
+    let units = (0..4)
+        .map(|i| random_bases(100 + i, 3000))
+        .collect::<Vec<_>>();
+    (0..16_u32)
+        .map(|sid| {
+            let seq = (0..32_u64)
+                .flat_map(|i| {
+                    [
+                        random_bases(1000 * sid as u64 + i + 1, 2000),
+                        units[i as usize % units.len()].clone(),
+                    ]
+                })
+                .collect::<Vec<_>>()
+                .concat();
+            (sid, None, format!("seq_{}", sid), seq)
+        })
+        .collect()
+}
+
+/// query a sequence of the repeat-rich DB, without the filter and with the filters skipping
+/// the shimmer pairs shared by more than `min_count` fragments
+fn bench_high_freq_filter(c: &mut Criterion) {
+    let seqs = repeat_rich_seqs();
+    let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+    sdb.load_seqs_from_seq_vec(&seqs);
+    let query_seq = seqs[0].3[..40000].to_vec();
+    let mut group = c.benchmark_group("high_freq_filter");
+    group.sample_size(10);
+    group.bench_with_input(BenchmarkId::new("none", 0), &query_seq, |b, query_seq| {
+        b.iter(|| sdb.query(query_seq, 0.5, false))
+    });
+    [8, 64].into_iter().for_each(|min_count| {
+        sdb.build_high_freq_filter(min_count);
+        group.bench_with_input(
+            BenchmarkId::new("min_count", min_count),
+            &query_seq,
+            |b, query_seq| b.iter(|| sdb.query(query_seq, 0.5, false)),
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_high_freq_filter);
+criterion_main!(benches);
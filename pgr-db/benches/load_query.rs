@@ -0,0 +1,139 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use pgr_db::aln::{sparse_aln, HitPair};
+use pgr_db::seq_db::{CompactSeqDB, GetSeq, SHMMRSPEC};
+use pgr_db::shmmrutils::match_reads;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// load the sequences of `test_seqs.fa` into a DB, see `build_seq_db` for the larger files and
+/// the fragment alignment modes
+fn bench_load_seqs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_seqs");
+    group.sample_size(10);
+    group.bench_function("test_seqs", |b| {
+        b.iter(|| {
+            let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+            sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+                .unwrap();
+            sdb
+        })
+    });
+    group.finish();
+}
+
+/// align the fragment pairs of the reconstruction tests with the deltas
+fn bench_match_reads(c: &mut Criterion) {
+    let base_frg = b"TATTTATATTTATTTATATATATTTATATATTTATATATATATTTATATATAAATAT".to_vec();
+    let frgs = [
+        b"TTTTTATTTTTTTAATTAATTAATTATTTATTTATTTATTTATTTATTTATTTATTT".to_vec(),
+        b"TTTTTTATTTTTTTAATTAATTAATTATTTATTTATTTATTTATTTATTTATTTATT".to_vec(),
+    ];
+    let mut group = c.benchmark_group("match_reads");
+    frgs.iter().enumerate().for_each(|(i, frg)| {
+        group.bench_with_input(
+            BenchmarkId::new("reconstruct_test", i + 1),
+            frg,
+            |b, frg| b.iter(|| match_reads(&base_frg, frg, true, 0.1, 0, 0, 32)),
+        );
+    });
+    group.finish();
+}
+
+/// chain the hits of `test_hits`, each round chains a fresh copy as the hits are sorted in
+/// place
+fn bench_sparse_aln(c: &mut Criterion) {
+    let f = BufReader::new(File::open("test/test_data/test_hits").unwrap());
+    let hits = f
+        .lines()
+        .map(|line| {
+            let v = line
+                .unwrap()
+                .split_ascii_whitespace()
+                .map(|s| s.parse::<u32>().unwrap())
+                .collect::<Vec<u32>>();
+            ((v[0], v[1], v[2] as u8), (v[3], v[4], v[5] as u8))
+        })
+        .collect::<Vec<HitPair>>();
+    c.bench_function("sparse_aln/test_hits", |b| {
+        b.iter_batched(
+            || hits.clone(),
+            |mut hits| sparse_aln(&mut hits, 8, 0.5),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+/// query a 2 kb segment of a sequence back to the DB of `test_seqs.fa`, with and without
+/// reconstructing the target bases of the hits
+fn bench_query(c: &mut Criterion) {
+    let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+    sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+        .unwrap();
+    let query_seq = sdb.get_sub_seq_by_id(0, 1000, 3000);
+    let mut group = c.benchmark_group("query");
+    group.sample_size(10);
+    [false, true].into_iter().for_each(|orient_to_query| {
+        group.bench_with_input(
+            BenchmarkId::new("orient_to_query", orient_to_query),
+            &query_seq,
+            |b, query_seq| b.iter(|| sdb.query(query_seq, 0.5, orient_to_query)),
+        );
+    });
+    group.finish();
+}
+
+/// query 256 short segments of the sequences of `test_seqs.fa` one at a time and as a batch,
+/// before and after building the per-target shimmer pair counts of the index
+fn bench_query_batch(c: &mut Criterion) {
+    let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+    sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+        .unwrap();
+    let long_seqs = sdb
+        .seqs
+        .iter()
+        .filter(|s| s.len > 2000)
+        .map(|s| (s.id, s.len as u32))
+        .collect::<Vec<_>>();
+    let query_seqs = (0..256_usize)
+        .map(|i| {
+            let (sid, len) = long_seqs[i % long_seqs.len()];
+            let bgn = (i as u32 * 997) % (len - 1000);
+            sdb.get_sub_seq_by_id(sid, bgn, bgn + 1000)
+        })
+        .collect::<Vec<_>>();
+    let mut group = c.benchmark_group("query_batch");
+    group.sample_size(10);
+    [false, true].into_iter().for_each(|pair_target_count| {
+        if pair_target_count {
+            sdb.build_pair_target_count();
+        }
+        group.bench_with_input(
+            BenchmarkId::new("loop/pair_target_count", pair_target_count),
+            &query_seqs,
+            |b, query_seqs| {
+                b.iter(|| {
+                    query_seqs
+                        .iter()
+                        .map(|query_seq| sdb.query(query_seq, 0.5, false))
+                        .collect::<Vec<_>>()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("batch/pair_target_count", pair_target_count),
+            &query_seqs,
+            |b, query_seqs| b.iter(|| sdb.query_batch(query_seqs, 0.5, false)),
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_load_seqs,
+    bench_match_reads,
+    bench_sparse_aln,
+    bench_query,
+    bench_query_batch
+);
+criterion_main!(benches);
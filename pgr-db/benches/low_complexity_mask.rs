@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pgr_db::seq_db::{CompactSeqDB, LowComplexityMask, SHMMRSPEC};
+
+fn random_bases(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            b"ACGT"[((state >> 33) % 4) as usize]
+        })
+        .collect()
+}
+
+/// the sequences of unique flanks around the simple repeats, every sequence carries the same
+/// repeats so their shimmers cluster in the `frag_map`
+fn repeat_heavy_seqs() -> Vec<(u32, Option<String>, String, Vec<u8>)> {
+    (0..16_u32)
+        .map(|sid| {
+            let seed = 3 * sid as u64;
+            let seq = [
+                random_bases(seed + 1, 2000),
+                b"CA".repeat(2000),
+                random_bases(seed + 2, 2000),
+                b"AAAT".repeat(1000),
+                b"GGC".repeat(1000),
+                random_bases(seed + 3, 2000),
+            ]
+            .concat();
+            (sid, None, format!("seq_{}", sid), seq)
+        })
+        .collect()
+}
+
+/// query a repeat-heavy sequence against the DBs built with and without the low-complexity mask
+fn bench_low_complexity_mask(c: &mut Criterion) {
+    let seqs = repeat_heavy_seqs();
+    let mut group = c.benchmark_group("low_complexity_mask");
+    group.sample_size(10);
+    [None, Some(LowComplexityMask::default())]
+        .into_iter()
+        .for_each(|low_complexity_mask| {
+            let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+            sdb.low_complexity_mask = low_complexity_mask;
+            sdb.load_seqs_from_seq_vec(&seqs);
+            println!(
+                "low_complexity_mask: {}, fragment signatures: {}",
+                low_complexity_mask.is_some(),
+                sdb.frag_map.values().map(|v| v.len()).sum::<usize>()
+            );
+            group.bench_with_input(
+                BenchmarkId::new("query", low_complexity_mask.is_some()),
+                &seqs[0].3,
+                |b, query_seq| b.iter(|| sdb.query(query_seq, 0.5, false)),
+            );
+        });
+    group.finish();
+}
+
+criterion_group!(benches, bench_low_complexity_mask);
+criterion_main!(benches);
@@ -0,0 +1,126 @@
+//! the resident memory of two threads loading the same DB with the fragments in memory and
+//! with the fragments in a shared memory-mapped store, each mode runs in a child process so
+//! the heap of one mode is not reused by the other, it reads `/proc/self/smaps_rollup`, so
+//! it only reports the numbers on Linux
+use pgr_db::seq_db::{CompactSeqDB, GetSeq, SHMMRSPEC};
+use std::hint::black_box;
+use std::process::Command;
+use std::sync::Barrier;
+
+const MODE_VAR: &str = "PGR_BENCH_FRAG_STORE_MODE";
+
+fn random_bases(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            b"ACGT"[((state >> 33) % 4) as usize]
+        })
+        .collect()
+}
+
+/// the `Pss` and the `Anonymous` memory of the process in kB, the pages of a file mapped by
+/// both threads are only counted once in the `Pss`
+fn resident_kb() -> Option<(u64, u64)> {
+    let rollup = std::fs::read_to_string("/proc/self/smaps_rollup").ok()?;
+    let field = |name: &str| {
+        rollup
+            .lines()
+            .find(|line| line.starts_with(name))
+            .and_then(|line| line.split_ascii_whitespace().nth(1))
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+    Some((field("Pss:")?, field("Anonymous:")?))
+}
+
+/// the memory growth while two threads hold the DBs they load and reconstruct
+fn two_thread_growth(load: impl Fn() -> CompactSeqDB + Sync) -> Option<(u64, u64)> {
+    let before = resident_kb()?;
+    let barrier = Barrier::new(3);
+    let after = std::thread::scope(|s| {
+        (0..2).for_each(|_| {
+            s.spawn(|| {
+                let sdb = load();
+                // touch every fragment, so the mapped pages are resident
+                (0..sdb.seqs.len() as u32).for_each(|sid| {
+                    black_box(sdb.get_seq_by_id(sid));
+                });
+                barrier.wait();
+                barrier.wait();
+            });
+        });
+        barrier.wait();
+        let after = resident_kb();
+        barrier.wait();
+        after
+    })?;
+    Some((
+        after.0.saturating_sub(before.0),
+        after.1.saturating_sub(before.1),
+    ))
+}
+
+fn run_mode(mode: &str, sdb_path: String, sdi_path: String, mfs_path: String) {
+    let growth = match mode {
+        "in_memory" => {
+            two_thread_growth(|| CompactSeqDB::load_from_file(sdb_path.clone()).unwrap())
+        }
+        _ => two_thread_growth(|| {
+            let mut sdb = CompactSeqDB::load_index(sdi_path.clone()).unwrap();
+            sdb.load_frags_from_mmap_store(mfs_path.clone()).unwrap();
+            sdb
+        }),
+    };
+    match growth {
+        Some((pss, anon)) => println!(
+            "frag_store/{}: pss +{} kB, anonymous +{} kB",
+            mode, pss, anon
+        ),
+        None => println!(
+            "frag_store/{}: /proc/self/smaps_rollup is not available",
+            mode
+        ),
+    }
+}
+
+fn main() {
+    let tmp_dir = std::env::temp_dir();
+    let path = |name: &str| tmp_dir.join(name).to_string_lossy().to_string();
+    let (sdb_path, sdi_path, mfs_path) = (
+        path("pgr_db_bench_frag_store.sdb"),
+        path("pgr_db_bench_frag_store.sdi"),
+        path("pgr_db_bench_frag_store.mfs"),
+    );
+    if let Ok(mode) = std::env::var(MODE_VAR) {
+        run_mode(&mode, sdb_path, sdi_path, mfs_path);
+        return;
+    }
+    // the cargo bench arguments are ignored, the DB of 64 random 100 kb sequences is
+    // written once for both modes
+    let seqs = (0..64_u32)
+        .map(|sid| {
+            (
+                sid,
+                None,
+                format!("seq_{}", sid),
+                random_bases(sid as u64 + 1, 100_000),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+    sdb.load_seqs_from_seq_vec(&seqs);
+    sdb.write_to_file(sdb_path.clone()).unwrap();
+    sdb.write_index(sdi_path.clone()).unwrap();
+    sdb.write_frags_to_mmap_store(mfs_path.clone()).unwrap();
+    drop(sdb);
+    let exe = std::env::current_exe().unwrap();
+    ["in_memory", "mmap_store"].into_iter().for_each(|mode| {
+        let status = Command::new(&exe).env(MODE_VAR, mode).status().unwrap();
+        assert!(status.success());
+    });
+    [sdb_path, sdi_path, mfs_path].iter().for_each(|p| {
+        let _ = std::fs::remove_file(p);
+    });
+}
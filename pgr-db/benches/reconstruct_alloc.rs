@@ -0,0 +1,77 @@
+use pgr_db::seq_db::{CompactSeqDB, GetSeq, SHMMRSPEC};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// the system allocator counting the allocations and the reallocations
+struct CountingAlloc;
+
+static N_ALLOCS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        N_ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        N_ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// the allocations and the seconds of running `f` for `n_rounds` rounds
+fn measure(n_rounds: usize, mut f: impl FnMut()) -> (u64, f64) {
+    let n_allocs = N_ALLOCS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    (0..n_rounds).for_each(|_| f());
+    (
+        N_ALLOCS.load(Ordering::Relaxed) - n_allocs,
+        start.elapsed().as_secs_f64(),
+    )
+}
+
+/// count the heap allocations of reconstructing all sequences of a DB with `get_seq_by_id`
+/// and with `reconstruct_into` reusing the buffers, the DB is built from `PGR_BENCH_FASTX`
+/// if it is set
+fn main() {
+    let filepath = std::env::var("PGR_BENCH_FASTX")
+        .unwrap_or_else(|_| "test/test_data/test_seqs.fa".to_string());
+    let mut sdb = CompactSeqDB::new(SHMMRSPEC);
+    sdb.load_seqs_from_fastx(filepath).unwrap();
+    let n_rounds = 10;
+    let n_calls = (n_rounds * sdb.seqs.len()) as f64;
+
+    let (n_allocs, secs) = measure(n_rounds, || {
+        sdb.seqs.iter().for_each(|s| {
+            std::hint::black_box(sdb.get_seq_by_id(s.id));
+        })
+    });
+    println!(
+        "get_seq_by_id:    {:>10.2} allocations/call {:>10.3} ms/round",
+        n_allocs as f64 / n_calls,
+        secs * 1000.0 / n_rounds as f64
+    );
+
+    let mut out = Vec::<u8>::new();
+    let mut scratch = Vec::<u8>::new();
+    // the first round grows the buffers to the longest sequence
+    let (n_allocs, secs) = measure(n_rounds, || {
+        sdb.seqs.iter().for_each(|s| {
+            sdb.reconstruct_into(s.id, &mut out, &mut scratch).unwrap();
+            std::hint::black_box(&out);
+        })
+    });
+    println!(
+        "reconstruct_into: {:>10.2} allocations/call {:>10.3} ms/round",
+        n_allocs as f64 / n_calls,
+        secs * 1000.0 / n_rounds as f64
+    );
+}
@@ -0,0 +1,41 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use pgr_db::fasta_io::{reverse_complement_scalar, reverse_complement_with_options};
+
+fn random_bases(len: usize) -> Vec<u8> {
+    let mut state = 1_u64;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            b"ACGT"[((state >> 33) % 4) as usize]
+        })
+        .collect()
+}
+
+/// reverse complement 1 Mb of bases cut into the fragments of a few sizes
+fn bench_reverse_complement(c: &mut Criterion) {
+    let bases = random_bases(1 << 20);
+    let mut group = c.benchmark_group("reverse_complement");
+    [150, 1000, 20000].into_iter().for_each(|frag_len| {
+        let frags = bases.chunks(frag_len).collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::new("scalar", frag_len), &frags, |b, frags| {
+            b.iter(|| {
+                frags.iter().for_each(|frag| {
+                    black_box(reverse_complement_scalar(black_box(frag), false));
+                })
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("table", frag_len), &frags, |b, frags| {
+            b.iter(|| {
+                frags.iter().for_each(|frag| {
+                    black_box(reverse_complement_with_options(black_box(frag), false));
+                })
+            })
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_reverse_complement);
+criterion_main!(benches);
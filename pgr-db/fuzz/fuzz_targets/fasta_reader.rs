@@ -0,0 +1,42 @@
+//! feed arbitrary bytes to `FastaReader`, a malformed input has to end with an error instead of
+//! a panic, run with `cargo +nightly fuzz run fasta_reader` in `pgr-db`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pgr_db::fasta_io::{decompressed_reader, FastaReader};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let name = "fuzz_input".to_string();
+
+    // the records through the compression detection, the errors of a corrupted compressed
+    // stream repeat, so the records are only read up to the first error
+    if let Ok(reader) = decompressed_reader(Cursor::new(data), &name) {
+        if let Ok(reader) = FastaReader::new(reader, &name, 1 << 10, false) {
+            for rec in reader.with_soft_mask(true) {
+                if rec.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // the records skipped by the name filter
+    if let Ok(reader) = FastaReader::new(Cursor::new(data), &name, 1 << 10, false) {
+        let names = [b"a".to_vec()].into_iter().collect();
+        for rec in reader.with_name_filter(names).with_skip_empty(true) {
+            if rec.is_err() {
+                break;
+            }
+        }
+    }
+
+    // the records read by their offsets
+    if let Ok(mut reader) = FastaReader::new(Cursor::new(data), &name, 1 << 10, false) {
+        if let Ok(offsets) = reader.record_offsets() {
+            offsets.into_iter().for_each(|(offset, _)| {
+                let _ = reader.read_at(offset);
+            });
+        }
+    }
+});
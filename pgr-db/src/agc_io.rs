@@ -310,6 +310,8 @@ impl<'a> Iterator for AGCFileIter<'a> {
                                 source: Some(s.clone()),
                                 id: c.as_bytes().to_vec(),
                                 seq,
+                                qual: None,
+                                soft_mask: None,
                             }
                         })
                         //let seq = self.get_seq(s.clone(), c.clone());
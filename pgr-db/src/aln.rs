@@ -1,22 +1,194 @@
 // use rayon::prelude::*;
+use crate::fasta_io::reverse_complement;
 use crate::seq_db::{self, FragmentHit};
-use crate::shmmrutils::{self, ShmmrSpec};
+use crate::shmmrutils::{
+    self, cigar_to_string, deltas_to_cigar, push_cigar_op, MatchReadsParams, ShmmrSpec,
+};
 use rustc_hash::{FxHashMap, FxHashSet};
+use std::borrow::Cow;
 use std::collections::HashSet;
 
+/// a shimmer pair hit `((q_bgn, q_end, q_orientation), (t_bgn, t_end, t_orientation))` of the
+/// query on a target, the positions are the ends of the k-mers of the shimmers, an orientation
+/// is 0 if the shimmer pair is in the order of its hashes along the sequence and 1 if it is
+/// reversed, only the two orientations together tell the strand, see `Strand::of`
 pub type HitPair = ((u32, u32, u8), (u32, u32, u8)); //(bgn1, end1, orientation1),  (bgn2, end2, orientation2)
 
+/// the gap cost of chaining two hits is `gap_open + gap_extend * gap_length`,
+/// `gap_open` is not charged when two hits are next to each other
+#[derive(Debug, Clone, Copy)]
+pub struct ChainParams {
+    pub gap_open: f32,
+    pub gap_extend: f32,
+    /// the most hits chained, the hits of a query over it (e.g. a very repetitive query) are
+    /// subsampled with `subsample_hits` before chaining, the hits dropped are not in any
+    /// chain, `None` (the default) chains all hits
+    pub max_hits: Option<usize>,
+    /// a query span is in a tandem repeat if its hits on the target are spaced evenly, i.e.
+    /// every spacing is within `tr_period_tolerance` times the median spacing (the period)
+    /// of the median
+    pub tr_period_tolerance: f32,
+    /// the extra cost of each repeat unit a link into a tandem repeat jumps, a link jumps
+    /// units if the target gap and the query gap differ by about a multiple of the period,
+    /// `0.0` (the default) chains through the tandem repeats with the gap cost only
+    pub tr_penalty: f32,
+}
+
+impl ChainParams {
+    pub fn new(gap_open: f32, gap_extend: f32) -> Self {
+        ChainParams {
+            gap_open,
+            gap_extend,
+            max_hits: None,
+            tr_period_tolerance: 0.1,
+            tr_penalty: 0.0,
+        }
+    }
+
+    /// the same params with the limit of the hits chained, see `max_hits`
+    pub fn with_max_hits(self, max_hits: usize) -> Self {
+        ChainParams {
+            max_hits: Some(max_hits),
+            ..self
+        }
+    }
+
+    /// the same params with the extra cost of jumping the units of the tandem repeats, see
+    /// `tr_penalty`
+    pub fn with_tr_penalty(self, tr_period_tolerance: f32, tr_penalty: f32) -> Self {
+        ChainParams {
+            tr_period_tolerance,
+            tr_penalty,
+            ..self
+        }
+    }
+
+    /// the linear gap cost `penalty * gap_length`
+    pub fn linear(penalty: f32) -> Self {
+        ChainParams {
+            gap_open: 0.0,
+            gap_extend: penalty,
+            max_hits: None,
+            tr_period_tolerance: 0.1,
+            tr_penalty: 0.0,
+        }
+    }
+
+    fn gap_cost(&self, gap_length: f32) -> f32 {
+        let open = if gap_length > 0.0 { self.gap_open } else { 0.0 };
+        open + self.gap_extend * gap_length
+    }
+
+    /// the cost of the units jumped by a link shifting `shift` bases off the diagonal in a
+    /// tandem repeat of `period`
+    fn tr_cost(&self, period: f32, shift: f32) -> f32 {
+        let units = (shift.abs() / period).round();
+        if units >= 1.0 && (shift.abs() - units * period).abs() <= self.tr_period_tolerance * period
+        {
+            self.tr_penalty * units
+        } else {
+            0.0
+        }
+    }
+}
+
+/// the periods of the query spans in tandem repeats, the hits of such a span on the target
+/// are spaced evenly (at least three hits at different positions) and the period is the
+/// median spacing, see `ChainParams::tr_period_tolerance`
+pub fn tandem_repeat_periods(
+    sp_hits: &[HitPair],
+    tr_period_tolerance: f32,
+) -> FxHashMap<(u32, u32, u8), f32> {
+    let mut span_t_bgns = FxHashMap::<(u32, u32, u8), Vec<u32>>::default();
+    sp_hits
+        .iter()
+        .for_each(|hp| span_t_bgns.entry(hp.0).or_default().push(hp.1 .0));
+    span_t_bgns
+        .into_iter()
+        .filter_map(|(span, mut t_bgns)| {
+            t_bgns.sort_unstable();
+            t_bgns.dedup();
+            if t_bgns.len() < 3 {
+                return None;
+            }
+            let mut spacings = t_bgns
+                .windows(2)
+                .map(|w| (w[1] - w[0]) as f32)
+                .collect::<Vec<_>>();
+            spacings.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+            let period = spacings[spacings.len() / 2];
+            if spacings
+                .iter()
+                .all(|s| (s - period).abs() <= tr_period_tolerance * period)
+            {
+                Some((span, period))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// chain the hits on a target by dynamic programming, the best chain is the first one
+///
+/// A hit extends a chain on the same `Strand` with the gap between the hits moving forward
+/// on the target, and on the opposite `Strand` with the gap moving backward on the target,
+/// so a chain can switch strands at an inversion, see `split_chain_by_orientation`.
 pub fn sparse_aln(
     sp_hits: &mut Vec<HitPair>,
     max_span: u32,
     penalty: f32,
 ) -> Vec<(f32, Vec<HitPair>)> {
+    sparse_aln_with_params(sp_hits, max_span, &ChainParams::linear(penalty))
+}
+
+pub fn sparse_aln_with_params(
+    sp_hits: &mut [HitPair],
+    max_span: u32,
+    chain_params: &ChainParams,
+) -> Vec<(f32, Vec<HitPair>)> {
+    sp_hits.sort_by(|a, b| a.0 .0.partial_cmp(&b.0 .0).unwrap());
+    sparse_aln_presorted_with_params(sp_hits, max_span, chain_params)
+}
+
+/// the same as `sparse_aln`, but the hits must be sorted by the query start already, e.g. the
+/// hits collected along the query, the sort is skipped and the order is only checked in the
+/// debug builds
+pub fn sparse_aln_presorted(
+    sp_hits: &[HitPair],
+    max_span: u32,
+    penalty: f32,
+) -> Vec<(f32, Vec<HitPair>)> {
+    sparse_aln_presorted_with_params(sp_hits, max_span, &ChainParams::linear(penalty))
+}
+
+pub fn sparse_aln_presorted_with_params(
+    sp_hits: &[HitPair],
+    max_span: u32,
+    chain_params: &ChainParams,
+) -> Vec<(f32, Vec<HitPair>)> {
+    debug_assert!(
+        sp_hits.windows(2).all(|w| w[0].0 .0 <= w[1].0 .0),
+        "the hits are not sorted by the query start"
+    );
+    let subsampled_hits;
+    let sp_hits = match chain_params.max_hits {
+        Some(max_hits) if sp_hits.len() > max_hits => {
+            subsampled_hits = subsample_hits(sp_hits, max_hits);
+            &subsampled_hits[..]
+        }
+        _ => sp_hits,
+    };
     // given a set of hits in the form of (bgn1, end1, orientation1),  (bgn2, end2, orientation2)
     // perform (banded) dynamic programming to group them into list of hit chains
-    sp_hits.sort_by(|a, b| a.0 .0.partial_cmp(&b.0 .0).unwrap());
     let mut v_s = FxHashMap::<HitPair, f32>::default(); // score for each vertex
     let mut best_pre_v = FxHashMap::<HitPair, Option<HitPair>>::default(); // look up for the best pre-vertex
     assert!(sp_hits.len() > 1);
+    let tr_periods = if chain_params.tr_penalty > 0.0 {
+        tandem_repeat_periods(sp_hits, chain_params.tr_period_tolerance)
+    } else {
+        FxHashMap::default()
+    };
     let first_hp = sp_hits[0];
     v_s.insert(first_hp, first_hp.0 .1 as f32 - first_hp.0 .0 as f32); // the score of the first node is just its length
     best_pre_v.insert(first_hp, None);
@@ -41,16 +213,17 @@ pub fn sparse_aln(
             let p_s = v_s.get(&pre_hp).unwrap_or(&0_f32);
             let mut s: f32 = *p_s + (hp.0 .1 as f32 - hp.0 .0 as f32);
 
-            if hp.0 .2 == hp.1 .2 {
+            let q_gap = hp.0 .0 as f32 - pre_hp.0 .1 as f32;
+            let t_gap = if Strand::of(&hp) == Strand::Forward {
                 // same orientation
-                s -= penalty
-                    * ((hp.0 .0 as f32 - pre_hp.0 .1 as f32).abs()
-                        + (hp.1 .0 as f32 - pre_hp.1 .1 as f32).abs());
+                hp.1 .0 as f32 - pre_hp.1 .1 as f32
             } else {
                 // opposite orientation
-                s -= penalty
-                    * ((hp.0 .0 as f32 - pre_hp.0 .1 as f32).abs()
-                        + (hp.1 .1 as f32 - pre_hp.1 .0 as f32).abs());
+                pre_hp.1 .0 as f32 - hp.1 .1 as f32
+            };
+            s -= chain_params.gap_cost(q_gap.abs() + t_gap.abs());
+            if let Some(period) = tr_periods.get(&hp.0).or_else(|| tr_periods.get(&pre_hp.0)) {
+                s -= chain_params.tr_cost(*period, t_gap - q_gap);
             }
 
             if s > best_s {
@@ -111,32 +284,209 @@ pub fn sparse_aln(
     out
 }
 
-pub type TargetHitPairLists = Vec<(u32, Vec<(f32, Vec<HitPair>)>)>; // target_id, Vec<(score, HitPairs)>
+/// keep `max_hits` (at least 2) of the hits in their order for chaining, the hits of the query
+/// spans hit fewer times are kept first as they are the less repetitive anchors, and the hits
+/// of the most repetitive spans kept are taken evenly along the query so the anchors kept are
+/// spread over it
+pub fn subsample_hits(sp_hits: &[HitPair], max_hits: usize) -> Vec<HitPair> {
+    let max_hits = max_hits.max(2);
+    if sp_hits.len() <= max_hits {
+        return sp_hits.to_vec();
+    }
+    let mut span_counts = FxHashMap::<(u32, u32, u8), usize>::default();
+    sp_hits
+        .iter()
+        .for_each(|hp| *span_counts.entry(hp.0).or_default() += 1);
+    let counts = sp_hits
+        .iter()
+        .map(|hp| span_counts[&hp.0])
+        .collect::<Vec<_>>();
+    let mut sorted_counts = counts.clone();
+    sorted_counts.sort_unstable();
+    let cutoff = sorted_counts[max_hits - 1];
+    let n_below = sorted_counts.partition_point(|&c| c < cutoff);
+    let n_at = sorted_counts.partition_point(|&c| c <= cutoff) - n_below;
+    let n_take = max_hits - n_below;
+    let mut i = 0_usize;
+    sp_hits
+        .iter()
+        .zip(counts)
+        .filter(|(_, c)| match (*c).cmp(&cutoff) {
+            std::cmp::Ordering::Less => true,
+            std::cmp::Ordering::Greater => false,
+            std::cmp::Ordering::Equal => {
+                // take `n_take` of the `n_at` hits evenly
+                let keep = (i + 1) * n_take / n_at > i * n_take / n_at;
+                i += 1;
+                keep
+            }
+        })
+        .map(|(hp, _)| *hp)
+        .collect()
+}
+
+pub type TargetHitPairList = (u32, Vec<(f32, Vec<HitPair>)>); // target_id, Vec<(score, HitPairs)>
+pub type TargetHitPairLists = Vec<TargetHitPairList>;
+
+/// the half-open interval `[bgn, end)` of the shimmer positions of a hit on a sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub bgn: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(bgn: u32, end: u32) -> Self {
+        Span { bgn, end }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end - self.bgn
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bgn == self.end
+    }
+}
+
+/// the orientation of the target relative to the query, i.e. the query matches the target
+/// as it is on `Forward` and its reverse complement matches the target on `Reverse`, the
+/// positions on both are always in their own forward coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+impl Strand {
+    /// the strand of a hit, `Forward` if the shimmer pair is in the same orientation on the
+    /// query and the target
+    pub fn of(hp: &HitPair) -> Self {
+        Strand::from_orientations(hp.0 .2, hp.1 .2)
+    }
 
+    /// the strand of the shimmer pair orientations on the query and on the target
+    pub fn from_orientations(query_orientation: u8, target_orientation: u8) -> Self {
+        if query_orientation == target_orientation {
+            Strand::Forward
+        } else {
+            Strand::Reverse
+        }
+    }
+
+    /// the `u8` strand of the outputs, 0 for `Forward` and 1 for `Reverse`
+    pub fn as_u8(self) -> u8 {
+        match self {
+            Strand::Forward => 0,
+            Strand::Reverse => 1,
+        }
+    }
+
+    /// `+` or `-` as the strand column of PAF and BED
+    pub fn as_char(self) -> char {
+        match self {
+            Strand::Forward => '+',
+            Strand::Reverse => '-',
+        }
+    }
+}
+
+/// a `HitPair` with the named fields, `query` is in the coordinates of the query and
+/// `target` is in the coordinates of the target sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Anchor {
+    pub query: Span,
+    pub target: Span,
+    pub strand: Strand,
+}
+
+impl From<HitPair> for Anchor {
+    fn from(hp: HitPair) -> Self {
+        let ((q_bgn, q_end, _), (t_bgn, t_end, _)) = hp;
+        Anchor {
+            query: Span::new(q_bgn, q_end),
+            target: Span::new(t_bgn, t_end),
+            strand: Strand::of(&hp),
+        }
+    }
+}
+
+/// convert a hit chain to the anchors
+pub fn chain_to_anchors(chain: &[HitPair]) -> Vec<Anchor> {
+    chain.iter().map(|&hp| Anchor::from(hp)).collect()
+}
+
+pub type TargetAnchorLists = Vec<(u32, Vec<(f32, Vec<Anchor>)>)>; // target_id, Vec<(score, Anchors)>
+
+/// the default of `max_count`, `query_max_count` and `target_max_count` of `query_fragment_to_hps`
+pub const DEFAULT_MAX_FRAG_COUNT: u32 = 128;
+/// the default of `max_aln_span` of `query_fragment_to_hps`
+pub const DEFAULT_MAX_ALN_SPAN: u32 = 8;
+/// the default of `min_chain_score` of `query_fragment_to_hps`, no chain is dropped by its score
+pub const DEFAULT_MIN_CHAIN_SCORE: f32 = f32::NEG_INFINITY;
+/// the default of `min_chain_len` of `query_fragment_to_hps`, a single hit is not a chain
+pub const DEFAULT_MIN_CHAIN_LEN: usize = 2;
+
+/// chain the shimmer pair hits of a query to each target sequence
+///
+/// The shimmer pairs hit more than `max_count` times (`query_max_count` for the query side,
+/// `target_max_count` for the hits on one target) are treated as repeats and dropped. The
+/// chaining only links the hits at most `max_aln_span` hits apart on the query and the
+/// target. Raising them keeps the hits in large repeat families and bridges longer
+/// rearranged or diverged regions, but the number of the hits and the chaining work grow
+/// quickly with both. They default to `DEFAULT_MAX_FRAG_COUNT` and `DEFAULT_MAX_ALN_SPAN`.
+/// The chains of less than `min_chain_len` hits or of a score less than `min_chain_score` are
+/// dropped, pass `DEFAULT_MIN_CHAIN_LEN` and `DEFAULT_MIN_CHAIN_SCORE` to only drop the single
+/// hits. With a `min_chain_len` of 1, the single hit on a target is a chain scored by its
+/// query span.
+///
+/// All counts come from `raw_query_hits`, the query `frag` and `shmmr_spec` are only kept
+/// for the callers. The hits keep the shimmer pair orientations of the query and the
+/// target, `Strand::of` tells if a hit is on the reverse strand relative to the query.
 #[allow(clippy::too_many_arguments)]
 pub fn query_fragment_to_hps(
     raw_query_hits: Vec<FragmentHit>,
-    frag: &Vec<u8>,
-    shmmr_spec: &ShmmrSpec,
+    _frag: &Vec<u8>,
+    _shmmr_spec: &ShmmrSpec,
     penalty: f32,
     max_count: Option<u32>,
     query_max_count: Option<u32>,
     target_max_count: Option<u32>,
     max_aln_span: Option<u32>,
+    min_chain_score: f32,
+    min_chain_len: usize,
+) -> TargetHitPairLists {
+    query_fragment_to_hps_with_pair_count(
+        raw_query_hits,
+        penalty,
+        max_count,
+        query_max_count,
+        target_max_count,
+        max_aln_span,
+        min_chain_score,
+        min_chain_len,
+        None,
+    )
+}
+
+/// the same as `query_fragment_to_hps`, but the hits of a shimmer pair on a target are
+/// counted with a `ShmmrPairTargetCount` table of the index when it is given, the table
+/// only depends on the index, so building it once saves the recount for every query
+#[allow(clippy::too_many_arguments)]
+pub fn query_fragment_to_hps_with_pair_count(
+    raw_query_hits: Vec<FragmentHit>,
+    penalty: f32,
+    max_count: Option<u32>,
+    query_max_count: Option<u32>,
+    target_max_count: Option<u32>,
+    max_aln_span: Option<u32>,
+    min_chain_score: f32,
+    min_chain_len: usize,
+    pair_target_count: Option<&seq_db::ShmmrPairTargetCount>,
 ) -> TargetHitPairLists {
     let mut shmmr_pair_hash_count = FxHashMap::<(u64, u64), u32>::default();
-    let mut query_shmmr_pair_hash_count = FxHashMap::<(u64, u64), u32>::default();
     let mut target_shmer_pair_count = FxHashMap::<(u64, u64, u32), u32>::default();
 
-    seq_db::pair_shmmrs(&shmmrutils::sequence_to_shmmrs(0, frag, shmmr_spec, false))
-        .iter()
-        .for_each(|shmmr_pair| {
-            let entry = query_shmmr_pair_hash_count
-                .entry((shmmr_pair.0.hash(), shmmr_pair.1.hash()))
-                .or_insert(0);
-            *entry += 1;
-        });
-
     raw_query_hits.iter().for_each(
         |(shmmr_pair_hash, _query_position, frag_signature): &(
             (u64, u64),
@@ -147,6 +497,9 @@ pub fn query_fragment_to_hps(
             // count shimmer pair hits
             let entry = shmmr_pair_hash_count.entry(*shmmr_pair_hash).or_insert(0);
             *entry += 1;
+            if pair_target_count.is_some() {
+                return;
+            }
 
             frag_signature
                 .iter()
@@ -168,20 +521,28 @@ pub fn query_fragment_to_hps(
             Vec<seq_db::FragmentSignature>,
         )| {
             let count = *shmmr_pair_hash_count.get(&shmmr_pair).unwrap_or(&0);
-            let max_count = max_count.unwrap_or(128);
+            let max_count = max_count.unwrap_or(DEFAULT_MAX_FRAG_COUNT);
             if count > max_count {
                 return;
             };
-            let max_count_query = query_max_count.unwrap_or(128);
+            let max_count_query = query_max_count.unwrap_or(DEFAULT_MAX_FRAG_COUNT);
             if count > max_count_query {
                 return;
             };
             let left_frag_coordinate = query_position;
             frag_signature.iter().for_each(|&(_frg_id, sid, pos0, pos1, orientation)| {
-                let count = *target_shmer_pair_count
-                    .get(&(shmmr_pair.0, shmmr_pair.1, sid))
-                    .unwrap_or(&0);
-                let max_count_target = target_max_count.unwrap_or(128);
+                let key = (shmmr_pair.0, shmmr_pair.1, sid);
+                // every hit of a shimmer pair carries the same fragment signatures, so the
+                // recount is the count in the index times the number of the hits
+                let count = if let Some(pair_target_count) = pair_target_count {
+                    pair_target_count
+                        .get(&key)
+                        .unwrap_or(&0)
+                        .saturating_mul(count)
+                } else {
+                    *target_shmer_pair_count.get(&key).unwrap_or(&0)
+                };
+                let max_count_target = target_max_count.unwrap_or(DEFAULT_MAX_FRAG_COUNT);
                 if count > max_count_target {
                     return;
                 };
@@ -192,38 +553,1248 @@ pub fn query_fragment_to_hps(
         },
     );
 
-    let max_aln_span = max_aln_span.unwrap_or(8);
-
+    let max_aln_span = max_aln_span.unwrap_or(DEFAULT_MAX_ALN_SPAN);
+    // the chains with the score less than `min_chain_score` or less than `min_chain_len`
+    // hits are dropped, and so are the targets without any chain left
     target_squence_id_to_hits
         .into_iter()
-        .filter(|(_sid, hps)| hps.len() > 1)
-        .map(|(sid, mut hps)| (sid, sparse_aln(&mut hps, max_aln_span, penalty)))
+        .filter(|(_sid, hps)| hps.len() >= min_chain_len)
+        .filter_map(|(sid, mut hps)| {
+            // `sparse_aln` chains two hits at least, a single hit is scored like the first
+            // node of a chain
+            let chains = if hps.len() == 1 {
+                let hp = hps[0];
+                vec![(hp.0 .1 as f32 - hp.0 .0 as f32, hps)]
+            } else {
+                sparse_aln(&mut hps, max_aln_span, penalty)
+            };
+            let chains = chains
+                .into_iter()
+                .filter(|(score, chain)| *score >= min_chain_score && chain.len() >= min_chain_len)
+                .collect::<Vec<_>>();
+            if chains.is_empty() {
+                None
+            } else {
+                Some((sid, chains))
+            }
+        })
         .collect::<Vec<_>>()
 }
 
-#[test]
+/// the same as `query_fragment_to_hps_with_pair_count`, but the chains are the `Anchor`s
+/// instead of the `HitPair` tuples
+#[allow(clippy::too_many_arguments)]
+pub fn query_fragment_to_anchors(
+    raw_query_hits: Vec<FragmentHit>,
+    penalty: f32,
+    max_count: Option<u32>,
+    query_max_count: Option<u32>,
+    target_max_count: Option<u32>,
+    max_aln_span: Option<u32>,
+    min_chain_score: f32,
+    min_chain_len: usize,
+    pair_target_count: Option<&seq_db::ShmmrPairTargetCount>,
+) -> TargetAnchorLists {
+    query_fragment_to_hps_with_pair_count(
+        raw_query_hits,
+        penalty,
+        max_count,
+        query_max_count,
+        target_max_count,
+        max_aln_span,
+        min_chain_score,
+        min_chain_len,
+        pair_target_count,
+    )
+    .into_iter()
+    .map(|(sid, chains)| {
+        let chains = chains
+            .into_iter()
+            .map(|(score, chain)| (score, chain_to_anchors(&chain)))
+            .collect();
+        (sid, chains)
+    })
+    .collect()
+}
 
-fn sparse_aln_test() {
-    use crate::aln::sparse_aln;
+/// split a hit chain into the runs of the hits with the same relative orientation
+pub fn split_chain_by_orientation(hps: &[HitPair]) -> Vec<&[HitPair]> {
+    let mut runs = Vec::<&[HitPair]>::new();
+    let mut run_bgn = 0_usize;
+    (1..=hps.len()).for_each(|i| {
+        if i == hps.len() || Strand::of(&hps[i]) != Strand::of(&hps[i - 1]) {
+            runs.push(&hps[run_bgn..i]);
+            run_bgn = i;
+        }
+    });
+    runs
+}
+
+/// the base coordinates `((query_start, query_end), (target_start, target_end))` covered by a run
+/// of hits, the shimmer positions of the hits are the ends of the k-mers
+pub fn hit_run_to_base_span(run: &[HitPair], k: u32) -> ((u32, u32), (u32, u32)) {
+    let query_start = run.iter().map(|hp| hp.0 .0).min().unwrap();
+    let query_end = run.iter().map(|hp| hp.0 .1).max().unwrap();
+    let target_start = run.iter().map(|hp| hp.1 .0).min().unwrap();
+    let target_end = run.iter().map(|hp| hp.1 .1).max().unwrap();
+    (
+        (query_start.saturating_sub(k), query_end),
+        (target_start.saturating_sub(k), target_end),
+    )
+}
+
+/// the numbers of the query bases `(query_clip_start, query_clip_end)` before and after a
+/// non-empty hit chain on a query of `query_len` bases, e.g. the soft clips of a SAM record,
+/// in the orientation of the query, see `hit_run_to_base_span` for the k-mer size `k`
+pub fn chain_query_clips(chain: &[HitPair], query_len: u32, k: u32) -> (u32, u32) {
+    let ((query_start, query_end), _) = hit_run_to_base_span(chain, k);
+    (query_start, query_len.saturating_sub(query_end))
+}
+
+/// the parameters of `extend_chain`
+#[derive(Debug, Clone, Copy)]
+pub struct ChainExtendParams {
+    /// the most bases aligned past each end of a chain
+    pub flank_len: u32,
+    /// the alignments of the flanks and of the extended spans, the flank alignments are
+    /// trimmed to the exact matches of `trim_anchor_len` bases so an extension does not end
+    /// in the unaligned bases
+    pub match_params: MatchReadsParams,
+}
+
+impl Default for ChainExtendParams {
+    fn default() -> Self {
+        ChainExtendParams {
+            flank_len: 500,
+            match_params: MatchReadsParams {
+                trim_anchor_len: Some(12),
+                ..MatchReadsParams::default()
+            },
+        }
+    }
+}
+
+/// the base coordinates of a run of hits extended by `extend_chain`, the target coordinates
+/// are on the forward strand of the target as the ones of `AlignmentHit`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedChain {
+    pub query_start: u32,
+    pub query_end: u32,
+    pub target_start: u32,
+    pub target_end: u32,
+    pub strand: Strand,
+    /// the fraction of the matched bases of the alignment of the extended spans, see
+    /// `OvlpMatch::identity`, `None` if the spans do not align within the tolerance
+    pub identity: Option<f32>,
+}
+
+/// the `(len0, len1)` of the alignment from the start of `seq0` and `seq1`, e.g. the flanks
+/// past an end of a chain, it is the alignment of the longest flanks aligning that are found
+/// by halving the flanks and then bisecting the lengths, and `(0, 0)` if the flanks get
+/// shorter than the trimming anchor before they align
+fn extend_into_flanks(seq0: &[u8], seq1: &[u8], params: &ChainExtendParams) -> (u32, u32) {
+    if seq0.is_empty() || seq1.is_empty() {
+        return (0, 0);
+    }
+    let min_len = params.match_params.trim_anchor_len.unwrap_or(1).max(1) as usize;
+    let align = |len: usize| {
+        shmmrutils::match_reads_with_params(
+            &seq0[..len.min(seq0.len())],
+            &seq1[..len.min(seq1.len())],
+            &params.match_params,
+        )
+    };
+    // the flanks of `lo` bases align and the ones of `hi` bases do not
+    let mut hi = (params.flank_len as usize).min(seq0.len().max(seq1.len())) + 1;
+    let mut lo = hi - 1;
+    let mut best = None;
+    while lo >= min_len {
+        best = align(lo);
+        if best.is_some() {
+            break;
+        }
+        hi = lo;
+        lo /= 2;
+    }
+    if best.is_none() {
+        return (0, 0);
+    }
+    while hi - lo > min_len {
+        let mid = (lo + hi) / 2;
+        match align(mid) {
+            Some(m) => {
+                best = Some(m);
+                lo = mid;
+            }
+            None => hi = mid,
+        }
+    }
+    best.map_or((0, 0), |m| (m.end0, m.end1))
+}
+
+/// extend a run of hits of the same `Strand` (see `split_chain_by_orientation`) past its
+/// outermost anchors by aligning the flanks of the query and the target bases with
+/// `match_reads`, and align the extended spans for the base-level identity
+///
+/// `query` and `target` are the whole sequences the hits are on, and `k` is the k-mer size
+/// of the shimmers, see `hit_run_to_base_span`
+pub fn extend_chain(
+    run: &[HitPair],
+    query: &[u8],
+    target: &[u8],
+    k: u32,
+    params: &ChainExtendParams,
+) -> ExtendedChain {
+    let strand = Strand::of(&run[0]);
+    let ((q_bgn, q_end), (t_bgn, t_end)) = hit_run_to_base_span(run, k);
+    let q_end = q_end.min(query.len() as u32);
+    let t_end = t_end.min(target.len() as u32);
+    // the target bases around the run in the orientation of the query, and the run on them
+    let w_bgn = t_bgn.saturating_sub(params.flank_len);
+    let w_end = t_end
+        .saturating_add(params.flank_len)
+        .min(target.len() as u32);
+    let window = &target[w_bgn as usize..w_end as usize];
+    let (window, w_t_bgn, w_t_end) = match strand {
+        Strand::Forward => (Cow::Borrowed(window), t_bgn - w_bgn, t_end - w_bgn),
+        Strand::Reverse => (
+            Cow::Owned(reverse_complement(window)),
+            w_end - t_end,
+            w_end - t_bgn,
+        ),
+    };
+
+    let reversed = |s: &[u8]| s.iter().rev().copied().collect::<Vec<u8>>();
+    let q_flank_bgn = q_bgn.saturating_sub(params.flank_len) as usize;
+    let (q_ext_bgn, t_ext_bgn) = extend_into_flanks(
+        &reversed(&query[q_flank_bgn..q_bgn as usize]),
+        &reversed(&window[..w_t_bgn as usize]),
+        params,
+    );
+    let (q_ext_end, t_ext_end) = extend_into_flanks(
+        &query[q_end as usize..],
+        &window[w_t_end as usize..],
+        params,
+    );
+    let (q_bgn, q_end) = (q_bgn - q_ext_bgn, q_end + q_ext_end);
+    let (w_t_bgn, w_t_end) = (w_t_bgn - t_ext_bgn, w_t_end + t_ext_end);
+
+    let span_params = MatchReadsParams {
+        trim_anchor_len: None,
+        ..params.match_params
+    };
+    let identity = shmmrutils::match_reads_with_params(
+        &query[q_bgn as usize..q_end as usize],
+        &window[w_t_bgn as usize..w_t_end as usize],
+        &span_params,
+    )
+    .map(|m| m.identity());
+    let (target_start, target_end) = match strand {
+        Strand::Forward => (w_bgn + w_t_bgn, w_bgn + w_t_end),
+        Strand::Reverse => (w_end - w_t_end, w_end - w_t_bgn),
+    };
+    ExtendedChain {
+        query_start: q_bgn,
+        query_end: q_end,
+        target_start,
+        target_end,
+        strand,
+        identity,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignmentHit {
+    /// the name of the target sequence
+    pub name: String,
+    pub seq_id: u32,
+    pub query_start: u32,
+    pub query_end: u32,
+    /// the query bases before `query_start` and after `query_end`, both are 0 if the whole
+    /// query is aligned, see `chain_query_clips`
+    pub query_clip_start: u32,
+    pub query_clip_end: u32,
+    pub target_start: u32,
+    pub target_end: u32,
+    /// 0 for the same orientation, 1 for the query aligned to the reverse strand
+    pub strand: u8,
+    /// the score of the chain
+    pub score: f32,
+    /// the target bases `[target_start, target_end)`, reverse complemented for the hits on
+    /// the reverse strand so they align to the query, if asked for
+    pub target_seq: Option<Vec<u8>>,
+}
+
+/// convert the hit chains from `sparse_aln` to PAF lines (without the newline)
+///
+/// A chain with hits of both relative orientations is split into runs of the
+/// same orientation, each run becomes a PAF line carrying the chain score in
+/// the `AS:i:` tag. The coordinates are 0-based and half-open. The number of
+/// residue matches is estimated by the total query span of the hits.
+pub fn chains_to_paf(
+    query_name: &str,
+    query_len: u32,
+    target_name: &str,
+    target_len: u32,
+    chains: &[(f32, Vec<HitPair>)],
+) -> Vec<String> {
+    let mut out = Vec::<String>::new();
+    chains.iter().for_each(|(score, hps)| {
+        split_chain_by_orientation(hps).into_iter().for_each(|run| {
+            let strand = Strand::of(&run[0]).as_char();
+            let q_bgn = run.iter().map(|hp| hp.0 .0).min().unwrap();
+            let q_end = run.iter().map(|hp| hp.0 .1).max().unwrap().min(query_len);
+            let t_bgn = run.iter().map(|hp| hp.1 .0).min().unwrap();
+            let t_end = run.iter().map(|hp| hp.1 .1).max().unwrap().min(target_len);
+            let aln_len = (q_end - q_bgn).max(t_end - t_bgn);
+            let n_match = run
+                .iter()
+                .map(|hp| hp.0 .1 - hp.0 .0)
+                .sum::<u32>()
+                .min(aln_len);
+            out.push(format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t255\tAS:i:{}",
+                query_name,
+                query_len,
+                q_bgn,
+                q_end,
+                strand,
+                target_name,
+                target_len,
+                t_bgn,
+                t_end,
+                n_match,
+                aln_len,
+                *score as i64
+            ));
+        });
+    });
+    out
+}
+
+/// convert the hit chains from `sparse_aln` to BED12 lines (without the newline) on the target
+///
+/// Each run of the same orientation of a chain becomes a feature named by the query, the
+/// blocks are the target bases of the hits, see `hit_run_to_base_span`, with the overlapping
+/// blocks merged. The hits not strictly colinear with the earlier hits along the target are
+/// dropped with a warning. The BED score is the chain score scaled to the best chain, from
+/// 0 to 1000.
+pub fn chains_to_bed12(
+    query_name: &str,
+    target_name: &str,
+    target_len: u32,
+    chains: &[(f32, Vec<HitPair>)],
+    k: u32,
+) -> Vec<String> {
+    let max_score = chains
+        .iter()
+        .map(|(score, _)| *score)
+        .fold(0.0_f32, f32::max);
+    let mut out = Vec::<String>::new();
+    chains.iter().for_each(|(score, hps)| {
+        let bed_score = if max_score > 0.0 {
+            (1000.0 * score / max_score).round().clamp(0.0, 1000.0) as u32
+        } else {
+            0
+        };
+        split_chain_by_orientation(hps).into_iter().for_each(|run| {
+            let reversed = Strand::of(&run[0]) == Strand::Reverse;
+            let mut hits = run.to_vec();
+            hits.sort_by_key(|hp| (hp.1 .0, hp.1 .1));
+            // the query moves backward along the target on the reverse strand
+            let mut colinear_hits = Vec::<HitPair>::new();
+            hits.into_iter().for_each(|hp| match colinear_hits.last() {
+                Some(last)
+                    if hp.1 .0 <= last.1 .0
+                        || (reversed && hp.0 .0 >= last.0 .0)
+                        || (!reversed && hp.0 .0 <= last.0 .0) => {}
+                _ => colinear_hits.push(hp),
+            });
+            if colinear_hits.len() < run.len() {
+                log::warn!(
+                    "{} of the {} hits of a chain of {} on {} are not colinear and dropped",
+                    run.len() - colinear_hits.len(),
+                    run.len(),
+                    query_name,
+                    target_name
+                );
+            }
+
+            let mut blocks = Vec::<(u32, u32)>::new();
+            colinear_hits.iter().for_each(|hp| {
+                let bgn = hp.1 .0.saturating_sub(k).min(target_len);
+                let end = hp.1 .1.min(target_len);
+                match blocks.last_mut() {
+                    Some(last) if bgn <= last.1 => last.1 = last.1.max(end),
+                    _ => blocks.push((bgn, end)),
+                }
+            });
+            let chrom_start = blocks[0].0;
+            let chrom_end = blocks.last().unwrap().1;
+            let block_sizes = blocks
+                .iter()
+                .map(|(bgn, end)| format!("{},", end - bgn))
+                .collect::<String>();
+            let block_starts = blocks
+                .iter()
+                .map(|(bgn, _)| format!("{},", bgn - chrom_start))
+                .collect::<String>();
+            out.push(format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t0\t{}\t{}\t{}",
+                target_name,
+                chrom_start,
+                chrom_end,
+                query_name,
+                bed_score,
+                if reversed { '-' } else { '+' },
+                chrom_start,
+                chrom_end,
+                blocks.len(),
+                block_sizes,
+                block_starts
+            ));
+        });
+    });
+    out
+}
+
+/// the CIGAR operations aligning `seq1` (the query) to `seq0` (the target) end to end,
+/// it falls back to a gapless alignment if the two sequences are too different to be aligned
+fn align_to_cigar(seq0: &[u8], seq1: &[u8]) -> Vec<(char, u32)> {
+    let len0 = seq0.len() as u32;
+    let len1 = seq1.len() as u32;
+    let m = if len0 == 0 || len1 == 0 {
+        None
+    } else {
+        shmmrutils::match_reads_with_params(seq0, seq1, &MatchReadsParams::default())
+    };
+    if let Some(m) = m.filter(|m| m.bgn0 == 0 && m.bgn1 == 0) {
+        let mut cigar = deltas_to_cigar(&m.deltas.unwrap_or_default(), m.end0, m.end1);
+        push_cigar_op(&mut cigar, 'D', len0 - m.end0);
+        push_cigar_op(&mut cigar, 'I', len1 - m.end1);
+        return cigar;
+    }
+    let mut cigar = Vec::<(char, u32)>::new();
+    let l = len0.min(len1);
+    push_cigar_op(&mut cigar, 'M', l);
+    push_cigar_op(&mut cigar, 'D', len0 - l);
+    push_cigar_op(&mut cigar, 'I', len1 - l);
+    cigar
+}
+
+/// convert the hit chains from `sparse_aln` to SAM records (without the newline)
+///
+/// The runs of the same orientation of a chain are aligned base by base between the
+/// matched shimmers with `match_reads`. The unaligned query ends are soft-clipped,
+/// a run aligned to the reverse strand has the `0x10` flag and the reverse complement
+/// of the query as its `SEQ`. The records after the first one are flagged as secondary
+/// alignments, the chain score is carried in the `AS:i:` tag.
+pub fn chains_to_sam(
+    query_name: &str,
+    query_seq: &[u8],
+    target_name: &str,
+    target_seq: &[u8],
+    chains: &[(f32, Vec<HitPair>)],
+    k: u32,
+) -> Vec<String> {
+    let query_len = query_seq.len() as u32;
+    let rc_query_seq = reverse_complement(query_seq);
+    let mut out = Vec::<String>::new();
+    chains.iter().for_each(|(score, hps)| {
+        split_chain_by_orientation(hps).into_iter().for_each(|run| {
+            let reversed = Strand::of(&run[0]) == Strand::Reverse;
+            // the end positions of the identical k-mers on the query (in the aligned
+            // orientation) and the target, for the reversed hits, the first shimmer of
+            // the query fragment matches the second shimmer of the target fragment
+            let mut anchors = run
+                .iter()
+                .flat_map(|hp| {
+                    if reversed {
+                        [
+                            (query_len + k - hp.0 .0, hp.1 .1),
+                            (query_len + k - hp.0 .1, hp.1 .0),
+                        ]
+                    } else {
+                        [(hp.0 .0, hp.1 .0), (hp.0 .1, hp.1 .1)]
+                    }
+                })
+                .filter(|&(q, t)| {
+                    q >= k && t >= k && q <= query_len && t <= target_seq.len() as u32
+                })
+                .collect::<Vec<_>>();
+            anchors.sort_by_key(|&(q, t)| (t, q));
+            let mut chained_anchors = Vec::<(u32, u32)>::new();
+            anchors
+                .into_iter()
+                .for_each(|(q, t)| match chained_anchors.last() {
+                    Some(&(q0, t0)) if q <= q0 || t <= t0 => {}
+                    _ => chained_anchors.push((q, t)),
+                });
+            if chained_anchors.is_empty() {
+                return;
+            }
+
+            let seq = if reversed {
+                &rc_query_seq[..]
+            } else {
+                query_seq
+            };
+            let (q_bgn, t_bgn) = chained_anchors[0];
+            let (q_end, _) = *chained_anchors.last().unwrap();
+            let mut cigar = Vec::<(char, u32)>::new();
+            push_cigar_op(&mut cigar, 'S', q_bgn - k);
+            push_cigar_op(&mut cigar, 'M', k);
+            chained_anchors.windows(2).for_each(|w| {
+                let (q0, t0) = w[0];
+                let (q1, t1) = w[1];
+                align_to_cigar(
+                    &target_seq[t0 as usize..t1 as usize],
+                    &seq[q0 as usize..q1 as usize],
+                )
+                .into_iter()
+                .for_each(|(op, len)| push_cigar_op(&mut cigar, op, len));
+            });
+            push_cigar_op(&mut cigar, 'S', query_len - q_end);
+
+            let mut flag = if reversed { 0x10 } else { 0 };
+            if !out.is_empty() {
+                flag |= 0x100;
+            }
+            out.push(format!(
+                "{}\t{}\t{}\t{}\t255\t{}\t*\t0\t0\t{}\t*\tAS:i:{}",
+                query_name,
+                flag,
+                target_name,
+                t_bgn - k + 1,
+                cigar_to_string(&cigar),
+                String::from_utf8_lossy(seq),
+                *score as i64
+            ));
+        });
+    });
+    out
+}
+
+/// the default distance from a chain end to a sequence end still counted as reaching
+/// the sequence end in `classify_overlap`, it also absorbs the k-mer size as the hits
+/// are at the k-mer ends
+pub const DEFAULT_OVERLAP_TOLERANCE: u32 = 1000;
+
+/// how a chain between a query and a target relates the two sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapType {
+    /// the chain covers the whole query or the whole target
+    Contained,
+    /// the 5' end of the query overlaps the 3' end of the target
+    Dovetail5,
+    /// the 3' end of the query overlaps the 5' end of the target
+    Dovetail3,
+    /// the chain leaves unmatched sequence on both sides of at least one end
+    Internal,
+}
+
+/// if most hits of a non-empty chain are in opposite orientations, and the query and the
+/// target spans of the hits
+fn chain_spans(chain: &[HitPair]) -> (bool, (u32, u32), (u32, u32)) {
+    let n_reversed = chain
+        .iter()
+        .filter(|hp| Strand::of(hp) == Strand::Reverse)
+        .count();
+    let reversed = n_reversed * 2 > chain.len();
+    let q_bgn = chain.iter().map(|hp| hp.0 .0).min().unwrap();
+    let q_end = chain.iter().map(|hp| hp.0 .1).max().unwrap();
+    let t_bgn = chain.iter().map(|hp| hp.1 .0).min().unwrap();
+    let t_end = chain.iter().map(|hp| hp.1 .1).max().unwrap();
+    (reversed, (q_bgn, q_end), (t_bgn, t_end))
+}
+
+/// classify a chain from `sparse_aln` with `DEFAULT_OVERLAP_TOLERANCE`
+pub fn classify_overlap(chain: &[HitPair], qlen: u32, tlen: u32) -> OverlapType {
+    classify_overlap_with_tolerance(chain, qlen, tlen, DEFAULT_OVERLAP_TOLERANCE)
+}
+
+/// classify a chain from `sparse_aln` as a containment, a dovetail overlap or an internal match
+///
+/// The 5' and 3' ends are those of the query oriented to the forward strand of the target,
+/// i.e. the ends of the query are swapped if most hits of the chain are in opposite
+/// orientations. A chain end within `tolerance` bases of a sequence end reaches that end.
+/// An empty chain is `Internal`.
+pub fn classify_overlap_with_tolerance(
+    chain: &[HitPair],
+    qlen: u32,
+    tlen: u32,
+    tolerance: u32,
+) -> OverlapType {
+    if chain.is_empty() {
+        return OverlapType::Internal;
+    }
+    let (reversed, (q_bgn, q_end), (t_bgn, t_end)) = chain_spans(chain);
+
+    let (q_left, q_right) = if reversed {
+        (qlen.saturating_sub(q_end), q_bgn)
+    } else {
+        (q_bgn, qlen.saturating_sub(q_end))
+    };
+    let t_left = t_bgn;
+    let t_right = tlen.saturating_sub(t_end);
+
+    if (q_left <= tolerance && q_right <= tolerance)
+        || (t_left <= tolerance && t_right <= tolerance)
+    {
+        OverlapType::Contained
+    } else if q_left <= tolerance && t_right <= tolerance {
+        OverlapType::Dovetail5
+    } else if q_right <= tolerance && t_left <= tolerance {
+        OverlapType::Dovetail3
+    } else {
+        OverlapType::Internal
+    }
+}
+
+/// a chain from `sparse_aln` with the short inverted segments merged into it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedChain {
+    /// the sum of the scores of the merged chains
+    pub score: f32,
+    /// the hits of the merged chains in the query order
+    pub hits: Vec<HitPair>,
+    /// the query spans `(bgn, end)` of the inverted segments in the chain
+    pub inversions: Vec<(u32, u32)>,
+}
+
+/// merge the chains of a single target separated by a short chain of the opposite orientation
+///
+/// The chains are walked in the order of their query starts. Two chains of the same
+/// orientation, colinear on both the query and the target, with a chain of the opposite
+/// orientation between them on both sequences, are merged into one chain recording the
+/// inverted segment. The gaps between the flanking chains on the query and on the target
+/// must be at most `max_inv_len`. The other chains are returned as they are.
+pub fn merge_chains_over_inversions(
+    chains: &[(f32, Vec<HitPair>)],
+    max_inv_len: u32,
+) -> Vec<MergedChain> {
+    let mut chains = chains
+        .iter()
+        .filter(|(_, hps)| !hps.is_empty())
+        .map(|(score, hps)| (*score, hps, chain_spans(hps)))
+        .collect::<Vec<_>>();
+    chains.sort_by_key(|(_, _, (_, q_span, _))| *q_span);
+
+    let mut merged = Vec::<MergedChain>::new();
+    let mut i = 0_usize;
+    while i < chains.len() {
+        let (score, hps, (reversed, q_span, t_span)) = chains[i];
+        let mut current = MergedChain {
+            score,
+            hits: hps.clone(),
+            inversions: vec![],
+        };
+        let (mut q_end, mut t_span) = (q_span.1, t_span);
+        while i + 2 < chains.len() {
+            let (inv_score, inv_hps, (inv_reversed, inv_q_span, inv_t_span)) = chains[i + 1];
+            let (next_score, next_hps, (next_reversed, next_q_span, next_t_span)) = chains[i + 2];
+            if inv_reversed == reversed || next_reversed != reversed {
+                break;
+            }
+            // the target gap between the flanking chains and the orientation along the target
+            let (t_gap_bgn, t_gap_end) = if reversed {
+                (next_t_span.1, t_span.0)
+            } else {
+                (t_span.1, next_t_span.0)
+            };
+            let colinear = q_end <= inv_q_span.0
+                && inv_q_span.1 <= next_q_span.0
+                && t_gap_bgn <= inv_t_span.0
+                && inv_t_span.1 <= t_gap_end;
+            if !colinear
+                || next_q_span.0 - q_end > max_inv_len
+                || t_gap_end - t_gap_bgn > max_inv_len
+            {
+                break;
+            }
+            current.score += inv_score + next_score;
+            current.hits.extend_from_slice(inv_hps);
+            current.hits.extend_from_slice(next_hps);
+            current.inversions.push(inv_q_span);
+            q_end = next_q_span.1;
+            t_span = (t_span.0.min(next_t_span.0), t_span.1.max(next_t_span.1));
+            i += 2;
+        }
+        merged.push(current);
+        i += 1;
+    }
+    merged
+}
+
+/// the default fraction of the query span of a chain overlapped by a better primary chain
+/// to make it a secondary chain in `label_chains`, the same as the minimap2 mask level
+pub const DEFAULT_MASK_LEVEL: f32 = 0.5;
+
+/// the highest mapping quality assigned by `label_chains`
+pub const MAX_MAPQ: u8 = 60;
+
+/// the primary or secondary label and the mapping quality of a chain of a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainLabel {
+    pub target_id: u32,
+    /// the index of the chain in the chains of the target
+    pub chain_index: usize,
+    pub score: f32,
+    /// the index of the primary chain overlapping this chain in the labels, `None` for a
+    /// primary chain
+    pub parent: Option<usize>,
+    /// 0 for a secondary chain
+    pub mapq: u8,
+}
+
+impl ChainLabel {
+    pub fn is_primary(&self) -> bool {
+        self.parent.is_none()
+    }
+}
+
+/// label the chains of a query to all targets with `DEFAULT_MASK_LEVEL`
+pub fn label_chains(target_chains: &[TargetHitPairList]) -> Vec<ChainLabel> {
+    label_chains_with_mask_level(target_chains, DEFAULT_MASK_LEVEL)
+}
+
+/// label the chains of a query from `query_fragment_to_hps` as primary or secondary, and
+/// assign the mapping quality, e.g. for the flags and the MAPQ of the SAM records
+///
+/// The chains of all targets are walked from the best score. A chain whose query span
+/// overlaps a primary chain by more than `mask_level` of the shorter span is secondary to
+/// it, otherwise it is a new primary chain, so a query may have several primary chains of
+/// the different query segments. The mapping quality of a primary chain of score `s1` with
+/// the best secondary chain of score `s2` is `MAX_MAPQ * (1 - s2 / s1)`, lowered in
+/// proportion for a chain of fewer than 10 hits. The labels are in the order of the scores
+/// descending, the empty chains are not labeled.
+pub fn label_chains_with_mask_level(
+    target_chains: &[TargetHitPairList],
+    mask_level: f32,
+) -> Vec<ChainLabel> {
+    let mut chains = target_chains
+        .iter()
+        .flat_map(|(sid, chains)| {
+            chains
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, hps))| !hps.is_empty())
+                .map(move |(idx, (score, hps))| (*sid, idx, *score, hps.len(), chain_spans(hps).1))
+        })
+        .collect::<Vec<_>>();
+    chains.sort_by(|a, b| {
+        b.2.partial_cmp(&a.2)
+            .unwrap()
+            .then(a.0.cmp(&b.0))
+            .then(a.1.cmp(&b.1))
+    });
+
+    let mut labels = Vec::<ChainLabel>::with_capacity(chains.len());
+    // the label index, the query span and the best secondary score of each primary chain
+    let mut primaries = Vec::<(usize, (u32, u32), Option<f32>)>::new();
+    chains
+        .iter()
+        .for_each(|&(target_id, chain_index, score, _, (q_bgn, q_end))| {
+            let parent = primaries.iter_mut().find(|(_, (p_bgn, p_end), _)| {
+                let overlap = q_end.min(*p_end).saturating_sub(q_bgn.max(*p_bgn));
+                let shorter = (q_end - q_bgn).min(p_end - p_bgn);
+                overlap as f32 > mask_level * shorter as f32
+            });
+            let parent = match parent {
+                Some((idx, _, sub_score)) => {
+                    sub_score.get_or_insert(score);
+                    Some(*idx)
+                }
+                None => {
+                    primaries.push((labels.len(), (q_bgn, q_end), None));
+                    None
+                }
+            };
+            labels.push(ChainLabel {
+                target_id,
+                chain_index,
+                score,
+                parent,
+                mapq: 0,
+            });
+        });
+
+    primaries.into_iter().for_each(|(idx, _, sub_score)| {
+        let score = labels[idx].score;
+        let n_hits = chains[idx].3;
+        let mapq = if score > 0.0 {
+            let gap = 1.0 - sub_score.unwrap_or(0.0).max(0.0) / score;
+            let hit_factor = (n_hits as f32 / 10.0).min(1.0);
+            (MAX_MAPQ as f32 * gap * hit_factor).round() as u8
+        } else {
+            0
+        };
+        labels[idx].mapq = mapq.min(MAX_MAPQ);
+    });
+    labels
+}
+
+#[cfg(test)]
+fn load_test_hits() -> Vec<HitPair> {
     use std::fs::File;
     use std::io::{BufRead, BufReader};
     let f = BufReader::new(File::open("./test/test_data/test_hits").unwrap());
-    let mut hp = Vec::<HitPair>::new();
-    f.lines().for_each(|s| {
-        if let Ok(s) = s {
-            let s = s.split_ascii_whitespace();
+    f.lines()
+        .map(|s| {
             let out = s
-                .into_iter()
+                .unwrap()
+                .split_ascii_whitespace()
                 .map(|s| s.parse::<u32>().unwrap())
                 .collect::<Vec<u32>>();
             assert_eq!(out.len(), 6);
-            hp.push((
+            (
                 (out[0], out[1], out[2] as u8),
                 (out[3], out[4], out[5] as u8),
-            ));
-        }
-    });
+            )
+        })
+        .collect::<Vec<HitPair>>()
+}
+
+#[test]
+
+fn sparse_aln_test() {
+    use crate::aln::sparse_aln;
+    let mut hp = load_test_hits();
 
     let out = sparse_aln(&mut hp, 8, 0.5_f32);
     out.iter().for_each(|(s, v)| println!("{} {}", s, v.len()));
 }
+
+#[test]
+fn chains_to_paf_test() {
+    let mut hp = load_test_hits();
+    let query_len = hp.iter().map(|h| h.0 .1).max().unwrap();
+    let target_len = hp.iter().map(|h| h.1 .1).max().unwrap();
+    let chains = sparse_aln(&mut hp, 8, 0.5_f32);
+    let paf = chains_to_paf("q", query_len, "t", target_len, &chains);
+    assert!(paf.len() >= chains.len());
+    paf.iter().for_each(|line| {
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        assert_eq!(fields.len(), 13);
+        let v = |i: usize| fields[i].parse::<u32>().unwrap();
+        assert!(v(2) <= v(3) && v(3) <= v(1));
+        assert!(v(7) <= v(8) && v(8) <= v(6));
+        assert!(fields[4] == "+" || fields[4] == "-");
+        assert!(fields[12].starts_with("AS:i:"));
+    });
+}
+
+#[test]
+fn sparse_aln_affine_gap_test() {
+    let hp = load_test_hits();
+    let linear = sparse_aln(&mut hp.clone(), 8, 0.5_f32);
+    let linear2 = sparse_aln_with_params(&mut hp.clone(), 8, &ChainParams::linear(0.5_f32));
+    assert_eq!(linear.len(), linear2.len());
+    let affine = sparse_aln_with_params(&mut hp.clone(), 8, &ChainParams::new(50.0, 0.1));
+    assert!(affine.len() < linear.len());
+    let n_hits =
+        |chains: &Vec<(f32, Vec<HitPair>)>| chains.iter().map(|c| c.1.len()).sum::<usize>();
+    assert_eq!(n_hits(&affine), hp.len());
+}
+
+#[test]
+fn sparse_aln_presorted_test() {
+    let mut hp = load_test_hits();
+    let chains = sparse_aln(&mut hp.clone(), 8, 0.5_f32);
+    hp.sort_by_key(|h| h.0 .0);
+    assert_eq!(sparse_aln_presorted(&hp, 8, 0.5_f32), chains);
+    assert_eq!(
+        sparse_aln_presorted_with_params(&hp, 8, &ChainParams::new(50.0, 0.1)),
+        sparse_aln_with_params(&mut hp.clone(), 8, &ChainParams::new(50.0, 0.1))
+    );
+}
+
+#[test]
+fn sparse_aln_max_hits_test() {
+    // a unique diagonal after 10 query spans hitting 100 targets each
+    let diagonal = (0..50_u32)
+        .map(|i| {
+            let q = 1000 + i * 100;
+            let t = 5000 + i * 100;
+            ((q, q + 50, 0_u8), (t, t + 50, 0_u8))
+        })
+        .collect::<Vec<HitPair>>();
+    let mut hp = (0..10_u32)
+        .flat_map(|q| {
+            (0..100_u32).map(move |t| ((q * 50, q * 50 + 50, 0), (t * 1000, t * 1000 + 50, 0)))
+        })
+        .collect::<Vec<HitPair>>();
+    hp.extend(diagonal.iter());
+
+    let subsampled = subsample_hits(&hp, 100);
+    assert_eq!(subsampled.len(), 100);
+    assert!(subsampled.windows(2).all(|w| w[0].0 .0 <= w[1].0 .0));
+    assert!(diagonal.iter().all(|h| subsampled.contains(h)));
+    // the repetitive hits kept are spread over the query spans
+    (0..10_u32).for_each(|q| {
+        assert_eq!(subsampled.iter().filter(|h| h.0 .0 == q * 50).count(), 5);
+    });
+    assert_eq!(subsample_hits(&hp[..10], 100), hp[..10].to_vec());
+
+    let params = ChainParams::linear(0.1).with_max_hits(100);
+    let chains = sparse_aln_with_params(&mut hp.clone(), 8, &params);
+    assert_eq!(chains.iter().map(|c| c.1.len()).sum::<usize>(), 100);
+    assert!(diagonal.iter().all(|h| chains[0].1.contains(h)));
+    assert_eq!(
+        sparse_aln_with_params(
+            &mut hp.clone(),
+            8,
+            &ChainParams::linear(0.1).with_max_hits(2000)
+        ),
+        sparse_aln(&mut hp, 8, 0.1)
+    );
+}
+
+#[test]
+fn sparse_aln_tr_penalty_test() {
+    // unique flanks around a tandem repeat of 8 units of 100 bases on both the query and the
+    // target, every unit of the query hits every unit of the target
+    let mut hp = (0..5_u32)
+        .chain(13..18_u32)
+        .map(|i| {
+            let q = i * 100;
+            ((q, q + 50, 0), (q + 10000, q + 10050, 0))
+        })
+        .collect::<Vec<HitPair>>();
+    (0..8_u32).for_each(|i| {
+        (0..8_u32).for_each(|j| {
+            let q = 500 + i * 100;
+            let t = 10500 + j * 100;
+            hp.push(((q, q + 50, 0), (t, t + 50, 0)));
+        })
+    });
+    let periods = tandem_repeat_periods(&hp, 0.1);
+    assert_eq!(periods.len(), 8);
+    assert!(periods.values().all(|&p| p == 100.0));
+
+    // the count of the links jumping off the diagonal
+    let n_jumps = |chains: &Vec<(f32, Vec<HitPair>)>| {
+        chains
+            .iter()
+            .flat_map(|c| c.1.windows(2))
+            .filter(|w| w[1].1 .0 - w[0].1 .0 != w[1].0 .0 - w[0].0 .0)
+            .count()
+    };
+    let chains = sparse_aln_with_params(&mut hp.clone(), 8, &ChainParams::linear(0.1));
+    assert_eq!(chains[0].1.len(), 18);
+    assert!(n_jumps(&chains) > 0);
+
+    let params = ChainParams::linear(0.1).with_tr_penalty(0.1, 100.0);
+    let tr_chains = sparse_aln_with_params(&mut hp.clone(), 8, &params);
+    assert_eq!(tr_chains[0], chains[0]);
+    assert_eq!(n_jumps(&tr_chains), 0);
+    assert_eq!(tr_chains.iter().map(|c| c.1.len()).sum::<usize>(), hp.len());
+}
+
+#[test]
+fn extend_chain_test() {
+    use crate::tests::random_bases;
+    // the query is the target bases [1000, 4000) with a substitution every 250 bases, and the
+    // hits only cover the query bases [484, 2450)
+    let target = random_bases(5, 6000);
+    let mut query = target[1000..4000].to_vec();
+    (125..3000)
+        .step_by(250)
+        .for_each(|i| query[i] = if query[i] == b'A' { b'C' } else { b'A' });
+    let k = 16;
+    let run = (500..2500)
+        .step_by(100)
+        .map(|q| ((q, q + 50, 0), (q + 1000, q + 1050, 0)))
+        .collect::<Vec<HitPair>>();
+    let params = ChainExtendParams {
+        flank_len: 1000,
+        ..ChainExtendParams::default()
+    };
+    let ext = extend_chain(&run, &query, &target, k, &params);
+    assert_eq!((ext.query_start, ext.query_end), (0, 3000));
+    assert_eq!((ext.target_start, ext.target_end), (1000, 4000));
+    assert_eq!(ext.strand, Strand::Forward);
+    let identity = ext.identity.unwrap();
+    assert!(identity > 0.99 && identity < 1.0);
+
+    // the same on the reverse complement of the query
+    let rc_query = reverse_complement(&query);
+    let rc_run = run
+        .iter()
+        .rev()
+        .map(|hp| {
+            let q = 3000 - hp.0 .1 + k;
+            ((q, q + 50, 0), (hp.1 .0, hp.1 .1, 1))
+        })
+        .collect::<Vec<HitPair>>();
+    let rc_ext = extend_chain(&rc_run, &rc_query, &target, k, &params);
+    assert_eq!((rc_ext.query_start, rc_ext.query_end), (0, 3000));
+    assert_eq!((rc_ext.target_start, rc_ext.target_end), (1000, 4000));
+    assert_eq!(rc_ext.strand, Strand::Reverse);
+
+    // the extension stops where the query diverges from the target
+    let mut diverged = query[..2600].to_vec();
+    diverged.extend(random_bases(6, 400));
+    let ext = extend_chain(&run, &diverged, &target, k, &params);
+    assert_eq!(ext.query_start, 0);
+    assert!(ext.query_end >= 2570 && ext.query_end <= 2610);
+    assert_eq!(ext.target_end - ext.query_end, 1000);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "not sorted")]
+fn sparse_aln_presorted_unsorted_test() {
+    let hp = vec![
+        ((200, 300, 0), (200, 300, 0)),
+        ((100, 200, 0), (100, 200, 0)),
+    ];
+    sparse_aln_presorted(&hp, 8, 0.5_f32);
+}
+
+#[test]
+fn classify_overlap_test() {
+    let fwd = |q: u32, t: u32| -> HitPair { ((q, q + 100, 0), (t, t + 100, 0)) };
+    let rev = |q: u32, t: u32| -> HitPair { ((q, q + 100, 1), (t, t + 100, 0)) };
+
+    // the query suffix [5000, 10000) matches the target prefix [0, 5000)
+    let chain = (0..50)
+        .map(|i| fwd(5000 + i * 100, i * 100))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        classify_overlap(&chain, 10000, 20000),
+        OverlapType::Dovetail3
+    );
+    // the same as seen from the target
+    let swapped = chain.iter().map(|&(q, t)| (t, q)).collect::<Vec<HitPair>>();
+    assert_eq!(
+        classify_overlap(&swapped, 20000, 10000),
+        OverlapType::Dovetail5
+    );
+
+    // the whole query matches inside the target
+    let chain = (0..100)
+        .map(|i| fwd(i * 100, 5000 + i * 100))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        classify_overlap(&chain, 10000, 20000),
+        OverlapType::Contained
+    );
+
+    // a match in the middle of both sequences
+    let chain = (0..20)
+        .map(|i| fwd(5000 + i * 100, 5000 + i * 100))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        classify_overlap(&chain, 20000, 20000),
+        OverlapType::Internal
+    );
+
+    // the query prefix [0, 5000) matches the target prefix in the opposite orientation,
+    // so the reverse complemented query ends with the overlap on the target 5' end
+    let chain = (0..50)
+        .map(|i| rev(4900 - i * 100, 50 + i * 100))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        classify_overlap(&chain, 10000, 20000),
+        OverlapType::Dovetail3
+    );
+    assert_eq!(
+        classify_overlap_with_tolerance(&chain, 10000, 20000, 0),
+        OverlapType::Internal
+    );
+    assert_eq!(classify_overlap(&[], 10000, 20000), OverlapType::Internal);
+}
+
+#[test]
+fn merge_chains_over_inversions_test() {
+    let fwd = |q: u32, t: u32| -> HitPair { ((q, q + 100, 0), (t, t + 100, 0)) };
+    let rev = |q: u32, t: u32| -> HitPair { ((q, q + 100, 1), (t, t + 100, 0)) };
+    // forward [0, 5000), an inverted [5000, 5500) and forward [5500, 10000) on both sequences
+    let chain0 = (0..50).map(|i| fwd(i * 100, i * 100)).collect::<Vec<_>>();
+    let inv = (0..5)
+        .map(|i| rev(5000 + i * 100, 5400 - i * 100))
+        .collect::<Vec<_>>();
+    let chain1 = (55..100).map(|i| fwd(i * 100, i * 100)).collect::<Vec<_>>();
+    // an unrelated chain further along the query
+    let other = (0..10)
+        .map(|i| fwd(20000 + i * 100, 50000 + i * 100))
+        .collect::<Vec<_>>();
+    let chains = vec![
+        (45.0, chain1.clone()),
+        (5.0, inv.clone()),
+        (10.0, other.clone()),
+        (50.0, chain0.clone()),
+    ];
+
+    let merged = merge_chains_over_inversions(&chains, 1000);
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged[0].score, 100.0);
+    assert_eq!(merged[0].inversions, vec![(5000, 5500)]);
+    assert_eq!(merged[0].hits, [chain0, inv, chain1].concat());
+    assert_eq!(merged[1].hits, other);
+    assert!(merged[1].inversions.is_empty());
+
+    // the gap is longer than the max inversion length
+    assert_eq!(merge_chains_over_inversions(&chains, 100).len(), 4);
+}
+
+#[test]
+fn hit_pair_to_anchor_test() {
+    let anchor = Anchor::from(((100, 156, 0), (2000, 2056, 1)));
+    assert_eq!(anchor.query, Span::new(100, 156));
+    assert_eq!(anchor.target, Span::new(2000, 2056));
+    assert_eq!(anchor.strand, Strand::Reverse);
+    assert_eq!(anchor.query.len(), 56);
+    assert_eq!(
+        Anchor::from(((100, 156, 1), (2000, 2056, 1))).strand,
+        Strand::Forward
+    );
+
+    let chain = (0..10)
+        .map(|i| {
+            (
+                (i * 100, i * 100 + 56, 0),
+                (i * 100 + 5000, i * 100 + 5056, 0),
+            )
+        })
+        .collect::<Vec<HitPair>>();
+    let anchors = chain_to_anchors(&chain);
+    assert_eq!(anchors.len(), chain.len());
+    anchors.iter().zip(chain.iter()).for_each(|(a, hp)| {
+        assert_eq!((a.query.bgn, a.query.end), (hp.0 .0, hp.0 .1));
+        assert_eq!((a.target.bgn, a.target.end), (hp.1 .0, hp.1 .1));
+        assert_eq!(a.strand, Strand::Forward);
+    });
+}
+
+#[test]
+fn chain_query_clips_test() {
+    let chain = vec![
+        ((100, 156, 0), (5100, 5156, 0)),
+        ((156, 300, 0), (5156, 5300, 0)),
+    ];
+    // the first hit starts at the end of the first k-mer
+    assert_eq!(chain_query_clips(&chain, 1000, 56), (44, 700));
+    // the chain reaching the query ends leaves no clip
+    assert_eq!(chain_query_clips(&chain, 300, 100), (0, 0));
+    assert_eq!(chain_query_clips(&chain, 250, 56), (44, 0));
+}
+
+#[test]
+fn chains_to_bed12_test() {
+    let chains = vec![
+        (
+            10.0_f32,
+            vec![
+                ((100, 200, 0), (1100, 1200, 0)),
+                ((200, 300, 0), (1200, 1300, 0)),
+                // not colinear with the hits before it, it is dropped
+                ((50, 150, 0), (1400, 1500, 0)),
+                ((400, 500, 0), (1500, 1600, 0)),
+            ],
+        ),
+        (
+            5.0_f32,
+            vec![
+                ((300, 400, 0), (2000, 2100, 1)),
+                ((200, 300, 0), (2100, 2200, 1)),
+            ],
+        ),
+    ];
+    let bed = chains_to_bed12("q", "t", 2150, &chains, 56);
+    assert_eq!(
+        bed,
+        vec![
+            "t\t1044\t1600\tq\t1000\t+\t1044\t1600\t0\t2\t256,156,\t0,400,".to_string(),
+            "t\t1944\t2150\tq\t500\t-\t1944\t2150\t0\t1\t206,\t0,".to_string(),
+        ]
+    );
+
+    let mut hp = load_test_hits();
+    let target_len = hp.iter().map(|h| h.1 .1).max().unwrap();
+    let chains = sparse_aln(&mut hp, 8, 0.5_f32);
+    let bed = chains_to_bed12("q", "t", target_len, &chains, 56);
+    assert!(bed.len() >= chains.len());
+    bed.iter().for_each(|line| {
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        assert_eq!(fields.len(), 12);
+        let v = |i: usize| fields[i].parse::<u32>().unwrap();
+        let list = |i: usize| {
+            fields[i]
+                .trim_end_matches(',')
+                .split(',')
+                .map(|s| s.parse::<u32>().unwrap())
+                .collect::<Vec<u32>>()
+        };
+        assert!(v(1) < v(2) && v(2) <= target_len);
+        assert!(v(4) <= 1000);
+        let (sizes, starts) = (list(10), list(11));
+        assert_eq!(sizes.len(), v(9) as usize);
+        assert_eq!(starts.len(), v(9) as usize);
+        // the blocks are sorted, disjoint and span the feature
+        assert_eq!(starts[0], 0);
+        assert_eq!(starts.last().unwrap() + sizes.last().unwrap(), v(2) - v(1));
+        (1..starts.len()).for_each(|i| assert!(starts[i - 1] + sizes[i - 1] < starts[i]));
+    });
+}
+
+#[test]
+fn label_chains_test() {
+    let fwd = |q: u32, t: u32| -> HitPair { ((q, q + 100, 0), (t, t + 100, 0)) };
+    let rev = |q: u32, t: u32| -> HitPair { ((q, q + 100, 1), (t, t + 100, 0)) };
+    let chain = |q: u32, t: u32, n: u32| -> Vec<HitPair> {
+        (0..n).map(|i| fwd(q + i * 100, t + i * 100)).collect()
+    };
+    let target_chains: TargetHitPairLists = vec![
+        (
+            0,
+            vec![
+                // the best chain of the query [0, 5000)
+                (100.0, chain(0, 10000, 50)),
+                // another copy of the query [3000, 5000)
+                (40.0, chain(3000, 30000, 20)),
+                (7.0, vec![]),
+            ],
+        ),
+        (
+            1,
+            vec![
+                // a copy of the query [0, 4000) on the reverse strand
+                (
+                    80.0,
+                    (0..40).map(|i| rev(3900 - i * 100, i * 100)).collect(),
+                ),
+                // a short chain of the query [8000, 8500)
+                (5.0, chain(8000, 50000, 5)),
+            ],
+        ),
+    ];
+    let label = |target_id: u32, chain_index: usize, score: f32, parent, mapq| ChainLabel {
+        target_id,
+        chain_index,
+        score,
+        parent,
+        mapq,
+    };
+    let labels = label_chains(&target_chains);
+    assert_eq!(
+        labels,
+        vec![
+            label(0, 0, 100.0, None, 12),
+            label(1, 0, 80.0, Some(0), 0),
+            label(0, 1, 40.0, Some(0), 0),
+            label(1, 1, 5.0, None, 30),
+        ]
+    );
+    assert_eq!(labels.iter().filter(|l| l.is_primary()).count(), 2);
+
+    // no chain is masked with the mask level 1
+    let labels = label_chains_with_mask_level(&target_chains, 1.0);
+    assert!(labels.iter().all(|l| l.is_primary()));
+    assert_eq!(
+        labels.iter().map(|l| l.mapq).collect::<Vec<_>>(),
+        vec![MAX_MAPQ, MAX_MAPQ, MAX_MAPQ, 30]
+    );
+    assert!(label_chains(&[]).is_empty());
+}
@@ -1,11 +1,15 @@
 #![warn(missing_docs)]
 //! function for error correction
 
-use crate::aln::query_fragment_to_hps;
+use crate::aln::{
+    query_fragment_to_hps, AlignmentHit, DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE,
+};
 use crate::fasta_io::reverse_complement;
 use crate::graph_utils::{ShmmrGraphNode, WeightedNode};
 use crate::seq_db::{self, CompactSeqDB, GetSeq, raw_query_fragment};
-use crate::shmmrutils::{sequence_to_shmmrs, ShmmrSpec};
+use crate::shmmrutils::{
+    deltas_to_cigar, match_reads_with_params, sequence_to_shmmrs, MatchReadsParams, ShmmrSpec,
+};
 use petgraph::algo::toposort;
 use petgraph::EdgeDirection::Outgoing;
 use petgraph::{graphmap::DiGraphMap, EdgeDirection::Incoming};
@@ -157,13 +161,15 @@ pub fn shmmr_dbg_consensus(
     seqs: Vec<Vec<u8>>,
     shmmr_spec: &Option<ShmmrSpec>,
 ) -> Result<Vec<(Vec<u8>, Vec<u32>)>, &'static str> {
-    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&ShmmrSpec {
+    let default_spec = ShmmrSpec {
         w: 31,
         k: 31,
         r: 1,
         min_span: 0,
         sketch: false,
-    });
+        ..ShmmrSpec::default()
+    };
+    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&default_spec);
     assert!(shmmr_spec.k % 2 == 1); // the k needs to odd to break symmetry
     assert!(shmmr_spec.min_span == 0); // if min_span != 0, we don't get consistent path
     let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
@@ -276,13 +282,15 @@ pub fn guided_shmmr_dbg_consensus(
     shmmr_spec: &Option<ShmmrSpec>,
     min_cov: u32,
 ) -> Result<(Vec<u8>, Vec<u32>), &'static str> {
-    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&ShmmrSpec {
+    let default_spec = ShmmrSpec {
         w: 31,
         k: 31,
         r: 1,
         min_span: 0,
         sketch: false,
-    });
+        ..ShmmrSpec::default()
+    };
+    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&default_spec);
     assert!(shmmr_spec.k % 2 == 1); // the k needs to odd to break symmetry
     assert!(shmmr_spec.min_span == 0); // if min_span != 0, we don't get consistent path
     let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
@@ -481,13 +489,15 @@ pub fn shmmr_sparse_aln_consensus(
     shmmr_spec: &Option<ShmmrSpec>,
     min_cov: u32,
 ) -> Result<Vec<(Vec<u8>, Vec<u32>)>, &'static str> {
-    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&ShmmrSpec {
+    let default_spec = ShmmrSpec {
         w: 33,
         k: 33,
         r: 1,
         min_span: 0,
         sketch: false,
-    });
+        ..ShmmrSpec::default()
+    };
+    let shmmr_spec = shmmr_spec.as_ref().unwrap_or(&default_spec);
     assert!(shmmr_spec.k % 2 == 1); // the k needs to odd to break symmetry
     assert!(shmmr_spec.min_span == 0); // if min_span != 0, we don't get consistent path
     let mut sdb = seq_db::CompactSeqDB::new(shmmr_spec.clone());
@@ -540,6 +550,8 @@ pub fn shmmr_sparse_aln_consensus_with_sdb(
             Some(32),
             Some(32),
             Some(33),
+            DEFAULT_MIN_CHAIN_SCORE,
+            DEFAULT_MIN_CHAIN_LEN,
         );
 
         let mut hit_map = FxHashMap::<(u32, u32, u8), Vec<(u32, (u32, u32, u8))>>::default();
@@ -681,6 +693,163 @@ pub fn shmmr_sparse_aln_consensus_with_sdb(
     Ok(out)
 }
 
+/// the majority-vote consensus of a pile-up of sequences on a target region
+///
+/// each `(offset, seq)` of the `pileup` is aligned to the target from `offset` with
+/// `match_reads_with_params`, e.g. the query span of a hit chain oriented to the target with
+/// the start of the chain on the target, and the sequences not aligned are skipped
+///
+/// at each target position, the base or the gap of the most aligned sequences covering it
+/// wins, the target base is kept on a tie, and the bases inserted before a target position are
+/// added if more than half of the sequences covering the position have the same insertion,
+/// `match_reads` aligns a substitution as an insertion next to a deletion, so it is voted in
+/// the same way
+///
+/// returns the consensus and the number of the sequences supporting each consensus base, the
+/// target bases not covered by any aligned sequence are kept with 0 support
+pub fn pileup_consensus(
+    target: &[u8],
+    pileup: &[(u32, Vec<u8>)],
+    params: &MatchReadsParams,
+) -> (Vec<u8>, Vec<u32>) {
+    fn add_vote(votes: &mut Vec<(u8, u32)>, base: u8) {
+        match votes.iter_mut().find(|v| v.0 == base) {
+            Some(v) => v.1 += 1,
+            None => votes.push((base, 1)),
+        }
+    }
+    // the CIGAR of an alignment is walked from the start of both sequences
+    let params = MatchReadsParams {
+        get_delta: true,
+        trim_anchor_len: None,
+        ..*params
+    };
+    // the votes of the bases (`-` for a gap) at each target position
+    let mut base_votes = vec![Vec::<(u8, u32)>::new(); target.len()];
+    // the votes of the bases inserted before each target position, and the number of the
+    // aligned sequences covering each of the positions
+    let mut ins_votes = FxHashMap::<usize, FxHashMap<Vec<u8>, u32>>::default();
+    let mut ins_coverage = vec![0_u32; target.len() + 1];
+    pileup.iter().for_each(|(offset, seq)| {
+        let offset = *offset as usize;
+        if offset >= target.len() {
+            return;
+        }
+        let m = match match_reads_with_params(&target[offset..], seq, &params) {
+            Some(m) => m,
+            None => return,
+        };
+        let cigar = deltas_to_cigar(&m.deltas.unwrap(), m.end0, m.end1);
+        let (mut t, mut q) = (offset, 0_usize);
+        let mut insertions = FxHashMap::<usize, Vec<u8>>::default();
+        cigar.into_iter().for_each(|(op, len)| {
+            let len = len as usize;
+            match op {
+                'M' => {
+                    (0..len).for_each(|i| add_vote(&mut base_votes[t + i], seq[q + i]));
+                    t += len;
+                    q += len;
+                }
+                'D' => {
+                    (0..len).for_each(|i| add_vote(&mut base_votes[t + i], b'-'));
+                    t += len;
+                }
+                _ => {
+                    insertions
+                        .entry(t)
+                        .or_default()
+                        .extend_from_slice(&seq[q..q + len]);
+                    q += len;
+                }
+            }
+        });
+        (offset..=t).for_each(|p| ins_coverage[p] += 1);
+        insertions.into_iter().for_each(|(p, ins)| {
+            *ins_votes.entry(p).or_default().entry(ins).or_insert(0) += 1;
+        });
+    });
+
+    let mut consensus = Vec::<u8>::with_capacity(target.len());
+    let mut support = Vec::<u32>::with_capacity(target.len());
+    (0..=target.len()).for_each(|p| {
+        if let Some(votes) = ins_votes.get(&p) {
+            // the smaller insertion wins a tie to keep the consensus deterministic
+            let (ins, count) = votes
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+                .unwrap();
+            if 2 * count > ins_coverage[p] {
+                consensus.extend_from_slice(ins);
+                support.extend(std::iter::repeat_n(*count, ins.len()));
+            }
+        }
+        if p == target.len() {
+            return;
+        }
+        let votes = &base_votes[p];
+        let target_count = votes.iter().find(|v| v.0 == target[p]).map_or(0, |v| v.1);
+        let (base, count) = votes
+            .iter()
+            .fold((target[p], target_count), |best, &(base, count)| {
+                if count > best.1 {
+                    (base, count)
+                } else {
+                    best
+                }
+            });
+        if base != b'-' {
+            consensus.push(base);
+            support.push(count);
+        }
+    });
+    (consensus, support)
+}
+
+/// the majority-vote consensus of the target span covered by a group of hit chains to the same
+/// target, e.g. the `AlignmentHit` from `CompactSeqDB::query`
+///
+/// `hits[i]` is a chain of `queries[i]`, the query span of each chain is oriented to the target
+/// and piled up from the start of the chain on the target, see `pileup_consensus`
+///
+/// returns the start of the covered span on the target, the consensus and the per-base support
+#[allow(clippy::type_complexity)]
+pub fn chain_group_consensus(
+    sdb: &CompactSeqDB,
+    hits: &[AlignmentHit],
+    queries: &[Vec<u8>],
+) -> Result<(u32, Vec<u8>, Vec<u32>), &'static str> {
+    if hits.len() != queries.len() {
+        return Err("the numbers of the hits and the queries are different");
+    }
+    if hits.is_empty() {
+        return Err("no hit chain to build the consensus");
+    }
+    let seq_id = hits[0].seq_id;
+    if hits.iter().any(|hit| hit.seq_id != seq_id) {
+        return Err("the hit chains are not on the same target");
+    }
+    let bgn = hits.iter().map(|hit| hit.target_start).min().unwrap();
+    let end = hits.iter().map(|hit| hit.target_end).max().unwrap();
+    let target = sdb.reconstruct_range(seq_id, bgn, end);
+    let pileup = hits
+        .iter()
+        .zip(queries)
+        .map(|(hit, query)| {
+            let query_end = (hit.query_end as usize).min(query.len());
+            let query_start = (hit.query_start as usize).min(query_end);
+            let seq = &query[query_start..query_end];
+            let seq = if hit.strand == 1 {
+                reverse_complement(seq)
+            } else {
+                seq.to_vec()
+            };
+            (hit.target_start - bgn, seq)
+        })
+        .collect::<Vec<_>>();
+    let (consensus, support) = pileup_consensus(&target, &pileup, &MatchReadsParams::default());
+    Ok((bgn, consensus, support))
+}
+
 #[cfg(test)]
 mod test {
     use crate::ec::guided_shmmr_dbg_consensus;
@@ -688,8 +857,10 @@ mod test {
     use crate::ec::shmmr_dbg_consensus;
     use crate::ec::shmmr_sparse_aln_consensus;
     use crate::ec::shmmr_sparse_aln_consensus_with_sdb;
+    use crate::ec::{chain_group_consensus, pileup_consensus};
+    use crate::fasta_io::reverse_complement;
     use crate::seq_db::{CompactSeqDB, GetSeq};
-    use crate::shmmrutils::ShmmrSpec;
+    use crate::shmmrutils::{MatchReadsParams, ShmmrSpec};
     #[test]
     fn test_naive_dbg_consensus() {
         let spec = ShmmrSpec {
@@ -698,6 +869,7 @@ mod test {
             r: 12,
             min_span: 12,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test.fa".to_string());
@@ -717,6 +889,7 @@ mod test {
             r: 12,
             min_span: 12,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test3.fa".to_string());
@@ -739,6 +912,7 @@ mod test {
             r: 12,
             min_span: 12,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test.fa".to_string());
@@ -759,6 +933,7 @@ mod test {
             r: 12,
             min_span: 12,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test5.fa".to_string());
@@ -781,6 +956,7 @@ mod test {
             r: 1,
             min_span: 0,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         let mut sdb = CompactSeqDB::new(spec);
         let _ = sdb.load_seqs_from_fastx("test/test_data/consensus_test5.fa".to_string());
@@ -791,4 +967,58 @@ mod test {
             println!("{:?}", c);
         }
     }
+
+    #[test]
+    fn test_pileup_consensus() {
+        let truth = crate::tests::random_bases(11, 2000);
+        // the target with a substitution, an insertion and a deletion
+        let mut target = truth.clone();
+        target[300] = if target[300] == b'A' { b'C' } else { b'A' };
+        target.splice(900..900, b"GT".iter().copied());
+        target.drain(1500..1503);
+        // the reads of the truth with an error of each read
+        let reads = (0..7)
+            .map(|i| {
+                let bgn = (i % 3) * 50;
+                let mut read = truth[bgn..].to_vec();
+                let p = 450 + 130 * i;
+                match i % 3 {
+                    0 => read[p] = if read[p] == b'G' { b'T' } else { b'G' },
+                    1 => read.insert(p, b'A'),
+                    _ => {
+                        read.remove(p);
+                    }
+                }
+                (bgn as u32, read)
+            })
+            .collect::<Vec<_>>();
+        let (consensus, support) = pileup_consensus(&target, &reads, &MatchReadsParams::default());
+        assert_eq!(consensus, truth);
+        assert_eq!(support.len(), consensus.len());
+        assert!(support[300] >= 4);
+        assert_eq!(
+            pileup_consensus(&target, &[], &MatchReadsParams::default()),
+            (target.clone(), vec![0; target.len()])
+        );
+
+        // the same pile-up from the query hits, with a read on the reverse strand
+        let sdb = CompactSeqDB::from_iter(
+            vec![("target".to_string(), target)].into_iter(),
+            ShmmrSpec::long_read(),
+        )
+        .unwrap();
+        let mut queries = reads.into_iter().map(|r| r.1).collect::<Vec<_>>();
+        queries.push(reverse_complement(&truth[20..]));
+        let hits = queries
+            .iter()
+            .map(|q| sdb.query(q, 0.5, false)[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(hits[7].strand, 1);
+        let (bgn, consensus, support) = chain_group_consensus(&sdb, &hits, &queries).unwrap();
+        assert!(bgn < 300);
+        assert!(consensus.len() > 1500);
+        assert_eq!(consensus.len(), support.len());
+        assert!(truth.windows(consensus.len()).any(|w| w == consensus));
+        assert!(chain_group_consensus(&sdb, &hits[..1], &queries).is_err());
+    }
 }
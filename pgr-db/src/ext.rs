@@ -163,6 +163,7 @@ impl SeqIndexDB {
             r,
             min_span,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
         sdb.load_seqs_from_fastx(filepath)?;
@@ -224,6 +225,7 @@ impl SeqIndexDB {
             r,
             min_span,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         self.backend = Backend::MEMORY;
         let source = Some(source.unwrap().to_string());
@@ -233,7 +235,7 @@ impl SeqIndexDB {
             .enumerate()
             .map(|(sid, v)| (sid as u32, source.clone(), v.0, v.1))
             .collect::<Vec<(u32, Option<String>, String, Vec<u8>)>>();
-        sdb.load_seqs_from_seq_vec(&seq_vec);
+        sdb.try_load_seqs_from_seq_vec(&seq_vec)?;
 
         self.shmmr_spec = Some(spec);
         let mut seq_index = FxHashMap::<(String, Option<String>), (u32, u32)>::default();
@@ -270,6 +272,8 @@ impl SeqIndexDB {
                 max_count_query,
                 max_count_target,
                 max_aln_span,
+                aln::DEFAULT_MIN_CHAIN_SCORE,
+                aln::DEFAULT_MIN_CHAIN_LEN,
             );
             Some(res)
         } else {
@@ -329,6 +333,8 @@ impl SeqIndexDB {
             max_count_query,
             max_count_target,
             max_aln_span,
+            aln::DEFAULT_MIN_CHAIN_SCORE,
+            aln::DEFAULT_MIN_CHAIN_LEN,
         );
         Some(res)
     }
@@ -1031,13 +1037,11 @@ pub fn get_fastx_reader(filepath: String) -> Result<GZFastaReader, std::io::Erro
 
     if is_gzfile {
         drop(std_buf);
-        Ok(GZFastaReader::GZFile(
-            FastaReader::new(gz_buf, &filepath, 256, false).unwrap(),
-        ))
+        let reader = FastaReader::new(gz_buf, &filepath, 256, false)?;
+        Ok(GZFastaReader::GZFile(reader))
     } else {
         drop(gz_buf);
-        Ok(GZFastaReader::RegularFile(
-            FastaReader::new(std_buf, &filepath, 256, false).unwrap(),
-        ))
+        let reader = FastaReader::new(std_buf, &filepath, 256, false)?;
+        Ok(GZFastaReader::RegularFile(reader))
     }
 }
@@ -1,14 +1,23 @@
 #![allow(dead_code)]
 
 use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::{self, BufReader, SeekFrom};
+use std::io::{self, BufReader, BufWriter, SeekFrom};
 #[derive(Debug, Clone)]
 pub struct SeqRec {
     pub source: Option<String>,
     pub id: Vec<u8>,
     pub seq: Vec<u8>,
+    /// the base quality string of a FASTQ record, `None` for FASTA records
+    pub qual: Option<Vec<u8>>,
+    /// the lowercase (soft-masked) intervals `[bgn, end)` of the bases, recorded when the
+    /// reader uppercases the sequence
+    pub soft_mask: Option<Vec<(u32, u32)>>,
 }
 
 enum Fastx {
@@ -21,35 +30,253 @@ pub struct FastaReader<R> {
     filename: String,
     seq_capacity: usize,
     keep_source: bool,
+    soft_mask: bool,
+    skip_empty: bool,
+    /// the number of the lines read so far, for reporting malformed input
+    line_number: usize,
+    /// whether the leading '>' or '@' of the next record is consumed
+    header_consumed: bool,
+    /// only the records with the ids in the set are returned if it is set
+    name_filter: Option<HashSet<Vec<u8>>>,
 }
 
-pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
-    let mut rev_seq = Vec::new();
-    for b in seq.iter().rev() {
-        match b {
-            b'A' => rev_seq.push(b'T'),
-            b'C' => rev_seq.push(b'G'),
-            b'G' => rev_seq.push(b'C'),
-            b'T' => rev_seq.push(b'A'),
-            b'a' => rev_seq.push(b't'),
-            b'c' => rev_seq.push(b'g'),
-            b'g' => rev_seq.push(b'c'),
-            b't' => rev_seq.push(b'a'),
-            b'N' => rev_seq.push(b'N'),
-            b'n' => rev_seq.push(b'n'),
-            _ => rev_seq.push(*b),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileCompression {
+    Plain,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+/// detect the compression format from the magic bytes, the bytes are only peeked
+/// from the buffer so the stream can be passed on for parsing
+pub fn detect_compression<R: BufRead>(reader: &mut R) -> Result<FileCompression, io::Error> {
+    let buf = reader.fill_buf()?;
+    let compression = if buf.starts_with(&[0x1F_u8, 0x8B_u8]) {
+        FileCompression::Gzip
+    } else if buf.starts_with(&[0x28_u8, 0xB5_u8, 0x2F_u8, 0xFD_u8]) {
+        FileCompression::Zstd
+    } else if buf.starts_with(b"BZh") {
+        FileCompression::Bzip2
+    } else {
+        FileCompression::Plain
+    };
+    Ok(compression)
+}
+
+/// open a (possibly compressed) file and return a reader of the decompressed bytes
+pub fn get_decompressed_reader(filepath: &String) -> Result<Box<dyn BufRead>, io::Error> {
+    decompressed_reader(BufReader::new(File::open(filepath)?), filepath)
+}
+
+/// wrap a (possibly compressed) stream in a reader of the decompressed bytes, `name` is the
+/// name of the stream in the logs and the errors, the corrupted compressed bytes are reported
+/// as the errors of the reads
+pub fn decompressed_reader<'a, R: BufRead + 'a>(
+    mut reader: R,
+    name: &str,
+) -> Result<Box<dyn BufRead + 'a>, io::Error> {
+    let compression = detect_compression(&mut reader)?;
+    let reader: Box<dyn BufRead + 'a> = match compression {
+        FileCompression::Plain => Box::new(reader),
+        FileCompression::Gzip => {
+            log::info!("input file: {} detected as gz-compressed file", name);
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        }
+        #[cfg(feature = "extra_codecs")]
+        FileCompression::Zstd => {
+            log::info!("input file: {} detected as zstd-compressed file", name);
+            Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(
+                reader,
+            )?))
+        }
+        #[cfg(feature = "extra_codecs")]
+        FileCompression::Bzip2 => {
+            log::info!("input file: {} detected as bzip2-compressed file", name);
+            Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader)))
+        }
+        #[cfg(not(feature = "extra_codecs"))]
+        FileCompression::Zstd | FileCompression::Bzip2 => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "input file: {} is {:?}-compressed, build with the `extra_codecs` feature to read it",
+                    name, compression
+                ),
+            ))
+        }
+    };
+    Ok(reader)
+}
+
+/// uppercase the sequence in place and return the intervals `[bgn, end)` of the lowercase bases
+pub fn extract_soft_mask(seq: &mut [u8]) -> Vec<(u32, u32)> {
+    let mut intervals = Vec::<(u32, u32)>::new();
+    let mut bgn: Option<usize> = None;
+    (0..seq.len()).for_each(|pos| {
+        if seq[pos].is_ascii_lowercase() {
+            seq[pos].make_ascii_uppercase();
+            if bgn.is_none() {
+                bgn = Some(pos);
+            }
+        } else if let Some(b) = bgn.take() {
+            intervals.push((b as u32, pos as u32));
         }
+    });
+    if let Some(b) = bgn {
+        intervals.push((b as u32, seq.len() as u32));
     }
-    rev_seq
+    intervals
+}
+
+/// lowercase the bases in the soft-masked intervals, the intervals out of the sequence are clipped
+pub fn apply_soft_mask(seq: &mut [u8], soft_mask: &[(u32, u32)]) {
+    soft_mask.iter().for_each(|&(bgn, end)| {
+        let end = (end as usize).min(seq.len());
+        let bgn = (bgn as usize).min(end);
+        seq[bgn..end].make_ascii_lowercase();
+    });
+}
+
+/// the complement of a base or an IUPAC ambiguity code, the case is preserved, `U` is not
+/// mapped, so a reverse complement twice gives back the RNA bases
+#[inline]
+const fn complement_base(b: u8) -> Option<u8> {
+    let c = match b.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'S' => b'S',
+        b'W' => b'W',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        b'N' => b'N',
+        _ => return None,
+    };
+    if b.is_ascii_lowercase() {
+        Some(c.to_ascii_lowercase())
+    } else {
+        Some(c)
+    }
+}
+
+/// the complements of all bytes by `complement_base`, the other bytes are mapped to
+/// `unknown`, or to themselves if it is `None`
+const fn complement_table(unknown: Option<u8>) -> [u8; 256] {
+    let mut table = [0_u8; 256];
+    let mut b = 0;
+    while b < 256 {
+        table[b] = match (complement_base(b as u8), unknown) {
+            (Some(c), _) => c,
+            (None, Some(u)) => u,
+            (None, None) => b as u8,
+        };
+        b += 1;
+    }
+    table
+}
+
+const COMPLEMENT: [u8; 256] = complement_table(None);
+const COMPLEMENT_OR_N: [u8; 256] = complement_table(Some(b'N'));
+
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    reverse_complement_with_options(seq, false)
+}
+
+/// reverse complement a sequence with the IUPAC ambiguity codes, the bytes that are not
+/// nucleotide codes are passed through or, if `unknown_to_n` is set, mapped to `N`
+///
+/// the bytes are complemented by a lookup table 16 bytes at a time, so the loop is free of
+/// branches and unrolled by the compiler, the output is the same as the one of
+/// `reverse_complement_scalar`
+pub fn reverse_complement_with_options(seq: &[u8], unknown_to_n: bool) -> Vec<u8> {
+    let table = if unknown_to_n {
+        &COMPLEMENT_OR_N
+    } else {
+        &COMPLEMENT
+    };
+    let mut out = vec![0_u8; seq.len()];
+    reverse_complement_to_slice(seq, table, &mut out);
+    out
+}
+
+/// the same as `reverse_complement`, but the reverse complement is appended to `out`, so a
+/// buffer reused across the calls saves the allocation of each call
+pub fn reverse_complement_into(seq: &[u8], out: &mut Vec<u8>) {
+    let start = out.len();
+    out.resize(start + seq.len(), 0);
+    reverse_complement_to_slice(seq, &COMPLEMENT, &mut out[start..]);
+}
+
+/// reverse complement the bases in place, the same as `reverse_complement` otherwise
+pub fn reverse_complement_in_place(seq: &mut [u8]) {
+    seq.reverse();
+    seq.iter_mut().for_each(|b| *b = COMPLEMENT[*b as usize]);
+}
+
+/// write the reverse complement of `seq` to `out` of the same length
+fn reverse_complement_to_slice(seq: &[u8], table: &[u8; 256], out: &mut [u8]) {
+    // the 16-byte chunks from the end of the input fill the output from the start, the
+    // first `seq.len() % 16` bytes of the input are left for the tail of the output
+    let mut chunks = seq.rchunks_exact(16);
+    let mut out_chunks = out.chunks_exact_mut(16);
+    (&mut chunks)
+        .zip(&mut out_chunks)
+        .for_each(|(chunk, out_chunk)| {
+            (0..16).for_each(|i| out_chunk[i] = table[chunk[15 - i] as usize]);
+        });
+    chunks
+        .remainder()
+        .iter()
+        .rev()
+        .zip(out_chunks.into_remainder())
+        .for_each(|(b, c)| *c = table[*b as usize]);
+}
+
+/// the byte at a time version of `reverse_complement_with_options`, kept as the reference
+/// for the tests and the benchmarks
+pub fn reverse_complement_scalar(seq: &[u8], unknown_to_n: bool) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|b| match complement_base(*b) {
+            Some(c) => c,
+            None if unknown_to_n => b'N',
+            None => *b,
+        })
+        .collect()
 }
 
 impl<R: BufRead> FastaReader<R> {
+    /// the leading blank lines are skipped and the format is set by the next byte, `>` for
+    /// FASTA and `@` for FASTQ, any other byte fails with `InvalidData` and its line number,
+    /// such an input used to be read as FASTA with the byte dropped, e.g. the bases without a
+    /// header
     pub fn new(
         mut inner: R,
         filename: &String,
         seq_capacity: usize,
         keep_source: bool,
     ) -> Result<Self, io::Error> {
+        // skip the leading blank lines
+        let mut line_number = 0_usize;
+        loop {
+            match inner.fill_buf()?.first().copied() {
+                Some(c) if c.is_ascii_whitespace() => {
+                    if c == b'\n' {
+                        line_number += 1;
+                    }
+                    inner.consume(1);
+                }
+                _ => break,
+            }
+        }
         let t: Fastx;
         {
             let r = inner.by_ref();
@@ -64,7 +291,16 @@ impl<R: BufRead> FastaReader<R> {
             match buf[0] {
                 b'>' => t = Fastx::FastA,
                 b'@' => t = Fastx::FastQ,
-                _ => t = Fastx::FastA,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} line {}: a record should start with '>' or '@'",
+                            filename,
+                            line_number + 1
+                        ),
+                    ))
+                }
             }
         }
         Ok(Self {
@@ -73,72 +309,271 @@ impl<R: BufRead> FastaReader<R> {
             filename: filename.to_string(),
             seq_capacity,
             keep_source,
+            soft_mask: false,
+            skip_empty: false,
+            line_number,
+            header_consumed: true,
+            name_filter: None,
         })
     }
 
+    /// only return the records whose ids are in `names`, the sequences of the other FASTA
+    /// records are skipped without being copied
+    pub fn with_name_filter(mut self, names: HashSet<Vec<u8>>) -> Self {
+        self.name_filter = Some(names);
+        self
+    }
+
+    fn is_filtered_out(&self, id: &[u8]) -> bool {
+        self.name_filter
+            .as_ref()
+            .is_some_and(|names| !names.contains(id))
+    }
+
+    /// consume the bytes through the next `delim` without keeping them, return the number of
+    /// the skipped newlines, the byte before `delim` and whether `delim` is found
+    fn skip_until(&mut self, delim: u8) -> io::Result<(usize, Option<u8>, bool)> {
+        let mut n_lines = 0_usize;
+        let mut last_byte = None;
+        loop {
+            let (found, used) = {
+                let buf = self.inner.fill_buf()?;
+                if buf.is_empty() {
+                    return Ok((n_lines, last_byte, false));
+                }
+                let end = buf.iter().position(|&c| c == delim);
+                let skipped = &buf[..end.unwrap_or(buf.len())];
+                n_lines += skipped.iter().filter(|&&c| c == b'\n').count();
+                if let Some(&c) = skipped.last() {
+                    last_byte = Some(c);
+                }
+                match end {
+                    Some(p) => (true, p + 1),
+                    None => (false, buf.len()),
+                }
+            };
+            self.inner.consume(used);
+            if found {
+                return Ok((n_lines, last_byte, true));
+            }
+        }
+    }
+
+    /// skip the records without any base
+    pub fn with_skip_empty(mut self, skip_empty: bool) -> Self {
+        self.skip_empty = skip_empty;
+        self
+    }
+
+    fn malformed_input_error(&self, line_number: usize, msg: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} line {}: {}", self.filename, line_number, msg),
+        )
+    }
+
+    /// uppercase the bases of the records and keep the lowercase intervals in `SeqRec::soft_mask`
+    pub fn with_soft_mask(mut self, soft_mask: bool) -> Self {
+        self.soft_mask = soft_mask;
+        self
+    }
+
     pub fn next_rec(&mut self) -> Option<io::Result<SeqRec>> {
-        match self.t {
+        let rec = match self.t {
             Fastx::FastA => self.fasta_next_rec(),
             Fastx::FastQ => self.fastq_next_rec(),
+        };
+        if self.soft_mask {
+            rec.map(|rec| {
+                rec.map(|mut rec| {
+                    rec.soft_mask = Some(extract_soft_mask(&mut rec.seq));
+                    rec
+                })
+            })
+        } else {
+            rec
         }
     }
 
     pub fn fasta_next_rec(&mut self) -> Option<io::Result<SeqRec>> {
-        let mut id_tmp = Vec::<u8>::with_capacity(128);
-        let mut seq = Vec::<u8>::with_capacity(self.seq_capacity);
+        loop {
+            let mut id_tmp = Vec::<u8>::with_capacity(128);
 
-        let res = self.inner.read_until(b'\n', &mut id_tmp);
-        if res.ok() == Some(0) {
-            return None;
-        }
-        let mut r = BufReader::new(&id_tmp[..]);
-        let mut id = Vec::<u8>::with_capacity(128);
-        let _res = r.read_until(b' ', &mut id);
-
-        let id = id
-            .into_iter()
-            .filter(|c| *c != b'\n' && *c != b' ' && *c != b'\r')
-            .collect::<Vec<u8>>();
-        let _x = self.inner.read_until(b'>', &mut seq);
-        let mut seq = seq
-            .drain(..)
-            .filter(|c| *c != b'\n' && *c != b'>' && *c != b'\r')
-            .collect::<Vec<u8>>();
-        if seq.capacity() as f32 > seq.len() as f32 * 1.2 {
-            seq.shrink_to_fit();
-        }
-        let source = if self.keep_source {
-            Some(self.filename.to_string())
-        } else {
-            None
-        };
-        let rec = SeqRec { source, id, seq };
+            match self.inner.read_until(b'\n', &mut id_tmp) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            };
+            self.line_number += 1;
+            let id = id_tmp
+                .split(|c| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or_default()
+                .to_vec();
 
-        Some(Ok(rec))
+            if self.is_filtered_out(&id) {
+                let (n_lines, last_byte, found) = match self.skip_until(b'>') {
+                    Ok(v) => v,
+                    Err(e) => return Some(Err(e)),
+                };
+                let seq_end_line = self.line_number + n_lines;
+                if found && last_byte.is_some_and(|c| c != b'\n') {
+                    return Some(Err(self.malformed_input_error(
+                        seq_end_line + 1,
+                        "'>' is not at the start of a line",
+                    )));
+                }
+                self.line_number = seq_end_line;
+                continue;
+            }
+
+            let mut seq = Vec::<u8>::with_capacity(self.seq_capacity);
+            if let Err(e) = self.inner.read_until(b'>', &mut seq) {
+                return Some(Err(e));
+            };
+            let seq_end_line = self.line_number + seq.iter().filter(|c| **c == b'\n').count();
+            // a '>' in the middle of a sequence line is not a header
+            if seq.len() >= 2 && seq[seq.len() - 1] == b'>' && seq[seq.len() - 2] != b'\n' {
+                return Some(Err(self.malformed_input_error(
+                    seq_end_line + 1,
+                    "'>' is not at the start of a line",
+                )));
+            }
+            self.line_number = seq_end_line;
+            let mut seq = seq
+                .drain(..)
+                .filter(|c| !c.is_ascii_whitespace() && *c != b'>')
+                .collect::<Vec<u8>>();
+            if seq.is_empty() && self.skip_empty {
+                continue;
+            }
+            if seq.capacity() as f32 > seq.len() as f32 * 1.2 {
+                seq.shrink_to_fit();
+            }
+            let source = if self.keep_source {
+                Some(self.filename.to_string())
+            } else {
+                None
+            };
+            let rec = SeqRec {
+                source,
+                id,
+                seq,
+                qual: None,
+                soft_mask: None,
+            };
+
+            return Some(Ok(rec));
+        }
     }
 
     pub fn fastq_next_rec(&mut self) -> Option<io::Result<SeqRec>> {
+        loop {
+            match self.fastq_next_rec_inner() {
+                Some(Ok(rec)) if rec.seq.is_empty() && self.skip_empty => continue,
+                Some(Ok(rec)) if self.is_filtered_out(&rec.id) => continue,
+                rec => return rec,
+            }
+        }
+    }
+
+    fn fastq_next_rec_inner(&mut self) -> Option<io::Result<SeqRec>> {
+        // get to the id line of the record, the blank lines are skipped
+        while !self.header_consumed {
+            let c = match self.inner.fill_buf() {
+                Ok(buf) => buf.first().copied(),
+                Err(e) => return Some(Err(e)),
+            };
+            match c {
+                None => return None,
+                Some(b'@') => {
+                    self.inner.consume(1);
+                    self.header_consumed = true;
+                }
+                Some(c) if c.is_ascii_whitespace() => {
+                    if c == b'\n' {
+                        self.line_number += 1;
+                    }
+                    self.inner.consume(1);
+                }
+                Some(_) => {
+                    // consume the bad line so the reader can move on
+                    let mut line = Vec::<u8>::new();
+                    if let Err(e) = self.inner.read_until(b'\n', &mut line) {
+                        return Some(Err(e));
+                    }
+                    self.line_number += 1;
+                    return Some(Err(self.malformed_input_error(
+                        self.line_number,
+                        "a fastq record should start with '@'",
+                    )));
+                }
+            }
+        }
+        self.header_consumed = false;
+
         let mut id_tmp = Vec::<u8>::with_capacity(128);
-        let mut seq = Vec::<u8>::with_capacity(self.seq_capacity);
+        match self.inner.read_until(b'\n', &mut id_tmp) {
+            //read id
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => return Some(Err(e)),
+        };
+        self.line_number += 1;
 
-        let _res = self.inner.read_until(b'\n', &mut id_tmp); //read id
+        // fetch the first id up to the first space
+        let id = id_tmp
+            .split(|c| c.is_ascii_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_vec();
 
-        // fetch the first id up to the first space, strip '\n'
-        let mut r = BufReader::new(&id_tmp[..]);
-        let mut id = Vec::<u8>::with_capacity(128);
-        let _res = r.read_until(b' ', &mut id);
-        let id = id
-            .into_iter()
-            .filter(|c| *c != b'\n' && *c != b' ' && *c != b'\r')
-            .collect::<Vec<u8>>();
+        // get the seq, it may span multiple lines up to the '+' separator line
+        let mut seq = Vec::<u8>::with_capacity(self.seq_capacity);
+        let mut line = Vec::<u8>::with_capacity(1024);
+        loop {
+            line.clear();
+            match self.inner.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    return Some(Err(self.malformed_input_error(
+                        self.line_number,
+                        &format!("truncated fastq record: {}", String::from_utf8_lossy(&id)),
+                    )))
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            };
+            self.line_number += 1;
+            if line[0] == b'+' {
+                break;
+            }
+            seq.extend(line.iter().filter(|c| !c.is_ascii_whitespace()));
+        }
 
-        // get the seq
-        let _res = self.inner.read_until(b'\n', &mut seq);
+        // get the QV, the quality lines are read until we have as many QVs as bases
+        let mut qual = Vec::<u8>::with_capacity(seq.len());
+        while qual.len() < seq.len() {
+            line.clear();
+            match self.inner.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            };
+            self.line_number += 1;
+            qual.extend(line.iter().filter(|c| !c.is_ascii_whitespace()));
+        }
 
-        let mut seq = seq
-            .drain(..)
-            .filter(|c| *c != b'\n' && *c != b'\r')
-            .collect::<Vec<u8>>();
+        if qual.len() != seq.len() {
+            return Some(Err(self.malformed_input_error(
+                self.line_number,
+                &format!(
+                    "fastq record: {} has {} bases but {} quality values",
+                    String::from_utf8_lossy(&id),
+                    seq.len(),
+                    qual.len()
+                ),
+            )));
+        }
 
         if seq.capacity() as f32 > seq.len() as f32 * 1.2 {
             seq.shrink_to_fit();
@@ -150,20 +585,156 @@ impl<R: BufRead> FastaReader<R> {
             None
         };
 
-        let rec = SeqRec { source, id, seq };
-        // ignore QV
-        let mut buf = Vec::<u8>::with_capacity(1024);
-        let _res = self.inner.read_until(b'+', &mut buf);
-        let _res = self.inner.read_until(b'\n', &mut buf);
-        let _res = self.inner.read_until(b'\n', &mut buf);
-        let res = self.inner.read_until(b'@', &mut buf); //get to id line
-        if res.ok() == Some(0) {
-            return None;
-        }
+        let rec = SeqRec {
+            source,
+            id,
+            seq,
+            qual: Some(qual),
+            soft_mask: None,
+        };
         Some(Ok(rec))
     }
 }
 
+/// the name of a stream read by `FastaReader::from_reader` in the errors and in the `source`
+/// of the records
+pub const READER_SOURCE_NAME: &str = "<stdin>";
+
+impl<'a> FastaReader<Box<dyn BufRead + 'a>> {
+    /// read the records from a (possibly compressed) stream without a file, e.g.
+    /// `std::io::stdin().lock()`, the compression is detected from the buffered bytes as for
+    /// the files and the stream is named `READER_SOURCE_NAME`
+    pub fn from_reader(buf: impl BufRead + 'a) -> Result<Self, io::Error> {
+        let name = READER_SOURCE_NAME.to_string();
+        FastaReader::new(decompressed_reader(buf, &name)?, &name, 1 << 14, true)
+    }
+}
+
+impl<R: BufRead + Seek> FastaReader<R> {
+    /// scan the stream from its start once and return the byte offset of the header line and
+    /// the id of every record, the reading position is restored after the scan
+    pub fn record_offsets(&mut self) -> io::Result<Vec<(u64, Vec<u8>)>> {
+        let position = self.inner.stream_position()?;
+        self.inner.seek(SeekFrom::Start(0))?;
+        let offsets = self.scan_record_offsets();
+        self.inner.seek(SeekFrom::Start(position))?;
+        offsets
+    }
+
+    fn scan_record_offsets(&mut self) -> io::Result<Vec<(u64, Vec<u8>)>> {
+        let mut offsets = Vec::<(u64, Vec<u8>)>::new();
+        let mut offset = 0_u64;
+        let mut line_number = 0_usize;
+        let mut line = Vec::<u8>::with_capacity(1024);
+        let mut next_line = |inner: &mut R, line: &mut Vec<u8>| -> io::Result<Option<u64>> {
+            line.clear();
+            let n = inner.read_until(b'\n', line)?;
+            let line_offset = offset;
+            offset += n as u64;
+            Ok(if n == 0 { None } else { Some(line_offset) })
+        };
+        let get_id = |line: &[u8]| {
+            line[1..]
+                .split(|c| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or_default()
+                .to_vec()
+        };
+        match self.t {
+            Fastx::FastA => {
+                while let Some(line_offset) = next_line(&mut self.inner, &mut line)? {
+                    if line.first() == Some(&b'>') {
+                        offsets.push((line_offset, get_id(&line)));
+                    }
+                }
+            }
+            Fastx::FastQ => {
+                while let Some(line_offset) = next_line(&mut self.inner, &mut line)? {
+                    line_number += 1;
+                    if line.iter().all(|c| c.is_ascii_whitespace()) {
+                        continue;
+                    }
+                    if line[0] != b'@' {
+                        return Err(self.malformed_input_error(
+                            line_number,
+                            "a fastq record should start with '@'",
+                        ));
+                    }
+                    let id = get_id(&line);
+                    // the sequence lines up to the '+' line, then as many QVs as bases
+                    let mut n_bases = 0_usize;
+                    loop {
+                        if next_line(&mut self.inner, &mut line)?.is_none() {
+                            return Err(self.malformed_input_error(
+                                line_number,
+                                &format!(
+                                    "truncated fastq record: {}",
+                                    String::from_utf8_lossy(&id)
+                                ),
+                            ));
+                        }
+                        line_number += 1;
+                        if line[0] == b'+' {
+                            break;
+                        }
+                        n_bases += line.iter().filter(|c| !c.is_ascii_whitespace()).count();
+                    }
+                    let mut n_qvs = 0_usize;
+                    while n_qvs < n_bases && next_line(&mut self.inner, &mut line)?.is_some() {
+                        line_number += 1;
+                        n_qvs += line.iter().filter(|c| !c.is_ascii_whitespace()).count();
+                    }
+                    offsets.push((line_offset, id));
+                }
+            }
+        }
+        Ok(offsets)
+    }
+
+    /// read the record with the header line at `offset`, e.g. one from `record_offsets`, the
+    /// name filter and `with_skip_empty` are not applied, and the following records can be
+    /// read after it
+    ///
+    /// the line numbers in the errors of the malformed input are counted from `offset`
+    pub fn read_at(&mut self, offset: u64) -> io::Result<SeqRec> {
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.line_number = 0;
+        let mut c = [0_u8; 1];
+        self.inner.read_exact(&mut c)?;
+        let expected = match self.t {
+            Fastx::FastA => b'>',
+            Fastx::FastQ => b'@',
+        };
+        if c[0] != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: no record starts at the offset {}",
+                    self.filename, offset
+                ),
+            ));
+        }
+        self.header_consumed = true;
+
+        let name_filter = self.name_filter.take();
+        let skip_empty = std::mem::replace(&mut self.skip_empty, false);
+        let rec = self.next_rec();
+        self.name_filter = name_filter;
+        self.skip_empty = skip_empty;
+        rec.unwrap_or_else(|| {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{}: no record starts at the offset {}",
+                    self.filename, offset
+                ),
+            ))
+        })
+    }
+}
+
+/// the records can be consumed with the iterator adapters, e.g., `reader.take(n)`,
+/// or with `rayon`'s `par_bridge()` for per-record parallel work
 impl<R: BufRead> Iterator for FastaReader<R> {
     type Item = io::Result<SeqRec>;
     fn next(&mut self) -> Option<Self::Item> {
@@ -171,6 +742,266 @@ impl<R: BufRead> Iterator for FastaReader<R> {
     }
 }
 
+/// the line ending of the records written by `FastaWriter`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// the function mapping a header, without the leading `>` or `@`, to the header to write
+pub type HeaderFormat = Box<dyn Fn(&[u8]) -> Vec<u8> + Send>;
+
+pub struct FastaWriter<W: Write> {
+    inner: BufWriter<W>,
+    /// the number of bases per sequence line of the FASTA records, 0 for writing the whole
+    /// sequence in one line, the FASTQ records are always written in 4 lines
+    pub line_width: usize,
+    pub line_ending: LineEnding,
+    /// write the description after the first whitespace of the header, otherwise only the
+    /// first token of the header is written
+    pub keep_description: bool,
+    header_format: Option<HeaderFormat>,
+}
+
+impl<W: Write> FastaWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_line_width(inner, 60)
+    }
+
+    pub fn with_line_width(inner: W, line_width: usize) -> Self {
+        FastaWriter {
+            inner: BufWriter::new(inner),
+            line_width,
+            line_ending: LineEnding::default(),
+            keep_description: true,
+            header_format: None,
+        }
+    }
+
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// see `keep_description`
+    pub fn with_description(mut self, keep_description: bool) -> Self {
+        self.keep_description = keep_description;
+        self
+    }
+
+    /// map each header by `header_format` before writing it, it is applied after the
+    /// description is dropped if `keep_description` is not set, e.g. to add a prefix to the
+    /// ids, the line ending is not a part of the header
+    pub fn with_header_format(
+        mut self,
+        header_format: impl Fn(&[u8]) -> Vec<u8> + Send + 'static,
+    ) -> Self {
+        self.header_format = Some(Box::new(header_format));
+        self
+    }
+
+    fn write_wrapped(&mut self, s: &[u8]) -> Result<(), io::Error> {
+        let line_ending = self.line_ending.as_bytes();
+        if self.line_width == 0 || s.is_empty() {
+            self.inner.write_all(s)?;
+            self.inner.write_all(line_ending)?;
+        } else {
+            for line in s.chunks(self.line_width) {
+                self.inner.write_all(line)?;
+                self.inner.write_all(line_ending)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_header(
+        &mut self,
+        marker: &[u8],
+        id: &[u8],
+        description: Option<&[u8]>,
+    ) -> Result<(), io::Error> {
+        let header = if !self.keep_description {
+            Cow::Borrowed(
+                id.split(|c| c.is_ascii_whitespace())
+                    .next()
+                    .unwrap_or_default(),
+            )
+        } else {
+            match description.filter(|d| !d.is_empty()) {
+                Some(description) => Cow::Owned([id, b" ".as_slice(), description].concat()),
+                None => Cow::Borrowed(id),
+            }
+        };
+        let header = match self.header_format.as_ref() {
+            Some(header_format) => Cow::Owned(header_format(header.as_ref())),
+            None => header,
+        };
+        self.inner.write_all(marker)?;
+        self.inner.write_all(&header)?;
+        self.inner.write_all(self.line_ending.as_bytes())
+    }
+
+    /// write a FASTA record, `id` is the whole header, see `keep_description`
+    pub fn write_rec(&mut self, id: &[u8], seq: &[u8]) -> Result<(), io::Error> {
+        self.write_rec_with_description(id, None, seq)
+    }
+
+    /// write a FASTA record with the description after the id separated by a space, the
+    /// description is not written if it is empty or `keep_description` is not set
+    pub fn write_rec_with_description(
+        &mut self,
+        id: &[u8],
+        description: Option<&[u8]>,
+        seq: &[u8],
+    ) -> Result<(), io::Error> {
+        self.write_header(b">", id, description)?;
+        self.write_wrapped(seq)
+    }
+
+    pub fn write_fastq_rec(&mut self, id: &[u8], seq: &[u8], qual: &[u8]) -> Result<(), io::Error> {
+        if seq.len() != qual.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "record: {} has {} bases but {} quality values",
+                    String::from_utf8_lossy(id),
+                    seq.len(),
+                    qual.len()
+                ),
+            ));
+        }
+        // the 4-line records, the sequence and the quality lines are not wrapped
+        let line_ending = self.line_ending.as_bytes();
+        self.write_header(b"@", id, None)?;
+        self.inner.write_all(seq)?;
+        self.inner.write_all(line_ending)?;
+        self.inner.write_all(b"+")?;
+        self.inner.write_all(line_ending)?;
+        self.inner.write_all(qual)?;
+        self.inner.write_all(line_ending)
+    }
+
+    /// write a record, the soft-masked intervals are restored as lowercase bases
+    pub fn write_seq_rec(&mut self, rec: &SeqRec) -> Result<(), io::Error> {
+        let masked_seq;
+        let seq = match &rec.soft_mask {
+            Some(soft_mask) if !soft_mask.is_empty() => {
+                let mut seq = rec.seq.clone();
+                apply_soft_mask(&mut seq, soft_mask);
+                masked_seq = seq;
+                &masked_seq
+            }
+            _ => &rec.seq,
+        };
+        match &rec.qual {
+            Some(qual) => self.write_fastq_rec(&rec.id, seq, qual),
+            None => self.write_rec(&rec.id, seq),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.inner.flush()
+    }
+}
+
+enum OutputFile {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+/// a plain or gzip-compressed output file, see `FastaWriter::create`
+pub struct FastxOutput {
+    file: OutputFile,
+    finished: bool,
+}
+
+impl FastxOutput {
+    /// create the file, it is gzip-compressed if `gzip` is set or the name ends with `.gz`
+    pub fn create(filepath: &str, gzip: bool) -> Result<Self, io::Error> {
+        let file = File::create(filepath)?;
+        let file = if gzip || filepath.ends_with(".gz") {
+            OutputFile::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            OutputFile::Plain(file)
+        };
+        Ok(FastxOutput {
+            file,
+            finished: false,
+        })
+    }
+
+    /// flush the output and write the end of the gzip stream, writing after it fails and
+    /// flushing after it does nothing
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        if self.finished {
+            return Ok(());
+        }
+        match &mut self.file {
+            OutputFile::Plain(f) => f.flush()?,
+            OutputFile::Gzip(e) => e.try_finish()?,
+        }
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Write for FastxOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "write to a finished output",
+            ));
+        }
+        match &mut self.file {
+            OutputFile::Plain(f) => f.write(buf),
+            OutputFile::Gzip(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        match &mut self.file {
+            OutputFile::Plain(f) => f.flush(),
+            OutputFile::Gzip(e) => e.flush(),
+        }
+    }
+}
+
+impl FastaWriter<FastxOutput> {
+    /// write the records to a file, it is gzip-compressed if `gzip` is set or the name ends
+    /// with `.gz`, call `finish` to get the errors of finalizing the gzip stream, which are
+    /// ignored when the writer is dropped
+    pub fn create(filepath: &str, gzip: bool) -> Result<Self, io::Error> {
+        Ok(FastaWriter::new(FastxOutput::create(filepath, gzip)?))
+    }
+
+    /// flush the records and finalize the output, nothing can be written after it
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        self.inner.flush()?;
+        self.inner.get_mut().finish()
+    }
+}
+
+impl<W: Write> Drop for FastaWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.inner.flush();
+    }
+}
+
 pub struct FastqStreamReader {
     inner: std::io::Stdin,
     seq_capacity: usize,
@@ -207,11 +1038,19 @@ impl Iterator for FastqStreamReader {
                     if self.inner.read_line(&mut buf).unwrap_or(0) == 0 {
                         return None;
                     };
-                    if self.inner.read_line(&mut buf).unwrap_or(0) == 0 {
+                    let mut qual = String::with_capacity(self.seq_capacity);
+                    if self.inner.read_line(&mut qual).unwrap_or(0) == 0 {
                         return None;
                     };
+                    let qual = Some(qual.trim().as_bytes().to_vec());
                     let source = None;
-                    let rec = SeqRec { source, id, seq };
+                    let rec = SeqRec {
+                        source,
+                        id,
+                        seq,
+                        qual,
+                        soft_mask: None,
+                    };
                     Some(Ok(rec))
                 } else {
                     None
@@ -275,7 +1114,13 @@ impl Iterator for FastaStreamReader {
             }
             let seq = seq[..].as_bytes().to_vec();
             let source = None;
-            let rec = SeqRec { source, id, seq };
+            let rec = SeqRec {
+                source,
+                id,
+                seq,
+                qual: None,
+                soft_mask: None,
+            };
             Some(Ok(rec))
         } else {
             None
@@ -360,7 +1205,7 @@ pub fn build(seq_list_file: &String, out_prefix: &String) -> Result<usize, io::E
             let fastx_buf = BufReader::new(MultiGzDecoder::new(&mut reader));
             let mut fastx_reader = FastaReader::new(fastx_buf, &input_fn, 1 << 14, true)?;
             while let Some(r) = fastx_reader.next_rec() {
-                let r = r.unwrap();
+                let r = r?;
                 if r.seq.len() < 500 {
                     //ignore very short reads
                     continue;
@@ -383,7 +1228,7 @@ pub fn build(seq_list_file: &String, out_prefix: &String) -> Result<usize, io::E
         } else {
             let mut fastx_reader = FastaReader::new(reader, &input_fn, 1 << 14, true)?;
             while let Some(r) = fastx_reader.next_rec() {
-                let r = r.unwrap();
+                let r = r?;
                 if r.seq.len() < 500 {
                     //ignore very short reads
                     continue;
@@ -1,5 +1,6 @@
 use crate::seq_db::{
-    self, read_mdb_file_to_frag_locations, CompactSeq, Fragment, Fragments, GetSeq,
+    self, read_mdb_file_to_frag_locations, CompactSeq, CompactSeqLayout, FragSource, Fragment,
+    Fragments, GetSeq,
 };
 use crate::shmmrutils::ShmmrSpec;
 use bincode::config;
@@ -7,8 +8,9 @@ use flate2::read::DeflateDecoder;
 use memmap2::Mmap;
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 pub type ShmmrToFragMapLocation = FxHashMap<(u64, u64), (usize, usize)>;
 
 pub struct CompactSeqFragFileStorage {
@@ -46,12 +48,19 @@ impl CompactSeqFragFileStorage {
         );
         let mut sdx_version_string = [0_u8;7];
         sdx_file.read_exact(&mut sdx_version_string).expect("sdx file reading error");
+        let seq_layout = match &sdx_version_string {
+            v if v == seq_db::SDX_FILE_VERSION.as_bytes() => CompactSeqLayout::Current,
+            v if v == seq_db::SDX_FILE_VERSION_0_5.as_bytes() => CompactSeqLayout::V0,
+            _ => panic!(
+                "unsupported sdx file version {}",
+                String::from_utf8_lossy(&sdx_version_string)
+            ),
+        };
         let config = config::standard();
-        let (frag_compress_chunk_size, frag_addr_offsets, seqs): (
-            usize,
-            Vec<(usize, usize, u32)>,
-            Vec<CompactSeq>,
-        ) = bincode::decode_from_std_read(&mut sdx_file, config).expect("read sdx file error");
+        let (frag_compress_chunk_size, frag_addr_offsets): (usize, Vec<(usize, usize, u32)>) =
+            bincode::decode_from_std_read(&mut sdx_file, config).expect("read sdx file error");
+        let seqs =
+            seq_db::decode_compact_seqs(&mut sdx_file, seq_layout).expect("read sdx file error");
         let f_file = File::open(frag_file_prefix.clone() + ".frg").expect("frag file open fail");
         let frag_file = unsafe { Mmap::map(&f_file).expect("frag file memory map creation fail") };
 
@@ -117,6 +126,16 @@ impl CompactSeqFragFileStorage {
                             //println!("p: {} {}", p, p + b.len());
                             _p += b.len();
                         }
+                        Fragment::Internal2Bit(p) => {
+                            let b = seq_db::unpack_bases(p);
+                            reconstructed_seq.extend_from_slice(&b[self.shmmr_spec.k as usize..]);
+                            _p += b.len();
+                        }
+                        Fragment::InternalNRuns(r) => {
+                            let b = seq_db::unpack_n_runs(r);
+                            reconstructed_seq.extend_from_slice(&b[self.shmmr_spec.k as usize..]);
+                            _p += b.len();
+                        }
                         Fragment::AlnSegments((frag_id, reversed, _length, a)) => {
                             let frag_group_id = *frag_id / self.frag_compress_chunk_size as u32;
                             let frag_group =
@@ -128,9 +147,9 @@ impl CompactSeqFragFileStorage {
                                     )
                                 });
 
-                            if let Fragment::Internal(base_seq) = frag_group
+                            if let Some(base_seq) = frag_group
                                 [*frag_id as usize % self.frag_compress_chunk_size]
-                                .clone()
+                                .internal_bases()
                             {
                                 let mut seq = seq_db::reconstruct_seq_from_aln_segs(&base_seq, a);
                                 if *reversed {
@@ -246,3 +265,113 @@ fn fetch_frag_group(
             .unwrap();
     frags
 }
+
+pub const MMAP_FRAG_STORE_VERSION: &str = "MFS:0.1";
+
+/// A read-only fragment store laid out as a flat file that is memory-mapped, so
+/// processes loading the same store share one copy of the fragment bytes.
+///
+/// layout: version string, number of fragments (u64), (offset, size) of each
+/// bincode-encoded fragment (u64, u64), and the encoded fragments
+#[derive(Debug)]
+pub struct MmapFragStore {
+    pub frag_file: Mmap,
+    pub frag_offsets: Vec<(usize, usize)>,
+}
+
+impl MmapFragStore {
+    pub fn new(filepath: String) -> Result<Self, io::Error> {
+        let f_file = File::open(&filepath)?;
+        let frag_file = unsafe { Mmap::map(&f_file)? };
+        let version_len = MMAP_FRAG_STORE_VERSION.len();
+        if frag_file.len() < version_len + 8
+            || &frag_file[..version_len] != MMAP_FRAG_STORE_VERSION.as_bytes()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a fragment store file", filepath),
+            ));
+        }
+        let mut u64bytes = [0_u8; 8];
+        let mut cursor = version_len;
+        u64bytes.clone_from_slice(&frag_file[cursor..cursor + 8]);
+        let n_frags = u64::from_le_bytes(u64bytes) as usize;
+        cursor += 8;
+        let truncated = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is truncated", filepath),
+            )
+        };
+        let index_end = n_frags
+            .checked_mul(16)
+            .and_then(|index_len| index_len.checked_add(cursor))
+            .ok_or_else(truncated)?;
+        if frag_file.len() < index_end {
+            return Err(truncated());
+        }
+        let frag_offsets = (0..n_frags)
+            .map(|_| {
+                u64bytes.clone_from_slice(&frag_file[cursor..cursor + 8]);
+                let offset = u64::from_le_bytes(u64bytes) as usize;
+                cursor += 8;
+                u64bytes.clone_from_slice(&frag_file[cursor..cursor + 8]);
+                let size = u64::from_le_bytes(u64bytes) as usize;
+                cursor += 8;
+                (offset, size)
+            })
+            .collect::<Vec<(usize, usize)>>();
+        // every fragment is in the file, so `get_frag` never reads past it
+        if frag_offsets.iter().any(|&(offset, size)| {
+            offset
+                .checked_add(size)
+                .is_none_or(|end| end > frag_file.len())
+        }) {
+            return Err(truncated());
+        }
+        Ok(Self {
+            frag_file,
+            frag_offsets,
+        })
+    }
+
+    pub fn write_frag_store(frags: &Fragments, filepath: String) -> Result<(), io::Error> {
+        let config = config::standard();
+        let encoded_frags = frags
+            .par_iter()
+            .map(|f| bincode::encode_to_vec(f, config).unwrap())
+            .collect::<Vec<Vec<u8>>>();
+        let mut out_file = BufWriter::new(File::create(filepath)?);
+        out_file.write_all(MMAP_FRAG_STORE_VERSION.as_bytes())?;
+        out_file.write_all(&(encoded_frags.len() as u64).to_le_bytes())?;
+        let mut offset = MMAP_FRAG_STORE_VERSION.len() + 8 + 16 * encoded_frags.len();
+        encoded_frags
+            .iter()
+            .try_for_each(|v| -> Result<(), io::Error> {
+                out_file.write_all(&(offset as u64).to_le_bytes())?;
+                out_file.write_all(&(v.len() as u64).to_le_bytes())?;
+                offset += v.len();
+                Ok(())
+            })?;
+        encoded_frags
+            .iter()
+            .try_for_each(|v| out_file.write_all(v))?;
+        out_file.flush()?;
+        Ok(())
+    }
+}
+
+impl FragSource for MmapFragStore {
+    fn get_frag(&self, frag_id: u32) -> Option<Cow<'_, Fragment>> {
+        let &(offset, size) = self.frag_offsets.get(frag_id as usize)?;
+        let config = config::standard();
+        let frag_bytes = self.frag_file.get(offset..offset.checked_add(size)?)?;
+        let (frag, _size): (Fragment, usize) =
+            bincode::decode_from_slice(frag_bytes, config).ok()?;
+        Some(Cow::Owned(frag))
+    }
+
+    fn frag_count(&self) -> usize {
+        self.frag_offsets.len()
+    }
+}
@@ -14,7 +14,6 @@ pub mod seq_db;
 //pub mod seqs2variants;
 pub mod ext;
 pub mod shmmrutils;
-
 #[cfg(test)]
 mod tests {
     use crate::fasta_io::FastaReader;
@@ -26,6 +25,19 @@ mod tests {
 
     use crate::seq_db::{self, deltas_to_aln_segs, reconstruct_seq_from_aln_segs};
 
+    /// `len` random bases, the same bases for the same `seed`
+    pub(crate) fn random_bases(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[((state >> 33) % 4) as usize]
+            })
+            .collect()
+    }
+
     pub fn load_seqs() -> HashMap<String, Vec<u8>> {
         let mut seqs = HashMap::<String, Vec<u8>>::new();
         let filepath = "test/test_data/test_seqs.fa";
@@ -163,6 +175,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn aln_segs_between_test() {
+        use seq_db::{aln_segs_between, try_reconstruct_seq_from_aln_segs, AlnSegment};
+        let base = random_bases(1, 2000);
+        let other = random_bases(2, 2000);
+
+        // a substitution, an insertion and a deletion
+        let mut seq = base.clone();
+        seq[100] = if seq[100] == b'A' { b'C' } else { b'A' };
+        seq.insert(500, b'T');
+        seq.drain(1000..1003);
+        let aln_segs = aln_segs_between(&base, &seq).unwrap();
+        assert_eq!(reconstruct_seq_from_aln_segs(&base, &aln_segs), seq);
+        assert_eq!(
+            aln_segs
+                .iter()
+                .filter(|s| matches!(s, AlnSegment::Insertion(_)))
+                .count(),
+            2
+        );
+        assert_eq!(
+            aln_segs_between(&base, &base).unwrap(),
+            vec![AlnSegment::FullMatch]
+        );
+        assert!(aln_segs_between(&base, &other).is_none());
+
+        // the segments decoded against another base
+        [
+            try_reconstruct_seq_from_aln_segs(&base[..500], &aln_segs),
+            try_reconstruct_seq_from_aln_segs(&base, &[AlnSegment::Match(5, 3)]),
+        ]
+        .into_iter()
+        .for_each(|r| assert_eq!(r.unwrap_err().kind(), std::io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn reconstruct_into_test() {
+        use crate::fasta_io::{
+            reverse_complement, reverse_complement_in_place, reverse_complement_into,
+        };
+        use seq_db::{Fragment, GetSeq};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx("test/test_data/test_rev.fa".to_string())
+            .unwrap();
+        // the second sequence is the reverse complement of the first one
+        assert!(sdb
+            .frags
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|f| matches!(f, Fragment::AlnSegments((_, true, _, _)))));
+        let mut out = Vec::<u8>::new();
+        let mut scratch = Vec::<u8>::new();
+        (0..2).for_each(|_| {
+            sdb.seqs.iter().for_each(|s| {
+                sdb.reconstruct_into(s.id, &mut out, &mut scratch).unwrap();
+                assert_eq!(out, sdb.get_seq_by_id(s.id));
+            })
+        });
+
+        let seq = sdb.get_seq_by_id(0);
+        let mut rc_seq = b"ACGT".to_vec();
+        reverse_complement_into(&seq, &mut rc_seq);
+        assert_eq!(rc_seq, [&b"ACGT"[..], &reverse_complement(&seq)].concat());
+        let mut rc_seq = seq.clone();
+        reverse_complement_in_place(&mut rc_seq);
+        assert_eq!(rc_seq, reverse_complement(&seq));
+    }
+
     #[test]
     fn rc_match() {
         use seq_db::GetSeq;
@@ -353,6 +434,7 @@ mod tests {
             r: 12,
             min_span: 24,
             sketch: false,
+            ..ShmmrSpec::default()
         };
         let out1 = sequence_to_shmmrs(0, &seq.to_vec(), &spec, true);
         println!("out1: {} {:?}", out1.len(), out1);
@@ -460,4 +542,3881 @@ mod tests {
         let sub_seq = sdb.get_sub_seq_by_id(sid, 250, 1423);
         assert_eq!(seq[250..1423], sub_seq[..]);
     }
+
+    #[test]
+    fn fastq_read_test() {
+        let filepath = "test/test_data/test_reads.fq".to_string();
+        let reader = BufReader::new(File::open(&filepath).unwrap());
+        let fastx_reader = FastaReader::new(reader, &filepath, 1 << 12, false).unwrap();
+        let recs = fastx_reader.map(|r| r.unwrap()).collect::<Vec<_>>();
+        assert_eq!(recs.len(), 3);
+        assert_eq!(recs[0].id, b"read1".to_vec());
+        assert_eq!(recs[1].seq, b"ACGTTGCAACGTTGCA".to_vec());
+        assert_eq!(recs[1].qual, Some(b"@@IIII@@IIII##II".to_vec()));
+        assert_eq!(recs[2].seq, b"GATTACA".to_vec());
+        assert_eq!(recs[2].qual, Some(b"+@II#II".to_vec()));
+        recs.iter()
+            .for_each(|r| assert_eq!(r.seq.len(), r.qual.as_ref().unwrap().len()));
+    }
+
+    #[test]
+    fn fastq_qual_length_mismatch_test() {
+        let filepath = "test/test_data/test_bad_reads.fq".to_string();
+        let reader = BufReader::new(File::open(&filepath).unwrap());
+        let mut fastx_reader = FastaReader::new(reader, &filepath, 1 << 12, false).unwrap();
+        let rec = fastx_reader.next_rec().unwrap();
+        assert!(rec.is_err());
+    }
+
+    #[test]
+    fn fasta_write_round_trip_test() {
+        use crate::fasta_io::FastaWriter;
+        let filepath = "test/test_data/test_seqs.fa".to_string();
+        let reader = BufReader::new(File::open(&filepath).unwrap());
+        let recs = FastaReader::new(reader, &filepath, 1 << 12, false)
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+
+        let mut buf = Vec::<u8>::new();
+        {
+            let mut writer = FastaWriter::with_line_width(&mut buf, 80);
+            recs.iter()
+                .for_each(|r| writer.write_rec(&r.id, &r.seq).unwrap());
+            writer.flush().unwrap();
+        }
+        assert!(buf
+            .split(|c| *c == b'\n')
+            .all(|line| line.len() <= 80 || line[0] == b'>'));
+
+        let recs2 = FastaReader::new(&buf[..], &filepath, 1 << 12, false)
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(recs.len(), recs2.len());
+        recs.iter().zip(recs2.iter()).for_each(|(r0, r1)| {
+            assert_eq!(r0.id, r1.id);
+            assert_eq!(r0.seq, r1.seq);
+        });
+    }
+
+    #[test]
+    fn fasta_write_gzip_test() {
+        use crate::fasta_io::{
+            detect_compression, get_decompressed_reader, FastaWriter, FastxOutput, FileCompression,
+        };
+        use std::io::Write;
+        let filepath = "test/test_data/test_seqs.fa".to_string();
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx(filepath.clone()).unwrap();
+        let read_recs = |filepath: &String| {
+            FastaReader::new(
+                get_decompressed_reader(filepath).unwrap(),
+                filepath,
+                1 << 12,
+                false,
+            )
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>()
+        };
+        let recs = read_recs(&filepath);
+
+        // the compression is selected by the extension
+        let gz_file = std::env::temp_dir().join("pgr_db_test_write.fa.gz");
+        let gz_file = gz_file.to_string_lossy().to_string();
+        let mut writer = FastaWriter::create(&gz_file, false).unwrap();
+        recs.iter()
+            .for_each(|r| writer.write_rec(&r.id, &r.seq).unwrap());
+        writer.finish().unwrap();
+        drop(writer);
+        let mut reader = BufReader::new(File::open(&gz_file).unwrap());
+        assert_eq!(
+            detect_compression(&mut reader).unwrap(),
+            FileCompression::Gzip
+        );
+        let recs2 = read_recs(&gz_file);
+        assert_eq!(recs.len(), recs2.len());
+        recs.iter().zip(recs2.iter()).for_each(|(r0, r1)| {
+            assert_eq!(r0.id, r1.id);
+            assert_eq!(r0.seq, r1.seq);
+        });
+        let _ = std::fs::remove_file(gz_file);
+
+        // or by the flag, the sequences are reconstructed to the output directly
+        let gz_file = std::env::temp_dir().join("pgr_db_test_reconstruct.fa");
+        let gz_file = gz_file.to_string_lossy().to_string();
+        let mut out = FastxOutput::create(&gz_file, true).unwrap();
+        sdb.seqs.iter().for_each(|s| {
+            writeln!(out, ">{}", s.name).unwrap();
+            sdb.reconstruct_to(s.id, &mut out).unwrap();
+            writeln!(out).unwrap();
+        });
+        out.finish().unwrap();
+        drop(out);
+        let recs2 = read_recs(&gz_file);
+        assert_eq!(recs.len(), recs2.len());
+        recs.iter().zip(recs2.iter()).for_each(|(r0, r1)| {
+            assert_eq!(r0.id, r1.id);
+            assert_eq!(r0.seq, r1.seq);
+        });
+        let _ = std::fs::remove_file(gz_file);
+    }
+
+    #[test]
+    fn export_all_test() {
+        use seq_db::{export_file_name, CompactSeqDB, PerFile};
+        let seqs = load_seqs();
+        let mut bases = seqs.values().cloned().collect::<Vec<_>>();
+        bases.sort();
+        let names = ["chr1", "chr/2 x", "chr_2_x", ".hidden"];
+        let sdb = CompactSeqDB::from_iter(
+            names
+                .iter()
+                .zip(bases.iter())
+                .map(|(name, seq)| (name.to_string(), seq.clone())),
+            seq_db::SHMMRSPEC,
+        )
+        .unwrap();
+        assert_eq!(export_file_name(b"chr/2 x"), "chr_2_x.fa");
+        assert_eq!(export_file_name(b""), "_.fa");
+        let record = |i: usize| [&b">"[..], names[i].as_bytes(), b"\n", &bases[i], b"\n"].concat();
+
+        let dir = std::env::temp_dir().join("pgr_db_test_export_all");
+        let dir = dir.to_string_lossy().to_string();
+        let files = sdb.export_all(dir.clone(), PerFile::Sequence).unwrap();
+        let file_names = files
+            .iter()
+            .map(|f| {
+                std::path::Path::new(f)
+                    .file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            file_names,
+            vec!["chr1.fa", "chr_2_x.fa", "chr_2_x_2.fa", "_.hidden.fa"]
+        );
+        files
+            .iter()
+            .enumerate()
+            .for_each(|(i, f)| assert_eq!(std::fs::read(f).unwrap(), record(i)));
+
+        let files = sdb.export_all(dir.clone(), PerFile::Combined).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            std::fs::read(&files[0]).unwrap(),
+            (0..names.len()).flat_map(record).collect::<Vec<u8>>()
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn detect_compression_test() {
+        use crate::fasta_io::{detect_compression, FileCompression};
+        let mut r = BufReader::new(File::open("test/test_data/test_seqs2.fa.gz").unwrap());
+        assert_eq!(detect_compression(&mut r).unwrap(), FileCompression::Gzip);
+        let mut r = BufReader::new(File::open("test/test_data/test_seqs.fa").unwrap());
+        assert_eq!(detect_compression(&mut r).unwrap(), FileCompression::Plain);
+        // the probe should not consume the stream
+        let mut buf = [0_u8; 1];
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[0], b'>');
+        let mut r = &[0x28_u8, 0xB5_u8, 0x2F_u8, 0xFD_u8, 0x00_u8][..];
+        assert_eq!(detect_compression(&mut r).unwrap(), FileCompression::Zstd);
+        let mut r = &b"BZh91AY&SY"[..];
+        assert_eq!(detect_compression(&mut r).unwrap(), FileCompression::Bzip2);
+    }
+
+    #[test]
+    fn fragment_len_test() {
+        use seq_db::Fragment;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+            .unwrap();
+        let k = sdb.shmmr_spec.k;
+        let frags = sdb.frags.as_ref().unwrap();
+        assert!(frags.iter().any(|f| matches!(f, Fragment::AlnSegments(_))));
+        sdb.seqs.iter().for_each(|s| {
+            let (frag_start, n_frags) = s.seq_frag_range;
+            let seq_len = (frag_start..frag_start + n_frags)
+                .map(|frag_id| frags[frag_id as usize].len(k))
+                .sum::<usize>();
+            assert_eq!(seq_len, s.len);
+        });
+    }
+
+    #[test]
+    fn reverse_aln_segments_test() {
+        use crate::fasta_io::reverse_complement;
+        use crate::frag_file_io::CompactSeqFragFileStorage;
+        use seq_db::{reconstruct_seq_from_aln_segs, Fragment, GetSeq};
+        // every other sequence is reverse complemented, so its fragments are aligned to the
+        // fragments of the other strand
+        let filepath = "test/test_data/test_seqs.fa".to_string();
+        let reader = BufReader::new(File::open(&filepath).unwrap());
+        let seqs = FastaReader::new(reader, &filepath, 1 << 12, false)
+            .unwrap()
+            .enumerate()
+            .map(|(i, r)| {
+                let r = r.unwrap();
+                let seq = if i % 2 == 1 {
+                    reverse_complement(&r.seq)
+                } else {
+                    r.seq
+                };
+                (String::from_utf8_lossy(&r.id).to_string(), seq)
+            })
+            .collect::<Vec<_>>();
+        let sdb =
+            seq_db::CompactSeqDB::from_iter(seqs.clone().into_iter(), seq_db::SHMMRSPEC).unwrap();
+        let k = sdb.shmmr_spec.k as usize;
+        let frags = sdb.frags.as_ref().unwrap();
+
+        let mut n_reversed = 0;
+        sdb.seqs.iter().for_each(|s| {
+            let seq = &seqs[s.id as usize].1;
+            let (frag_start, n_frags) = s.seq_frag_range;
+            let mut offset = 0_usize;
+            (frag_start..frag_start + n_frags).for_each(|frag_id| {
+                let frag = &frags[frag_id as usize];
+                let frag_len = frag.len(k as u32);
+                if let Fragment::AlnSegments((base_frag_id, true, length, aln_segs)) = frag {
+                    let base_seq = frags[*base_frag_id as usize].internal_bases().unwrap();
+                    let bases = reconstruct_seq_from_aln_segs(&base_seq, aln_segs);
+                    assert_eq!(bases.len(), *length as usize);
+                    // the leading k-mer overlaps the fragment before it
+                    assert_eq!(
+                        reverse_complement(&bases),
+                        seq[offset - k..offset + frag_len]
+                    );
+                    assert_eq!(
+                        sdb.get_sub_seq_by_id(
+                            s.id,
+                            (offset - k) as u32,
+                            (offset + frag_len) as u32
+                        ),
+                        seq[offset - k..offset + frag_len]
+                    );
+                    n_reversed += 1;
+                }
+                offset += frag_len;
+            });
+            assert_eq!(offset, seq.len());
+            assert_eq!(sdb.get_seq_by_id(s.id), *seq);
+        });
+        assert!(n_reversed > 0);
+        assert!(sdb.verify_all().iter().all(|(_, ok)| *ok));
+
+        // and the same from the fragment files
+        let prefix = std::env::temp_dir().join("pgr_db_test_reverse_aln_segments");
+        let prefix = prefix.to_string_lossy().to_string();
+        sdb.write_to_frag_files(prefix.clone(), None);
+        sdb.write_shmmr_map_index(prefix.clone()).unwrap();
+        let seq_storage = CompactSeqFragFileStorage::new(prefix.clone());
+        sdb.seqs.iter().for_each(|s| {
+            assert_eq!(seq_storage.get_seq_by_id(s.id), seqs[s.id as usize].1);
+        });
+        ["frg", "sdx", "mdb", "midx"].iter().for_each(|ext| {
+            let _ = std::fs::remove_file(format!("{}.{}", prefix, ext));
+        });
+    }
+
+    #[test]
+    fn seq_db_write_load_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let filepath = std::env::temp_dir().join("pgr_db_test_seqs.sdb");
+        let filepath = filepath.to_string_lossy().to_string();
+        sdb.write_to_file(filepath.clone()).unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(filepath.clone()).unwrap();
+        assert_eq!(sdb.seqs.len(), sdb2.seqs.len());
+        assert_eq!(sdb.frag_map.len(), sdb2.frag_map.len());
+        (0..sdb.seqs.len() as u32).for_each(|sid| {
+            assert_eq!(sdb.get_seq_by_id(sid), sdb2.get_seq_by_id(sid));
+        });
+        let _ = std::fs::remove_file(filepath);
+    }
+
+    #[test]
+    fn verify_fragment_test() {
+        use seq_db::{Fragment, FragmentError};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.enable_frag_crcs().unwrap();
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let n_frags = sdb.frags.as_ref().unwrap().len() as u32;
+        assert_eq!(sdb.frag_crcs.as_ref().unwrap().len(), n_frags as usize);
+
+        // the checksums recorded from the input sequences match the decoded fragments, and
+        // they are kept by the DB file
+        let filepath = std::env::temp_dir().join("pgr_db_verify_fragment.sdb");
+        let filepath = filepath.to_string_lossy().to_string();
+        sdb.write_to_file(filepath.clone()).unwrap();
+        let mut sdb = seq_db::CompactSeqDB::load_from_file(filepath.clone()).unwrap();
+        let _ = std::fs::remove_file(filepath);
+        (0..n_frags).for_each(|frag_id| sdb.verify_fragment(frag_id).unwrap());
+        let crcs = sdb.frag_crcs.take().unwrap();
+        sdb.enable_frag_crcs().unwrap();
+        assert_eq!(sdb.frag_crcs.as_ref().unwrap(), &crcs);
+        assert_eq!(
+            sdb.verify_fragment(n_frags),
+            Err(FragmentError::NotFound(n_frags))
+        );
+
+        let frags = sdb.frags.as_mut().unwrap();
+        let aln_frag_id = frags
+            .iter()
+            .position(|f| matches!(f, Fragment::AlnSegments(_)))
+            .unwrap() as u32;
+        let prefix_frag_id = sdb.seqs[0].seq_frag_range.0;
+        if let Fragment::Prefix(b) = &mut frags[prefix_frag_id as usize] {
+            b[0] = if b[0] == b'A' { b'C' } else { b'A' };
+        }
+        if let Fragment::AlnSegments(d) = &mut frags[aln_frag_id as usize] {
+            d.2 += 1;
+        }
+        assert!(matches!(
+            sdb.verify_fragment(prefix_frag_id),
+            Err(FragmentError::ChecksumMismatch { frag_id, .. }) if frag_id == prefix_frag_id
+        ));
+        assert!(matches!(
+            sdb.verify_fragment(aln_frag_id),
+            Err(FragmentError::LengthMismatch { .. })
+        ));
+
+        // without the checksums, only the decoding of the fragments is checked
+        sdb.frag_crcs = None;
+        assert!(sdb.verify_fragment(prefix_frag_id).is_ok());
+        assert!(sdb.verify_fragment(aln_frag_id).is_err());
+    }
+
+    #[test]
+    fn verify_all_test() {
+        use seq_db::Fragment;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        assert!(sdb.seqs.iter().all(|s| s.checksum.is_some()));
+        let all_ok = (0..sdb.seqs.len() as u32)
+            .map(|sid| (sid, true))
+            .collect::<Vec<_>>();
+        assert_eq!(sdb.verify_all(), all_ok);
+
+        // the checksums are kept by the DB file and the index file
+        let filepath = std::env::temp_dir().join("pgr_db_verify_all.sdb");
+        let filepath = filepath.to_string_lossy().to_string();
+        sdb.write_to_file(filepath.clone()).unwrap();
+        let mut sdb = seq_db::CompactSeqDB::load_from_file(filepath.clone()).unwrap();
+        assert_eq!(sdb.verify_all(), all_ok);
+        sdb.write_index(filepath.clone()).unwrap();
+        let sdb_idx = seq_db::CompactSeqDB::load_index(filepath.clone()).unwrap();
+        let _ = std::fs::remove_file(filepath);
+        assert_eq!(
+            sdb_idx.seqs.iter().map(|s| s.checksum).collect::<Vec<_>>(),
+            sdb.seqs.iter().map(|s| s.checksum).collect::<Vec<_>>()
+        );
+
+        // a changed base is caught, also the sequences without a checksum are not ok
+        let prefix_frag_id = sdb.seqs[2].seq_frag_range.0;
+        if let Fragment::Prefix(b) = &mut sdb.frags.as_mut().unwrap()[prefix_frag_id as usize] {
+            b[0] = if b[0] == b'A' { b'C' } else { b'A' };
+        }
+        sdb.seqs[5].checksum = None;
+        sdb.verify_all().into_iter().for_each(|(sid, ok)| {
+            assert_eq!(ok, sid != 2 && sid != 5);
+        });
+    }
+
+    #[test]
+    fn reconstruct_with_mask_test() {
+        use seq_db::GetSeq;
+        use std::io::Write;
+        let seqs = load_seqs();
+        let mut names = seqs.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let mut masked_seq = seqs[&names[0]].clone();
+        masked_seq[100..300].make_ascii_lowercase();
+        masked_seq[1000..1050].make_ascii_lowercase();
+        let plain_seq = seqs[&names[1]].clone();
+        let filepath = std::env::temp_dir().join("pgr_db_soft_mask.fa");
+        let filepath = filepath.to_string_lossy().to_string();
+        let mut out = File::create(&filepath).unwrap();
+        writeln!(out, ">masked\n{}", String::from_utf8_lossy(&masked_seq)).unwrap();
+        writeln!(out, ">plain\n{}", String::from_utf8_lossy(&plain_seq)).unwrap();
+        drop(out);
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx_with_soft_mask(filepath.clone())
+            .unwrap();
+        let _ = std::fs::remove_file(&filepath);
+        assert_eq!(sdb.seqs[0].soft_mask, Some(vec![(100, 300), (1000, 1050)]));
+        assert_eq!(sdb.seqs[1].soft_mask, None);
+        assert_eq!(sdb.get_seq_by_id(0), seqs[&names[0]]);
+        assert_eq!(sdb.reconstruct_with_mask(0), masked_seq);
+        assert_eq!(sdb.reconstruct_with_mask(1), plain_seq);
+        assert!(sdb.verify_all().iter().all(|&(_, ok)| ok));
+
+        // the soft masks are kept by the DB file and the index file
+        let filepath = std::env::temp_dir().join("pgr_db_soft_mask.sdb");
+        let filepath = filepath.to_string_lossy().to_string();
+        sdb.write_to_file(filepath.clone()).unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(filepath.clone()).unwrap();
+        assert_eq!(sdb2.reconstruct_with_mask(0), masked_seq);
+        assert_eq!(sdb2.reconstruct_with_mask(1), plain_seq);
+        sdb.write_index(filepath.clone()).unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_index(filepath.clone()).unwrap();
+        let _ = std::fs::remove_file(filepath);
+        assert_eq!(sdb2.seqs[0].soft_mask, sdb.seqs[0].soft_mask);
+    }
+
+    #[test]
+    fn mmap_frag_store_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let seqs = (0..sdb.seqs.len() as u32)
+            .map(|sid| sdb.get_seq_by_id(sid))
+            .collect::<Vec<_>>();
+        let filepath = std::env::temp_dir().join("pgr_db_test_seqs.mfs");
+        let filepath = filepath.to_string_lossy().to_string();
+        sdb.write_frags_to_mmap_store(filepath.clone()).unwrap();
+        sdb.load_frags_from_mmap_store(filepath.clone()).unwrap();
+        assert!(sdb.frags.is_none());
+        seqs.iter().enumerate().for_each(|(sid, seq)| {
+            assert_eq!(*seq, sdb.get_seq_by_id(sid as u32));
+        });
+        assert_eq!(seqs[1][100..200], sdb.get_sub_seq_by_id(1, 100, 200)[..]);
+
+        // the stores with the offsets past the end of the file are rejected
+        use crate::frag_file_io::{MmapFragStore, MMAP_FRAG_STORE_VERSION};
+        let bytes = std::fs::read(&filepath).unwrap();
+        let cursor = MMAP_FRAG_STORE_VERSION.len();
+        let bad_filepath = std::env::temp_dir().join("pgr_db_test_seqs_bad.mfs");
+        let bad_filepath = bad_filepath.to_string_lossy().to_string();
+        let mut bad_bytes = bytes.clone();
+        bad_bytes[cursor..cursor + 8].copy_from_slice(&(u64::MAX / 8).to_le_bytes());
+        std::fs::write(&bad_filepath, &bad_bytes).unwrap();
+        assert!(MmapFragStore::new(bad_filepath.clone()).is_err());
+        let mut bad_bytes = bytes.clone();
+        bad_bytes[cursor + 16..cursor + 24].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&bad_filepath, &bad_bytes).unwrap();
+        assert!(MmapFragStore::new(bad_filepath.clone()).is_err());
+        let _ = std::fs::remove_file(bad_filepath);
+        let _ = std::fs::remove_file(filepath);
+
+        sdb.frag_store = None;
+        assert!(sdb.frag_source().is_err());
+    }
+
+    #[test]
+    fn load_seqs_parallel_test() {
+        use seq_db::GetSeq;
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open("test/test_data/test_seqs.fa").unwrap()),
+            &"test/test_data/test_seqs.fa".to_string(),
+            1 << 14,
+            true,
+        )
+        .unwrap();
+        let seqs = (&mut reader)
+            .enumerate()
+            .map(|(sid, r)| {
+                let r = r.unwrap();
+                let name = String::from_utf8_lossy(&r.id).to_string();
+                (sid as u32, r.source, name, r.seq)
+            })
+            .collect::<Vec<_>>();
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_seq_vec(&seqs);
+        [1, 4].into_iter().for_each(|n| {
+            let mut sdb2 = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+            sdb2.load_seqs_parallel(&seqs, Some(n));
+            assert_eq!(
+                sdb.frags.as_ref().unwrap().len(),
+                sdb2.frags.as_ref().unwrap().len()
+            );
+            seqs.iter().for_each(|(sid, _, _, seq)| {
+                assert_eq!(sdb2.get_seq_by_id(*sid), *seq);
+                assert_eq!(sdb.get_seq_by_id(*sid), sdb2.get_seq_by_id(*sid));
+            });
+        });
+    }
+
+    #[test]
+    fn shmmrs_from_seq_test() {
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open("test/test_data/test_seqs.fa").unwrap()),
+            &"test/test_data/test_seqs.fa".to_string(),
+            1 << 14,
+            true,
+        )
+        .unwrap();
+        let seq = reader.next().unwrap().unwrap().seq;
+        let spec = seq_db::SHMMRSPEC;
+        let shmmrs0 = shmmrutils::sequence_to_shmmrs(0, &seq, &spec, false);
+        let shmmrs1 = shmmrutils::shmmrs_from_seq(&seq[..], &spec);
+        assert!(!shmmrs1.is_empty());
+        assert_eq!(shmmrs0.len(), shmmrs1.len());
+        shmmrs0.iter().zip(shmmrs1.iter()).for_each(|(m0, m1)| {
+            assert_eq!((m0.x, m0.y), (m1.x, m1.y));
+        });
+    }
+
+    #[test]
+    fn seq_db_kmer_size_test() {
+        use seq_db::GetSeq;
+        use shmmrutils::ShmmrSpec;
+        let sdbs = [24, 32, seq_db::KMERSIZE]
+            .into_iter()
+            .map(|k| {
+                let spec = ShmmrSpec {
+                    w: 48,
+                    k,
+                    r: 4,
+                    min_span: 32,
+                    sketch: false,
+                    ..ShmmrSpec::default()
+                };
+                let mut sdb = seq_db::CompactSeqDB::new(spec);
+                let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+                sdb
+            })
+            .collect::<Vec<_>>();
+        let ref_seqs = (0..sdbs[0].seqs.len() as u32)
+            .map(|sid| sdbs[0].get_seq_by_id(sid))
+            .collect::<Vec<_>>();
+        sdbs.iter().enumerate().for_each(|(i, sdb)| {
+            let filepath = std::env::temp_dir().join(format!("pgr_db_test_seqs_k{}.sdb", i));
+            let filepath = filepath.to_string_lossy().to_string();
+            sdb.write_to_file(filepath.clone()).unwrap();
+            let sdb2 = seq_db::CompactSeqDB::load_from_file(filepath.clone()).unwrap();
+            assert_eq!(sdb.shmmr_spec.k, sdb2.shmmr_spec.k);
+            ref_seqs.iter().enumerate().for_each(|(sid, seq)| {
+                assert_eq!(*seq, sdb2.get_seq_by_id(sid as u32));
+            });
+            let _ = std::fs::remove_file(filepath);
+        });
+    }
+
+    #[test]
+    fn match_reads_identity_test() {
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open("test/test_data/test_seqs.fa").unwrap()),
+            &"test/test_data/test_seqs.fa".to_string(),
+            1 << 14,
+            true,
+        )
+        .unwrap();
+        let seq0 = reader.next().unwrap().unwrap().seq[1000..2000].to_vec();
+        let m = match_reads(&seq0, &seq0, true, 0.1, 0, 0, 32).unwrap();
+        assert_eq!(m.indel_count(), 0);
+        assert_eq!(m.identity(), 1.0);
+
+        let mut seq1 = seq0.clone();
+        seq1.insert(500, b'A');
+        let m = match_reads(&seq0, &seq1, true, 0.1, 0, 0, 32).unwrap();
+        assert_eq!(m.indel_count(), 1);
+        assert!((m.identity() - 1000.0 / 1001.0).abs() < 1e-6);
+
+        let mut seq1 = seq0.clone();
+        seq1[300] = if seq1[300] == b'A' { b'C' } else { b'A' };
+        let m = match_reads(&seq0, &seq1, true, 0.1, 0, 0, 32).unwrap();
+        assert_eq!(m.indel_count(), 2);
+        assert!((m.identity() - 999.0 / 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn soft_mask_test() {
+        use crate::fasta_io::FastaWriter;
+        let input = b">seq0\nacgtACGTNNacg\ntACGTaa\n>seq1\nACGT\n".to_vec();
+        let filepath = "memory".to_string();
+        let recs = FastaReader::new(&input[..], &filepath, 1 << 12, false)
+            .unwrap()
+            .with_soft_mask(true)
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(recs[0].seq, b"ACGTACGTNNACGTACGTAA".to_vec());
+        assert_eq!(recs[0].soft_mask, Some(vec![(0, 4), (10, 14), (18, 20)]));
+        assert_eq!(recs[1].soft_mask, Some(vec![]));
+
+        let mut buf = Vec::<u8>::new();
+        {
+            let mut writer = FastaWriter::with_line_width(&mut buf, 0);
+            recs.iter().for_each(|r| writer.write_seq_rec(r).unwrap());
+        }
+        assert_eq!(buf, b">seq0\nacgtACGTNNacgtACGTaa\n>seq1\nACGT\n".to_vec());
+    }
+
+    #[test]
+    fn fasta_writer_header_format_test() {
+        use crate::fasta_io::{FastaWriter, LineEnding};
+        let write_recs = |line_ending: LineEnding, keep_description: bool, prefix: bool| {
+            let mut buf = Vec::<u8>::new();
+            {
+                let mut writer = FastaWriter::with_line_width(&mut buf, 4)
+                    .with_line_ending(line_ending)
+                    .with_description(keep_description);
+                if prefix {
+                    writer = writer.with_header_format(|h| [b"hg002#".as_slice(), h].concat());
+                }
+                writer.write_rec(b"seq0 chr1:1-6", b"ACGTAC").unwrap();
+                writer
+                    .write_rec_with_description(b"seq1", Some(b"len=4".as_slice()), b"ACGT")
+                    .unwrap();
+                writer
+                    .write_rec_with_description(b"seq2", Some(b"".as_slice()), b"")
+                    .unwrap();
+                // the FASTQ records are not wrapped
+                writer
+                    .write_fastq_rec(b"read0 r", b"ACGTAC", b"II#III")
+                    .unwrap();
+            }
+            String::from_utf8(buf).unwrap()
+        };
+        let expected = [
+            (true, false, ">seq0 chr1:1-6\nACGT\nAC\n>seq1 len=4\nACGT\n>seq2\n\n@read0 r\nACGTAC\n+\nII#III\n"),
+            (false, false, ">seq0\nACGT\nAC\n>seq1\nACGT\n>seq2\n\n@read0\nACGTAC\n+\nII#III\n"),
+            (
+                true,
+                true,
+                ">hg002#seq0 chr1:1-6\nACGT\nAC\n>hg002#seq1 len=4\nACGT\n>hg002#seq2\n\n@hg002#read0 r\nACGTAC\n+\nII#III\n",
+            ),
+            (
+                false,
+                true,
+                ">hg002#seq0\nACGT\nAC\n>hg002#seq1\nACGT\n>hg002#seq2\n\n@hg002#read0\nACGTAC\n+\nII#III\n",
+            ),
+        ];
+        expected
+            .iter()
+            .for_each(|(keep_description, prefix, expected)| {
+                assert_eq!(
+                    write_recs(LineEnding::Lf, *keep_description, *prefix),
+                    *expected
+                );
+                assert_eq!(
+                    write_recs(LineEnding::CrLf, *keep_description, *prefix),
+                    expected.replace('\n', "\r\n")
+                );
+            });
+        // the default is the whole header with `\n`
+        let mut buf = Vec::<u8>::new();
+        FastaWriter::with_line_width(&mut buf, 0)
+            .write_rec(b"seq0 desc", b"ACGT")
+            .unwrap();
+        assert_eq!(buf, b">seq0 desc\nACGT\n".to_vec());
+    }
+
+    #[test]
+    fn seq_db_query_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let query_seq = sdb.get_sub_seq_by_id(0, 500, 3000);
+        let hits = sdb.query(&query_seq, 0.5, false);
+        assert!(!hits.is_empty());
+        assert!(hits.windows(2).all(|h| h[0].score >= h[1].score));
+        let hit = hits.iter().find(|h| h.seq_id == 0).unwrap();
+        assert_eq!(hit.name, sdb.seqs[0].name);
+        assert_eq!(hit.strand, 0);
+        assert!(hit.query_start < hit.query_end && hit.query_end <= query_seq.len() as u32);
+        assert_eq!(hit.target_start, hit.query_start + 500);
+        assert_eq!(hit.target_end, hit.query_end + 500);
+        assert_eq!(hit.query_clip_start, hit.query_start);
+        assert_eq!(hit.query_clip_end, query_seq.len() as u32 - hit.query_end);
+    }
+
+    #[test]
+    fn seq_db_add_seqs_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let n_seqs = sdb.seqs.len();
+        let n_frags = sdb.frags.as_ref().unwrap().len();
+        let config = bincode::config::standard();
+        let frags_bytes = bincode::encode_to_vec(sdb.frags.as_ref().unwrap(), config).unwrap();
+        let seq_frag_ranges = sdb
+            .seqs
+            .iter()
+            .map(|s| s.seq_frag_range)
+            .collect::<Vec<_>>();
+        let seqs = (0..n_seqs as u32)
+            .map(|sid| sdb.get_seq_by_id(sid))
+            .collect::<Vec<_>>();
+
+        // the first sequence of test_rev.fa is also in test_seqs.fa
+        sdb.duplicate_name_policy = Some(seq_db::DuplicateNamePolicy::Keep);
+        sdb.add_seqs_from_file("test/test_data/test_rev.fa".to_string())
+            .unwrap();
+        let new_seq = seqs[0][200..2200].to_vec();
+        let sid = sdb.add_seq("new_seq".to_string(), new_seq.clone()).unwrap();
+        assert_eq!(sid as usize, n_seqs + 2);
+        assert_eq!(sdb.seqs.len(), n_seqs + 3);
+
+        let frags = &sdb.frags.as_ref().unwrap()[..n_frags];
+        assert_eq!(bincode::encode_to_vec(frags, config).unwrap(), frags_bytes);
+        (0..n_seqs).for_each(|sid| {
+            assert_eq!(sdb.seqs[sid].seq_frag_range, seq_frag_ranges[sid]);
+            assert_eq!(sdb.get_seq_by_id(sid as u32), seqs[sid]);
+        });
+
+        let filepath = "test/test_data/test_rev.fa".to_string();
+        let rev_seqs = FastaReader::new(
+            BufReader::new(File::open(&filepath).unwrap()),
+            &filepath,
+            1 << 14,
+            false,
+        )
+        .unwrap()
+        .map(|r| {
+            let r = r.unwrap();
+            (String::from_utf8_lossy(&r.id).to_string(), r.seq)
+        })
+        .collect::<HashMap<String, Vec<u8>>>();
+        (0..2).for_each(|i| {
+            let s = &sdb.seqs[n_seqs + i];
+            assert_eq!(sdb.get_seq_by_id(s.id), *rev_seqs.get(&s.name).unwrap());
+        });
+        assert_eq!(sdb.get_seq_by_id(sid), new_seq);
+    }
+
+    #[test]
+    fn seq_db_stats_test() {
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let stats = sdb.stats();
+        assert_eq!(stats.n_seqs, sdb.seqs.len());
+        assert_eq!(
+            stats.total_bases,
+            load_seqs().values().map(|s| s.len()).sum::<usize>()
+        );
+        assert_eq!(stats.n_frags, sdb.frags.as_ref().unwrap().len());
+        assert_eq!(
+            stats.n_frags,
+            stats.n_prefix_suffix_frags + stats.n_internal_frags + stats.n_aln_segment_frags
+        );
+        assert_eq!(stats.n_prefix_suffix_frags, 2 * stats.n_seqs);
+        assert!(stats.n_aln_segment_frags > 0);
+        assert!(stats.compression_ratio > 1.0);
+    }
+
+    #[test]
+    fn reconstruct_to_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let seqs = load_seqs();
+        let mut out = Vec::<u8>::new();
+        (0..sdb.seqs.len() as u32).for_each(|sid| {
+            out.clear();
+            sdb.reconstruct_to(sid, &mut out).unwrap();
+            assert_eq!(out, sdb.get_seq_by_id(sid));
+            assert_eq!(out, seqs[&sdb.seqs[sid as usize].name]);
+        });
+    }
+
+    #[test]
+    fn corrupted_aln_segments_ref_test() {
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let frags = sdb.frags.as_mut().unwrap();
+        let frag_id = frags
+            .iter()
+            .position(|f| matches!(f, seq_db::Fragment::AlnSegments(_)))
+            .unwrap() as u32;
+        let sid = sdb
+            .seqs
+            .iter()
+            .position(|s| {
+                s.seq_frag_range.0 <= frag_id && frag_id < s.seq_frag_range.0 + s.seq_frag_range.1
+            })
+            .unwrap() as u32;
+        let prefix_frag_id = sdb.seqs[0].seq_frag_range.0;
+        if let seq_db::Fragment::AlnSegments(d) = &mut frags[frag_id as usize] {
+            d.0 = prefix_frag_id;
+        }
+
+        let err = sdb.try_get_seq_by_id(sid).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let msg = err.to_string();
+        assert!(msg.contains(&format!("fragment {} of sequence {}", frag_id, sid)));
+        assert!(msg.contains(&format!("non-internal fragment {}", prefix_frag_id)));
+        assert!(sdb.reconstruct_to(sid, &mut Vec::<u8>::new()).is_err());
+        // the other sequences do not depend on the rewired fragment
+        assert!((0..sdb.seqs.len() as u32)
+            .filter(|&s| s != sid)
+            .all(|s| sdb.try_get_seq_by_id(s).is_ok()));
+    }
+
+    #[test]
+    fn seq_db_from_iter_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let named_seqs = (0..sdb.seqs.len() as u32)
+            .map(|sid| (sdb.seqs[sid as usize].name.clone(), sdb.get_seq_by_id(sid)))
+            .collect::<Vec<_>>();
+        let sdb2 =
+            seq_db::CompactSeqDB::from_iter(named_seqs.clone().into_iter(), seq_db::SHMMRSPEC)
+                .unwrap();
+        assert_eq!(sdb2.seqs.len(), named_seqs.len());
+        named_seqs
+            .iter()
+            .enumerate()
+            .for_each(|(sid, (name, seq))| {
+                assert_eq!(sdb2.seqs[sid].name, *name);
+                assert!(sdb2.seqs[sid].source.is_none());
+                assert_eq!(sdb2.get_seq_by_id(sid as u32), *seq);
+            });
+        assert_eq!(sdb2.frag_map.len(), sdb.frag_map.len());
+    }
+
+    #[test]
+    fn seq_db_name_lookup_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        sdb.seqs.iter().for_each(|s| {
+            assert_eq!(sdb.id_of(&s.name), Some(s.id));
+            assert_eq!(sdb.name_of(s.id), Some(s.name.as_str()));
+        });
+        assert_eq!(sdb.id_of("no_such_seq"), None);
+        assert_eq!(sdb.name_of(sdb.seqs.len() as u32), None);
+
+        let seq = sdb.get_sub_seq_by_id(0, 100, 3000);
+        let sid = sdb.add_seq("added".to_string(), seq).unwrap();
+        assert_eq!(sdb.id_of("added"), Some(sid));
+        assert_eq!(sdb.name_of(sid), Some("added"));
+
+        let filepath = std::env::temp_dir()
+            .join("pgr_db_name_lookup.sdb")
+            .to_string_lossy()
+            .to_string();
+        sdb.write_to_file(filepath.clone()).unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(filepath).unwrap();
+        assert_eq!(sdb2.id_of("added"), Some(sid));
+        assert_eq!(sdb2.id_of(&sdb.seqs[0].name), Some(0));
+    }
+
+    #[test]
+    fn duplicate_name_policy_test() {
+        use seq_db::{DuplicateNamePolicy, GetSeq};
+        let filepath = "test/test_data/test_dup_names.fa".to_string();
+        let names = |sdb: &seq_db::CompactSeqDB| {
+            sdb.seqs.iter().map(|s| s.name.clone()).collect::<Vec<_>>()
+        };
+
+        // the Error policy stops at the first duplicate
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.duplicate_name_policy = Some(DuplicateNamePolicy::Error);
+        let err = sdb.load_seqs_from_fastx(filepath.clone()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(names(&sdb), vec!["seq_a", "seq_b"]);
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.duplicate_name_policy = Some(DuplicateNamePolicy::Error);
+        assert!(sdb.load_index_from_fastx(filepath.clone()).is_err());
+        assert_eq!(names(&sdb), vec!["seq_a", "seq_b"]);
+
+        // without a policy, the loaders keep the duplicates and the others take the Error
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        assert_eq!(sdb.duplicate_name_policy, None);
+        assert_eq!(DuplicateNamePolicy::default(), DuplicateNamePolicy::Error);
+        sdb.load_seqs_from_fastx(filepath.clone()).unwrap();
+        assert_eq!(names(&sdb), vec!["seq_a", "seq_b", "seq_a", "seq_a"]);
+        let err = sdb
+            .add_seq("seq_b".to_string(), b"ACGT".to_vec())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let seq_vec = vec![(4, None, "seq_b".to_string(), b"ACGT".to_vec())];
+        assert!(sdb.try_load_seqs_from_seq_vec(&seq_vec).is_err());
+        assert!(sdb.try_load_index_from_seq_vec(&seq_vec).is_err());
+        assert_eq!((sdb.seqs.len(), sdb.duplicate_name_policy), (4, None));
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.duplicate_name_policy = Some(DuplicateNamePolicy::Rename);
+        sdb.load_seqs_from_fastx(filepath.clone()).unwrap();
+        assert_eq!(names(&sdb), vec!["seq_a", "seq_b", "seq_a_1", "seq_a_2"]);
+        let sid = sdb
+            .add_seq("seq_a_1".to_string(), b"ACGT".to_vec())
+            .unwrap();
+        assert_eq!(sdb.name_of(sid), Some("seq_a_1_1"));
+        (0..4).for_each(|sid| assert_eq!(sdb.id_of(&names(&sdb)[sid]), Some(sid as u32)));
+        let mut sdi = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdi.duplicate_name_policy = Some(DuplicateNamePolicy::Rename);
+        sdi.load_index_from_fastx(filepath.clone()).unwrap();
+        assert_eq!(names(&sdi), names(&sdb)[..4].to_vec());
+
+        let mut sdb_keep = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb_keep.duplicate_name_policy = Some(DuplicateNamePolicy::Keep);
+        sdb_keep.load_seqs_from_fastx(filepath).unwrap();
+        assert_eq!(names(&sdb_keep), vec!["seq_a", "seq_b", "seq_a", "seq_a"]);
+        assert_eq!(sdb_keep.id_of("seq_a"), Some(0));
+        (0..4).for_each(|sid| {
+            assert_eq!(sdb_keep.get_seq_by_id(sid), sdb.get_seq_by_id(sid));
+        });
+    }
+
+    #[test]
+    fn seq_db_error_test() {
+        use seq_db::{CompactSeqDB, SeqDbError};
+        let path = |name: &str| {
+            std::env::temp_dir()
+                .join(name)
+                .to_string_lossy()
+                .to_string()
+        };
+
+        // a missing file is an error instead of a panic
+        let mut sdb = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let err = sdb
+            .load_seqs_from_fastx(path("pgr_db_missing.fa"))
+            .unwrap_err();
+        assert!(matches!(&err, SeqDbError::Io(e) if e.kind() == std::io::ErrorKind::NotFound));
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert!(matches!(
+            CompactSeqDB::load_from_file(path("pgr_db_missing.sdb")),
+            Err(SeqDbError::Io(_))
+        ));
+        let mut spec = seq_db::SHMMRSPEC;
+        spec.k = 64;
+        assert!(matches!(
+            CompactSeqDB::try_new(spec),
+            Err(SeqDbError::Io(_))
+        ));
+
+        // the sequences before a malformed record are loaded
+        let filepath = path("pgr_db_error_malformed.fa");
+        std::fs::write(&filepath, b">a\nACGT\n>b\nAC>GT\n").unwrap();
+        let err = sdb.load_seqs_from_fastx(filepath.clone()).unwrap_err();
+        assert!(matches!(err, SeqDbError::Parse(_)));
+        assert_eq!(sdb.seqs.len(), 1);
+        std::fs::write(&filepath, b"ACGT\n").unwrap();
+        let mut sdb = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        assert!(matches!(
+            sdb.load_index_from_fastx(filepath.clone()),
+            Err(SeqDbError::Parse(_))
+        ));
+        let _ = std::fs::remove_file(filepath);
+        let err = CompactSeqDB::from_iter(
+            ["a", "a"].iter().map(|n| (n.to_string(), b"ACGT".to_vec())),
+            seq_db::SHMMRSPEC,
+        )
+        .unwrap_err();
+        assert!(matches!(err, SeqDbError::Parse(_)));
+        let io_err: std::io::Error = err.into();
+        assert_eq!(io_err.kind(), std::io::ErrorKind::InvalidData);
+
+        // the files of other formats
+        [
+            CompactSeqDB::load_from_file("test/test_data/test_seqs.fa".to_string()),
+            CompactSeqDB::load_index("test/test_data/test_seqs.fa".to_string()),
+        ]
+        .into_iter()
+        .for_each(|r| assert!(matches!(r, Err(SeqDbError::Format(_)))));
+        let filepath = path("pgr_db_error_short.sdb");
+        std::fs::write(&filepath, b"SDB").unwrap();
+        assert!(matches!(
+            CompactSeqDB::load_from_file(filepath.clone()),
+            Err(SeqDbError::Format(_))
+        ));
+        let _ = std::fs::remove_file(filepath);
+
+        // a DB file missing the fragments of its last sequence
+        let mut sdb = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+            .unwrap();
+        sdb.frags.as_mut().unwrap().pop();
+        let filepath = path("pgr_db_error_truncated.sdb");
+        sdb.write_to_file(filepath.clone()).unwrap();
+        let err = CompactSeqDB::load_from_file(filepath.clone()).unwrap_err();
+        assert!(matches!(err, SeqDbError::Integrity(_)));
+        assert!(err.to_string().contains("out of the"));
+        let _ = std::fs::remove_file(filepath);
+    }
+
+    #[test]
+    fn raw_seq_name_test() {
+        use seq_db::{DuplicateNamePolicy, GetSeq};
+        let filepath = "test/test_data/test_raw_ids.fa".to_string();
+        let raw_ids: [&[u8]; 3] = [b"seq\xff1", b"seq\xfe1", b"plain"];
+        let name_bytes = |sdb: &seq_db::CompactSeqDB| {
+            sdb.seqs
+                .iter()
+                .map(|s| s.name_bytes().to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        // the two ids are the same in the lossy form, but they are not duplicates
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx(filepath.clone()).unwrap();
+        assert_eq!(name_bytes(&sdb), raw_ids.map(|id| id.to_vec()).to_vec());
+        assert_eq!(sdb.seqs[0].name, sdb.seqs[1].name);
+        assert_eq!(sdb.seqs[0].name, "seq\u{FFFD}1");
+        assert_eq!(sdb.seqs[2].raw_name, None);
+        raw_ids
+            .iter()
+            .enumerate()
+            .for_each(|(sid, id)| assert_eq!(sdb.id_of_bytes(id), Some(sid as u32)));
+        assert_eq!(sdb.id_of("plain"), Some(2));
+        assert_eq!(sdb.id_of(&sdb.seqs[0].name), None);
+
+        let mut sdi = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdi.load_index_from_fastx(filepath.clone()).unwrap();
+        assert_eq!(name_bytes(&sdi), name_bytes(&sdb));
+
+        let sdb_file = std::env::temp_dir().join("pgr_db_test_raw_ids.sdb");
+        let sdb_file = sdb_file.to_string_lossy().to_string();
+        sdb.write_to_file(sdb_file.clone()).unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(sdb_file.clone()).unwrap();
+        assert_eq!(name_bytes(&sdb2), name_bytes(&sdb));
+        assert_eq!(sdb2.id_of_bytes(raw_ids[1]), Some(1));
+        (0..3).for_each(|sid| assert_eq!(sdb2.get_seq_by_id(sid), sdb.get_seq_by_id(sid)));
+        let _ = std::fs::remove_file(sdb_file);
+
+        // the GFA paths are named by the raw bytes
+        let mut gfa = Vec::<u8>::new();
+        sdb.write_gfa(&mut gfa).unwrap();
+        let path_names = gfa
+            .split(|&c| c == b'\n')
+            .filter_map(|line| line.strip_prefix(b"P\t"))
+            .map(|line| line.split(|&c| c == b'\t').next().unwrap().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(path_names, name_bytes(&sdb));
+
+        sdb.duplicate_name_policy = Some(DuplicateNamePolicy::Rename);
+        sdb.load_seqs_from_fastx(filepath).unwrap();
+        assert_eq!(sdb.seqs[3].name_bytes(), b"seq\xff1_1");
+        assert_eq!(sdb.seqs[3].name, "seq\u{FFFD}1_1");
+        assert_eq!(sdb.seqs[5].raw_name, None);
+        assert_eq!(sdb.id_of("plain_1"), Some(5));
+    }
+
+    #[test]
+    fn base_composition_test() {
+        use seq_db::BaseComposition;
+        use std::io::Write;
+        let mut seq = random_bases(17, 2000);
+        seq[100..110].fill(b'N');
+        seq[200..205].make_ascii_lowercase();
+        seq[300] = b'R';
+        seq[301] = b'n';
+        let expected = BaseComposition {
+            n_count: 11,
+            lowercase_count: 6,
+            other_count: 1,
+        };
+        assert_eq!(BaseComposition::of(&seq), expected);
+        let seqs = vec![(0, None, "a".to_string(), seq.clone())];
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_seq_vec(&seqs);
+        assert_eq!(sdb.seqs[0].base_composition, None);
+        // counted on the reconstructed bases
+        assert_eq!(sdb.base_composition(0), expected);
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.tally_base_composition = true;
+        sdb.load_seqs_from_seq_vec(&seqs);
+        assert_eq!(sdb.seqs[0].base_composition, Some(expected));
+        let sdb_file = std::env::temp_dir().join("pgr_db_test_base_composition.sdb");
+        let sdb_file = sdb_file.to_string_lossy().to_string();
+        sdb.write_to_file(sdb_file.clone()).unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(sdb_file.clone()).unwrap();
+        assert_eq!(sdb2.seqs[0].base_composition, Some(expected));
+        let _ = std::fs::remove_file(sdb_file);
+
+        let mut sdi = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdi.tally_base_composition = true;
+        sdi.load_index_from_seq_vec(&seqs);
+        assert_eq!(sdi.base_composition(0), expected);
+
+        // the lowercase bases are uppercased by the soft mask
+        let fa_file = std::env::temp_dir().join("pgr_db_test_base_composition.fa");
+        let fa_file = fa_file.to_string_lossy().to_string();
+        let mut out = File::create(&fa_file).unwrap();
+        writeln!(out, ">a\n{}", String::from_utf8_lossy(&seq)).unwrap();
+        drop(out);
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.tally_base_composition = true;
+        sdb.load_seqs_from_fastx_with_soft_mask(fa_file.clone())
+            .unwrap();
+        assert_eq!(sdb.seqs[0].base_composition, Some(expected));
+        let _ = std::fs::remove_file(fa_file);
+    }
+
+    #[test]
+    fn seq_index_test() {
+        use crate::aln::{query_fragment_to_hps, DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE};
+        use seq_db::{GetSeq, SeqIndex};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+            .unwrap();
+        let queries = (0..4).map(|sid| sdb.get_seq_by_id(sid)).collect::<Vec<_>>();
+        let index = SeqIndex::from(sdb.clone());
+        assert_eq!(*index.shmmr_spec(), sdb.shmmr_spec);
+        assert_eq!(index.frag_map().len(), sdb.frag_map.len());
+        queries.iter().for_each(|frag| {
+            let raw_query_hits = seq_db::raw_query_fragment(&sdb.frag_map, frag, &sdb.shmmr_spec);
+            assert_eq!(index.query(frag).unwrap(), raw_query_hits);
+            let mut expected = query_fragment_to_hps(
+                raw_query_hits,
+                frag,
+                &sdb.shmmr_spec,
+                0.5,
+                None,
+                None,
+                None,
+                None,
+                DEFAULT_MIN_CHAIN_SCORE,
+                DEFAULT_MIN_CHAIN_LEN,
+            );
+            let mut res = index.query_to_hps(frag, 0.5).unwrap();
+            assert!(!res.is_empty());
+            expected.sort_by_key(|(sid, _)| *sid);
+            res.sort_by_key(|(sid, _)| *sid);
+            assert_eq!(res, expected);
+        });
+
+        let mut shmmr_spec = sdb.shmmr_spec.clone();
+        shmmr_spec.hash_id += 1;
+        let err = SeqIndex::new(sdb.frag_map.clone(), shmmr_spec).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(SeqIndex::new(sdb.frag_map, sdb.shmmr_spec).is_ok());
+    }
+
+    #[test]
+    fn query_batch_test() {
+        use crate::aln::{
+            query_fragment_to_hps, query_fragment_to_hps_with_pair_count, DEFAULT_MIN_CHAIN_LEN,
+            DEFAULT_MIN_CHAIN_SCORE,
+        };
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let mut frags = Vec::<Vec<u8>>::new();
+        (0..20).for_each(|sid| {
+            let seq = sdb.get_seq_by_id(sid);
+            (0..seq.len().saturating_sub(1500))
+                .step_by(700)
+                .for_each(|bgn| frags.push(seq[bgn..bgn + 1500].to_vec()));
+        });
+        assert!(frags.len() >= 60);
+        let expected = frags
+            .iter()
+            .map(|frag| sdb.query(frag, 0.5, true))
+            .collect::<Vec<_>>();
+        assert!(expected.iter().any(|hits| !hits.is_empty()));
+        assert_eq!(sdb.query_batch(&frags, 0.5, true), expected);
+
+        sdb.build_pair_target_count();
+        assert_eq!(sdb.query_batch(&frags, 0.5, true), expected);
+        assert_eq!(sdb.query(&frags[0], 0.5, true), expected[0]);
+
+        // the counts from the table filter the same hits as the recount
+        let pair_target_count = sdb.pair_target_count.as_ref().unwrap();
+        frags.iter().take(8).for_each(|frag| {
+            let raw_query_hits = seq_db::raw_query_fragment(&sdb.frag_map, frag, &sdb.shmmr_spec);
+            let mut res0 = query_fragment_to_hps(
+                raw_query_hits.clone(),
+                frag,
+                &sdb.shmmr_spec,
+                0.5,
+                None,
+                None,
+                Some(1),
+                None,
+                DEFAULT_MIN_CHAIN_SCORE,
+                DEFAULT_MIN_CHAIN_LEN,
+            );
+            let mut res1 = query_fragment_to_hps_with_pair_count(
+                raw_query_hits,
+                0.5,
+                None,
+                None,
+                Some(1),
+                None,
+                DEFAULT_MIN_CHAIN_SCORE,
+                DEFAULT_MIN_CHAIN_LEN,
+                Some(pair_target_count),
+            );
+            res0.sort_by_key(|(sid, _)| *sid);
+            res1.sort_by_key(|(sid, _)| *sid);
+            assert_eq!(res0, res1);
+        });
+
+        // adding a sequence drops the counts
+        sdb.add_seq("extra".to_string(), frags[0].clone()).unwrap();
+        assert!(sdb.pair_target_count.is_none());
+    }
+
+    #[test]
+    fn short_seq_test() {
+        use seq_db::{Fragment, GetSeq};
+        let seqs = load_seqs();
+        let mut names = seqs.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let short_seq = b"ACGTA".to_vec();
+        let seq_iter = names
+            .iter()
+            .take(2)
+            .map(|name| (name.clone(), seqs[name].clone()))
+            .chain([("short".to_string(), short_seq.clone())]);
+        let mut sdb = seq_db::CompactSeqDB::from_iter(seq_iter, seq_db::SHMMRSPEC).unwrap();
+        let sid = sdb.id_of("short").unwrap();
+        assert_eq!(sdb.seqs[sid as usize].len, 5);
+        assert_eq!(sdb.seqs[sid as usize].seq_frag_range.1, 1);
+        let frag_id = sdb.seqs[sid as usize].seq_frag_range.0;
+        assert!(matches!(
+            &sdb.frags.as_ref().unwrap()[frag_id as usize],
+            Fragment::Prefix(b) if *b == short_seq
+        ));
+        assert_eq!(sdb.get_seq_by_id(sid), short_seq);
+        assert_eq!(sdb.get_sub_seq_by_id(sid, 1, 4), b"CGT".to_vec());
+        assert!(sdb.frag_map.values().flatten().all(|sig| sig.1 != sid));
+
+        let sid = sdb.add_seq("empty".to_string(), vec![]).unwrap();
+        assert_eq!(sdb.get_seq_by_id(sid), Vec::<u8>::new());
+
+        assert!(sdb.query(&short_seq, 0.5, true).is_empty());
+        assert!(sdb
+            .query_batch(&[short_seq, vec![]], 0.5, true)
+            .iter()
+            .all(|hits| hits.is_empty()));
+    }
+
+    #[test]
+    fn fragment_diff_test() {
+        use seq_db::DiffOp;
+        let seqs = load_seqs();
+        let mut names = seqs.keys().collect::<Vec<_>>();
+        names.sort();
+        let seq_a = seqs[names[0]].clone();
+        let inserted = random_bases(7, 300);
+        let seq_b = [&seq_a[..1500], &inserted[..], &seq_a[1500..]].concat();
+        let sdb = seq_db::CompactSeqDB::from_iter(
+            vec![
+                ("a".to_string(), seq_a.clone()),
+                ("b".to_string(), seq_b.clone()),
+            ]
+            .into_iter(),
+            seq_db::SHMMRSPEC,
+        )
+        .unwrap();
+
+        let ops = sdb.fragment_diff(0, 0);
+        assert_eq!(ops.len(), 1);
+        assert!(matches!(
+            &ops[0],
+            DiffOp::Shared { span_a, span_b, .. }
+                if *span_a == (0, seq_a.len() as u32) && span_a == span_b
+        ));
+
+        // the shared runs and the deletions tile `a`, the shared runs and the insertions tile `b`
+        let ops = sdb.fragment_diff(0, 1);
+        let (mut end_a, mut end_b) = (0_u32, 0_u32);
+        let mut frag_ids = (Vec::<u32>::new(), Vec::<u32>::new());
+        ops.iter().for_each(|op| match op {
+            DiffOp::Shared {
+                frags_a,
+                span_a,
+                frags_b,
+                span_b,
+            } => {
+                assert_eq!(frags_a.len(), frags_b.len());
+                assert_eq!(span_a.1 - span_a.0, span_b.1 - span_b.0);
+                assert_eq!((span_a.0, span_b.0), (end_a, end_b));
+                (end_a, end_b) = (span_a.1, span_b.1);
+                frag_ids.0.extend(frags_a);
+                frag_ids.1.extend(frags_b);
+            }
+            DiffOp::Deletion {
+                frags_a,
+                span_a,
+                pos_b,
+            } => {
+                assert_eq!((span_a.0, *pos_b), (end_a, end_b));
+                end_a = span_a.1;
+                frag_ids.0.extend(frags_a);
+            }
+            DiffOp::Insertion {
+                frags_b,
+                span_b,
+                pos_a,
+            } => {
+                assert_eq!((*pos_a, span_b.0), (end_a, end_b));
+                end_b = span_b.1;
+                frag_ids.1.extend(frags_b);
+            }
+        });
+        assert_eq!((end_a, end_b), (seq_a.len() as u32, seq_b.len() as u32));
+        let range = |sid: usize| {
+            let (bgn, len) = sdb.seqs[sid].seq_frag_range;
+            (bgn..bgn + len).collect::<Vec<_>>()
+        };
+        assert_eq!(frag_ids, (range(0), range(1)));
+
+        // the insertion is caught in a single run of the fragments of `b`
+        let n_shared = ops
+            .iter()
+            .filter(|op| matches!(op, DiffOp::Shared { .. }))
+            .count();
+        assert_eq!(n_shared, 2);
+        assert!(ops.iter().any(|op| matches!(
+            op,
+            DiffOp::Insertion { span_b, .. } if span_b.0 <= 1500 && 1800 <= span_b.1
+        )));
+    }
+
+    #[test]
+    fn shmmr_spec_validate_test() {
+        use shmmrutils::ShmmrSpec;
+        assert_eq!(ShmmrSpec::assembly(), seq_db::SHMMRSPEC);
+        [
+            ShmmrSpec::assembly(),
+            ShmmrSpec::long_read(),
+            ShmmrSpec::short_read(),
+        ]
+        .iter()
+        .for_each(|spec| assert!(spec.validate().is_ok()));
+
+        let spec = ShmmrSpec::short_read();
+        let invalid_specs = [
+            ShmmrSpec {
+                k: 0,
+                ..spec.clone()
+            },
+            ShmmrSpec {
+                k: 57,
+                ..spec.clone()
+            },
+            ShmmrSpec {
+                r: 0,
+                ..spec.clone()
+            },
+            ShmmrSpec {
+                r: 13,
+                ..spec.clone()
+            },
+            ShmmrSpec {
+                w: 0,
+                ..spec.clone()
+            },
+            ShmmrSpec {
+                w: 129,
+                ..spec.clone()
+            },
+        ];
+        invalid_specs.iter().for_each(|spec| {
+            let e = spec.validate().unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        });
+        // the window size is not used by the sketch shimmers
+        assert!(ShmmrSpec {
+            w: 0,
+            ..ShmmrSpec::assembly()
+        }
+        .validate()
+        .is_ok());
+
+        let mut sdb = seq_db::CompactSeqDB::new(ShmmrSpec::assembly());
+        sdb.shmmr_spec.k = 64;
+        let e = sdb
+            .load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("k = 64"));
+        assert!(sdb.seqs.is_empty());
+    }
+
+    #[test]
+    fn shmmr_spec_density_test() {
+        use shmmrutils::ShmmrSpec;
+        let seq = random_bases(1, 2_000_000);
+        [
+            (ShmmrSpec::assembly().with_density(2), 2),
+            (
+                ShmmrSpec {
+                    min_span: 16,
+                    ..ShmmrSpec::assembly()
+                }
+                .with_density(10),
+                10,
+            ),
+            (ShmmrSpec::long_read().with_density(20), 20),
+        ]
+        .iter()
+        .for_each(|(spec, density)| {
+            assert!(spec.validate().is_ok());
+            let shmmrs = shmmrutils::shmmrs_from_seq(&seq, spec);
+            let realized = shmmrs.len() as f64 * 1000.0 / seq.len() as f64;
+            assert!(
+                (realized - *density as f64).abs() < 0.05 * *density as f64,
+                "{:?}: {} shimmers per kb",
+                spec,
+                realized
+            );
+        });
+        // the density can not be reached if the shimmers are too close for `min_span`
+        [0, 3].iter().for_each(|density| {
+            let e = ShmmrSpec::assembly()
+                .with_density(*density)
+                .validate()
+                .unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        });
+        // the window size is not used with a density
+        assert!(ShmmrSpec {
+            w: 0,
+            ..ShmmrSpec::short_read().with_density(20)
+        }
+        .validate()
+        .is_ok());
+
+        // the density is kept in the seq db and the shimmer map files
+        let spec = ShmmrSpec::assembly().with_density(2);
+        let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+            .unwrap();
+        let prefix = std::env::temp_dir()
+            .join("pgr_db_density")
+            .to_string_lossy()
+            .to_string();
+        sdb.write_to_file(prefix.clone() + ".sdb").unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(prefix.clone() + ".sdb").unwrap();
+        assert_eq!(sdb2.shmmr_spec, spec);
+        sdb.write_shmmr_map_index(prefix.clone()).unwrap();
+        let (loaded_spec, frag_map) = seq_db::read_mdb_file(prefix.clone() + ".mdb").unwrap();
+        assert_eq!(loaded_spec, spec);
+        assert_eq!(frag_map, sdb.frag_map);
+        let (loaded_spec, locations) =
+            seq_db::read_mdb_file_to_frag_locations(prefix.clone() + ".mdb").unwrap();
+        assert_eq!(loaded_spec, spec);
+        assert_eq!(locations.len(), sdb.frag_map.len());
+        ["sdb", "mdb", "midx"].iter().for_each(|ext| {
+            let _ = std::fs::remove_file(format!("{}.{}", prefix, ext));
+        });
+    }
+
+    #[test]
+    fn protein_seq_db_test() {
+        use seq_db::{Fragment, GetSeq};
+        use shmmrutils::{Alphabet, ShmmrSpec};
+        let mut x = 7_u64;
+        let mut rand_residues = |alphabet: &[u8], len: usize| {
+            (0..len)
+                .map(|_| {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    alphabet[(x % alphabet.len() as u64) as usize]
+                })
+                .collect::<Vec<u8>>()
+        };
+        // a protein with a stretch of the residues that would be 2-bit packed as the bases
+        let mut p0 = rand_residues(b"ACDEFGHIKLMNPQRSTVWY", 3000);
+        p0.splice(1000..1200, rand_residues(b"ACGT", 200));
+        let mut p1 = p0.clone();
+        (150..3000)
+            .step_by(300)
+            .for_each(|i| p1[i] = if p1[i] == b'A' { b'C' } else { b'A' });
+
+        let spec = ShmmrSpec::protein();
+        assert!(spec.validate().is_ok());
+        assert!(ShmmrSpec {
+            k: 26,
+            ..spec.clone()
+        }
+        .validate()
+        .is_err());
+        let shmmrs = shmmrutils::shmmrs_from_seq(&p0, &spec);
+        assert!(!shmmrs.is_empty());
+        assert!(shmmrs.iter().all(|m| m.strand() == 0));
+
+        let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
+        sdb.load_seqs_from_seq_vec(&vec![(0, None, "p0".to_string(), p0.clone())]);
+        assert_eq!(sdb.get_seq_by_id(0), p0);
+        assert!(!sdb
+            .frags
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|f| matches!(f, Fragment::Internal2Bit(_))));
+        let hits = sdb.query(&p1[500..2500], 0.1, false);
+        assert!(!hits.is_empty());
+        assert!(hits.iter().all(|h| h.strand == 0));
+        assert_eq!(hits[0].seq_id, 0);
+
+        // the alphabet is kept in the seq db and the shimmer map files
+        let prefix = std::env::temp_dir()
+            .join("pgr_db_protein")
+            .to_string_lossy()
+            .to_string();
+        sdb.write_to_file(prefix.clone() + ".sdb").unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(prefix.clone() + ".sdb").unwrap();
+        assert_eq!(sdb2.shmmr_spec.alphabet, Alphabet::Protein);
+        assert_eq!(sdb2.get_seq_by_id(0), p0);
+        sdb.write_shmmr_map_index(prefix.clone()).unwrap();
+        let (loaded_spec, _) = seq_db::read_mdb_file(prefix.clone() + ".mdb").unwrap();
+        assert_eq!(loaded_spec, spec);
+        ["sdb", "mdb", "midx"].iter().for_each(|ext| {
+            let _ = std::fs::remove_file(format!("{}.{}", prefix, ext));
+        });
+    }
+
+    #[test]
+    fn shmmr_spec_levels_test() {
+        use seq_db::GetSeq;
+        use shmmrutils::{ShmmrSpec, DEFAULT_REDUCTION_LEVELS, MAX_REDUCTION_LEVELS};
+        let seq = random_bases(11, 200_000);
+        let spec = ShmmrSpec {
+            w: 24,
+            k: 21,
+            r: 3,
+            ..ShmmrSpec::long_read()
+        };
+        assert_eq!(spec.levels, DEFAULT_REDUCTION_LEVELS);
+        assert!(spec
+            .clone()
+            .with_levels(MAX_REDUCTION_LEVELS)
+            .validate()
+            .is_ok());
+        let e = spec
+            .clone()
+            .with_levels(MAX_REDUCTION_LEVELS + 1)
+            .validate()
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+        // each level gives the fewer shimmers and the fewer shimmer pairs to index
+        let specs = (0..=4)
+            .map(|levels| spec.clone().with_levels(levels))
+            .collect::<Vec<_>>();
+        let n_shmmrs = specs
+            .iter()
+            .map(|spec| shmmrutils::shmmrs_from_seq(&seq, spec).len())
+            .collect::<Vec<_>>();
+        assert!(n_shmmrs.windows(2).all(|n| n[0] > n[1]), "{:?}", n_shmmrs);
+        let n_pairs = specs
+            .iter()
+            .map(|spec| {
+                let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
+                sdb.load_seqs_from_seq_vec(&vec![(0, None, "seq0".to_string(), seq.clone())]);
+                assert_eq!(sdb.get_seq_by_id(0), seq);
+                sdb.frag_map.len()
+            })
+            .collect::<Vec<_>>();
+        assert!(n_pairs.windows(2).all(|n| n[0] > n[1]), "{:?}", n_pairs);
+        // the reductions are not used without a reduction factor
+        let r1 = ShmmrSpec {
+            r: 1,
+            ..spec.clone()
+        };
+        let shmmr_keys = |spec: &ShmmrSpec| {
+            shmmrutils::shmmrs_from_seq(&seq, spec)
+                .iter()
+                .map(|m| (m.x, m.y))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            shmmr_keys(&r1.clone().with_levels(0)),
+            shmmr_keys(&r1.with_levels(4))
+        );
+
+        // the levels are kept in the seq db and the shimmer map files
+        let spec = spec.with_levels(4);
+        let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
+        sdb.load_seqs_from_seq_vec(&vec![(0, None, "seq0".to_string(), seq.clone())]);
+        let prefix = std::env::temp_dir()
+            .join("pgr_db_levels")
+            .to_string_lossy()
+            .to_string();
+        sdb.write_to_file(prefix.clone() + ".sdb").unwrap();
+        let sdb2 = seq_db::CompactSeqDB::load_from_file(prefix.clone() + ".sdb").unwrap();
+        assert_eq!(sdb2.shmmr_spec, spec);
+        sdb.write_shmmr_map_index(prefix.clone()).unwrap();
+        let (loaded_spec, frag_map) = seq_db::read_mdb_file(prefix.clone() + ".mdb").unwrap();
+        assert_eq!(loaded_spec, spec);
+        assert_eq!(frag_map, sdb.frag_map);
+        let (loaded_spec, _) =
+            seq_db::read_mdb_file_to_frag_locations(prefix.clone() + ".mdb").unwrap();
+        assert_eq!(loaded_spec, spec);
+        ["sdb", "mdb", "midx"].iter().for_each(|ext| {
+            let _ = std::fs::remove_file(format!("{}.{}", prefix, ext));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "r = 0")]
+    fn seq_db_invalid_shmmr_spec_test() {
+        let _ = seq_db::CompactSeqDB::new(shmmrutils::ShmmrSpec {
+            r: 0,
+            ..shmmrutils::ShmmrSpec::long_read()
+        });
+    }
+
+    #[test]
+    fn build_metrics_test() {
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        assert!(sdb.build_metrics.is_none());
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let metrics = sdb
+            .load_seqs_from_fastx_with_metrics("test/test_data/test_seqs.fa".to_string())
+            .unwrap();
+        assert!(sdb.build_metrics.is_none());
+        let stats = sdb.stats();
+        assert_eq!(metrics.n_seqs as usize, stats.n_seqs);
+        assert_eq!(metrics.n_bases as usize, stats.total_bases);
+        assert_eq!(metrics.n_frags as usize, stats.n_frags);
+        assert_eq!(metrics.n_aln_frags as usize, stats.n_aln_segment_frags);
+        assert!(metrics.n_aln_calls >= metrics.n_aln_frags);
+        assert!(metrics.shmmr_nanos > 0 && metrics.frag_nanos > 0);
+
+        // the metrics of a load are added to the ones already being accumulated
+        let seqs = load_seqs();
+        let seq_vec = vec![(
+            0,
+            None,
+            "a".to_string(),
+            seqs.values().next().unwrap().clone(),
+        )];
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.build_metrics = Some(seq_db::BuildMetrics::default());
+        sdb.duplicate_name_policy = Some(seq_db::DuplicateNamePolicy::Keep);
+        let m0 = sdb.load_seqs_from_seq_vec_with_metrics(&seq_vec);
+        let m1 = sdb.load_seqs_from_seq_vec_with_metrics(&seq_vec);
+        assert_eq!((m0.n_seqs, m1.n_seqs), (1, 1));
+        assert!(m1.n_aln_frags > 0);
+        let mut total = m0.clone();
+        total.merge(&m1);
+        assert_eq!(sdb.build_metrics, Some(total));
+    }
+
+    #[test]
+    fn compress_with_alignment_test() {
+        use seq_db::GetSeq;
+        let filepath = "test/test_data/test_seqs.fa".to_string();
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        assert!(sdb.compress_with_alignment);
+        let aligned = sdb
+            .load_seqs_from_fastx_with_metrics(filepath.clone())
+            .unwrap();
+        assert!(aligned.n_aln_frags > 0);
+
+        let mut sdb_literal = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb_literal.compress_with_alignment = false;
+        let literal = sdb_literal
+            .load_seqs_from_fastx_with_metrics(filepath)
+            .unwrap();
+        assert_eq!((literal.n_aln_calls, literal.n_aln_frags), (0, 0));
+        assert_eq!(literal.n_frags, aligned.n_frags);
+        let stats = sdb_literal.stats();
+        assert_eq!(stats.n_aln_segment_frags, 0);
+        assert!(stats.stored_bytes > sdb.stats().stored_bytes);
+        (0..sdb.seqs.len() as u32).for_each(|sid| {
+            assert_eq!(sdb_literal.get_seq_by_id(sid), sdb.get_seq_by_id(sid));
+        });
+    }
+
+    #[test]
+    fn multi_db_query_test() {
+        let seqs = load_seqs();
+        let mut names = seqs.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let shards = names
+            .chunks(names.len() / 2 + 1)
+            .map(|names| {
+                seq_db::CompactSeqDB::from_iter(
+                    names
+                        .iter()
+                        .map(|name| (name.clone(), seqs.get(name).unwrap().clone())),
+                    seq_db::SHMMRSPEC,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(shards.len(), 2);
+        let mdb = seq_db::MultiDb::new(shards);
+
+        // the same sequence ids in different shards are different sequences
+        let name0 = mdb.name_of(0, 0).unwrap();
+        let name1 = mdb.name_of(1, 0).unwrap();
+        assert_ne!(name0, name1);
+        assert_eq!(mdb.id_of(name1), Some((1, 0)));
+        assert_eq!(mdb.get_seq_by_id(1, 0), *seqs.get(name1).unwrap());
+
+        let query = seqs.get(name1).unwrap()[500..2500].to_vec();
+        let hits = mdb.query(&query, 0.5, true);
+        assert!(hits.iter().any(|h| h.shard_id == 0));
+        assert!(hits.windows(2).all(|w| w[0].hit.score >= w[1].hit.score));
+        hits.iter().for_each(|h| {
+            assert_eq!(
+                mdb.name_of(h.shard_id, h.hit.seq_id),
+                Some(h.hit.name.as_str())
+            );
+        });
+        let shard1_hits = mdb.shards[1].query(&query, 0.5, true);
+        assert_eq!(
+            hits.iter()
+                .filter(|h| h.shard_id == 1)
+                .map(|h| h.hit.clone())
+                .collect::<Vec<_>>(),
+            shard1_hits
+        );
+        let top = hits
+            .iter()
+            .find(|h| h.shard_id == 1 && h.hit.seq_id == 0)
+            .unwrap();
+        assert_eq!(top.hit.strand, 0);
+        assert_eq!(top.hit.target_start, top.hit.query_start + 500);
+    }
+
+    #[test]
+    fn minimizer_histogram_test() {
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let stats = sdb.stats();
+        let histogram = sdb.minimizer_histogram();
+        assert!(!histogram.contains_key(&0));
+        assert_eq!(histogram.values().sum::<u64>(), stats.n_shmmr_pairs as u64);
+        assert_eq!(
+            histogram
+                .iter()
+                .map(|(count, n)| *count as u64 * n)
+                .sum::<u64>(),
+            stats.n_frag_refs as u64
+        );
+        // the shimmer pairs are shared by the haplotypes
+        assert!(*histogram.keys().last().unwrap() > 1);
+
+        // an inverted index from the fragments to the shimmer pairs
+        let mut frag_to_pair = HashMap::<u32, (u64, u64)>::new();
+        sdb.iter_index().for_each(|(shmmr_pair, sigs)| {
+            assert_eq!(sdb.frag_map[&shmmr_pair], *sigs);
+            sigs.iter().for_each(|sig| {
+                frag_to_pair.insert(sig.0, shmmr_pair);
+            });
+        });
+        assert_eq!(sdb.iter_index().count(), stats.n_shmmr_pairs);
+        assert_eq!(frag_to_pair.len(), stats.n_frag_refs);
+    }
+
+    #[test]
+    fn all_vs_all_overlaps_test() {
+        use crate::fasta_io::reverse_complement;
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open("test/test_data/test_seqs.fa").unwrap()),
+            &"test/test_data/test_seqs.fa".to_string(),
+            1 << 14,
+            true,
+        )
+        .unwrap();
+        let seq = reader.next().unwrap().unwrap().seq;
+        let reads = vec![
+            (0, None, "r0".to_string(), seq[0..2000].to_vec()),
+            (1, None, "r1".to_string(), seq[1000..3000].to_vec()),
+            (2, None, "r2".to_string(), reverse_complement(&seq[1500..])),
+        ];
+        let spec = shmmrutils::ShmmrSpec {
+            w: 24,
+            k: 24,
+            r: 2,
+            min_span: 0,
+            sketch: false,
+            ..shmmrutils::ShmmrSpec::default()
+        };
+        let overlaps =
+            seq_db::all_vs_all_overlaps(&reads, &spec, &seq_db::OverlapParams::default());
+        assert!(overlaps.iter().all(|o| o.query_id < o.target_id));
+        let o = overlaps
+            .iter()
+            .find(|o| o.query_id == 0 && o.target_id == 1)
+            .unwrap();
+        assert_eq!(o.strand, 0);
+        assert!(o.query_start >= 1000 && o.query_end <= 2000);
+        assert_eq!(o.target_start + 1000, o.query_start);
+        assert_eq!(o.target_end + 1000, o.query_end);
+        assert!(overlaps
+            .iter()
+            .any(|o| o.query_id == 1 && o.target_id == 2 && o.strand == 1));
+
+        // the streamed overlaps are the same as the collected ones, even through a channel
+        // holding one query at a time
+        let key = |o: &seq_db::Overlap| {
+            (
+                o.query_id,
+                o.target_id,
+                o.query_start,
+                o.query_end,
+                o.target_start,
+                o.strand,
+            )
+        };
+        let params = seq_db::OverlapParams {
+            channel_capacity: 1,
+            ..Default::default()
+        };
+        let mut streamed = Vec::<seq_db::Overlap>::new();
+        seq_db::all_vs_all_overlaps_with_callback(&reads, &spec, &params, |o| streamed.push(o));
+        let mut streamed = streamed.iter().map(key).collect::<Vec<_>>();
+        streamed.sort();
+        let mut collected = overlaps.iter().map(key).collect::<Vec<_>>();
+        collected.sort();
+        assert_eq!(streamed, collected);
+    }
+
+    #[test]
+    fn fasta_reader_iterator_test() {
+        use rayon::prelude::*;
+        let filepath = "test/test_data/test_seqs.fa".to_string();
+        let get_reader = || {
+            FastaReader::new(
+                BufReader::new(File::open(&filepath).unwrap()),
+                &filepath,
+                1 << 14,
+                false,
+            )
+            .unwrap()
+        };
+        let mut n_recs = 0_usize;
+        let mut total_len = 0_usize;
+        for rec in get_reader() {
+            n_recs += 1;
+            total_len += rec.unwrap().seq.len();
+        }
+        assert_eq!(n_recs, load_seqs().len());
+        assert_eq!(get_reader().take(3).count(), 3);
+        let total_len2 = get_reader()
+            .par_bridge()
+            .map(|rec| rec.unwrap().seq.len())
+            .sum::<usize>();
+        assert_eq!(total_len, total_len2);
+    }
+
+    #[test]
+    fn reverse_complement_iupac_test() {
+        use crate::fasta_io::{reverse_complement, reverse_complement_with_options};
+        let seq = b"ACGTRYSWKMBDHVNacgtryswkmbdhvn".to_vec();
+        assert_eq!(
+            reverse_complement(&seq),
+            b"nbdhvkmwsryacgtNBDHVKMWSRYACGT".to_vec()
+        );
+        assert_eq!(reverse_complement(&reverse_complement(&seq)), seq);
+        // the RNA bases are passed through like the other unknown bytes
+        let rna = b"ACGURYNacgu".to_vec();
+        assert_eq!(reverse_complement(&rna), b"ucgtNRYUCGT".to_vec());
+        assert_eq!(reverse_complement(&reverse_complement(&rna)), rna);
+        assert_eq!(reverse_complement(b"AC-X"), b"X-GT".to_vec());
+        assert_eq!(
+            reverse_complement_with_options(b"AC-X", true),
+            b"NNGT".to_vec()
+        );
+    }
+
+    #[test]
+    fn reverse_complement_table_test() {
+        use crate::fasta_io::{reverse_complement_scalar, reverse_complement_with_options};
+        let mut state = 3_u64;
+        let mut rand = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            state >> 33
+        };
+        // the lengths around the 16-byte chunks, with any bytes or with the bases
+        (0..2000).for_each(|i| {
+            let len = (rand() % 100) as usize;
+            let seq = (0..len)
+                .map(|_| {
+                    if i % 2 == 0 {
+                        rand() as u8
+                    } else {
+                        b"ACGTNacgtn"[(rand() % 10) as usize]
+                    }
+                })
+                .collect::<Vec<u8>>();
+            [false, true].into_iter().for_each(|unknown_to_n| {
+                assert_eq!(
+                    reverse_complement_with_options(&seq, unknown_to_n),
+                    reverse_complement_scalar(&seq, unknown_to_n)
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn match_reads_with_params_test() {
+        use shmmrutils::{match_reads_with_params, MatchReadsParams};
+        let seqs = load_seqs();
+        let mut seqs = seqs.values();
+        let seq0 = seqs.next().unwrap()[..2000].to_vec();
+        let seq1 = seqs.next().unwrap()[..2000].to_vec();
+        let m0 = match_reads(&seq0, &seq1, true, 0.1, 0, 0, 32);
+        let m1 = match_reads_with_params(&seq0, &seq1, &MatchReadsParams::default());
+        assert_eq!(m0.is_some(), m1.is_some());
+        if let (Some(m0), Some(m1)) = (m0, m1) {
+            assert_eq!(
+                (m0.bgn0, m0.end0, m0.bgn1, m0.end1, m0.dist),
+                (m1.bgn0, m1.end0, m1.bgn1, m1.end1, m1.dist)
+            );
+        }
+
+        let mut seq2 = seq0.clone();
+        (0..seq2.len()).step_by(50).for_each(|i| {
+            seq2[i] = if seq2[i] == b'A' { b'C' } else { b'A' };
+        });
+        let params = MatchReadsParams {
+            max_deltas: Some(2),
+            ..Default::default()
+        };
+        assert!(match_reads_with_params(&seq0, &seq2, &params).is_none());
+        let params = MatchReadsParams {
+            max_deltas: Some(256),
+            ..Default::default()
+        };
+        assert!(match_reads_with_params(&seq0, &seq2, &params).is_some());
+    }
+
+    #[test]
+    fn messy_fastx_test() {
+        let filepath = "test/test_data/test_messy.fa".to_string();
+        let get_recs = |skip_empty: bool| {
+            FastaReader::new(
+                BufReader::new(File::open(&filepath).unwrap()),
+                &filepath,
+                1 << 12,
+                false,
+            )
+            .unwrap()
+            .with_skip_empty(skip_empty)
+            .map(|r| {
+                let r = r.unwrap();
+                (
+                    String::from_utf8(r.id).unwrap(),
+                    String::from_utf8(r.seq).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>()
+        };
+        let recs = get_recs(false);
+        let expected = [
+            ("seq0", "ACGTACGT"),
+            ("empty0", ""),
+            ("seq1", "TTTT"),
+            ("empty1", ""),
+            ("seq2", "GGCC"),
+        ];
+        assert_eq!(recs.len(), expected.len());
+        recs.iter().zip(expected.iter()).for_each(|(r, e)| {
+            assert_eq!((r.0.as_str(), r.1.as_str()), *e);
+        });
+        assert_eq!(get_recs(true).len(), 3);
+
+        let filepath = "test/test_data/test_messy.fq".to_string();
+        let recs = FastaReader::new(
+            BufReader::new(File::open(&filepath).unwrap()),
+            &filepath,
+            1 << 12,
+            false,
+        )
+        .unwrap()
+        .collect::<Vec<_>>();
+        assert_eq!(recs.len(), 4);
+        let r0 = recs[0].as_ref().unwrap();
+        assert_eq!((&r0.id[..], &r0.seq[..]), (&b"r0"[..], &b"ACGT"[..]));
+        assert_eq!(r0.qual.as_ref().unwrap(), b"IIII");
+        let err = recs[2].as_ref().unwrap_err();
+        assert!(err.to_string().contains("line 10"));
+        assert_eq!(recs[3].as_ref().unwrap().id, b"r2");
+
+        let bad_input = b">s0\nAC>GT\n".to_vec();
+        let mut reader = FastaReader::new(&bad_input[..], &filepath, 1 << 12, false).unwrap();
+        let err = reader.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        assert!(FastaReader::new(&b"ACGT\n"[..], &filepath, 1 << 12, false).is_err());
+        let err = FastaReader::new(&b"\n\r\nACGT\n"[..], &filepath, 1 << 12, false)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 3"));
+    }
+
+    #[test]
+    fn reconstruct_range_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        (0..sdb.seqs.len() as u32).step_by(5).for_each(|sid| {
+            let seq = sdb.get_seq_by_id(sid);
+            let len = seq.len() as u32;
+            [
+                (0, len),
+                (0, 1),
+                (len - 1, len),
+                (100, 1900),
+                (1234, 1235),
+                (57, 3000),
+            ]
+            .into_iter()
+            .filter(|(bgn, end)| bgn < end && *end <= len)
+            .for_each(|(bgn, end)| {
+                assert_eq!(
+                    sdb.reconstruct_range(sid, bgn, end),
+                    seq[bgn as usize..end as usize].to_vec()
+                );
+            });
+            assert!(sdb.reconstruct_range(sid, len, len).is_empty());
+            assert_eq!(
+                sdb.reconstruct_range(sid, len - 10, len + 10),
+                seq[len as usize - 10..]
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn reconstruct_range_seq_id_test() {
+        let sdb = seq_db::CompactSeqDB::from_iter(
+            vec![("a".to_string(), b"ACGT".to_vec())].into_iter(),
+            seq_db::SHMMRSPEC,
+        )
+        .unwrap();
+        let _ = sdb.reconstruct_range(1, 0, 4);
+    }
+
+    #[test]
+    fn fragment_coverage_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let n_seqs = sdb.seqs.len() as u32;
+        let coverage = sdb.fragment_coverage(0);
+        assert_eq!(coverage.len(), sdb.seqs[0].seq_frag_range.1 as usize);
+        assert_eq!(coverage[0], 0);
+        assert_eq!(*coverage.last().unwrap(), 0);
+        assert!(coverage.iter().all(|c| *c < n_seqs));
+        assert!(coverage.iter().any(|c| *c > 0));
+
+        let seq = sdb.get_seq_by_id(0);
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.add_seq("s0".to_string(), seq.clone()).unwrap();
+        sdb.add_seq("s1".to_string(), seq).unwrap();
+        let coverage = sdb.fragment_coverage(1);
+        let n = coverage.len();
+        assert!(coverage[1..n - 1].iter().all(|c| *c == 1));
+    }
+
+    #[test]
+    fn write_gfa_test() {
+        use crate::fasta_io::reverse_complement;
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        // the reverse complemented sequences share the segments in the other orientation
+        sdb.duplicate_name_policy = Some(seq_db::DuplicateNamePolicy::Rename);
+        sdb.add_seqs_from_file("test/test_data/test_rev.fa".to_string())
+            .unwrap();
+        let mut gfa = Vec::<u8>::new();
+        sdb.write_gfa(&mut gfa).unwrap();
+        let gfa = String::from_utf8(gfa).unwrap();
+        assert!(gfa.starts_with("H\tVN:Z:1.0\n"));
+
+        let mut segments = HashMap::<String, Vec<u8>>::new();
+        let mut n_links = 0;
+        let mut paths = Vec::<(String, Vec<u8>)>::new();
+        let oriented = |segments: &HashMap<String, Vec<u8>>, name: &str| {
+            let (id, orientation) = name.split_at(name.len() - 1);
+            let bases = segments.get(id).unwrap();
+            if orientation == "-" {
+                reverse_complement(bases)
+            } else {
+                bases.clone()
+            }
+        };
+        gfa.lines().skip(1).for_each(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            match fields[0] {
+                "S" => {
+                    segments.insert(fields[1].to_string(), fields[2].as_bytes().to_vec());
+                }
+                "L" => {
+                    // the overlap is the same bases at the ends of the two segments
+                    let overlap = fields[5].trim_end_matches('M').parse::<usize>().unwrap();
+                    let s0 = oriented(&segments, &format!("{}{}", fields[1], fields[2]));
+                    let s1 = oriented(&segments, &format!("{}{}", fields[3], fields[4]));
+                    assert_eq!(s0[s0.len() - overlap..], s1[..overlap]);
+                    n_links += 1;
+                }
+                "P" => {
+                    let overlaps = fields[3]
+                        .split(',')
+                        .map(|o| o.trim_end_matches('M').parse::<usize>().unwrap_or(0));
+                    let mut names = fields[2].split(',');
+                    let mut seq = oriented(&segments, names.next().unwrap());
+                    names.zip(overlaps).for_each(|(name, overlap)| {
+                        seq.extend_from_slice(&oriented(&segments, name)[overlap..])
+                    });
+                    paths.push((fields[1].to_string(), seq));
+                }
+                _ => panic!("unexpected GFA line: {}", line),
+            }
+        });
+        assert!(segments.len() < sdb.frags.as_ref().unwrap().len());
+        assert!(n_links > 0);
+        assert_eq!(paths.len(), sdb.seqs.len());
+        paths
+            .iter()
+            .zip(sdb.seqs.iter())
+            .for_each(|((name, seq), s)| {
+                assert_eq!(*name, s.name);
+                assert_eq!(*seq, sdb.get_seq_by_id(s.id));
+            });
+    }
+
+    #[test]
+    fn anchors_between_test() {
+        use crate::fasta_io::reverse_complement;
+        use std::collections::BTreeSet;
+        let seqs = load_seqs();
+        let seq = seqs.values().next().unwrap().clone();
+        let k = seq_db::SHMMRSPEC.k;
+        let len = seq.len() as u32;
+        let named_seqs = vec![
+            ("a".to_string(), seq.clone()),
+            ("b".to_string(), seq[500..3000].to_vec()),
+            ("c".to_string(), reverse_complement(&seq)),
+        ];
+        let sdb =
+            seq_db::CompactSeqDB::from_iter(named_seqs.into_iter(), seq_db::SHMMRSPEC).unwrap();
+
+        let anchors = sdb.anchors_between(0, 1);
+        assert!(!anchors.is_empty());
+        assert!(anchors.windows(2).all(|w| w[0] < w[1]));
+        assert!(anchors
+            .iter()
+            .all(|&(q, t, strand)| q == t + 500 && strand == 0));
+        let anchors = sdb.anchors_between(0, 2);
+        assert!(!anchors.is_empty());
+        assert!(anchors
+            .iter()
+            .all(|&(q, t, strand)| q + t == len + k && strand == 1));
+        let swapped = sdb
+            .anchors_between(2, 0)
+            .into_iter()
+            .map(|(t, q, strand)| (q, t, strand))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(swapped, anchors.into_iter().collect::<BTreeSet<_>>());
+    }
+
+    #[test]
+    fn shared_fragments_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let n_seqs = sdb.seqs.len();
+        let (frag_start, n_frags) = sdb.seqs[0].seq_frag_range;
+        let shared = sdb.shared_fragments(0, 1);
+        assert!(!shared.is_empty());
+        assert!(shared.windows(2).all(|w| w[0] < w[1]));
+        assert!(shared
+            .iter()
+            .all(|frag_id| (frag_start..frag_start + n_frags).contains(frag_id)));
+        // a fragment is shared with another sequence iff it is covered by it
+        let coverage = sdb.fragment_coverage(0);
+        let shared_with = (1..n_seqs as u32)
+            .map(|sid| sdb.shared_fragments(0, sid))
+            .collect::<Vec<_>>();
+        (0..n_frags).for_each(|i| {
+            let shared_with_any = shared_with
+                .iter()
+                .any(|shared| shared.contains(&(frag_start + i)));
+            assert_eq!(shared_with_any, coverage[i as usize] > 0);
+        });
+
+        let matrix = sdb.pairwise_similarity_matrix();
+        assert_eq!(matrix.len(), n_seqs);
+        (0..n_seqs).for_each(|a| {
+            assert_eq!(matrix[a].len(), n_seqs);
+            assert_eq!(matrix[a][a], 1.0);
+            (0..n_seqs).for_each(|b| {
+                assert_eq!(matrix[a][b], matrix[b][a]);
+                assert!((0.0..=1.0).contains(&matrix[a][b]));
+                assert_eq!(
+                    matrix[a][b] > 0.0,
+                    !sdb.shared_fragments(a as u32, b as u32).is_empty()
+                );
+            });
+        });
+
+        let seq = sdb.get_seq_by_id(0);
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.add_seq("s0".to_string(), seq.clone()).unwrap();
+        sdb.add_seq("s1".to_string(), seq).unwrap();
+        sdb.add_seq("s2".to_string(), b"AC".to_vec()).unwrap();
+        let matrix = sdb.pairwise_similarity_matrix();
+        assert_eq!(matrix[0][1], 1.0);
+        assert_eq!(matrix[2], vec![0.0; 3]);
+    }
+
+    #[test]
+    fn seq_db_reproducible_build_test() {
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open("test/test_data/test_seqs.fa").unwrap()),
+            &"test/test_data/test_seqs.fa".to_string(),
+            1 << 14,
+            true,
+        )
+        .unwrap();
+        let seqs = (&mut reader)
+            .enumerate()
+            .map(|(sid, r)| {
+                let r = r.unwrap();
+                let name = String::from_utf8_lossy(&r.id).to_string();
+                (sid as u32, r.source, name, r.seq)
+            })
+            .collect::<Vec<_>>();
+        let tmp_dir = std::env::temp_dir();
+        let build = |n_threads: usize| {
+            let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+            sdb.load_seqs_parallel(&seqs, Some(n_threads));
+            let prefix = tmp_dir
+                .join(format!("pgr_db_reproducible_{}", n_threads))
+                .to_string_lossy()
+                .to_string();
+            sdb.write_to_file(prefix.clone() + ".sdb").unwrap();
+            sdb.write_shmmr_map_index(prefix.clone()).unwrap();
+            let out = [".sdb", ".mdb", ".midx"]
+                .iter()
+                .map(|ext| {
+                    let mut buf = Vec::<u8>::new();
+                    File::open(prefix.clone() + ext)
+                        .unwrap()
+                        .read_to_end(&mut buf)
+                        .unwrap();
+                    let _ = std::fs::remove_file(prefix.clone() + ext);
+                    buf
+                })
+                .collect::<Vec<Vec<u8>>>();
+            let frags =
+                bincode::encode_to_vec(sdb.frags.as_ref().unwrap(), bincode::config::standard())
+                    .unwrap();
+            (frags, out)
+        };
+        let (frags0, files0) = build(1);
+        let (frags1, files1) = build(4);
+        assert_eq!(frags0, frags1);
+        assert_eq!(files0, files1);
+    }
+
+    #[test]
+    fn query_fragment_to_anchors_test() {
+        use crate::aln::{
+            chain_to_anchors, query_fragment_to_anchors, query_fragment_to_hps,
+            DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE,
+        };
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let seq = sdb.get_sub_seq_by_id(3, 200, 3000);
+        let raw_query_hits = seq_db::raw_query_fragment(&sdb.frag_map, &seq, &sdb.shmmr_spec);
+        let hps = query_fragment_to_hps(
+            raw_query_hits.clone(),
+            &seq,
+            &sdb.shmmr_spec,
+            0.5,
+            None,
+            None,
+            None,
+            None,
+            DEFAULT_MIN_CHAIN_SCORE,
+            DEFAULT_MIN_CHAIN_LEN,
+        );
+        let anchors = query_fragment_to_anchors(
+            raw_query_hits,
+            0.5,
+            None,
+            None,
+            None,
+            None,
+            DEFAULT_MIN_CHAIN_SCORE,
+            DEFAULT_MIN_CHAIN_LEN,
+            None,
+        );
+        assert!(!anchors.is_empty());
+        assert_eq!(anchors.len(), hps.len());
+        anchors
+            .iter()
+            .zip(hps.iter())
+            .for_each(|((sid0, chains0), (sid1, chains1))| {
+                assert_eq!(sid0, sid1);
+                chains0.iter().zip(chains1.iter()).for_each(
+                    |((score0, chain0), (score1, chain1))| {
+                        assert_eq!(score0, score1);
+                        assert_eq!(*chain0, chain_to_anchors(chain1));
+                    },
+                );
+            });
+        // the query span of an anchor is in the query, the target span in the target
+        let (_, chains) = anchors.iter().find(|(sid, _)| *sid == 3).unwrap();
+        assert!(chains.iter().any(|(_, chain)| chain.iter().all(|a| {
+            a.strand == crate::aln::Strand::Forward && a.target.bgn == a.query.bgn + 200
+        })));
+    }
+
+    #[test]
+    fn query_fragment_to_hps_chain_filter_test() {
+        use crate::aln::{query_fragment_to_hps, DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE};
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let seq = sdb.get_seq_by_id(0);
+        let query = |min_chain_score: f32, min_chain_len: usize| {
+            let raw_query_hits = seq_db::raw_query_fragment(&sdb.frag_map, &seq, &sdb.shmmr_spec);
+            query_fragment_to_hps(
+                raw_query_hits,
+                &seq,
+                &sdb.shmmr_spec,
+                0.5,
+                None,
+                None,
+                None,
+                None,
+                min_chain_score,
+                min_chain_len,
+            )
+        };
+        let all_hits = query(DEFAULT_MIN_CHAIN_SCORE, DEFAULT_MIN_CHAIN_LEN);
+        let n_chains =
+            |hits: &crate::aln::TargetHitPairLists| hits.iter().map(|h| h.1.len()).sum::<usize>();
+        // the single hits are only kept with `min_chain_len` 1
+        assert!(all_hits
+            .iter()
+            .all(|(_, chains)| chains.iter().all(|c| c.1.len() >= 2)));
+        assert!(n_chains(&query(DEFAULT_MIN_CHAIN_SCORE, 1)) >= n_chains(&all_hits));
+        let hits = query(DEFAULT_MIN_CHAIN_SCORE, 5);
+        assert!(hits
+            .iter()
+            .all(|(_, chains)| !chains.is_empty() && chains.iter().all(|c| c.1.len() >= 5)));
+        assert!(n_chains(&hits) <= n_chains(&all_hits));
+        let hits = query(1000.0, DEFAULT_MIN_CHAIN_LEN);
+        assert!(hits
+            .iter()
+            .all(|(_, chains)| chains.iter().all(|c| c.0 >= 1000.0)));
+        assert!(query(f32::MAX, DEFAULT_MIN_CHAIN_LEN).is_empty());
+
+        // a single hit on a target is a chain of its own with `min_chain_len` 1 (or 0)
+        let raw_query_hits = vec![
+            ((1, 2), (100, 124, 0), vec![(0, 7, 500, 524, 0)]),
+            ((3, 4), (200, 224, 0), vec![(1, 9, 300, 324, 0)]),
+            ((5, 6), (224, 248, 0), vec![(2, 9, 324, 348, 0)]),
+        ];
+        let query = |min_chain_len: usize| {
+            let mut hits = query_fragment_to_hps(
+                raw_query_hits.clone(),
+                &seq,
+                &sdb.shmmr_spec,
+                0.5,
+                None,
+                None,
+                None,
+                None,
+                DEFAULT_MIN_CHAIN_SCORE,
+                min_chain_len,
+            );
+            hits.sort_by_key(|(sid, _)| *sid);
+            hits
+        };
+        let hits = query(1);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(
+            hits[0],
+            (7, vec![(24.0, vec![((100, 124, 0), (500, 524, 0))])])
+        );
+        assert_eq!(hits[1].1[0].1.len(), 2);
+        assert_eq!(query(0), hits);
+        assert_eq!(query(DEFAULT_MIN_CHAIN_LEN), hits[1..].to_vec());
+    }
+
+    #[test]
+    fn n_run_frags_test() {
+        use seq_db::{pack_n_runs, unpack_n_runs, Fragment, GetSeq};
+        let seq = [&b"ACGTN"[..], &[b'N'; 40], b"ACNNGT", &[b'N'; 100]].concat();
+        let n_runs = pack_n_runs(&seq).unwrap();
+        assert_eq!(n_runs.1, vec![(4, 41), (51, 100)]);
+        assert_eq!(n_runs.2, b"ACGTACNNGT".to_vec());
+        assert_eq!(unpack_n_runs(&n_runs), seq);
+        assert!(pack_n_runs(b"ACGTNNNNACGT").is_none());
+
+        let seqs = load_seqs();
+        let mut names = seqs.keys().collect::<Vec<_>>();
+        names.sort();
+        // the test sequences are short, a few of them are joined to flank a 10 kb gap
+        let base_seq = names
+            .iter()
+            .take(12)
+            .flat_map(|name| seqs[*name].iter().copied())
+            .collect::<Vec<u8>>();
+        let gap = (20000_usize, 30000_usize);
+        let seq = [&base_seq[..gap.0], &[b'N'; 10000], &base_seq[gap.1..]].concat();
+        let sdb = seq_db::CompactSeqDB::from_iter(
+            vec![("gapped".to_string(), seq.clone())].into_iter(),
+            seq_db::SHMMRSPEC,
+        )
+        .unwrap();
+        assert_eq!(sdb.get_seq_by_id(0), seq);
+
+        // no shimmers from the gap, and the gap is kept as a run in a single fragment
+        let k = sdb.shmmr_spec.k as usize;
+        sdb.frag_map.values().flatten().for_each(|sig| {
+            let (bgn, end) = (sig.2 as usize - k, sig.3 as usize);
+            assert!(bgn >= gap.1 || end <= gap.0 || (bgn < gap.0 && gap.1 < end));
+        });
+        let n_run_frags = sdb
+            .frags
+            .as_ref()
+            .unwrap()
+            .iter()
+            .filter_map(|f| match f {
+                Fragment::InternalNRuns(r) => Some(r),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(n_run_frags.len(), 1);
+        assert_eq!(n_run_frags[0].1.len(), 1);
+        assert_eq!(n_run_frags[0].1[0].1, 10000);
+        // only the bases between the gap and the flanking shimmers are kept as they are
+        assert!(n_run_frags[0].2.len() * 2 < 10000);
+    }
+
+    #[test]
+    fn max_fragment_len_test() {
+        use seq_db::{CompactSeqDB, GetSeq};
+        // a 100 kb gap without shimmers between two flanks, and a sequence of the gap only
+        let gapped = [
+            &random_bases(1, 5000)[..],
+            &[b'N'; 100_000],
+            &random_bases(2, 5000),
+        ]
+        .concat();
+        let seqs = vec![
+            (0, None, "gapped".to_string(), gapped),
+            (1, None, "gap".to_string(), vec![b'N'; 100_000]),
+            (2, None, "other".to_string(), random_bases(3, 3000)),
+        ];
+        let build = |max_fragment_len| {
+            let mut sdb = CompactSeqDB::new(seq_db::SHMMRSPEC);
+            sdb.max_fragment_len = max_fragment_len;
+            sdb.enable_frag_crcs().unwrap();
+            sdb.load_seqs_from_seq_vec(&seqs);
+            sdb
+        };
+        let max_frag_len = |sdb: &CompactSeqDB| {
+            let frags = sdb.frags.as_ref().unwrap();
+            frags.iter().map(|f| f.base_len()).max().unwrap()
+        };
+
+        let unsplit_sdb = build(None);
+        assert!(max_frag_len(&unsplit_sdb) >= 100_000);
+        let sdb = build(Some(4096));
+        assert!(max_frag_len(&sdb) <= 4096);
+        assert!(sdb.seqs[1].seq_frag_range.1 > 100_000 / 4096);
+        seqs.iter()
+            .for_each(|(sid, _, _, seq)| assert_eq!(sdb.get_seq_by_id(*sid), *seq));
+        assert!(sdb.verify_all().iter().all(|(_, ok)| *ok));
+        let n_frags = sdb.frags.as_ref().unwrap().len() as u32;
+        assert!((0..n_frags).all(|frag_id| sdb.verify_fragment(frag_id).is_ok()));
+        // the pieces of the split fragments are not added to the `frag_map`
+        assert_eq!(sdb.frag_map.len(), unsplit_sdb.frag_map.len());
+
+        let mut sdb = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.max_fragment_len = Some(sdb.shmmr_spec.k);
+        let err = sdb
+            .load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn find_substring_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string())
+            .unwrap();
+        let seqs = (0..sdb.seqs.len() as u32)
+            .map(|sid| sdb.get_seq_by_id(sid))
+            .collect::<Vec<_>>();
+        let naive_find = |pattern: &[u8]| {
+            seqs.iter()
+                .enumerate()
+                .flat_map(|(sid, seq)| {
+                    seq.windows(pattern.len())
+                        .enumerate()
+                        .filter(|(_, w)| *w == pattern)
+                        .map(move |(pos, _)| (sid as u32, pos as u32))
+                })
+                .collect::<Vec<_>>()
+        };
+        let k = sdb.shmmr_spec.k as usize;
+        let patterns = [0_usize, 997, 2011, seqs[3].len() - 400]
+            .into_iter()
+            .flat_map(|pos| [6, 12, k, k + 1, 300].map(|len| seqs[3][pos..pos + len].to_vec()))
+            .collect::<Vec<_>>();
+
+        // a temporary index without `build_substring_index`
+        assert_eq!(sdb.find_substring(&patterns[1]), naive_find(&patterns[1]));
+        sdb.build_substring_index();
+        patterns.iter().for_each(|pattern| {
+            let hits = sdb.find_substring(pattern);
+            assert!(!hits.is_empty());
+            assert_eq!(hits, naive_find(pattern));
+        });
+        assert!(sdb.find_substring(b"ACGTXACGT").is_empty());
+        assert!(sdb.find_substring(b"").is_empty());
+    }
+
+    #[test]
+    fn packed_internal_frags_test() {
+        use seq_db::{pack_bases, unpack_bases, Fragment, GetSeq};
+        let seq = b"ACGTNNACGTTGCAN".to_vec();
+        let packed = pack_bases(&seq).unwrap();
+        assert_eq!(packed.1.len(), 4);
+        assert_eq!(packed.2, vec![4, 5, 14]);
+        assert_eq!(unpack_bases(&packed), seq);
+        assert!(pack_bases(b"ACGTacgt").is_none());
+        assert!(pack_bases(b"ACGTRY").is_none());
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let mut packed_bases = 0_usize;
+        let mut packed_bytes = 0_usize;
+        sdb.frags.as_ref().unwrap().iter().for_each(|f| {
+            if let Fragment::Internal2Bit(p) = f {
+                packed_bases += p.0 as usize;
+                packed_bytes += p.1.len() + 4 * p.2.len();
+            }
+        });
+        assert!(packed_bases > 0);
+        assert!(packed_bytes * 3 < packed_bases);
+        // the bytes stored if the internal fragments were kept one byte per base
+        let stats = sdb.stats();
+        let unpacked_stored_bytes = stats.stored_bytes - packed_bytes + packed_bases;
+        assert!(stats.stored_bytes < unpacked_stored_bytes);
+
+        let seqs = load_seqs();
+        sdb.seqs.iter().for_each(|s| {
+            assert_eq!(sdb.get_seq_by_id(s.id), *seqs.get(&s.name).unwrap());
+        });
+    }
+
+    #[test]
+    fn seq_db_concurrent_query_test() {
+        use seq_db::GetSeq;
+        use std::sync::Arc;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let queries = (0..8_u32)
+            .map(|sid| sdb.get_sub_seq_by_id(sid, 200, 2800))
+            .collect::<Vec<_>>();
+        let baseline = queries
+            .iter()
+            .map(|q| sdb.query(q, 0.5, false))
+            .collect::<Vec<_>>();
+
+        let sdb = Arc::new(sdb);
+        let queries = Arc::new(queries);
+        let handles = (0..4)
+            .map(|_| {
+                let sdb = Arc::clone(&sdb);
+                let queries = Arc::clone(&queries);
+                std::thread::spawn(move || {
+                    queries
+                        .iter()
+                        .map(|q| sdb.query(q, 0.5, false))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| {
+            assert_eq!(h.join().unwrap(), baseline);
+        });
+    }
+
+    #[test]
+    fn chains_to_sam_test() {
+        use crate::aln::{
+            chains_to_sam, query_fragment_to_hps, DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE,
+        };
+        use crate::fasta_io::reverse_complement;
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let target_seq = sdb.get_seq_by_id(0);
+        let mut query_seq = target_seq[500..3000].to_vec();
+        query_seq.drain(700..703);
+        query_seq.splice(1500..1500, b"ACGTA".iter().copied());
+        let k = sdb.shmmr_spec.k;
+
+        for (reversed, query_seq) in [
+            (false, query_seq.clone()),
+            (true, reverse_complement(&query_seq)),
+        ] {
+            let raw_query_hits =
+                seq_db::raw_query_fragment(&sdb.frag_map, &query_seq, &sdb.shmmr_spec);
+            let target_hits = query_fragment_to_hps(
+                raw_query_hits,
+                &query_seq,
+                &sdb.shmmr_spec,
+                0.5,
+                None,
+                None,
+                None,
+                None,
+                DEFAULT_MIN_CHAIN_SCORE,
+                DEFAULT_MIN_CHAIN_LEN,
+            );
+            let (_, chains) = target_hits.iter().find(|(sid, _)| *sid == 0).unwrap();
+            let sam = chains_to_sam("q", &query_seq, "t", &target_seq, &chains[..1], k);
+            assert_eq!(sam.len(), 1);
+            let fields = sam[0].split('\t').collect::<Vec<&str>>();
+            assert_eq!(fields.len(), 12);
+            assert_eq!(fields[1], if reversed { "16" } else { "0" });
+            let seq = fields[9].as_bytes();
+            assert_eq!(seq.len(), query_seq.len());
+
+            // every aligned base pair of the CIGAR should be identical
+            let mut t = fields[3].parse::<usize>().unwrap() - 1;
+            let mut q = 0_usize;
+            let mut n = 0_usize;
+            let mut n_indel = 0_usize;
+            fields[5].bytes().for_each(|c| {
+                if c.is_ascii_digit() {
+                    n = n * 10 + (c - b'0') as usize;
+                    return;
+                }
+                match c {
+                    b'M' => {
+                        assert_eq!(target_seq[t..t + n], seq[q..q + n]);
+                        t += n;
+                        q += n;
+                    }
+                    b'I' | b'S' => q += n,
+                    b'D' => t += n,
+                    _ => panic!("unexpected CIGAR op {}", c as char),
+                }
+                if c == b'I' || c == b'D' {
+                    n_indel += n;
+                }
+                n = 0;
+            });
+            assert_eq!(q, query_seq.len());
+            assert!(t <= target_seq.len());
+            assert_eq!(n_indel, 8);
+        }
+    }
+
+    #[test]
+    fn load_seqs_progress_test() {
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let mut progress = Vec::<(usize, String)>::new();
+        sdb.load_seqs_from_fastx_with_progress(
+            "test/test_data/test_seqs.fa".to_string(),
+            |count, name| progress.push((count, name.to_string())),
+        )
+        .unwrap();
+        assert_eq!(progress.len(), sdb.seqs.len());
+        progress
+            .iter()
+            .zip(sdb.seqs.iter())
+            .enumerate()
+            .for_each(|(i, (p, s))| {
+                assert_eq!(p.0, i + 1);
+                assert_eq!(p.1, s.name);
+            });
+    }
+
+    #[test]
+    fn deltas_to_cigar_test() {
+        use shmmrutils::{cigar_to_string, deltas_to_cigar};
+        let seqs = load_seqs();
+        let seq0 = seqs.values().next().unwrap()[..2000].to_vec();
+        let mut seq1 = seq0.clone();
+        seq1.drain(500..503);
+        seq1.splice(1200..1200, b"GATTACA".iter().copied());
+
+        let m = match_reads(&seq0, &seq1, true, 0.1, 0, 0, 32).unwrap();
+        let cigar = deltas_to_cigar(m.deltas.as_ref().unwrap(), m.end0, m.end1);
+        let len_of = |ops: &[char]| {
+            cigar
+                .iter()
+                .filter(|(op, _)| ops.contains(op))
+                .map(|(_, len)| len)
+                .sum::<u32>()
+        };
+        assert_eq!(len_of(&['M', 'D']), m.end0);
+        assert_eq!(len_of(&['M', 'I']), m.end1);
+        assert_eq!(len_of(&['D']), 3);
+        assert_eq!(len_of(&['I']), 7);
+        assert!(cigar.windows(2).all(|w| w[0].0 != w[1].0));
+
+        // the matched blocks are identical bases
+        let (mut x, mut y) = (0_usize, 0_usize);
+        cigar.iter().for_each(|&(op, len)| {
+            let len = len as usize;
+            match op {
+                'M' => {
+                    assert_eq!(seq0[x..x + len], seq1[y..y + len]);
+                    x += len;
+                    y += len;
+                }
+                'D' => x += len,
+                'I' => y += len,
+                _ => panic!("unexpected CIGAR op {}", op),
+            }
+        });
+
+        let cigar_str = cigar_to_string(&cigar);
+        assert!(cigar_str.starts_with(&format!("{}M", cigar[0].1)));
+        assert_eq!(
+            cigar_str.matches(|c: char| c.is_ascii_alphabetic()).count(),
+            cigar.len()
+        );
+    }
+
+    #[test]
+    fn high_freq_filter_test() {
+        use seq_db::{raw_query_fragment, raw_query_fragment_with_filter, GetSeq, HighFreqFilter};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let min_count = 16;
+        let filter = HighFreqFilter::new(&sdb.frag_map, min_count);
+        let mut n_high_freq = 0;
+        sdb.frag_map.iter().for_each(|(sp, v)| {
+            assert_eq!(filter.is_high_freq(sp), v.len() > min_count);
+            if v.len() > min_count {
+                n_high_freq += 1;
+            }
+        });
+        assert!(n_high_freq > 0);
+        assert_eq!(filter.len(), n_high_freq);
+
+        // a filter built on the empty DB is kept up to date by the loads and `add_seq`
+        let mut sdb2 = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb2.build_high_freq_filter(min_count);
+        let _ = sdb2.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let loaded_filter = sdb2.high_freq_filter.as_ref().unwrap();
+        assert_eq!(loaded_filter.len(), n_high_freq);
+        sdb.frag_map.keys().for_each(|sp| {
+            assert_eq!(loaded_filter.is_high_freq(sp), filter.is_high_freq(sp));
+        });
+        // all shimmer pairs of the copied sequence become frequent
+        let seq = sdb.get_seq_by_id(3);
+        (0..=min_count).for_each(|i| {
+            sdb2.add_seq(format!("copy_{}", i), seq.clone()).unwrap();
+        });
+        let rebuilt_filter = HighFreqFilter::new(&sdb2.frag_map, min_count);
+        let loaded_filter = sdb2.high_freq_filter.as_ref().unwrap();
+        assert!(rebuilt_filter.len() > n_high_freq);
+        assert_eq!(loaded_filter.len(), rebuilt_filter.len());
+        sdb2.frag_map.keys().for_each(|sp| {
+            assert_eq!(
+                loaded_filter.is_high_freq(sp),
+                rebuilt_filter.is_high_freq(sp)
+            );
+        });
+
+        let query_seq = sdb.get_seq_by_id(3);
+        let hits = raw_query_fragment(&sdb.frag_map, &query_seq, &sdb.shmmr_spec);
+        let filtered_hits = raw_query_fragment_with_filter(
+            &sdb.frag_map,
+            &query_seq,
+            &sdb.shmmr_spec,
+            Some(&filter),
+        );
+        assert_eq!(hits.len(), filtered_hits.len());
+        let mut n_skipped = 0;
+        hits.iter().zip(filtered_hits.iter()).for_each(|(h0, h1)| {
+            assert_eq!((h0.0, h0.1), (h1.0, h1.1));
+            if h0.2.len() > min_count {
+                assert!(h1.2.is_empty());
+                n_skipped += 1;
+            } else {
+                assert_eq!(h0.2, h1.2);
+            }
+        });
+        assert!(n_skipped > 0);
+
+        // nothing is skipped if no shimmer pair is above the count
+        let baseline = sdb.query(&query_seq, 0.5, false);
+        let max_count = sdb.frag_map.values().map(|v| v.len()).max().unwrap();
+        sdb.build_high_freq_filter(max_count);
+        assert_eq!(sdb.query(&query_seq, 0.5, false), baseline);
+    }
+
+    #[test]
+    fn prune_index_test() {
+        use seq_db::{raw_query_fragment, GetSeq};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let seqs = (0..sdb.seqs.len() as u32)
+            .map(|sid| sdb.get_seq_by_id(sid))
+            .collect::<Vec<_>>();
+        let frag_map = sdb.frag_map.clone();
+        assert_eq!(sdb.prune_index(1), 0);
+        let min_multiplicity = 3;
+        let n_pruned = frag_map
+            .values()
+            .filter(|v| v.len() < min_multiplicity as usize)
+            .count();
+        assert!(n_pruned > 0 && n_pruned < frag_map.len());
+        // query with a sequence having some of the pruned shimmer pairs
+        let query_sid = frag_map
+            .values()
+            .filter(|v| v.len() < min_multiplicity as usize)
+            .map(|v| v[0].1)
+            .min()
+            .unwrap();
+        let query_seq = seqs[query_sid as usize].clone();
+        let hits = raw_query_fragment(&sdb.frag_map, &query_seq, &sdb.shmmr_spec);
+        assert_eq!(sdb.prune_index(min_multiplicity), n_pruned);
+        assert_eq!(sdb.frag_map.len(), frag_map.len() - n_pruned);
+        assert!(sdb
+            .frag_map
+            .values()
+            .all(|v| v.len() >= min_multiplicity as usize));
+
+        // the reconstruction is not changed
+        (0..sdb.seqs.len() as u32).for_each(|sid| {
+            assert_eq!(sdb.get_seq_by_id(sid), seqs[sid as usize]);
+        });
+
+        // the queries miss the pruned shimmer pairs only
+        let pruned_hits = raw_query_fragment(&sdb.frag_map, &query_seq, &sdb.shmmr_spec);
+        assert_eq!(hits.len(), pruned_hits.len());
+        let mut n_missed = 0;
+        hits.iter().zip(pruned_hits.iter()).for_each(|(h0, h1)| {
+            assert_eq!((h0.0, h0.1), (h1.0, h1.1));
+            if h0.2.len() < min_multiplicity as usize {
+                assert!(h1.2.is_empty());
+                assert!(!sdb.frag_map.contains_key(&h0.0));
+                n_missed += 1;
+            } else {
+                assert_eq!(h0.2, h1.2);
+            }
+        });
+        assert!(n_missed > 0);
+        assert!(!sdb.query(&query_seq, 0.5, false).is_empty());
+    }
+
+    #[test]
+    fn target_coverage_test() {
+        use crate::aln::{query_fragment_to_hps, DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE};
+        use crate::fasta_io::reverse_complement;
+        use seq_db::{raw_query_fragment, GetSeq};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let k = sdb.shmmr_spec.k as usize;
+        let target_len = sdb.seqs[0].len;
+
+        let chains = vec![
+            // a forward chain
+            (
+                1.0,
+                vec![
+                    ((100, 200, 0), (1000, 1100, 0)),
+                    ((300, 400, 0), (1200, 1300, 0)),
+                ],
+            ),
+            // a reverse chain overlapping the next one
+            (
+                1.0,
+                vec![
+                    ((100, 200, 0), (2000, 2100, 1)),
+                    ((300, 400, 0), (1800, 1900, 1)),
+                ],
+            ),
+            (1.0, vec![((0, 100, 1), (1850, 2200, 1))]),
+            // the two runs of the chain overlap on the target
+            (
+                1.0,
+                vec![
+                    ((0, 100, 0), (1100, 1200, 0)),
+                    ((500, 600, 0), (1150, 1250, 1)),
+                ],
+            ),
+            // clipped to the end of the target
+            (
+                1.0,
+                vec![(
+                    (0, 100, 0),
+                    (target_len as u32 - 10, target_len as u32 + 50, 0),
+                )],
+            ),
+            (1.0, vec![]),
+        ];
+        let mut expected = vec![0_u32; target_len];
+        [
+            (1000 - k, 1300),
+            (1800 - k, 2100),
+            (1850 - k, 2200),
+            (1100 - k, 1250),
+            (target_len - 10 - k, target_len),
+        ]
+        .iter()
+        .for_each(|(bgn, end)| expected[*bgn..*end].iter_mut().for_each(|d| *d += 1));
+        let coverage = sdb.target_coverage(0, &chains);
+        assert_eq!(coverage, expected);
+        assert_eq!(coverage[1100], 2);
+        assert_eq!(coverage[1900], 2);
+        assert!(sdb.target_coverage(0, &[]).iter().all(|d| *d == 0));
+
+        // the chains of the reads from both strands of the target
+        let seq = sdb.get_seq_by_id(0);
+        let reads = [seq[..2400].to_vec(), reverse_complement(&seq[1000..])];
+        let chains = reads
+            .iter()
+            .flat_map(|read| {
+                let raw_query_hits = raw_query_fragment(&sdb.frag_map, read, &sdb.shmmr_spec);
+                query_fragment_to_hps(
+                    raw_query_hits,
+                    read,
+                    &sdb.shmmr_spec,
+                    0.5,
+                    None,
+                    None,
+                    None,
+                    None,
+                    DEFAULT_MIN_CHAIN_SCORE,
+                    DEFAULT_MIN_CHAIN_LEN,
+                )
+                .into_iter()
+                .filter(|(sid, _)| *sid == 0)
+                .flat_map(|(_, chains)| chains)
+                .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let coverage = sdb.target_coverage(0, &chains);
+        assert_eq!(coverage.len(), target_len);
+        assert!(coverage[500] >= 1);
+        assert!(coverage[1600] >= 2);
+        assert!(coverage[2500] >= 1);
+    }
+
+    #[test]
+    fn fragment_occurrences_test() {
+        use seq_db::{FragmentError, GetSeq};
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let k = sdb.shmmr_spec.k;
+        let seqs = sdb
+            .seqs
+            .iter()
+            .map(|s| {
+                (
+                    s.id,
+                    s.source.clone(),
+                    s.name.clone(),
+                    sdb.get_seq_by_id(s.id),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // the (sid, offset, bases) of each fragment
+        let frags = sdb.frags.as_ref().unwrap();
+        let mut frag_locations = Vec::<(u32, usize, Vec<u8>)>::new();
+        sdb.seqs.iter().for_each(|seq| {
+            let (frag_start, n_frags) = seq.seq_frag_range;
+            let mut pos = 0_usize;
+            (frag_start..frag_start + n_frags).for_each(|frag_id| {
+                let frag = &frags[frag_id as usize];
+                let offset = pos + frag.len(k) - frag.base_len() as usize;
+                let bases =
+                    seqs[seq.id as usize].3[offset..offset + frag.base_len() as usize].to_vec();
+                frag_locations.push((seq.id, offset, bases));
+                pos += frag.len(k);
+            });
+        });
+        assert_eq!(frag_locations.len(), frags.len());
+
+        // the index is updated as the sequences are added
+        let n_seqs = seqs.len() / 2;
+        let mut sdb2 = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb2.load_seqs_from_seq_vec(&seqs[..n_seqs].to_vec());
+        sdb2.build_fragment_occurrences().unwrap();
+        sdb2.load_seqs_from_seq_vec(&seqs[n_seqs..].to_vec());
+        assert!(sdb2.frag_occurrences.is_some());
+
+        let mut n_shared = 0;
+        (0..frag_locations.len() as u32)
+            .step_by(7)
+            .for_each(|frag_id| {
+                let (_, _, bases) = &frag_locations[frag_id as usize];
+                let expected = frag_locations
+                    .iter()
+                    .filter(|(_, _, b)| b == bases)
+                    .map(|(sid, offset, _)| (*sid, *offset))
+                    .collect::<Vec<_>>();
+                let occurrences = sdb2.fragment_occurrences(frag_id);
+                assert_eq!(occurrences, expected);
+                if occurrences.len() > 1 {
+                    n_shared += 1;
+                }
+            });
+        assert!(n_shared > 0);
+
+        // a temporary index without `build_fragment_occurrences`
+        (0..frag_locations.len() as u32)
+            .step_by(500)
+            .for_each(|frag_id| {
+                assert_eq!(
+                    sdb.fragment_occurrences(frag_id),
+                    sdb2.fragment_occurrences(frag_id)
+                );
+            });
+
+        let n_frags = frag_locations.len() as u32;
+        assert_eq!(
+            sdb2.try_fragment_occurrences(n_frags),
+            Err(FragmentError::NotFound(n_frags))
+        );
+    }
+
+    #[test]
+    fn sketch_test() {
+        use seq_db::{jaccard_estimate, GetSeq};
+        assert_eq!(jaccard_estimate(&[1, 2, 3, 4], &[2, 3, 4, 5]), 0.75);
+        // the two smallest hashes of the union are not shared
+        assert_eq!(jaccard_estimate(&[1, 3], &[2, 3, 4]), 0.0);
+        assert_eq!(jaccard_estimate(&[], &[1]), 0.0);
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let n_seqs = sdb.seqs.len() as u32;
+        let seq = sdb.get_seq_by_id(0);
+        let mut mutated = seq.clone();
+        [1000, 2000].into_iter().for_each(|p| {
+            mutated[p] = if mutated[p] == b'A' { b'C' } else { b'A' };
+        });
+        let random_seq = random_bases(1, seq.len());
+        sdb.load_seqs_from_seq_vec(&vec![
+            (n_seqs, None, "mutated".to_string(), mutated),
+            (n_seqs + 1, None, "random".to_string(), random_seq),
+        ]);
+
+        let size = 8;
+        let sketch = sdb.sketch(0, size);
+        assert_eq!(sketch.len(), size);
+        assert!(sketch.windows(2).all(|w| w[0] < w[1]));
+        // a smaller sketch is the head of a larger one
+        assert_eq!(sketch[..], sdb.sketch(0, 1000)[..size]);
+        assert_eq!(jaccard_estimate(&sketch, &sketch), 1.0);
+
+        let mutated_estimate = jaccard_estimate(&sketch, &sdb.sketch(n_seqs, size));
+        assert!(mutated_estimate >= 0.5);
+        assert_eq!(
+            jaccard_estimate(&sketch, &sdb.sketch(n_seqs + 1, size)),
+            0.0
+        );
+
+        // no bases to compute the shimmers on for an index-only DB
+        let mut index_db = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        index_db.load_index_from_seq_vec(&vec![(0, None, "seq".to_string(), seq)]);
+        assert!(index_db.try_sketch(0, size).is_err());
+    }
+
+    #[test]
+    fn merge_seq_db_test() {
+        use seq_db::{AlnSegment, CompactSeqDB, DuplicateNamePolicy, Fragment, GetSeq, SeqDbError};
+        let mut sdb = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let mut seqs = sdb
+            .seqs
+            .iter()
+            .map(|s| (s.name.clone(), sdb.get_seq_by_id(s.id)))
+            .collect::<Vec<_>>();
+        // a sequence in both DBs
+        seqs.push(seqs[0].clone());
+        let build = |seqs: &[(String, Vec<u8>)], compress_with_alignment: bool| {
+            let mut sdb = CompactSeqDB::new(seq_db::SHMMRSPEC);
+            sdb.duplicate_name_policy = Some(DuplicateNamePolicy::Rename);
+            sdb.compress_with_alignment = compress_with_alignment;
+            sdb.load_seqs_from_seq_vec(
+                &seqs
+                    .iter()
+                    .enumerate()
+                    .map(|(sid, (name, seq))| (sid as u32, None, name.clone(), seq.clone()))
+                    .collect(),
+            );
+            sdb
+        };
+        let n_seqs = seqs.len() / 2;
+        let mut merged = build(&seqs[..n_seqs], true);
+        merged.duplicate_name_policy = Some(DuplicateNamePolicy::Error);
+        // all internal fragments of `other` are literal
+        let other = build(&seqs[n_seqs..], false);
+        let n_frags = merged.frags.as_ref().unwrap().len();
+
+        // the name of the shared sequence is a duplicate
+        assert!(matches!(
+            merged.merge(other.clone()),
+            Err(SeqDbError::Parse(_))
+        ));
+        assert_eq!(merged.seqs.len(), n_seqs);
+        assert_eq!(merged.frags.as_ref().unwrap().len(), n_frags);
+
+        merged.duplicate_name_policy = Some(DuplicateNamePolicy::Rename);
+        merged.build_high_freq_filter(2);
+        merged.merge(other).unwrap();
+        assert_eq!(merged.seqs.len(), seqs.len());
+        let filter = merged.high_freq_filter.as_ref().unwrap();
+        assert_eq!(
+            filter.len(),
+            seq_db::HighFreqFilter::new(&merged.frag_map, 2).len()
+        );
+        seqs.iter().enumerate().for_each(|(sid, (_, seq))| {
+            assert_eq!(merged.get_seq_by_id(sid as u32), *seq);
+        });
+        let last = seqs.len() as u32 - 1;
+        assert_eq!(
+            merged.name_of(last),
+            Some(format!("{}_1", seqs[0].0).as_str())
+        );
+        assert_eq!(merged.id_of(&seqs[0].0), Some(0));
+
+        // the fragments of the shared sequence are stored as the copies of the ones of the DB
+        let frags = merged.frags.as_ref().unwrap();
+        let (frag_start, n_seq_frags) = merged.seqs[last as usize].seq_frag_range;
+        assert!(
+            (frag_start..frag_start + n_seq_frags).any(|frag_id| matches!(
+                &frags[frag_id as usize],
+                Fragment::AlnSegments((base_frag_id, false, _, aln_segs))
+                    if (*base_frag_id as usize) < n_frags && aln_segs[..] == [AlnSegment::FullMatch]
+            ))
+        );
+
+        // the fragment signatures are the ones of a DB built from all sequences
+        let sorted_frag_map = |sdb: &CompactSeqDB| {
+            let mut frag_map = sdb
+                .frag_map
+                .iter()
+                .map(|(shmmr_pair, sigs)| {
+                    let mut sigs = sigs.clone();
+                    sigs.sort_unstable();
+                    (*shmmr_pair, sigs)
+                })
+                .collect::<Vec<_>>();
+            frag_map.sort_unstable();
+            frag_map
+        };
+        assert_eq!(
+            sorted_frag_map(&merged),
+            sorted_frag_map(&build(&seqs, true))
+        );
+
+        let mut index_db = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        index_db.load_index_from_seq_vec(&vec![(0, None, "index".to_string(), seqs[0].1.clone())]);
+        assert!(merged.merge(index_db.clone()).is_err());
+
+        // a new DB takes an index, but not once it has the fragments of a load
+        let mut new_db = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        new_db.merge(index_db.clone()).unwrap();
+        assert!(new_db.frags.is_none());
+        assert_eq!(new_db.seqs.len(), 1);
+        let mut empty_db = CompactSeqDB::new(seq_db::SHMMRSPEC);
+        empty_db.load_seqs_from_seq_vec(&vec![]);
+        assert!(empty_db.frags.is_some());
+        assert!(empty_db.merge(index_db).is_err());
+        assert!(empty_db.seqs.is_empty());
+    }
+
+    #[test]
+    fn query_fragment_to_hps_defaults_test() {
+        use crate::aln::{
+            query_fragment_to_hps, DEFAULT_MAX_ALN_SPAN, DEFAULT_MAX_FRAG_COUNT,
+            DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE,
+        };
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let query_seq = sdb.get_seq_by_id(0);
+        let query = |max_count: Option<u32>, max_aln_span: Option<u32>| {
+            let raw_query_hits =
+                seq_db::raw_query_fragment(&sdb.frag_map, &query_seq, &sdb.shmmr_spec);
+            let mut res = query_fragment_to_hps(
+                raw_query_hits,
+                &query_seq,
+                &sdb.shmmr_spec,
+                0.5,
+                max_count,
+                max_count,
+                max_count,
+                max_aln_span,
+                DEFAULT_MIN_CHAIN_SCORE,
+                DEFAULT_MIN_CHAIN_LEN,
+            );
+            res.sort_by_key(|(sid, _)| *sid);
+            format!("{:?}", res)
+        };
+        assert_eq!(
+            query(None, None),
+            query(Some(DEFAULT_MAX_FRAG_COUNT), Some(DEFAULT_MAX_ALN_SPAN))
+        );
+        assert_ne!(query(None, None), query(Some(0), None));
+    }
+
+    #[test]
+    fn query_fragment_to_hps_strand_test() {
+        use crate::aln::{
+            chain_to_anchors, query_fragment_to_hps, Strand, DEFAULT_MIN_CHAIN_LEN,
+            DEFAULT_MIN_CHAIN_SCORE,
+        };
+        use crate::fasta_io::reverse_complement;
+        let target = random_bases(1, 20000);
+        let sdb = seq_db::CompactSeqDB::from_iter(
+            vec![("target".to_string(), target.clone())].into_iter(),
+            seq_db::SHMMRSPEC,
+        )
+        .unwrap();
+        let k = sdb.shmmr_spec.k;
+        let (t_offset, len) = (5000_u32, 5000_u32);
+        let fwd_query = target[t_offset as usize..(t_offset + len) as usize].to_vec();
+        let rev_query = reverse_complement(&fwd_query);
+        let query = |query_seq: &Vec<u8>| {
+            let raw_query_hits =
+                seq_db::raw_query_fragment(&sdb.frag_map, query_seq, &sdb.shmmr_spec);
+            let res = query_fragment_to_hps(
+                raw_query_hits,
+                query_seq,
+                &sdb.shmmr_spec,
+                0.5,
+                None,
+                None,
+                None,
+                None,
+                DEFAULT_MIN_CHAIN_SCORE,
+                DEFAULT_MIN_CHAIN_LEN,
+            );
+            assert_eq!(res.len(), 1);
+            assert_eq!(res[0].0, 0);
+            res[0]
+                .1
+                .iter()
+                .flat_map(|c| c.1.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let hps = query(&fwd_query);
+        assert!(!hps.is_empty());
+        hps.iter().for_each(|hp| {
+            assert_eq!(Strand::of(hp), Strand::Forward);
+            assert_eq!((hp.1 .0, hp.1 .1), (t_offset + hp.0 .0, t_offset + hp.0 .1));
+        });
+
+        // the k-mer ending at `q` on the reverse complemented query ends at
+        // `t_offset + len + k - q` on the target, and the shimmers of a pair are swapped
+        let hps = query(&rev_query);
+        assert!(!hps.is_empty());
+        hps.iter().for_each(|hp| {
+            assert_eq!(Strand::of(hp), Strand::Reverse);
+            let t_end = t_offset + len + k;
+            assert_eq!((hp.1 .0, hp.1 .1), (t_end - hp.0 .1, t_end - hp.0 .0));
+        });
+        assert!(chain_to_anchors(&hps)
+            .iter()
+            .all(|a| a.strand == Strand::Reverse));
+        assert_eq!(Strand::Reverse.as_char(), '-');
+        assert_eq!(Strand::Reverse.as_u8(), 1);
+    }
+
+    #[test]
+    fn canonical_shmmr_spec_test() {
+        use crate::fasta_io::reverse_complement;
+        use shmmrutils::shmmrs_from_seq;
+        use std::collections::HashSet;
+        let seqs = load_seqs();
+        let seq = seqs.values().next().unwrap().clone();
+        // the first k-mer of a sequence is not hashed, so both strands leave out the k-mers
+        // at the two ends
+        let fwd_seq = seq[..seq.len() - 1].to_vec();
+        let rc_seq = reverse_complement(&seq[1..]);
+        let mut spec = shmmrutils::ShmmrSpec {
+            w: 24,
+            k: 24,
+            r: 2,
+            min_span: 0,
+            sketch: true,
+            ..shmmrutils::ShmmrSpec::default()
+        };
+        let hashes = |seq: &[u8], spec: &shmmrutils::ShmmrSpec| {
+            shmmrs_from_seq(seq, spec)
+                .iter()
+                .map(|m| m.hash())
+                .collect::<HashSet<u64>>()
+        };
+        let fwd = hashes(&fwd_seq, &spec);
+        assert!(!fwd.is_empty());
+        assert_eq!(fwd, hashes(&rc_seq, &spec));
+
+        spec.canonical = false;
+        assert_ne!(hashes(&fwd_seq, &spec), hashes(&rc_seq, &spec));
+
+        // the hashing mode is kept in the shimmer map file
+        let mut sdb = seq_db::CompactSeqDB::new(spec.clone());
+        sdb.load_seqs_from_seq_vec(&vec![(0, None, "seq".to_string(), seq)]);
+        let prefix = std::env::temp_dir()
+            .join("pgr_db_forward_only_spec")
+            .to_string_lossy()
+            .to_string();
+        sdb.write_shmmr_map_index(prefix.clone()).unwrap();
+        let (loaded_spec, frag_map) = seq_db::read_mdb_file(prefix + ".mdb").unwrap();
+        assert_eq!(loaded_spec, spec);
+        assert_eq!(frag_map.len(), sdb.frag_map.len());
+    }
+
+    #[test]
+    fn custom_shmmr_hasher_test() {
+        use seq_db::GetSeq;
+        use shmmrutils::{sequence_to_shmmrs, sequence_to_shmmrs_with_hasher};
+        use shmmrutils::{DefaultShmmrHasher, ShmmrHasher};
+        use std::sync::Arc;
+
+        #[derive(Debug)]
+        struct RotatedHasher;
+        impl ShmmrHasher for RotatedHasher {
+            fn hash_id(&self) -> u32 {
+                7
+            }
+            fn hash_mmer(&self, mmer: (u64, u64)) -> u64 {
+                DefaultShmmrHasher.hash_mmer(mmer).rotate_left(17)
+            }
+        }
+
+        let seqs = load_seqs();
+        let mut names = seqs.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let seq_vec = names
+            .iter()
+            .take(8)
+            .enumerate()
+            .map(|(sid, name)| (sid as u32, None, name.clone(), seqs[name].clone()))
+            .collect::<Vec<_>>();
+        let query_seq = seq_vec[3].3[200..2200].to_vec();
+        let hit_spans = |sdb: &seq_db::CompactSeqDB| {
+            let mut spans = sdb
+                .try_query(&query_seq, 0.1, false)
+                .unwrap()
+                .iter()
+                .map(|h| (h.seq_id, h.query_start, h.target_start, h.target_end))
+                .collect::<Vec<_>>();
+            spans.sort();
+            spans
+        };
+
+        let mut sdb =
+            seq_db::CompactSeqDB::new_with_hasher(seq_db::SHMMRSPEC, Arc::new(RotatedHasher))
+                .unwrap();
+        assert_eq!(sdb.shmmr_spec.hash_id, 7);
+        sdb.load_seqs_from_seq_vec(&seq_vec);
+        let hits = hit_spans(&sdb);
+        assert!(hits.iter().any(|h| h.0 == 3));
+
+        // the shimmers of the other hash do not match the ones of the default hash
+        let custom =
+            sequence_to_shmmrs_with_hasher(0, &query_seq, &sdb.shmmr_spec, false, &RotatedHasher)
+                .unwrap();
+        let default = sequence_to_shmmrs(0, &query_seq, &seq_db::SHMMRSPEC, false);
+        assert_ne!(
+            custom.iter().map(|m| m.x).collect::<Vec<_>>(),
+            default.iter().map(|m| m.x).collect::<Vec<_>>()
+        );
+        let err = sequence_to_shmmrs_with_hasher(
+            0,
+            &query_seq,
+            &seq_db::SHMMRSPEC,
+            false,
+            &RotatedHasher,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        // a DB file loaded without its hash rejects the queries until the hash is set
+        let filepath = std::env::temp_dir().join("pgr_db_custom_hasher.sdb");
+        let filepath = filepath.to_string_lossy().to_string();
+        sdb.write_to_file(filepath.clone()).unwrap();
+        let mut sdb2 = seq_db::CompactSeqDB::load_from_file(filepath.clone()).unwrap();
+        assert_eq!(sdb2.shmmr_spec, sdb.shmmr_spec);
+        let err = sdb2.try_query(&query_seq, 0.1, false).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(sdb2.set_hasher(Arc::new(DefaultShmmrHasher)).is_err());
+        sdb2.set_hasher(Arc::new(RotatedHasher)).unwrap();
+        assert_eq!(hit_spans(&sdb2), hits);
+        assert_eq!(sdb2.get_seq_by_id(3), seq_vec[3].3);
+        let _ = std::fs::remove_file(filepath);
+
+        // the hash id is kept in the shimmer map file
+        let prefix = std::env::temp_dir()
+            .join("pgr_db_custom_hasher")
+            .to_string_lossy()
+            .to_string();
+        sdb.write_shmmr_map_index(prefix.clone()).unwrap();
+        let (loaded_spec, _) = seq_db::read_mdb_file(prefix + ".mdb").unwrap();
+        assert_eq!(loaded_spec, sdb.shmmr_spec);
+    }
+
+    #[test]
+    fn fasta_reader_from_reader_test() {
+        use crate::fasta_io::READER_SOURCE_NAME;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+        let input = b">a\nACGT\nAC\n>b\nGGT\n".to_vec();
+        let recs = FastaReader::from_reader(&input[..])
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(recs.len(), 2);
+        assert_eq!(recs[0].seq, b"ACGTAC".to_vec());
+        assert_eq!(recs[1].source, Some(READER_SOURCE_NAME.to_string()));
+
+        // a gzip-compressed stream is detected on the buffered bytes
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&input).unwrap();
+        let gz_bytes = encoder.finish().unwrap();
+        let gz_recs = FastaReader::from_reader(BufReader::new(&gz_bytes[..]))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(gz_recs.len(), 2);
+        recs.iter().zip(gz_recs.iter()).for_each(|(r0, r1)| {
+            assert_eq!(r0.id, r1.id);
+            assert_eq!(r0.seq, r1.seq);
+        });
+
+        let err = FastaReader::from_reader(&b"ACGT\n"[..]).err().unwrap();
+        assert!(err.to_string().starts_with(READER_SOURCE_NAME));
+    }
+
+    #[test]
+    fn fasta_reader_malformed_input_test() {
+        use crate::fasta_io::decompressed_reader;
+        use flate2::write::GzEncoder;
+        use std::io::{Cursor, Write};
+        let name = "malformed_input".to_string();
+        let read_all = |data: &[u8]| -> Result<usize, std::io::Error> {
+            let reader = decompressed_reader(Cursor::new(data.to_vec()), &name)?;
+            let reader = FastaReader::new(reader, &name, 1 << 10, false)?;
+            let mut n_recs = 0;
+            for rec in reader {
+                rec?;
+                n_recs += 1;
+            }
+            Ok(n_recs)
+        };
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b">a\nACGT\n>b\nAC\n").unwrap();
+        let gz_bytes = encoder.finish().unwrap();
+        assert_eq!(read_all(&gz_bytes).unwrap(), 2);
+        let mut corrupted = gz_bytes.clone();
+        let mid = corrupted.len() / 2;
+        corrupted[mid] ^= 0xFF;
+        assert!(read_all(&corrupted).is_err());
+        assert!(read_all(&gz_bytes[..gz_bytes.len() - 4]).is_err());
+        assert!(read_all(&[0x1F, 0x8B]).is_err());
+
+        [
+            &b""[..],
+            b"\n\n",
+            b"ACGT\n",
+            b">a\nAC>GT\n",
+            b"@r\nACGT\n",
+            b"@r\nACGT\n+\nII\n",
+            b"@r\nAC\n+\nII\nxyz\n",
+        ]
+        .iter()
+        .for_each(|data| assert!(read_all(data).is_err()));
+        [(&b">"[..], 0), (b"@", 0), (b">\n", 1), (b">a", 1)]
+            .iter()
+            .for_each(|(data, n_recs)| assert_eq!(read_all(data).unwrap(), *n_recs));
+
+        // the malformed files are reported by the readers of the files too
+        let filepath = std::env::temp_dir().join("pgr_db_malformed_input.fa");
+        std::fs::write(&filepath, b"ACGT\n").unwrap();
+        let filepath = filepath.to_string_lossy().to_string();
+        assert!(crate::ext::get_fastx_reader(filepath.clone()).is_err());
+        let _ = std::fs::remove_file(filepath);
+    }
+
+    #[test]
+    fn fasta_reader_name_filter_test() {
+        use std::collections::HashSet;
+        let seqs = load_seqs();
+        let filepath = "test/test_data/test_seqs.fa".to_string();
+        let names = FastaReader::new(
+            BufReader::new(File::open(&filepath).unwrap()),
+            &filepath,
+            1 << 14,
+            false,
+        )
+        .unwrap()
+        .map(|r| r.unwrap().id)
+        .collect::<Vec<_>>();
+        let selected = [names[3].clone(), names[40].clone()];
+
+        let reader = FastaReader::new(
+            BufReader::new(File::open(&filepath).unwrap()),
+            &filepath,
+            1 << 14,
+            false,
+        )
+        .unwrap()
+        .with_name_filter(selected.iter().cloned().collect::<HashSet<Vec<u8>>>());
+        let recs = reader.map(|r| r.unwrap()).collect::<Vec<_>>();
+        assert_eq!(recs.len(), 2);
+        recs.iter().zip(selected.iter()).for_each(|(rec, name)| {
+            assert_eq!(rec.id, *name);
+            assert_eq!(
+                rec.seq,
+                *seqs
+                    .get(&String::from_utf8_lossy(name).to_string())
+                    .unwrap()
+            );
+        });
+
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open("test/test_data/test_reads.fq").unwrap()),
+            &"test/test_data/test_reads.fq".to_string(),
+            1 << 14,
+            false,
+        )
+        .unwrap()
+        .with_name_filter(HashSet::new());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn fasta_reader_record_offsets_test() {
+        let filepath = "test/test_data/test_seqs.fa".to_string();
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open(&filepath).unwrap()),
+            &filepath,
+            1 << 14,
+            false,
+        )
+        .unwrap();
+        let recs = (&mut reader)
+            .take(2)
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        let offsets = reader.record_offsets().unwrap();
+        // the reading goes on from where it was before the scan
+        let rest = (&mut reader).map(|r| r.unwrap()).collect::<Vec<_>>();
+        assert_eq!(offsets.len(), recs.len() + rest.len());
+        recs.iter()
+            .chain(rest.iter())
+            .zip(offsets.iter())
+            .for_each(|(rec, (_, id))| assert_eq!(rec.id, *id));
+
+        let rec = reader.read_at(offsets[40].0).unwrap();
+        assert_eq!(rec.id, rest[38].id);
+        assert_eq!(rec.seq, rest[38].seq);
+        assert_eq!(reader.next().unwrap().unwrap().id, rest[39].id);
+        let last = reader.read_at(offsets.last().unwrap().0).unwrap();
+        assert_eq!(last.seq, rest.last().unwrap().seq);
+        assert!(reader.next().is_none());
+        let e = reader.read_at(offsets[1].0 + 1).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+        // a quality line starting with '@' is not a header
+        let filepath = "test/test_data/test_reads.fq".to_string();
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open(&filepath).unwrap()),
+            &filepath,
+            1 << 14,
+            false,
+        )
+        .unwrap();
+        let offsets = reader.record_offsets().unwrap();
+        let recs = (&mut reader).map(|r| r.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            offsets.iter().map(|(_, id)| id.clone()).collect::<Vec<_>>(),
+            recs.iter().map(|r| r.id.clone()).collect::<Vec<_>>()
+        );
+        let rec = reader.read_at(offsets[1].0).unwrap();
+        assert_eq!(rec.seq, recs[1].seq);
+        assert_eq!(rec.qual, recs[1].qual);
+
+        // the empty records are indexed and read even if they are skipped by the iteration
+        let filepath = "test/test_data/test_messy.fa".to_string();
+        let mut reader = FastaReader::new(
+            BufReader::new(File::open(&filepath).unwrap()),
+            &filepath,
+            1 << 14,
+            false,
+        )
+        .unwrap()
+        .with_skip_empty(true);
+        let offsets = reader.record_offsets().unwrap();
+        assert_eq!(offsets[1].1, b"empty0".to_vec());
+        assert!(reader.read_at(offsets[1].0).unwrap().seq.is_empty());
+        assert_eq!(reader.next().unwrap().unwrap().id, b"seq1".to_vec());
+    }
+
+    #[test]
+    fn seq_db_query_orient_to_query_test() {
+        use crate::fasta_io::reverse_complement;
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let fwd_query = sdb.get_sub_seq_by_id(0, 500, 3000);
+        let rev_query = reverse_complement(&fwd_query);
+        for (query_seq, strand) in [(fwd_query, 0_u8), (rev_query, 1_u8)] {
+            let hits = sdb.query(&query_seq, 0.5, true);
+            let hit = hits.iter().find(|h| h.seq_id == 0).unwrap();
+            assert_eq!(hit.strand, strand);
+            assert_eq!(
+                hit.target_seq.as_ref().unwrap()[..],
+                query_seq[hit.query_start as usize..hit.query_end as usize]
+            );
+            assert!(sdb
+                .query(&query_seq, 0.5, false)
+                .iter()
+                .all(|h| h.target_seq.is_none()));
+        }
+    }
+
+    #[test]
+    fn seq_db_index_only_test() {
+        use seq_db::GetSeq;
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        let _ = sdb.load_seqs_from_fastx("test/test_data/test_seqs.fa".to_string());
+        let tmp_dir = std::env::temp_dir();
+        let path = |name: &str| tmp_dir.join(name).to_string_lossy().to_string();
+        sdb.write_index(path("pgr_db_index_only.sdi")).unwrap();
+        sdb.write_to_file(path("pgr_db_index_only.sdb")).unwrap();
+        sdb.write_frags_to_mmap_store(path("pgr_db_index_only.mfs"))
+            .unwrap();
+        let index_size = std::fs::metadata(path("pgr_db_index_only.sdi"))
+            .unwrap()
+            .len();
+        let db_size = std::fs::metadata(path("pgr_db_index_only.sdb"))
+            .unwrap()
+            .len();
+        assert!(index_size < db_size);
+
+        let mut sdb_index =
+            seq_db::CompactSeqDB::load_index(path("pgr_db_index_only.sdi")).unwrap();
+        assert!(sdb_index.frags.is_none());
+        assert_eq!(sdb_index.seqs.len(), sdb.seqs.len());
+        assert_eq!(sdb_index.frag_map.len(), sdb.frag_map.len());
+        let query_seq = sdb.get_sub_seq_by_id(5, 100, 3000);
+        assert_eq!(
+            sdb_index.query(&query_seq, 0.5, false),
+            sdb.query(&query_seq, 0.5, false)
+        );
+        assert!(seq_db::CompactSeqDB::load_from_file(path("pgr_db_index_only.sdi")).is_err());
+
+        sdb_index
+            .load_frags_from_mmap_store(path("pgr_db_index_only.mfs"))
+            .unwrap();
+        assert_eq!(sdb_index.get_seq_by_id(5), sdb.get_seq_by_id(5));
+    }
+
+    #[test]
+    fn seq_db_masked_regions_test() {
+        use seq_db::GetSeq;
+        let seqs = load_seqs();
+        let mut names = seqs.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let seq_vec = names
+            .iter()
+            .take(4)
+            .enumerate()
+            .map(|(sid, name)| (sid as u32, None, name.clone(), seqs[name].clone()))
+            .collect::<Vec<_>>();
+        let (bgn, end) = (400_u32, 3200_u32);
+        let mut masks = seq_db::MaskedRegions::default();
+        masks.insert(names[0].clone(), vec![(1500, end), (bgn, 1600)]);
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.load_seqs_from_seq_vec_with_masks(&seq_vec, &masks)
+            .unwrap();
+        let mut sdb_unmasked = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb_unmasked.load_seqs_from_seq_vec(&seq_vec);
+
+        seq_vec.iter().for_each(|(sid, _, _, seq)| {
+            assert_eq!(sdb.get_seq_by_id(*sid), *seq);
+        });
+        let k = sdb.shmmr_spec.k;
+        sdb.frag_map.values().flatten().for_each(|sig| {
+            assert!(sig.1 != 0 || sig.3 <= bgn || end <= sig.2 - k);
+        });
+        assert!(
+            sdb.frag_map.values().flatten().count()
+                < sdb_unmasked.frag_map.values().flatten().count()
+        );
+
+        let query_seq = seq_vec[0].3[(bgn + 300) as usize..(end - 300) as usize].to_vec();
+        assert!(sdb_unmasked
+            .query(&query_seq, 0.5, false)
+            .iter()
+            .any(|hit| hit.seq_id == 0));
+        assert!(!sdb
+            .query(&query_seq, 0.5, false)
+            .iter()
+            .any(|hit| hit.seq_id == 0));
+    }
+
+    #[test]
+    fn low_complexity_mask_test() {
+        use seq_db::{low_complexity_intervals, raw_query_fragment, GetSeq, LowComplexityMask};
+        // unique flanks around a dinucleotide, a tetranucleotide and a homopolymer repeat
+        let seq_vec = (0..4_u32)
+            .map(|sid| {
+                let seed = 3 * sid as u64;
+                let seq = [
+                    random_bases(seed + 1, 1500),
+                    b"CA".repeat(600),
+                    random_bases(seed + 2, 1000),
+                    b"AAAT".repeat(300),
+                    vec![b'T'; 500],
+                    random_bases(seed + 3, 1500),
+                ]
+                .concat();
+                (sid, None, format!("seq_{}", sid), seq)
+            })
+            .collect::<Vec<_>>();
+        let lc_mask = LowComplexityMask::default();
+        let intervals = low_complexity_intervals(&seq_vec[0].3, &lc_mask);
+        assert_eq!(intervals.len(), 2);
+        assert!(intervals[0].0 <= 1500 && 2700 <= intervals[0].1);
+        assert!(intervals[1].0 <= 3700 && 5400 <= intervals[1].1);
+        assert!(low_complexity_intervals(&random_bases(13, 10000), &lc_mask).is_empty());
+
+        let mut sdb = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb.low_complexity_mask = Some(lc_mask);
+        sdb.load_seqs_from_seq_vec(&seq_vec);
+        let mut sdb_unmasked = seq_db::CompactSeqDB::new(seq_db::SHMMRSPEC);
+        sdb_unmasked.load_seqs_from_seq_vec(&seq_vec);
+        seq_vec.iter().for_each(|(sid, _, _, seq)| {
+            assert_eq!(sdb.get_seq_by_id(*sid), *seq);
+        });
+
+        // the index is smaller and a query looks up fewer fragments
+        let n_sigs = |sdb: &seq_db::CompactSeqDB| sdb.frag_map.values().flatten().count();
+        assert!(n_sigs(&sdb) < n_sigs(&sdb_unmasked));
+        let n_hits = |sdb: &seq_db::CompactSeqDB| {
+            raw_query_fragment(&sdb.frag_map, &seq_vec[0].3, &sdb.shmmr_spec)
+                .iter()
+                .map(|h| h.2.len())
+                .sum::<usize>()
+        };
+        assert!(n_hits(&sdb) < n_hits(&sdb_unmasked));
+        assert!(sdb
+            .query(&seq_vec[0].3, 0.5, false)
+            .iter()
+            .any(|hit| hit.seq_id == 0));
+    }
+
+    #[test]
+    fn match_reads_noisy_pair_test() {
+        use shmmrutils::{match_reads_with_params, MatchReadsParams};
+        let mut state = 1_u64;
+        let mut rand = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            state >> 33
+        };
+        let seq0 = (0..2000)
+            .map(|_| b"ACGT"[(rand() % 4) as usize])
+            .collect::<Vec<u8>>();
+        // 15% of the bases differ, 60% of them are substitutions, 20% insertions and 20% deletions
+        let mut seq1 = Vec::<u8>::new();
+        seq0.iter().for_each(|&b| {
+            let p = (rand() % 10000) as f64 / 10000.0;
+            if p < 0.15 * 0.6 {
+                let i = b"ACGT".iter().position(|&c| c == b).unwrap() as u64;
+                seq1.push(b"ACGT"[((i + 1 + rand() % 3) % 4) as usize]);
+            } else if p < 0.15 * 0.8 {
+                seq1.push(b);
+                seq1.push(b"ACGT"[(rand() % 4) as usize]);
+            } else if p >= 0.15 {
+                seq1.push(b);
+            }
+        });
+
+        let params = MatchReadsParams {
+            tolerance: 0.15,
+            ..Default::default()
+        };
+        let m = match_reads_with_params(&seq0, &seq1, &params).unwrap();
+        assert!(m.end0 as usize == seq0.len() || m.end1 as usize == seq1.len());
+        let cigar = shmmrutils::deltas_to_cigar(&m.deltas.unwrap(), m.end0, m.end1);
+        let aligned = |ops: &str| {
+            cigar
+                .iter()
+                .filter(|(op, _)| ops.contains(*op))
+                .map(|(_, len)| len)
+                .sum::<u32>()
+        };
+        assert_eq!(aligned("MD"), m.end0);
+        assert_eq!(aligned("MI"), m.end1);
+
+        let params = MatchReadsParams {
+            tolerance: 0.05,
+            ..Default::default()
+        };
+        assert!(match_reads_with_params(&seq0, &seq1, &params).is_none());
+    }
+
+    #[test]
+    fn match_reads_trim_to_anchors_test() {
+        use shmmrutils::{match_reads_with_params, MatchReadsParams};
+        let mut state = 7_u64;
+        let mut rand = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            state >> 33
+        };
+        let seq0 = (0..1000)
+            .map(|_| b"ACGT"[(rand() % 4) as usize])
+            .collect::<Vec<u8>>();
+        // the 10 bases of the head and the 20 bases of the tail all differ, with a substitution
+        // and an insertion in between
+        let mut seq1 = seq0.clone();
+        (0..10).chain(980..1000).for_each(|i| {
+            let c = b"ACGT".iter().position(|&c| c == seq1[i]).unwrap();
+            seq1[i] = b"ACGT"[(c + 1) % 4];
+        });
+        seq1[400] = if seq1[400] == b'A' { b'C' } else { b'A' };
+        seq1.insert(450, b'A');
+
+        let m = match_reads_with_params(&seq0, &seq1, &MatchReadsParams::default()).unwrap();
+        assert_eq!((m.bgn0, m.bgn1), (0, 0));
+        assert!(m.end0 > 980 && m.end1 > 980);
+
+        let params = MatchReadsParams {
+            trim_anchor_len: Some(16),
+            ..Default::default()
+        };
+        let t = match_reads_with_params(&seq0, &seq1, &params).unwrap();
+        assert!(t.bgn0 >= 10 && t.bgn1 >= 10 && t.end0 <= 980 && t.end1 <= 981);
+        assert!(t.end0 - t.bgn0 >= 940);
+        assert_eq!(
+            seq0[t.bgn0 as usize..t.bgn0 as usize + 16],
+            seq1[t.bgn1 as usize..t.bgn1 as usize + 16]
+        );
+        assert_eq!(
+            seq0[t.end0 as usize - 16..t.end0 as usize],
+            seq1[t.end1 as usize - 16..t.end1 as usize]
+        );
+        let deltas = t.deltas.as_ref().unwrap();
+        assert!(deltas.iter().all(|d| d.x > t.bgn0 && d.x < t.end0));
+        assert!(t.dist >= 3);
+        assert_eq!(m.trim_to_anchors(16).unwrap().end0, t.end0);
+        assert!(m.trim_to_anchors(2000).is_none());
+    }
 }
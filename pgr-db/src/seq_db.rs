@@ -1,13 +1,27 @@
 #[cfg(feature = "with_agc")]
 use crate::agc_io::AGCFile;
-use crate::fasta_io::{reverse_complement, FastaReader, SeqRec};
+use crate::aln::{
+    chain_query_clips, hit_run_to_base_span, query_fragment_to_hps,
+    query_fragment_to_hps_with_pair_count, split_chain_by_orientation, AlignmentHit, HitPair,
+    Strand, TargetHitPairLists, DEFAULT_MIN_CHAIN_LEN, DEFAULT_MIN_CHAIN_SCORE,
+};
+use crate::fasta_io::{
+    apply_soft_mask, get_decompressed_reader, reverse_complement, reverse_complement_in_place,
+    FastaReader, SeqRec,
+};
+use crate::frag_file_io::MmapFragStore;
 use crate::graph_utils::{AdjList, AdjPair, ShmmrGraphNode};
-use crate::shmmrutils::{match_reads, sequence_to_shmmrs, DeltaPoint, ShmmrSpec, MM128};
+/// the edits of the alignments, re-exported with the `AlnSegment` encoding built from them
+pub use crate::shmmrutils::DeltaPoint;
+use crate::shmmrutils::{
+    match_reads, match_reads_with_params, sequence_to_shmmrs, sequence_to_shmmrs_with_hasher,
+    Alphabet, DefaultShmmrHasher, MatchReadsParams, ShmmrHasher, ShmmrSpec,
+    DEFAULT_REDUCTION_LEVELS, DEFAULT_SHMMR_HASH_ID, MM128,
+};
 use bincode::{config, Decode, Encode};
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
-use flate2::bufread::MultiGzDecoder;
 use flate2::write::DeflateEncoder;
-use flate2::Compression;
+use flate2::{Compression, Crc};
 use memmap2::Mmap;
 use petgraph::graphmap::DiGraphMap;
 use petgraph::visit::Dfs;
@@ -15,10 +29,17 @@ use petgraph::EdgeDirection::{Incoming, Outgoing};
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
 
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::io::{self, BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
+/// the default k-mer size, a DB uses the `k` of its own `ShmmrSpec` to build and
+/// reconstruct the fragments, and the spec is stored with the DB files
 pub const KMERSIZE: u32 = 56;
 pub const SHMMRSPEC: ShmmrSpec = ShmmrSpec {
     w: 80,
@@ -26,25 +47,43 @@ pub const SHMMRSPEC: ShmmrSpec = ShmmrSpec {
     r: 4,
     min_span: 64,
     sketch: true,
+    canonical: true,
+    hash_id: DEFAULT_SHMMR_HASH_ID,
+    density: None,
+    alphabet: Alphabet::Nucleotide,
+    levels: DEFAULT_REDUCTION_LEVELS,
 };
 
+pub const SEQ_DB_FILE_VERSION: &str = "SDB:0.1";
+pub const SEQ_DB_INDEX_FILE_VERSION: &str = "SDI:0.1";
+/// the version of the .sdx files written by `write_to_frag_files`
+pub const SDX_FILE_VERSION: &str = "SDX:0.6";
+/// the version of the .sdx files written before the sequence metadata were extended, still
+/// readable
+pub const SDX_FILE_VERSION_0_5: &str = "SDX:0.5";
+
 pub type Bases = Vec<u8>;
 pub type AlnSegments = (u32, bool, u32, Vec<AlnSegment>); //(refFragID, orientation, SeqLength, AlnSegments)
+pub type PackedBases = (u32, Vec<u8>, Vec<u32>); //(SeqLength, 2-bit packed bases, positions of `N`)
+pub type NRunBases = (u32, Vec<(u32, u32)>, Bases); //(SeqLength, (position, length) of the `N` runs, the other bases)
 
-#[derive(Debug, Clone, Decode, Encode)]
+/// the `N` runs shorter than this are kept in the literal bases of the `InternalNRuns` fragments
+pub const MIN_N_RUN_LEN: u32 = 32;
+
+/// the edit model of the fragments stored as the alignments to a base fragment, a fragment is
+/// the concatenation of its segments, see `reconstruct_seq_from_aln_segs`, the substitutions
+/// and the deletions are the gaps between the `Match` ranges
+#[derive(Debug, Clone, PartialEq, Eq, Decode, Encode)]
 pub enum AlnSegment {
     // this still use a lot of space, we will find way to reduce the memory footprint later
+    /// the whole base fragment
     FullMatch,
     // u16 should be enough, the max span should be less than 128 * 144 = 18423 * 2 < 2**16
+    /// the bases `[bgn, end)` of the base fragment
     Match(u32, u32),
+    /// a base not in the base fragment
     Insertion(u8),
 }
-#[allow(clippy::large_enum_variant)]
-enum GZFastaReader {
-    GZFile(FastaReader<BufReader<MultiGzDecoder<BufReader<File>>>>),
-    RegularFile(FastaReader<BufReader<BufReader<File>>>),
-}
-
 #[derive(Debug, Clone, Decode, Encode)]
 pub enum Fragment {
     // size = 40, align = 8
@@ -52,6 +91,43 @@ pub enum Fragment {
     Prefix(Bases),
     Internal(Bases),
     Suffix(Bases),
+    // boxed to keep the size of the enum, only built for the bases in `ACGTN`
+    Internal2Bit(Box<PackedBases>),
+    // the internal fragments mostly in the `N` runs, e.g. the assembly gaps
+    InternalNRuns(Box<NRunBases>),
+}
+
+impl Fragment {
+    /// the bases of an internal fragment, unpacked if it is stored in the 2-bit form
+    pub fn internal_bases(&self) -> Option<Cow<'_, Bases>> {
+        match self {
+            Fragment::Internal(b) => Some(Cow::Borrowed(b)),
+            Fragment::Internal2Bit(p) => Some(Cow::Owned(unpack_bases(p))),
+            Fragment::InternalNRuns(r) => Some(Cow::Owned(unpack_n_runs(r))),
+            _ => None,
+        }
+    }
+
+    /// the number of bases in the fragment, including the leading k-mer overlap of
+    /// the internal fragments
+    pub fn base_len(&self) -> u32 {
+        match self {
+            Fragment::AlnSegments(d) => d.2,
+            Fragment::Prefix(b) | Fragment::Internal(b) | Fragment::Suffix(b) => b.len() as u32,
+            Fragment::Internal2Bit(p) => p.0,
+            Fragment::InternalNRuns(r) => r.0,
+        }
+    }
+
+    /// the number of bases the fragment adds to the reconstructed sequence, i.e. `base_len`
+    /// without the leading k-mer overlap of the internal fragments (including the ones stored
+    /// as `AlnSegments`), it does not decode the bases
+    pub fn len(&self, k: u32) -> usize {
+        match self {
+            Fragment::Prefix(b) | Fragment::Suffix(b) => b.len(),
+            _ => self.base_len().saturating_sub(k) as usize,
+        }
+    }
 }
 
 impl fmt::Display for Fragment {
@@ -65,23 +141,212 @@ impl fmt::Display for Fragment {
             Fragment::Prefix(b) => write!(f, "Seq:{} AlignSegs:None", String::from_utf8_lossy(b)),
             Fragment::Internal(b) => write!(f, "Seq:{} AlignSegs:None", String::from_utf8_lossy(b)),
             Fragment::Suffix(b) => write!(f, "Seq:{} AlignSegs:None", String::from_utf8_lossy(b)),
+            Fragment::Internal2Bit(p) => write!(
+                f,
+                "Seq:{} AlignSegs:None",
+                String::from_utf8_lossy(&unpack_bases(p))
+            ),
+            Fragment::InternalNRuns(r) => write!(
+                f,
+                "Seq:{} AlignSegs:None",
+                String::from_utf8_lossy(&unpack_n_runs(r))
+            ),
+        }
+    }
+}
+
+/// the problems of a fragment found by `CompactSeqDB::verify_fragment`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    /// the fragment is not in the DB or the fragments are not loaded
+    NotFound(u32),
+    /// an aligned fragment refers to a missing or a non-internal base fragment
+    BadBase { frag_id: u32, base_frag_id: u32 },
+    /// an alignment segment of an aligned fragment is out of its base fragment
+    BadSegments(u32),
+    /// the number of the bases differs from the length recorded in the fragment
+    LengthMismatch {
+        frag_id: u32,
+        expected: u32,
+        got: u32,
+    },
+    /// the CRC32 of the bases differs from the one recorded when the fragment is built
+    ChecksumMismatch {
+        frag_id: u32,
+        expected: u32,
+        got: u32,
+    },
+}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FragmentError::NotFound(frag_id) => write!(f, "fragment {} is not found", frag_id),
+            FragmentError::BadBase {
+                frag_id,
+                base_frag_id,
+            } => write!(
+                f,
+                "fragment {} refers to non-internal fragment {}",
+                frag_id, base_frag_id
+            ),
+            FragmentError::BadSegments(frag_id) => write!(
+                f,
+                "the alignment segments of fragment {} are out of the base fragment",
+                frag_id
+            ),
+            FragmentError::LengthMismatch {
+                frag_id,
+                expected,
+                got,
+            } => write!(
+                f,
+                "fragment {} has {} bases, expect {}",
+                frag_id, got, expected
+            ),
+            FragmentError::ChecksumMismatch {
+                frag_id,
+                expected,
+                got,
+            } => write!(
+                f,
+                "fragment {} has checksum {:08x}, expect {:08x}",
+                frag_id, got, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+impl From<FragmentError> for io::Error {
+    fn from(e: FragmentError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+    }
+}
+
+/// the errors of creating and loading a `CompactSeqDB`, they convert into `io::Error` for the
+/// callers returning `io::Result`, see `kind`
+#[derive(Debug)]
+pub enum SeqDbError {
+    /// reading a file fails, or a check of the settings fails with its `io::Error`, e.g. an
+    /// invalid `ShmmrSpec` with `InvalidInput`
+    Io(io::Error),
+    /// the input sequences can not be loaded, e.g. a malformed fastx record or a duplicate
+    /// name under `DuplicateNamePolicy::Error`
+    Parse(String),
+    /// a file is not a seq db (index) file of a supported version or it fails to decode
+    Format(String),
+    /// the loaded parts of a DB do not fit together, e.g. a sequence refers to the fragments
+    /// missing in the fragment store
+    Integrity(String),
+}
+
+impl SeqDbError {
+    /// the `io::ErrorKind` of the error, `InvalidData` for the errors other than `Io`
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            SeqDbError::Io(e) => e.kind(),
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for SeqDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeqDbError::Io(e) => write!(f, "{}", e),
+            SeqDbError::Parse(msg) => write!(f, "parse error: {}", msg),
+            SeqDbError::Format(msg) => write!(f, "format error: {}", msg),
+            SeqDbError::Integrity(msg) => write!(f, "integrity error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SeqDbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SeqDbError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SeqDbError {
+    fn from(e: io::Error) -> Self {
+        SeqDbError::Io(e)
+    }
+}
+
+impl From<FragmentError> for SeqDbError {
+    fn from(e: FragmentError) -> Self {
+        SeqDbError::Integrity(e.to_string())
+    }
+}
+
+impl From<SeqDbError> for io::Error {
+    fn from(e: SeqDbError) -> Self {
+        match e {
+            SeqDbError::Io(e) => e,
+            e => io::Error::new(e.kind(), e.to_string()),
         }
     }
 }
 
+/// a malformed fastx input (`InvalidData`) is a `Parse` error, e.g. a bad record or a corrupted
+/// gzip stream, the other errors of reading the input stay `Io` errors
+fn record_error(e: io::Error) -> SeqDbError {
+    if e.kind() == io::ErrorKind::InvalidData {
+        SeqDbError::Parse(e.to_string())
+    } else {
+        SeqDbError::Io(e)
+    }
+}
+
+/// the CRC32 of the bases of a fragment
+fn bases_crc(bases: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(bases);
+    crc.sum()
+}
+
 pub type ShmmrPair = (u64, u64);
 
 pub type Fragments = Vec<Fragment>;
 pub type FragmentSignature = (u32, u32, u32, u32, u8); //frg_id, seq_id, bgn, end, orientation(to shimmer pair)
 pub type ShmmrToFrags = FxHashMap<ShmmrPair, Vec<FragmentSignature>>;
+/// the half-open base intervals `[bgn, end)` excluded from the shimmer generation, by
+/// the sequence name
+pub type MaskedRegions = FxHashMap<String, Vec<(u32, u32)>>;
 pub type ShmmrIndexFileLocation = Vec<(ShmmrPair, (usize, usize))>;
 pub type ShmmrToIndexFileLocation = FxHashMap<ShmmrPair, (usize, usize)>;
 
+/// a source of fragments, the DB can look up the fragments from the in-memory vector
+/// or from a read-only file shared by many processes
+pub trait FragSource {
+    fn get_frag(&self, frag_id: u32) -> Option<Cow<'_, Fragment>>;
+    fn frag_count(&self) -> usize;
+}
+
+impl FragSource for Fragments {
+    fn get_frag(&self, frag_id: u32) -> Option<Cow<'_, Fragment>> {
+        self.get(frag_id as usize).map(Cow::Borrowed)
+    }
+
+    fn frag_count(&self) -> usize {
+        self.len()
+    }
+}
+
 pub trait GetSeq {
     fn get_seq_by_id(&self, sid: u32) -> Vec<u8>;
     fn get_sub_seq_by_id(&self, sid: u32, bgn: u32, end: u32) -> Vec<u8>;
 }
 
+/// the sequence ids are kept as the raw bytes of the records, an id that is valid UTF-8 is
+/// the `name`, otherwise the bytes are kept in `raw_name` and `name` is the lossy UTF-8 form
+/// for display, the name lookups and the written files use the raw bytes, so the ids survive
+/// the round trips, see `name_bytes`
 #[derive(Debug, Clone, Decode, Encode)]
 pub struct CompactSeq {
     pub source: Option<String>,
@@ -89,6 +354,192 @@ pub struct CompactSeq {
     pub id: u32,
     pub seq_frag_range: (u32, u32), // (start, len)
     pub len: usize,
+    /// the CRC32 of the bases at build time, see `CompactSeqDB::verify_all`, it is `None` for
+    /// the index-only sequences and the ones loaded from the files written without it
+    pub checksum: Option<u32>,
+    /// the lowercase (soft-masked) intervals `[bgn, end)` of the input, the bases are stored
+    /// uppercase, see `CompactSeqDB::reconstruct_with_mask`
+    pub soft_mask: Option<Vec<(u32, u32)>>,
+    /// the id bytes of the record when they are not valid UTF-8, `None` otherwise
+    pub raw_name: Option<Vec<u8>>,
+    /// the base counts of the input, see `CompactSeqDB::tally_base_composition`
+    pub base_composition: Option<BaseComposition>,
+}
+
+impl CompactSeq {
+    /// the raw bytes of the sequence id
+    pub fn name_bytes(&self) -> &[u8] {
+        self.raw_name.as_deref().unwrap_or(self.name.as_bytes())
+    }
+}
+
+/// the counts of the bases of a sequence for the QC of the inputs, a lowercase `n` is counted
+/// in both `n_count` and `lowercase_count`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Decode, Encode)]
+pub struct BaseComposition {
+    /// the number of the `N` and `n` bases
+    pub n_count: u32,
+    /// the number of the lowercase (soft-masked) bytes
+    pub lowercase_count: u32,
+    /// the number of the bytes other than `ACGTN` of either case, e.g. the IUPAC ambiguity
+    /// codes or the malformed input
+    pub other_count: u32,
+}
+
+impl BaseComposition {
+    /// count the bases of a sequence
+    pub fn of(seq: &[u8]) -> Self {
+        let mut composition = BaseComposition::default();
+        seq.iter().for_each(|&b| {
+            match b.to_ascii_uppercase() {
+                b'N' => composition.n_count += 1,
+                b'A' | b'C' | b'G' | b'T' => {}
+                _ => composition.other_count += 1,
+            }
+            if b.is_ascii_lowercase() {
+                composition.lowercase_count += 1;
+            }
+        });
+        composition
+    }
+}
+
+/// split the id of a record into the `name` and the `raw_name` of a `CompactSeq`
+pub fn seq_name_from_id(id: Vec<u8>) -> (String, Option<Vec<u8>>) {
+    match String::from_utf8(id) {
+        Ok(name) => (name, None),
+        Err(e) => (
+            String::from_utf8_lossy(e.as_bytes()).into_owned(),
+            Some(e.into_bytes()),
+        ),
+    }
+}
+
+/// the `CompactSeq` of the `SDX_FILE_VERSION_0_5` files
+#[derive(Decode)]
+struct CompactSeqV0 {
+    source: Option<String>,
+    name: String,
+    id: u32,
+    seq_frag_range: (u32, u32),
+    len: usize,
+}
+
+impl From<CompactSeqV0> for CompactSeq {
+    fn from(seq: CompactSeqV0) -> Self {
+        CompactSeq {
+            source: seq.source,
+            name: seq.name,
+            id: seq.id,
+            seq_frag_range: seq.seq_frag_range,
+            len: seq.len,
+            checksum: None,
+            soft_mask: None,
+            raw_name: None,
+            base_composition: None,
+        }
+    }
+}
+
+/// the layouts of the `CompactSeq` in the files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CompactSeqLayout {
+    V0,
+    Current,
+}
+
+/// decode the sequence metadata of a seq db, index or .sdx file, the fields missing in the
+/// `V0` layout of the `SDX_FILE_VERSION_0_5` files are decoded as `None`
+pub(crate) fn decode_compact_seqs<R: Read>(
+    in_file: &mut R,
+    layout: CompactSeqLayout,
+) -> Result<Vec<CompactSeq>, bincode::error::DecodeError> {
+    let config = config::standard();
+    match layout {
+        CompactSeqLayout::Current => bincode::decode_from_std_read(in_file, config),
+        CompactSeqLayout::V0 => {
+            bincode::decode_from_std_read::<Vec<CompactSeqV0>, _, _>(in_file, config)
+                .map(|seqs| seqs.into_iter().map(CompactSeq::from).collect())
+        }
+    }
+}
+
+/// the counts and the wall-clock times (in nanoseconds) of the phases of loading the sequences,
+/// accumulated when `CompactSeqDB::build_metrics` is set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildMetrics {
+    pub n_seqs: u64,
+    pub n_bases: u64,
+    /// the number of the fragments stored, including the prefixes and the suffixes
+    pub n_frags: u64,
+    /// the time of computing the shimmers of the sequences
+    pub shmmr_nanos: u64,
+    /// the time of cutting the sequences into the fragments, which includes `aln_nanos`
+    pub frag_nanos: u64,
+    /// the number of the `match_reads` calls aligning a fragment to an existing one
+    pub n_aln_calls: u64,
+    /// the number of the fragments stored as `Fragment::AlnSegments`
+    pub n_aln_frags: u64,
+    /// the time of the `match_reads` calls summed over the threads, so it can be more than
+    /// `frag_nanos` when the fragments are aligned in parallel
+    pub aln_nanos: u64,
+}
+
+impl BuildMetrics {
+    /// add the counts and the times of `other` to `self`
+    pub fn merge(&mut self, other: &BuildMetrics) {
+        self.n_seqs += other.n_seqs;
+        self.n_bases += other.n_bases;
+        self.n_frags += other.n_frags;
+        self.shmmr_nanos += other.shmmr_nanos;
+        self.frag_nanos += other.frag_nanos;
+        self.n_aln_calls += other.n_aln_calls;
+        self.n_aln_frags += other.n_aln_frags;
+        self.aln_nanos += other.aln_nanos;
+    }
+}
+
+/// what a load does with a sequence whose name is already in the DB, see
+/// `CompactSeqDB::duplicate_name_policy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateNamePolicy {
+    /// fail with `InvalidData`, the sequences loaded before the duplicate are kept
+    #[default]
+    Error,
+    /// load the sequence as `<name>_<n>` with the smallest `n` (from 1) not in the DB
+    Rename,
+    /// load the sequence with the name, `id_of` returns the first sequence with the name
+    Keep,
+}
+
+/// the FASTA files `CompactSeqDB::export_all` writes the sequences to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerFile {
+    /// one `<name>.fa` per sequence, with the name sanitized by `export_file_name`
+    Sequence,
+    /// all sequences in `seqs.fa` in the order of the sequence ids
+    Combined,
+}
+
+/// the file name of a sequence for `PerFile::Sequence`, the bytes other than the ASCII
+/// letters, digits, `.`, `-` and `_` are replaced by `_`, and a name starting with `.` (or an
+/// empty name) gets a leading `_`, so the file stays in the export directory
+pub fn export_file_name(name: &[u8]) -> String {
+    let mut file_name = name
+        .iter()
+        .map(|&c| {
+            if c.is_ascii_alphanumeric() || c == b'.' || c == b'-' || c == b'_' {
+                c as char
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if file_name.is_empty() || file_name.starts_with('.') {
+        file_name.insert(0, '_');
+    }
+    file_name.push_str(".fa");
+    file_name
 }
 
 #[derive(Debug, Clone)]
@@ -97,6 +548,417 @@ pub struct CompactSeqDB {
     pub seqs: Vec<CompactSeq>,
     pub frag_map: ShmmrToFrags,
     pub frags: Option<Fragments>,
+    /// the memory-mapped fragment store used for reconstruction when `frags` is not loaded
+    pub frag_store: Option<Arc<MmapFragStore>>,
+    /// the shimmer pairs skipped by the queries, see `build_high_freq_filter`, it is updated
+    /// as the sequences are added
+    pub high_freq_filter: Option<HighFreqFilter>,
+    /// the per-target counts of the shimmer pairs used by the queries, see
+    /// `build_pair_target_count`, it is dropped when a sequence is added
+    pub pair_target_count: Option<ShmmrPairTargetCount>,
+    /// the suffix array of the fragments for `find_substring`, see `build_substring_index`,
+    /// it is dropped when a sequence is added
+    pub substring_index: Option<SubstringIndex>,
+    /// the positions of the fragments for `fragment_occurrences`, see
+    /// `build_fragment_occurrences`, it is updated as the sequences are added
+    pub frag_occurrences: Option<FragmentOccurrences>,
+    /// the metrics of loading the sequences, only accumulated if it is not `None`
+    pub build_metrics: Option<BuildMetrics>,
+    /// the CRC32 of the bases of each fragment in the sequence orientation, including the
+    /// leading k-mer overlap of the internal fragments, see `enable_frag_crcs`
+    pub frag_crcs: Option<Vec<u32>>,
+    /// what the loads do with the names already in the DB, if it is not set, the loaders of
+    /// the sequences and the indexes keep the duplicates as they always did, and `from_iter`,
+    /// the `try_` loaders, `add_seq` and `merge` take the default `DuplicateNamePolicy::Error`
+    pub duplicate_name_policy: Option<DuplicateNamePolicy>,
+    /// align the new internal fragments to the existing ones of the same shimmer pair and
+    /// store them as `Fragment::AlnSegments` when it saves space, it is `true` by default,
+    /// turning it off skips the alignments for a faster build of a larger DB
+    pub compress_with_alignment: bool,
+    /// the most bases stored in a literal fragment, a longer one (e.g. in a low-complexity
+    /// region or an `N` run without shimmers) is split at every `max_fragment_len - k` bases
+    /// into the pieces stored as the internal fragments without a shimmer pair, it must be
+    /// larger than k, the fragments are not split if it is `None` (the default)
+    pub max_fragment_len: Option<u32>,
+    /// mask the low-complexity windows of the loaded sequences as the `MaskedRegions`, so the
+    /// clustered shimmers of e.g. the simple repeats are not in the `frag_map`, the windows
+    /// are stored as literal fragments, the sequences are not masked if it is `None` (the
+    /// default)
+    pub low_complexity_mask: Option<LowComplexityMask>,
+    /// count the `BaseComposition` of each loaded sequence into `CompactSeq::base_composition`,
+    /// it is `false` by default
+    pub tally_base_composition: bool,
+    /// the id of the first sequence of each name (the raw bytes), updated as the sequences
+    /// are loaded
+    name_to_id: FxHashMap<Vec<u8>, u32>,
+    /// the hash of the shimmers, it is `DefaultShmmrHasher` unless it is set by
+    /// `new_with_hasher` or `set_hasher`
+    hasher: Arc<dyn ShmmrHasher>,
+}
+
+fn build_name_to_id(seqs: &[CompactSeq]) -> FxHashMap<Vec<u8>, u32> {
+    let mut name_to_id = FxHashMap::<Vec<u8>, u32>::default();
+    seqs.iter().for_each(|s| {
+        name_to_id.entry(s.name_bytes().to_vec()).or_insert(s.id);
+    });
+    name_to_id
+}
+
+/// the shimmer pairs shared by more than `min_count` fragments
+///
+/// the set is exact, so the filtered queries keep all hits of the low-frequency shimmer
+/// pairs, a filter in a `CompactSeqDB` is updated as the sequences are added
+#[derive(Debug, Clone)]
+pub struct HighFreqFilter {
+    high_freq_pairs: FxHashSet<ShmmrPair>,
+    pub min_count: usize,
+}
+
+impl HighFreqFilter {
+    pub fn new(frag_map: &ShmmrToFrags, min_count: usize) -> Self {
+        let high_freq_pairs = frag_map
+            .iter()
+            .filter(|(_, v)| v.len() > min_count)
+            .map(|(k, _)| *k)
+            .collect::<FxHashSet<ShmmrPair>>();
+        HighFreqFilter {
+            high_freq_pairs,
+            min_count,
+        }
+    }
+
+    /// add the shimmer pair if its `count` of the fragments is above `min_count`
+    pub fn update(&mut self, shmmr_pair: &ShmmrPair, count: usize) {
+        if count > self.min_count {
+            self.high_freq_pairs.insert(*shmmr_pair);
+        }
+    }
+
+    /// the fragment signatures of a shimmer pair are skipped if it is in the filter
+    pub fn is_high_freq(&self, shmmr_pair: &ShmmrPair) -> bool {
+        self.high_freq_pairs.contains(shmmr_pair)
+    }
+
+    /// the number of the shimmer pairs in the filter
+    pub fn len(&self) -> usize {
+        self.high_freq_pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.high_freq_pairs.is_empty()
+    }
+}
+
+/// the number of the fragment signatures of each shimmer pair on each target sequence,
+/// keyed by `(shimmer_hash0, shimmer_hash1, seq_id)`
+pub type ShmmrPairTargetCount = FxHashMap<(u64, u64, u32), u32>;
+
+pub fn shmmr_pair_target_count(frag_map: &ShmmrToFrags) -> ShmmrPairTargetCount {
+    let mut pair_target_count = ShmmrPairTargetCount::default();
+    frag_map.iter().for_each(|(sp, v)| {
+        v.iter()
+            .for_each(|(_frg_id, sid, _bgn, _end, _orientation)| {
+                *pair_target_count.entry((sp.0, sp.1, *sid)).or_insert(0) += 1;
+            })
+    });
+    pair_target_count
+}
+
+/// the bottom-s MinHash estimate of the Jaccard index of two sketches of
+/// `CompactSeqDB::sketch`, i.e. the fraction of the `s` smallest hashes of the union in both
+/// sketches, `s` is the size of the smaller sketch, so the sketches should be taken with the
+/// same size, it is 0 if a sketch is empty
+pub fn jaccard_estimate(sketch_a: &[u64], sketch_b: &[u64]) -> f32 {
+    let size = sketch_a.len().min(sketch_b.len());
+    if size == 0 {
+        return 0.0;
+    }
+    let (mut i, mut j) = (0_usize, 0_usize);
+    let mut n_shared = 0_usize;
+    // each step takes the next smallest hash of the union
+    (0..size).for_each(|_| match sketch_a[i].cmp(&sketch_b[j]) {
+        std::cmp::Ordering::Less => i += 1,
+        std::cmp::Ordering::Greater => j += 1,
+        std::cmp::Ordering::Equal => {
+            n_shared += 1;
+            i += 1;
+            j += 1;
+        }
+    });
+    n_shared as f32 / size as f32
+}
+
+/// a suffix array over the distinct fragments of a DB for the exact substring search, see
+/// `CompactSeqDB::find_substring`
+///
+/// each fragment is indexed in the sequence orientation with the k bases before it, so a
+/// pattern of up to `k + 1` bases is within an indexed fragment wherever it is along a
+/// sequence, the fragments of the same bases are indexed once, it takes about 5 bytes per
+/// indexed base
+#[derive(Debug, Clone)]
+pub struct SubstringIndex {
+    /// the indexed bases of the distinct fragments, each followed by a `0`
+    text: Vec<u8>,
+    suffix_array: Vec<u32>,
+    /// the offset of each distinct fragment in `text`
+    frag_offsets: Vec<u32>,
+    /// the `(seq_id, the position of the indexed bases, the number of the indexed bases
+    /// before the fragment)` of the copies of each distinct fragment
+    frag_locations: Vec<Vec<(u32, u32, u32)>>,
+    pub k: u32,
+}
+
+impl SubstringIndex {
+    /// index the fragments of all sequences, it panics if the fragments are not loaded or
+    /// the indexed bases do not fit in the `u32` offsets
+    pub fn new(sdb: &CompactSeqDB) -> Self {
+        let k = sdb.shmmr_spec.k;
+        let frags = sdb.frag_source().unwrap_or_else(|e| panic!("{}", e));
+        let mut text = Vec::<u8>::new();
+        let mut frag_offsets = Vec::<u32>::new();
+        let mut frag_locations = Vec::<Vec<(u32, u32, u32)>>::new();
+        let mut distinct_frags = FxHashMap::<Vec<u8>, u32>::default();
+        sdb.seqs.iter().for_each(|seq| {
+            let bases = sdb.get_seq_by_id(seq.id);
+            let (frag_start, n_frags) = seq.seq_frag_range;
+            let mut pos = 0_usize;
+            (frag_start..frag_start + n_frags).for_each(|frag_id| {
+                let frag_len = frags.get_frag(frag_id).unwrap().len(k);
+                let bgn = pos.saturating_sub(k as usize);
+                let frag_bases = &bases[bgn..pos + frag_len];
+                let distinct_id = match distinct_frags.get(frag_bases) {
+                    Some(distinct_id) => *distinct_id,
+                    None => {
+                        let distinct_id = frag_offsets.len() as u32;
+                        frag_offsets.push(text.len() as u32);
+                        text.extend_from_slice(frag_bases);
+                        text.push(0);
+                        assert!(
+                            text.len() <= u32::MAX as usize,
+                            "too many bases for the substring index"
+                        );
+                        frag_locations.push(vec![]);
+                        distinct_frags.insert(frag_bases.to_vec(), distinct_id);
+                        distinct_id
+                    }
+                };
+                frag_locations[distinct_id as usize].push((seq.id, bgn as u32, (pos - bgn) as u32));
+                pos += frag_len;
+            });
+        });
+        drop(distinct_frags);
+        let suffix_array = suffix_array(&text);
+        SubstringIndex {
+            text,
+            suffix_array,
+            frag_offsets,
+            frag_locations,
+            k,
+        }
+    }
+
+    /// the `(seq_id, pos)` of the occurrences of a pattern of at most `k + 1` bases in the
+    /// order of the sequences and the positions, the longer patterns are only found within
+    /// a fragment
+    fn find(&self, pattern: &[u8]) -> Vec<(u32, u32)> {
+        if pattern.is_empty() || pattern.contains(&0) {
+            return vec![];
+        }
+        let prefix = |i: &u32| {
+            let suffix = &self.text[*i as usize..];
+            &suffix[..suffix.len().min(pattern.len())]
+        };
+        let bgn = self.suffix_array.partition_point(|i| prefix(i) < pattern);
+        let end = self.suffix_array.partition_point(|i| prefix(i) <= pattern);
+        let mut hits = Vec::<(u32, u32)>::new();
+        self.suffix_array[bgn..end].iter().for_each(|&i| {
+            let distinct_id = self.frag_offsets.partition_point(|&o| o <= i) - 1;
+            let offset = i - self.frag_offsets[distinct_id];
+            self.frag_locations[distinct_id]
+                .iter()
+                .for_each(|&(seq_id, pos, overlap)| {
+                    // an occurrence within the bases before the fragment is found in the
+                    // fragments before it
+                    if offset + pattern.len() as u32 > overlap {
+                        hits.push((seq_id, pos + offset));
+                    }
+                });
+        });
+        hits.sort_unstable();
+        hits
+    }
+}
+
+/// the positions of the fragments in the sequences and the fragments of the same bases for
+/// `fragment_occurrences`, the fragments are grouped by the CRC32 of their bases in the
+/// sequence orientation, including the leading k-mer overlap of the internal fragments, it
+/// takes about 8 bytes per fragment and 4 bytes per fragment in the groups
+#[derive(Debug, Clone, Default)]
+pub struct FragmentOccurrences {
+    /// the `(seq_id, offset)` of each fragment, the fragments of the index-only sequences
+    /// are not indexed
+    positions: Vec<(u32, u32)>,
+    /// the fragments of each `(CRC32, base_len)` of the bases
+    groups: FxHashMap<(u32, u32), Vec<u32>>,
+}
+
+impl FragmentOccurrences {
+    /// index the fragments of all sequences, the fragments are decoded for their
+    /// checksums unless `frag_crcs` is set
+    pub fn new(sdb: &CompactSeqDB) -> Result<Self, FragmentError> {
+        let mut occurrences = FragmentOccurrences::default();
+        sdb.seqs
+            .iter()
+            .try_for_each(|seq| occurrences.add_seq(sdb, seq))?;
+        Ok(occurrences)
+    }
+
+    /// index the fragments of a sequence, they are the last ones in the DB as a sequence is
+    /// added
+    fn add_seq(&mut self, sdb: &CompactSeqDB, seq: &CompactSeq) -> Result<(), FragmentError> {
+        let Ok(frags) = sdb.frag_source() else {
+            return Ok(());
+        };
+        let k = sdb.shmmr_spec.k;
+        let (frag_start, n_frags) = seq.seq_frag_range;
+        let frag_end = (frag_start + n_frags) as usize;
+        if self.positions.len() < frag_end {
+            self.positions.resize(frag_end, (u32::MAX, 0));
+        }
+        let mut pos = 0_usize;
+        (frag_start..frag_start + n_frags).try_for_each(|frag_id| {
+            let frag = frags
+                .get_frag(frag_id)
+                .ok_or(FragmentError::NotFound(frag_id))?;
+            let base_len = frag.base_len();
+            let frag_len = frag.len(k);
+            let offset = pos.saturating_sub(base_len as usize - frag_len);
+            self.positions[frag_id as usize] = (seq.id, offset as u32);
+            let crc = match sdb.frag_crcs.as_ref().and_then(|c| c.get(frag_id as usize)) {
+                Some(&crc) => crc,
+                None => bases_crc(&sdb.fragment_bases(frag_id)?),
+            };
+            self.groups
+                .entry((crc, base_len))
+                .or_default()
+                .push(frag_id);
+            pos += frag_len;
+            Ok(())
+        })
+    }
+
+    /// the `(seq_id, offset)` of the fragments of the same bases as `frag_id`, including
+    /// itself, in the order of the sequences and the offsets
+    fn occurrences(
+        &self,
+        sdb: &CompactSeqDB,
+        frag_id: u32,
+    ) -> Result<Vec<(u32, usize)>, FragmentError> {
+        if !matches!(self.positions.get(frag_id as usize), Some(&(sid, _)) if sid != u32::MAX) {
+            return Err(FragmentError::NotFound(frag_id));
+        }
+        let bases = sdb.fragment_bases(frag_id)?;
+        let crc = match sdb.frag_crcs.as_ref().and_then(|c| c.get(frag_id as usize)) {
+            Some(&crc) => crc,
+            None => bases_crc(&bases),
+        };
+        let mut occurrences = Vec::<(u32, usize)>::new();
+        for &copy_id in self.groups[&(crc, bases.len() as u32)].iter() {
+            // the bases are compared for the fragments of a colliding checksum
+            if copy_id == frag_id || sdb.fragment_bases(copy_id)? == bases {
+                let (sid, offset) = self.positions[copy_id as usize];
+                occurrences.push((sid, offset as usize));
+            }
+        }
+        occurrences.sort_unstable();
+        Ok(occurrences)
+    }
+}
+
+/// the suffix array of the text by prefix doubling, the suffixes are sorted in
+/// O(n log^2 n) time with two `u32` per base in addition to the suffix array
+fn suffix_array(text: &[u8]) -> Vec<u32> {
+    let n = text.len();
+    let mut suffix_array = (0..n as u32).collect::<Vec<u32>>();
+    let mut rank = text.iter().map(|&b| b as u32).collect::<Vec<u32>>();
+    let mut next_rank = vec![0_u32; n];
+    let mut h = 1_usize;
+    while h < n {
+        // the ranks of the first `h` bases and then of the next `h` bases, the suffixes
+        // shorter than `h` bases come first
+        let key = |rank: &[u32], i: u32| {
+            let i = i as usize;
+            (rank[i], rank.get(i + h).map_or(0, |r| r + 1))
+        };
+        suffix_array.par_sort_unstable_by_key(|&i| key(&rank, i));
+        next_rank[suffix_array[0] as usize] = 0;
+        (1..n).for_each(|j| {
+            let new_rank = key(&rank, suffix_array[j - 1]) != key(&rank, suffix_array[j]);
+            next_rank[suffix_array[j] as usize] =
+                next_rank[suffix_array[j - 1] as usize] + new_rank as u32;
+        });
+        std::mem::swap(&mut rank, &mut next_rank);
+        if rank[suffix_array[n - 1] as usize] as usize == n - 1 {
+            break;
+        }
+        h <<= 1;
+    }
+    suffix_array
+}
+
+/// a run of the fragment-level diff of two sequences from `CompactSeqDB::fragment_diff`,
+/// the fragment ids are the ids of the fragments of each sequence and the spans
+/// `(bgn, end)` are the approximate base coordinates covered by the fragments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffOp {
+    /// the fragments with the same shimmer pairs and lengths, or the same bases if they are
+    /// not indexed, in both sequences
+    Shared {
+        frags_a: Vec<u32>,
+        span_a: (u32, u32),
+        frags_b: Vec<u32>,
+        span_b: (u32, u32),
+    },
+    /// the fragments of `a` not in `b`, `pos_b` is where they are missing in `b`
+    Deletion {
+        frags_a: Vec<u32>,
+        span_a: (u32, u32),
+        pos_b: u32,
+    },
+    /// the fragments of `b` not in `a`, `pos_a` is where they are missing in `a`
+    Insertion {
+        frags_b: Vec<u32>,
+        span_b: (u32, u32),
+        pos_a: u32,
+    },
+}
+
+/// the key to compare the fragments of two sequences
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FragKey {
+    // the shimmer pair with the orientation and the length of an indexed fragment
+    Shmmr(ShmmrGraphNode, u32),
+    Bases(Vec<u8>),
+}
+
+/// the summary of the fragment de-duplication of a `CompactSeqDB`
+#[derive(Debug, Clone, Default)]
+pub struct DbStats {
+    pub n_seqs: usize,
+    /// the total number of bases of the input sequences
+    pub total_bases: usize,
+    pub n_frags: usize,
+    pub n_prefix_suffix_frags: usize,
+    pub n_internal_frags: usize,
+    pub n_aln_segment_frags: usize,
+    /// the number of the distinct shimmer pairs
+    pub n_shmmr_pairs: usize,
+    /// the number of the fragment signatures referred by the shimmer pairs
+    pub n_frag_refs: usize,
+    /// the bytes of the literal bases plus the bytes of the alignment segments
+    pub stored_bytes: usize,
+    /// `total_bases / stored_bytes`
+    pub compression_ratio: f64,
 }
 
 pub fn pair_shmmrs(shmmrs: &Vec<MM128>) -> Vec<(&MM128, &MM128)> {
@@ -110,12 +972,19 @@ pub fn pair_shmmrs(shmmrs: &Vec<MM128>) -> Vec<(&MM128, &MM128)> {
     shmmr_pairs
 }
 
+/// encode `frg` as the edits of `base_frg` from the delta points of a `match_reads`
+/// alignment of `frg` (`seq1`) to `base_frg` (`seq0`), `endx` and `endy` are the
+/// `OvlpMatch::end0` and `OvlpMatch::end1` of the alignment
+///
+/// the alignment has to start at the starts of both, the bases of `frg` after `endy` are
+/// encoded as the insertions and the ones of `base_frg` after `endx` are dropped, see
+/// `aln_segs_between` for the alignment checked by the reconstruction
 pub fn deltas_to_aln_segs(
-    deltas: &Vec<DeltaPoint>,
+    deltas: &[DeltaPoint],
     endx: usize,
     endy: usize,
-    base_frg: &Vec<u8>,
-    frg: &Vec<u8>,
+    base_frg: &[u8],
+    frg: &[u8],
 ) -> Vec<AlnSegment> {
     let mut aln_segs = Vec::<AlnSegment>::new();
     if deltas.is_empty() && base_frg.len() == frg.len() {
@@ -155,64 +1024,606 @@ pub fn deltas_to_aln_segs(
     aln_segs
 }
 
+/// the sequence encoded by the segments relative to `base_seq`, it panics if a `Match` is out
+/// of `base_seq`, see `try_reconstruct_seq_from_aln_segs`
 pub fn reconstruct_seq_from_aln_segs(base_seq: &[u8], aln_segs: &[AlnSegment]) -> Vec<u8> {
+    try_reconstruct_seq_from_aln_segs(base_seq, aln_segs).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// the same as `reconstruct_seq_from_aln_segs`, but it fails with `InvalidData` if a `Match`
+/// is not a range of `base_seq`, e.g. the segments are decoded against another base
+pub fn try_reconstruct_seq_from_aln_segs(
+    base_seq: &[u8],
+    aln_segs: &[AlnSegment],
+) -> Result<Vec<u8>, io::Error> {
     let mut seq = Vec::<u8>::new();
-    for s in aln_segs.iter() {
-        match s {
-            AlnSegment::FullMatch => {
-                seq.extend_from_slice(base_seq);
-            }
-            AlnSegment::Match(x1, x2) => {
-                seq.extend_from_slice(&base_seq[*x1 as usize..*x2 as usize]);
-            }
-            AlnSegment::Insertion(c) => {
-                seq.push(*c);
-            }
-        }
+    try_reconstruct_seq_from_aln_segs_into(base_seq, aln_segs, &mut seq)?;
+    Ok(seq)
+}
+
+/// the same as `try_reconstruct_seq_from_aln_segs`, but the sequence is appended to `out`,
+/// `out` is not changed if it fails
+pub fn try_reconstruct_seq_from_aln_segs_into(
+    base_seq: &[u8],
+    aln_segs: &[AlnSegment],
+    out: &mut Vec<u8>,
+) -> Result<(), io::Error> {
+    if let Some(AlnSegment::Match(x1, x2)) = aln_segs.iter().find(|s| match s {
+        AlnSegment::Match(x1, x2) => x1 > x2 || *x2 as usize > base_seq.len(),
+        _ => false,
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the segment Match({}, {}) is not in the base sequence of {} bases",
+                x1,
+                x2,
+                base_seq.len()
+            ),
+        ));
     }
-    seq
+    aln_segs.iter().for_each(|s| match s {
+        AlnSegment::FullMatch => out.extend_from_slice(base_seq),
+        AlnSegment::Match(x1, x2) => out.extend_from_slice(&base_seq[*x1 as usize..*x2 as usize]),
+        AlnSegment::Insertion(c) => out.push(*c),
+    });
+    Ok(())
 }
 
-impl CompactSeqDB {
-    pub fn new(shmmr_spec: ShmmrSpec) -> Self {
-        let seqs = Vec::<CompactSeq>::new();
-        let frag_map = ShmmrToFrags::default();
-        let frags = None;
-        CompactSeqDB {
-            shmmr_spec,
-            seqs,
-            frag_map,
-            frags,
-        }
+/// encode `seq` as the edits of `base_seq` by aligning them with `match_reads` with the
+/// parameters of the fragment compression, `None` if the two are not aligned end to end
+/// within the tolerance, the segments returned always reconstruct `seq`
+///
+/// e.g. to store the variants of a sequence relative to a reference contig outside a DB
+pub fn aln_segs_between(base_seq: &[u8], seq: &[u8]) -> Option<Vec<AlnSegment>> {
+    if base_seq.is_empty() || seq.is_empty() {
+        return None;
     }
+    let m = match_reads_with_params(base_seq, seq, &MatchReadsParams::default())?;
+    if m.bgn0 != 0 || m.bgn1 != 0 {
+        return None;
+    }
+    let deltas = m.deltas.unwrap_or_default();
+    let aln_segs = deltas_to_aln_segs(&deltas, m.end0 as usize, m.end1 as usize, base_seq, seq);
+    if reconstruct_seq_from_aln_segs(base_seq, &aln_segs) != seq {
+        return None;
+    }
+    Some(aln_segs)
+}
 
-    pub fn seq_to_compressed(
-        &mut self,
-        source: Option<String>,
-        name: String,
-        id: u32,
-        seq: &Vec<u8>,
-        shmmrs: Vec<MM128>,
-        try_compress: bool,
-    ) -> CompactSeq {
-        let mut seq_frags = Vec::<u32>::new();
+/// pack the bases into 2-bit codes (A:0, C:1, G:2, T:3), four bases per byte,
+/// with the positions of `N` kept in a side list, returns `None` for any other byte
+/// (e.g. soft-masked or IUPAC bases) so the caller can keep the byte form
+pub fn pack_bases(seq: &[u8]) -> Option<PackedBases> {
+    let mut packed = vec![0_u8; (seq.len() + 3) >> 2];
+    let mut n_pos = Vec::<u32>::new();
+    for (i, c) in seq.iter().enumerate() {
+        let code = match c {
+            b'A' => 0_u8,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            b'N' => {
+                n_pos.push(i as u32);
+                0
+            }
+            _ => return None,
+        };
+        packed[i >> 2] |= code << ((i & 0x03) << 1);
+    }
+    Some((seq.len() as u32, packed, n_pos))
+}
 
-        assert!(self.frags.is_some());
-        let frags: &mut Vec<Fragment> = self.frags.as_mut().unwrap();
+pub fn unpack_bases(packed: &PackedBases) -> Bases {
+    let mut seq = Bases::with_capacity(packed.0 as usize);
+    unpack_bases_into(packed, &mut seq);
+    seq
+}
 
-        let mut frg_id = frags.len() as u32;
-        let mut seq_len = 0_usize;
+/// the same as `unpack_bases`, but the bases are appended to `out`
+pub fn unpack_bases_into(packed: &PackedBases, out: &mut Vec<u8>) {
+    let (len, codes, n_pos) = packed;
+    let start = out.len();
+    out.extend(
+        (0..*len as usize).map(|i| b"ACGT"[((codes[i >> 2] >> ((i & 0x03) << 1)) & 0x03) as usize]),
+    );
+    n_pos.iter().for_each(|&p| out[start + p as usize] = b'N');
+}
 
-        //assert!(shmmrs.len() > 0);
-        if shmmrs.is_empty() {
-            let frg = seq[..].to_vec();
-            frags.push(Fragment::Prefix(frg));
-            seq_frags.push(frg_id);
-            // frg_id += 1;
+/// keep the `N` runs of at least `MIN_N_RUN_LEN` bases as their positions and lengths with
+/// the other bases as they are, returns `None` unless the runs cover most of the bases
+pub fn pack_n_runs(seq: &[u8]) -> Option<NRunBases> {
+    if seq.iter().filter(|&&c| c == b'N').count() * 2 <= seq.len() {
+        return None;
+    }
+    let mut runs = Vec::<(u32, u32)>::new();
+    let mut bases = Bases::new();
+    let mut i = 0_usize;
+    while i < seq.len() {
+        let run_len = seq[i..].iter().take_while(|&&c| c == b'N').count();
+        if run_len as u32 >= MIN_N_RUN_LEN {
+            runs.push((i as u32, run_len as u32));
+            i += run_len;
+        } else {
+            bases.push(seq[i]);
+            i += 1;
+        }
+    }
+    if bases.len() * 2 >= seq.len() {
+        return None;
+    }
+    Some((seq.len() as u32, runs, bases))
+}
+
+pub fn unpack_n_runs(n_runs: &NRunBases) -> Bases {
+    let mut seq = Bases::with_capacity(n_runs.0 as usize);
+    unpack_n_runs_into(n_runs, &mut seq);
+    seq
+}
+
+/// the same as `unpack_n_runs`, but the bases are appended to `out`
+pub fn unpack_n_runs_into(n_runs: &NRunBases, out: &mut Vec<u8>) {
+    let (_, runs, bases) = n_runs;
+    let start = out.len();
+    let mut bases = bases.iter();
+    runs.iter().for_each(|&(pos, run_len)| {
+        out.extend(bases.by_ref().take(start + pos as usize - out.len()));
+        out.resize(out.len() + run_len as usize, b'N');
+    });
+    out.extend(bases);
+}
+
+/// sort and merge the masked intervals so they can be searched with `overlaps_masked`
+fn merge_masked_intervals(masked: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut intervals = masked
+        .iter()
+        .filter(|(bgn, end)| bgn < end)
+        .copied()
+        .collect::<Vec<_>>();
+    intervals.sort_unstable();
+    let mut merged = Vec::<(u32, u32)>::new();
+    intervals
+        .into_iter()
+        .for_each(|(bgn, end)| match merged.last_mut() {
+            Some(last) if bgn <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((bgn, end)),
+        });
+    merged
+}
+
+/// the low-complexity masking of the loads, see `CompactSeqDB::low_complexity_mask`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LowComplexityMask {
+    /// the bases of the sliding window
+    pub window: u32,
+    /// a window is masked if the Shannon entropy (in bits, at most 6) of its 3-mers is below it
+    pub min_entropy: f32,
+}
+
+impl Default for LowComplexityMask {
+    /// the windows of 64 bases below 3 bits, e.g. the repeats of the units up to about 8 bases
+    fn default() -> Self {
+        LowComplexityMask {
+            window: 64,
+            min_entropy: 3.0,
+        }
+    }
+}
+
+/// the merged intervals `[bgn, end)` of the low-complexity windows of a sequence, the
+/// 3-mers with a base other than `ACGT` (in either case) are not counted, and a window
+/// without a counted 3-mer is not masked
+pub fn low_complexity_intervals(seq: &[u8], mask: &LowComplexityMask) -> Vec<(u32, u32)> {
+    let window = mask.window as usize;
+    if window < 3 || seq.len() < window {
+        return vec![];
+    }
+    let code = |b: u8| match b {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    };
+    // the 2-bit codes of each 3-mer
+    let trimers = seq
+        .windows(3)
+        .map(|w| Some((code(w[0])? << 4) | (code(w[1])? << 2) | code(w[2])?))
+        .collect::<Vec<Option<usize>>>();
+    let n_trimers = window - 2;
+    let c_log_c = (0..=n_trimers)
+        .map(|c| c as f64 * (c.max(1) as f64).log2())
+        .collect::<Vec<f64>>();
+    // the running sum of `c * log2(c)` over the counts of the 3-mers in the window
+    let mut counts = [0_usize; 64];
+    let (mut n, mut sum) = (0_usize, 0_f64);
+    let mut intervals = Vec::<(u32, u32)>::new();
+    trimers.iter().enumerate().for_each(|(i, t)| {
+        if i >= n_trimers {
+            if let Some(t) = trimers[i - n_trimers] {
+                sum -= c_log_c[counts[t]] - c_log_c[counts[t] - 1];
+                counts[t] -= 1;
+                n -= 1;
+            }
+        }
+        if let Some(t) = *t {
+            sum += c_log_c[counts[t] + 1] - c_log_c[counts[t]];
+            counts[t] += 1;
+            n += 1;
+        }
+        if i + 1 < n_trimers || n == 0 {
+            return;
+        }
+        let entropy = (n as f64).log2() - sum / n as f64;
+        // the rounding errors of the running sum are far below 1e-9 bits
+        if entropy + 1e-9 < mask.min_entropy as f64 {
+            let (bgn, end) = ((i + 3 - window) as u32, (i + 3) as u32);
+            match intervals.last_mut() {
+                Some(last) if bgn <= last.1 => last.1 = end,
+                _ => intervals.push((bgn, end)),
+            }
+        }
+    });
+    intervals
+}
+
+/// if `[bgn, end)` overlaps any of the sorted and merged `masked` intervals
+fn overlaps_masked(masked: &[(u32, u32)], bgn: u32, end: u32) -> bool {
+    let i = masked.partition_point(|&(_, e)| e <= bgn);
+    i < masked.len() && masked[i].0 < end
+}
+
+/// the literal internal fragment of the bases, packed if the bases allow it, the protein
+/// residues are never 2-bit packed
+fn literal_internal_frag(frg: &[u8], alphabet: Alphabet) -> Fragment {
+    if let Some(r) = pack_n_runs(frg) {
+        Fragment::InternalNRuns(Box::new(r))
+    } else if let Some(p) = (alphabet == Alphabet::Nucleotide)
+        .then(|| pack_bases(frg))
+        .flatten()
+    {
+        Fragment::Internal2Bit(Box::new(p))
+    } else {
+        Fragment::Internal(frg.to_vec())
+    }
+}
 
-            let frg = Vec::<u8>::new();
-            frags.push(Fragment::Suffix(frg));
-            seq_frags.push(frg_id);
+/// split the bases `[bgn, end)` a fragment adds to the sequence at fixed intervals into the
+/// spans of the pieces storing at most `max_len` bases, the first piece is stored with
+/// `overlap` bases before `bgn` and the others with the k-mer overlap, it is a single span if
+/// `max_len` is `None`
+fn split_frag_span(
+    bgn: usize,
+    end: usize,
+    overlap: usize,
+    k: usize,
+    max_len: Option<u32>,
+) -> Vec<(usize, usize)> {
+    let max_len = match max_len {
+        Some(max_len) => max_len as usize,
+        None => return vec![(bgn, end)],
+    };
+    let mut spans = vec![(bgn, end.min(bgn + max_len - overlap))];
+    while let Some(&(_, b)) = spans.last().filter(|s| s.1 < end) {
+        spans.push((b, end.min(b + max_len - k)));
+    }
+    spans
+}
+
+/// push the literal bases `[bgn, end)` of `seq` to the fragments as the pieces split by
+/// `split_frag_span`, the first piece is a prefix if `prefix` and the last piece is a suffix
+/// if `suffix`, the other pieces are internal fragments, it returns the number of the pieces
+#[allow(clippy::too_many_arguments)]
+fn push_literal_frags(
+    frags: &mut Fragments,
+    frag_crcs: &mut Option<&mut Vec<u32>>,
+    seq: &[u8],
+    bgn: usize,
+    end: usize,
+    prefix: bool,
+    suffix: bool,
+    k: usize,
+    max_len: Option<u32>,
+    alphabet: Alphabet,
+) -> u32 {
+    let spans = split_frag_span(bgn, end, if prefix { 0 } else { k }, k, max_len);
+    let n_spans = spans.len();
+    spans.into_iter().enumerate().for_each(|(i, (b, e))| {
+        let (b, frg) = if prefix && i == 0 {
+            (b, Fragment::Prefix(seq[b..e].to_vec()))
+        } else if suffix && i + 1 == n_spans {
+            (b, Fragment::Suffix(seq[b..e].to_vec()))
+        } else {
+            (b - k, literal_internal_frag(&seq[b - k..e], alphabet))
+        };
+        if let Some(crcs) = frag_crcs.as_mut() {
+            crcs.push(bases_crc(&seq[b..e]));
+        }
+        frags.push(frg);
+    });
+    n_spans as u32
+}
+
+/// the index pairs of the matched items along a shortest edit script between `a` and `b`
+/// (Myers' O((n+m)D) greedy algorithm), the memory grows with the square of the edit distance
+fn diff_matches<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = n + m;
+    let offset = max_d + 1;
+    let mut v = vec![0_isize; 2 * offset as usize + 1];
+    // the furthest reaching x of the diagonals `-d..=d` before each step `d`
+    let mut trace = Vec::<Vec<isize>>::new();
+    'outer: for d in 0..=max_d {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let ki = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[ki - 1] < v[ki + 1]) {
+                v[ki + 1]
+            } else {
+                v[ki - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[ki] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+        }
+    }
+
+    let mut matches = Vec::<(usize, usize)>::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..trace.len() as isize).rev() {
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev_v = |k: isize| trace[d as usize][(k + d) as usize];
+            let k = x - y;
+            let prev_k = if k == -d || (k != d && prev_v(k - 1) < prev_v(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = prev_v(prev_k);
+            (prev_x, prev_x - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    matches.reverse();
+    matches
+}
+
+impl CompactSeqDB {
+    /// create an empty DB, it panics if the `shmmr_spec` is not valid, see `ShmmrSpec::validate`,
+    /// the shimmers are computed by `DefaultShmmrHasher`
+    pub fn new(shmmr_spec: ShmmrSpec) -> Self {
+        Self::try_new(shmmr_spec).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// the same as `new`, but an invalid `shmmr_spec` fails with `SeqDbError::Io` of
+    /// `InvalidInput`
+    pub fn try_new(shmmr_spec: ShmmrSpec) -> Result<Self, SeqDbError> {
+        Self::new_with_hasher(shmmr_spec, Arc::new(DefaultShmmrHasher))
+    }
+
+    /// the same as `try_new`, but the shimmers are computed by `hasher`, its id is recorded in
+    /// the `hash_id` of the spec, so the DB files can only be queried with the same hash
+    pub fn new_with_hasher(
+        mut shmmr_spec: ShmmrSpec,
+        hasher: Arc<dyn ShmmrHasher>,
+    ) -> Result<Self, SeqDbError> {
+        shmmr_spec.validate()?;
+        shmmr_spec.hash_id = hasher.hash_id();
+        let seqs = Vec::<CompactSeq>::new();
+        let frag_map = ShmmrToFrags::default();
+        let frags = None;
+        Ok(CompactSeqDB {
+            shmmr_spec,
+            seqs,
+            frag_map,
+            frags,
+            frag_store: None,
+            high_freq_filter: None,
+            pair_target_count: None,
+            substring_index: None,
+            frag_occurrences: None,
+            build_metrics: None,
+            frag_crcs: None,
+            duplicate_name_policy: None,
+            compress_with_alignment: true,
+            max_fragment_len: None,
+            low_complexity_mask: None,
+            tally_base_composition: false,
+            name_to_id: FxHashMap::default(),
+            hasher,
+        })
+    }
+
+    /// set the hash of the shimmers of a DB loaded from a file, it fails with `InvalidInput` if
+    /// the hasher is not the one recorded in the `hash_id` of the spec
+    pub fn set_hasher(&mut self, hasher: Arc<dyn ShmmrHasher>) -> Result<(), std::io::Error> {
+        self.shmmr_spec.check_hasher(hasher.as_ref())?;
+        self.hasher = hasher;
+        Ok(())
+    }
+
+    /// compute the shimmers of a sequence with the hash of the DB, it fails with
+    /// `InvalidInput` if the DB is loaded from a file built with another hash and the hash is
+    /// not set by `set_hasher`
+    pub fn seq_shmmrs(&self, sid: u32, seq: &[u8]) -> Result<Vec<MM128>, std::io::Error> {
+        sequence_to_shmmrs_with_hasher(sid, seq, &self.shmmr_spec, false, self.hasher.as_ref())
+    }
+
+    fn push_seq(&mut self, seq: CompactSeq) {
+        self.pair_target_count = None;
+        self.substring_index = None;
+        // the index is dropped if a fragment of the sequence does not decode
+        if let Some(mut frag_occurrences) = self.frag_occurrences.take() {
+            if frag_occurrences.add_seq(self, &seq).is_ok() {
+                self.frag_occurrences = Some(frag_occurrences);
+            }
+        }
+        self.name_to_id
+            .entry(seq.name_bytes().to_vec())
+            .or_insert(seq.id);
+        self.seqs.push(seq);
+    }
+
+    /// run `load` with the `duplicate_name_policy` set to the default `DuplicateNamePolicy::Error`
+    /// if it is not set
+    fn with_duplicate_name_error<T>(&mut self, load: impl FnOnce(&mut Self) -> T) -> T {
+        let policy = self.duplicate_name_policy;
+        self.duplicate_name_policy = Some(policy.unwrap_or_default());
+        let res = load(self);
+        self.duplicate_name_policy = policy;
+        res
+    }
+
+    /// the `(name, raw_name)` to load a sequence with under the `duplicate_name_policy`
+    fn resolve_seq_name(
+        &self,
+        name: &str,
+        raw_name: Option<&[u8]>,
+    ) -> Result<(String, Option<Vec<u8>>), SeqDbError> {
+        let name_bytes = raw_name.unwrap_or(name.as_bytes());
+        if !self.name_to_id.contains_key(name_bytes) {
+            return Ok((name.to_string(), raw_name.map(|b| b.to_vec())));
+        }
+        match self
+            .duplicate_name_policy
+            .unwrap_or(DuplicateNamePolicy::Keep)
+        {
+            DuplicateNamePolicy::Error => Err(SeqDbError::Parse(format!(
+                "duplicate sequence name: {}",
+                name
+            ))),
+            DuplicateNamePolicy::Rename => Ok((1..)
+                .map(|n| {
+                    let mut new_name_bytes = name_bytes.to_vec();
+                    new_name_bytes.extend(format!("_{}", n).as_bytes());
+                    new_name_bytes
+                })
+                .find(|new_name_bytes| !self.name_to_id.contains_key(new_name_bytes))
+                .map(seq_name_from_id)
+                .unwrap()),
+            DuplicateNamePolicy::Keep => Ok((name.to_string(), raw_name.map(|b| b.to_vec()))),
+        }
+    }
+
+    /// the name of a sequence
+    pub fn name_of(&self, seq_id: u32) -> Option<&str> {
+        self.seqs.get(seq_id as usize).map(|s| s.name.as_str())
+    }
+
+    /// the id of the (first) sequence with the name
+    pub fn id_of(&self, name: &str) -> Option<u32> {
+        self.id_of_bytes(name.as_bytes())
+    }
+
+    /// the id of the (first) sequence with the raw bytes of the name, e.g. an id that is not
+    /// valid UTF-8, see `CompactSeq::name_bytes`
+    pub fn id_of_bytes(&self, name: &[u8]) -> Option<u32> {
+        self.name_to_id.get(name).copied()
+    }
+
+    /// build a DB from the `(name, bases)` of the sequences, e.g. the sequences generated in
+    /// memory, the sequence ids follow the order of the iterator, an invalid spec fails, and
+    /// so does a duplicate name unless the `duplicate_name_policy` is set
+    pub fn from_iter(
+        iter: impl Iterator<Item = (String, Vec<u8>)>,
+        shmmr_spec: ShmmrSpec,
+    ) -> Result<Self, SeqDbError> {
+        let mut sdb = CompactSeqDB::try_new(shmmr_spec)?;
+        let mut named_seqs = iter.map(|(name, seq)| (None, name.into_bytes(), seq));
+        sdb.with_duplicate_name_error(|sdb| {
+            sdb.load_named_seqs(&mut named_seqs, None, &mut |_, _| {})
+        })?;
+        Ok(sdb)
+    }
+
+    pub fn seq_to_compressed(
+        &mut self,
+        source: Option<String>,
+        name: String,
+        id: u32,
+        seq: &Vec<u8>,
+        shmmrs: Vec<MM128>,
+        try_compress: bool,
+    ) -> CompactSeq {
+        self.seq_to_compressed_with_mask(source, name, id, seq, shmmrs, try_compress, &[])
+    }
+
+    /// the same as `seq_to_compressed`, but the shimmers with the k-mers overlapping the
+    /// `masked` intervals are dropped, and the fragments overlapping the intervals are kept
+    /// as literal bases that are not added to the `frag_map`, so they are never hit by
+    /// the queries nor used as the base of the other fragments
+    #[allow(clippy::too_many_arguments)]
+    pub fn seq_to_compressed_with_mask(
+        &mut self,
+        source: Option<String>,
+        name: String,
+        id: u32,
+        seq: &[u8],
+        shmmrs: Vec<MM128>,
+        try_compress: bool,
+        masked: &[(u32, u32)],
+    ) -> CompactSeq {
+        self.check_max_fragment_len()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let k = self.shmmr_spec.k;
+        let max_frag_len = self.max_fragment_len;
+        let alphabet = self.shmmr_spec.alphabet;
+        let masked = merge_masked_intervals(masked);
+        let shmmrs = if masked.is_empty() {
+            shmmrs
+        } else {
+            shmmrs
+                .into_iter()
+                .filter(|m| {
+                    let end = m.pos() + 1;
+                    !overlaps_masked(&masked, end.saturating_sub(k), end)
+                })
+                .collect()
+        };
+        let mut seq_frags = Vec::<u32>::new();
+        // (the number of the calls, the nanoseconds) of the fragment alignments
+        let aln_counters = self
+            .build_metrics
+            .as_ref()
+            .map(|_| (AtomicU64::new(0), AtomicU64::new(0)));
+
+        assert!(self.frags.is_some());
+        let frags: &mut Vec<Fragment> = self.frags.as_mut().unwrap();
+        let mut frag_crcs = self.frag_crcs.as_mut();
+
+        let mut frg_id = frags.len() as u32;
+        let mut seq_len = 0_usize;
+
+        // a sequence without shimmers, e.g. one shorter than k, is stored as a single
+        // literal fragment, or the pieces of it split by `max_fragment_len`
+        if shmmrs.is_empty() {
+            let n_frags = push_literal_frags(
+                frags,
+                &mut frag_crcs,
+                seq,
+                0,
+                seq.len(),
+                true,
+                true,
+                k as usize,
+                max_frag_len,
+                alphabet,
+            );
+            seq_frags.extend(frg_id..frg_id + n_frags);
 
             return CompactSeq {
                 source,
@@ -220,15 +1631,29 @@ impl CompactSeqDB {
                 id,
                 seq_frag_range: (seq_frags[0], seq_frags.len() as u32),
                 len: seq.len(),
+                checksum: Some(bases_crc(seq)),
+                soft_mask: None,
+                raw_name: None,
+                base_composition: None,
             };
         }
         // prefix
         let end = (shmmrs[0].pos() + 1) as usize;
-        let frg = seq[..end].to_vec();
-        seq_len += frg.len();
-        frags.push(Fragment::Prefix(frg));
-        seq_frags.push(frg_id);
-        frg_id += 1;
+        seq_len += end;
+        let n_frags = push_literal_frags(
+            frags,
+            &mut frag_crcs,
+            seq,
+            0,
+            end,
+            true,
+            false,
+            k as usize,
+            max_frag_len,
+            alphabet,
+        );
+        seq_frags.extend(frg_id..frg_id + n_frags);
+        frg_id += n_frags;
 
         let internal_frags = pair_shmmrs(&shmmrs)
             .par_iter()
@@ -245,14 +1670,29 @@ impl CompactSeqDB {
                 let frg_len = end - bgn;
                 let mut aligned = false;
                 let mut out_frag = None;
+                let indexed = !overlaps_masked(&masked, bgn - k, end);
 
-                if frg_len > 128 && try_compress && self.frag_map.contains_key(&shmmr_pair) {
+                if indexed
+                    && frg_len > 128
+                    && try_compress
+                    && self.frag_map.contains_key(&shmmr_pair)
+                {
                     let e = self.frag_map.get(&shmmr_pair).unwrap();
                     for t_frg_id in e.iter() {
                         let base_frg = frags.get(t_frg_id.0 as usize).unwrap();
-                        if let Fragment::Internal(b) = base_frg {
-                            let base_frg = b;
+                        // the first piece of a fragment split by `max_fragment_len` is not
+                        // the whole base fragment
+                        if base_frg.base_len() != t_frg_id.3 - t_frg_id.2 + k {
+                            continue;
+                        }
+                        if let Some(b) = base_frg.internal_bases() {
+                            let base_frg = b.as_ref();
                             //assert!(base_frg.len() > KMERSIZE as usize);
+                            // a protein fragment between the same shimmers in the other
+                            // order is not the reverse complement of this one
+                            if orientation != t_frg_id.4 && alphabet == Alphabet::Protein {
+                                continue;
+                            }
                             let frg;
                             let rc;
                             if orientation != t_frg_id.4 {
@@ -268,7 +1708,15 @@ impl CompactSeqDB {
                             //assert!(frg.len() > KMERSIZE as usize);
                             //the max span should be less than 128 * 144 = 18423 * 2 < 2**16
                             assert!(base_frg.len() < (1 << 32) - 1);
+                            let start = aln_counters.as_ref().map(|_| Instant::now());
                             let m = match_reads(base_frg, &frg, true, 0.1, 0, 0, 32);
+                            if let (Some((calls, nanos)), Some(start)) = (&aln_counters, start) {
+                                calls.fetch_add(1, Ordering::Relaxed);
+                                nanos.fetch_add(
+                                    start.elapsed().as_nanos() as u64,
+                                    Ordering::Relaxed,
+                                );
+                            }
                             if let Some(m) = m {
                                 let deltas: Vec<DeltaPoint> = m.deltas.unwrap();
                                 let aln_segs = deltas_to_aln_segs(
@@ -295,15 +1743,16 @@ impl CompactSeqDB {
 
                                 out_frag = Some((
                                     shmmr_pair,
-                                    Fragment::AlnSegments((
+                                    Some(Fragment::AlnSegments((
                                         t_frg_id.0,
                                         rc,
                                         frg.len() as u32,
                                         aln_segs,
-                                    )),
+                                    ))),
                                     bgn,
                                     end,
                                     orientation,
+                                    indexed,
                                 ));
                                 aligned = true;
                                 break; // we aligned to the first one of the fragments
@@ -315,36 +1764,84 @@ impl CompactSeqDB {
                 };
 
                 if !aligned || !try_compress {
-                    let frg = seq[(bgn - self.shmmr_spec.k) as usize..end as usize].to_vec();
-                    out_frag = Some((shmmr_pair, Fragment::Internal(frg), bgn, end, orientation));
+                    let frg = &seq[(bgn - k) as usize..end as usize];
+                    // an oversized fragment is split into the literal pieces as the fragments
+                    // are pushed
+                    let frg = if matches!(max_frag_len, Some(m) if frg.len() > m as usize) {
+                        None
+                    } else {
+                        Some(literal_internal_frag(frg, alphabet))
+                    };
+                    out_frag = Some((shmmr_pair, frg, bgn, end, orientation, indexed));
                 };
                 out_frag
             })
             .collect::<Vec<_>>();
 
+        if let (Some(metrics), Some((calls, nanos))) = (self.build_metrics.as_mut(), aln_counters) {
+            metrics.n_aln_calls += calls.into_inner();
+            metrics.aln_nanos += nanos.into_inner();
+            metrics.n_aln_frags += internal_frags
+                .iter()
+                .flatten()
+                .filter(|v| matches!(v.1, Some(Fragment::AlnSegments(_))))
+                .count() as u64;
+        }
+
         // TODO: parallelize by sharding the key
         internal_frags.iter().for_each(|v| match v {
-            Some((shmmr, frg, bgn, end, orientation)) => {
-                if !self.frag_map.contains_key(shmmr) {
-                    self.frag_map
-                        .insert(*shmmr, Vec::<(u32, u32, u32, u32, u8)>::new());
+            Some((shmmr, frg, bgn, end, orientation, indexed)) => {
+                if *indexed {
+                    let e = self.frag_map.entry(*shmmr).or_default();
+                    e.push((frg_id, id, *bgn, *end, *orientation));
+                    if let Some(filter) = self.high_freq_filter.as_mut() {
+                        filter.update(shmmr, e.len());
+                    }
                 }
-                let e = self.frag_map.get_mut(shmmr).unwrap();
-                e.push((frg_id, id, *bgn, *end, *orientation));
                 seq_len += (*end - *bgn) as usize;
-                frags.push(frg.clone());
-                seq_frags.push(frg_id);
-                frg_id += 1;
+                let n_frags = match frg {
+                    Some(frg) => {
+                        if let Some(crcs) = frag_crcs.as_mut() {
+                            crcs.push(bases_crc(&seq[(*bgn - k) as usize..*end as usize]));
+                        }
+                        frags.push(frg.clone());
+                        1
+                    }
+                    None => push_literal_frags(
+                        frags,
+                        &mut frag_crcs,
+                        seq,
+                        *bgn as usize,
+                        *end as usize,
+                        false,
+                        false,
+                        k as usize,
+                        max_frag_len,
+                        alphabet,
+                    ),
+                };
+                seq_frags.extend(frg_id..frg_id + n_frags);
+                frg_id += n_frags;
             }
             None => {}
         });
 
         // suffix
         let bgn = (shmmrs[shmmrs.len() - 1].pos() + 1) as usize;
-        let frg = seq[bgn..].to_vec();
-        seq_len += frg.len();
-        frags.push(Fragment::Suffix(frg));
-        seq_frags.push(frg_id);
+        seq_len += seq.len() - bgn;
+        let n_frags = push_literal_frags(
+            frags,
+            &mut frag_crcs,
+            seq,
+            bgn,
+            seq.len(),
+            false,
+            true,
+            k as usize,
+            max_frag_len,
+            alphabet,
+        );
+        seq_frags.extend(frg_id..frg_id + n_frags);
 
         assert_eq!(seq_len, seq.len());
         CompactSeq {
@@ -353,6 +1850,10 @@ impl CompactSeqDB {
             id,
             seq_frag_range: (seq_frags[0], seq_frags.len() as u32),
             len: seq.len(),
+            checksum: Some(bases_crc(seq)),
+            soft_mask: None,
+            raw_name: None,
+            base_composition: None,
         }
     }
 
@@ -373,6 +1874,10 @@ impl CompactSeqDB {
                     id,
                     seq_frag_range: (0, 0),
                     len: seqlen,
+                    checksum: None,
+                    soft_mask: None,
+                    raw_name: None,
+                    base_composition: None,
                 },
                 vec![],
             );
@@ -412,45 +1917,22 @@ impl CompactSeqDB {
                 id,
                 seq_frag_range,
                 len: seqlen,
+                checksum: None,
+                soft_mask: None,
+                raw_name: None,
+                base_composition: None,
             },
             internal_frags,
         )
     }
 
-    fn get_fastx_reader(&mut self, filepath: String) -> Result<GZFastaReader, std::io::Error> {
-        let file = File::open(&filepath)?;
-        let mut reader = BufReader::new(file);
-        let mut is_gzfile = false;
-        {
-            let r = reader.by_ref();
-            let mut buf = Vec::<u8>::new();
-            let _ = r.take(2).read_to_end(&mut buf);
-            if buf == [0x1F_u8, 0x8B_u8] {
-                log::info!("input file: {} detected as gz-compressed file", filepath);
-                is_gzfile = true;
-            }
-        }
-        drop(reader);
-
-        let file = File::open(&filepath)?;
-        let reader = BufReader::new(file);
-        let gz_buf = BufReader::new(MultiGzDecoder::new(reader));
-
-        let file = File::open(&filepath)?;
-        let reader = BufReader::new(file);
-        let std_buf = BufReader::new(reader);
-
-        if is_gzfile {
-            drop(std_buf);
-            Ok(GZFastaReader::GZFile(
-                FastaReader::new(gz_buf, &filepath, 1 << 14, true).unwrap(),
-            ))
-        } else {
-            drop(gz_buf);
-            Ok(GZFastaReader::RegularFile(
-                FastaReader::new(std_buf, &filepath, 1 << 14, true).unwrap(),
-            ))
-        }
+    fn get_fastx_reader(
+        &mut self,
+        filepath: String,
+    ) -> Result<FastaReader<Box<dyn BufRead>>, SeqDbError> {
+        self.shmmr_spec.validate()?;
+        let reader = get_decompressed_reader(&filepath).map_err(record_error)?;
+        FastaReader::new(reader, &filepath, 1 << 14, true).map_err(record_error)
     }
 
     fn get_shmmrs_from_seqs(
@@ -460,7 +1942,9 @@ impl CompactSeqDB {
         let all_shmmrs = seqs
             .par_iter()
             .map(|(sid, _, _, seq)| {
-                let shmmrs = sequence_to_shmmrs(*sid, seq, &self.shmmr_spec, false);
+                let shmmrs = self
+                    .seq_shmmrs(*sid, seq)
+                    .unwrap_or_else(|e| panic!("{}", e));
                 //let shmmrs = sequence_to_shmmrs2(*sid, &seq, 80, KMERSIZE, 4);
                 (*sid, shmmrs)
             })
@@ -468,9 +1952,35 @@ impl CompactSeqDB {
         all_shmmrs
     }
 
-    fn load_seq_from_reader(&mut self, reader: &mut dyn Iterator<Item = io::Result<SeqRec>>) {
+    fn load_seq_from_reader(
+        &mut self,
+        reader: &mut dyn Iterator<Item = io::Result<SeqRec>>,
+        masks: Option<&MaskedRegions>,
+        progress: &mut dyn FnMut(usize, &str),
+    ) -> Result<(), SeqDbError> {
+        // the sequences before a bad record are loaded
+        let mut bad_rec = None;
+        let mut named_seqs = reader
+            .map_while(|rec| rec.map_err(|e| bad_rec = Some(e)).ok())
+            .map(|rec| (rec.source, rec.id, rec.seq));
+        let res = self.load_named_seqs(&mut named_seqs, masks, progress);
+        drop(named_seqs);
+        res?;
+        bad_rec.map_or(Ok(()), |e| Err(record_error(e)))
+    }
+
+    /// load the `(source, id, bases)` of the sequences in batches, the sequence ids follow
+    /// the sequences already in the DB, the raw bytes of the ids are kept, see `CompactSeq`
+    fn load_named_seqs(
+        &mut self,
+        named_seqs: &mut dyn Iterator<Item = (Option<String>, Vec<u8>, Vec<u8>)>,
+        masks: Option<&MaskedRegions>,
+        progress: &mut dyn FnMut(usize, &str),
+    ) -> Result<(), SeqDbError> {
         let mut seqs = <Vec<(u32, Option<String>, String, Vec<u8>)>>::new();
+        let mut raw_names = Vec::<Option<Vec<u8>>>::new();
         let mut sid = self.seqs.len() as u32;
+        let mut n_loaded = 0_usize;
         if self.frags.is_none() {
             self.frags = Some(Fragments::new());
         };
@@ -479,13 +1989,13 @@ impl CompactSeqDB {
             let mut count = 0;
             let mut end_ext_loop = false;
             seqs.clear();
+            raw_names.clear();
 
             loop {
-                if let Some(rec) = reader.next() {
-                    let rec = rec.unwrap();
-                    let source = rec.source.clone();
-                    let seqname = String::from_utf8_lossy(&rec.id).into_owned();
-                    seqs.push((sid, source, seqname, rec.seq));
+                if let Some((source, id, seq)) = named_seqs.next() {
+                    let (seqname, raw_name) = seq_name_from_id(id);
+                    seqs.push((sid, source, seqname, seq));
+                    raw_names.push(raw_name);
                     sid += 1;
                 } else {
                     end_ext_loop = true;
@@ -497,61 +2007,445 @@ impl CompactSeqDB {
                 }
             }
 
-            self.load_seqs_from_seq_vec(&seqs);
+            self.load_masked_seqs(&seqs, &raw_names, masks, &mut |count, name| {
+                progress(n_loaded + count, name)
+            })?;
+            n_loaded += seqs.len();
             if end_ext_loop {
                 break;
             }
         }
+        Ok(())
     }
 
+    /// load the sequences, the duplicate names are kept unless the `duplicate_name_policy` is
+    /// set, it panics on a duplicate name under `DuplicateNamePolicy::Error`, a `max_fragment_len`
+    /// not larger than k, or a DB loaded from a file without its hash, see
+    /// `try_load_seqs_from_seq_vec`
     pub fn load_seqs_from_seq_vec(&mut self, seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>) {
+        self.load_masked_seqs(seqs, &[], None, &mut |_, _| {})
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// the same as `load_seqs_from_seq_vec`, but it fails instead of panicking, a duplicate
+    /// name fails with `SeqDbError::Parse` under `DuplicateNamePolicy::Error`, the default if the
+    /// `duplicate_name_policy` is not set
+    pub fn try_load_seqs_from_seq_vec(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+    ) -> Result<(), SeqDbError> {
+        self.with_duplicate_name_error(|sdb| sdb.load_masked_seqs(seqs, &[], None, &mut |_, _| {}))
+    }
+
+    /// load the sequences and call `progress` with the number of the loaded sequences and
+    /// the name of the sequence after each sequence is added to the DB, it fails like
+    /// `try_load_seqs_from_seq_vec` but keeps the duplicate names unless the
+    /// `duplicate_name_policy` is set
+    pub fn load_seqs_from_seq_vec_with_progress(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+        mut progress: impl FnMut(usize, &str),
+    ) -> Result<(), SeqDbError> {
+        self.load_masked_seqs(seqs, &[], None, &mut progress)
+    }
+
+    /// load the sequences without generating the shimmers in the `masks` intervals of each
+    /// sequence (looked up by the sequence name), the fragments covering the intervals are
+    /// stored as literal bases excluded from the `frag_map`, it fails like
+    /// `load_seqs_from_seq_vec_with_progress`
+    pub fn load_seqs_from_seq_vec_with_masks(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+        masks: &MaskedRegions,
+    ) -> Result<(), SeqDbError> {
+        self.load_masked_seqs(seqs, &[], Some(masks), &mut |_, _| {})
+    }
+
+    /// the `raw_names` are the `CompactSeq::raw_name` of the sequences, it can be empty if
+    /// all names are valid UTF-8
+    fn load_masked_seqs(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+        raw_names: &[Option<Vec<u8>>],
+        masks: Option<&MaskedRegions>,
+        progress: &mut dyn FnMut(usize, &str),
+    ) -> Result<(), SeqDbError> {
+        self.check_max_fragment_len()?;
         if self.frags.is_none() {
             self.frags = Some(Fragments::new());
         }
+        let start = self.build_metrics.as_ref().map(|_| Instant::now());
         let all_shmmrs = self.get_shmmrs_from_seqs(seqs);
-        seqs.iter()
-            .zip(all_shmmrs)
-            .for_each(|((sid, source, seqname, seq), (_sid, shmmrs))| {
-                let compress_seq = self.seq_to_compressed(
-                    source.clone(),
-                    seqname.clone(),
-                    *sid,
-                    seq,
-                    shmmrs,
-                    true,
-                );
-                self.seqs.push(compress_seq);
+        if let (Some(metrics), Some(start)) = (self.build_metrics.as_mut(), start) {
+            metrics.shmmr_nanos += start.elapsed().as_nanos() as u64;
+        }
+        for (i, ((sid, source, seqname, seq), (_sid, shmmrs))) in
+            seqs.iter().zip(all_shmmrs).enumerate()
+        {
+            let raw_name = raw_names.get(i).and_then(|raw_name| raw_name.as_deref());
+            let (name, raw_name) = self.resolve_seq_name(seqname, raw_name)?;
+            let masked = masks
+                .and_then(|masks| masks.get(seqname))
+                .map(|v| &v[..])
+                .unwrap_or(&[]);
+            let low_complexity_masked;
+            let masked = match self.low_complexity_mask.as_ref() {
+                Some(lc_mask) => {
+                    low_complexity_masked =
+                        [masked, &low_complexity_intervals(seq, lc_mask)[..]].concat();
+                    &low_complexity_masked[..]
+                }
+                None => masked,
+            };
+            let start = self.build_metrics.as_ref().map(|_| Instant::now());
+            let mut compress_seq = self.seq_to_compressed_with_mask(
+                source.clone(),
+                name,
+                *sid,
+                seq,
+                shmmrs,
+                self.compress_with_alignment,
+                masked,
+            );
+            compress_seq.raw_name = raw_name;
+            if self.tally_base_composition {
+                compress_seq.base_composition = Some(BaseComposition::of(seq));
+            }
+            if let (Some(metrics), Some(start)) = (self.build_metrics.as_mut(), start) {
+                metrics.frag_nanos += start.elapsed().as_nanos() as u64;
+                metrics.n_seqs += 1;
+                metrics.n_bases += compress_seq.len as u64;
+                metrics.n_frags += compress_seq.seq_frag_range.1 as u64;
+            }
+            self.push_seq(compress_seq);
+            progress(i + 1, seqname);
+        }
+        Ok(())
+    }
+
+    /// load the sequences with the shimmer extraction and the fragment alignment running
+    /// in a thread pool of `num_threads` threads (rayon's default if `None`)
+    ///
+    /// the fragments are merged into the DB in the input order, so the fragment ids and the
+    /// reconstructed sequences do not depend on the number of threads
+    pub fn load_seqs_parallel(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+        num_threads: Option<usize>,
+    ) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads.unwrap_or(0))
+            .build()
+            .expect("fail to build the thread pool");
+        pool.install(|| self.load_seqs_from_seq_vec(seqs));
+    }
+
+    /// load the sequences from a fastx file, a missing file fails with `SeqDbError::Io` and a
+    /// bad record with `SeqDbError::Parse` after the sequences before it are loaded
+    pub fn load_seqs_from_fastx(&mut self, filepath: String) -> Result<(), SeqDbError> {
+        self.load_seqs_from_fastx_with_progress(filepath, |_, _| {})
+    }
+
+    /// load the sequences from a fastx file and return the metrics of this load, the metrics
+    /// are also added to `build_metrics` if it is already set
+    pub fn load_seqs_from_fastx_with_metrics(
+        &mut self,
+        filepath: String,
+    ) -> Result<BuildMetrics, SeqDbError> {
+        self.load_with_metrics(|sdb| sdb.load_seqs_from_fastx(filepath))
+    }
+
+    /// load the sequences from a vector and return the metrics of this load, the metrics are
+    /// also added to `build_metrics` if it is already set
+    pub fn load_seqs_from_seq_vec_with_metrics(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+    ) -> BuildMetrics {
+        self.load_with_metrics(|sdb| {
+            sdb.load_seqs_from_seq_vec(seqs);
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    fn load_with_metrics(
+        &mut self,
+        load: impl FnOnce(&mut Self) -> Result<(), SeqDbError>,
+    ) -> Result<BuildMetrics, SeqDbError> {
+        let prev = self.build_metrics.replace(BuildMetrics::default());
+        let res = load(self);
+        let metrics = std::mem::replace(&mut self.build_metrics, prev).unwrap_or_default();
+        if let Some(acc) = self.build_metrics.as_mut() {
+            acc.merge(&metrics);
+        }
+        res.map(|_| metrics)
+    }
+
+    /// load the sequences from a fastx file and call `progress` with the running count and
+    /// the name of the sequence after each sequence is loaded
+    pub fn load_seqs_from_fastx_with_progress(
+        &mut self,
+        filepath: String,
+        mut progress: impl FnMut(usize, &str),
+    ) -> Result<(), SeqDbError> {
+        let mut reader = self.get_fastx_reader(filepath)?;
+        self.load_seq_from_reader(&mut reader, None, &mut progress)
+    }
+
+    /// load the sequences from a fastx file without generating the shimmers in the `masks`
+    /// intervals, see `load_seqs_from_seq_vec_with_masks`
+    pub fn load_seqs_from_fastx_with_masks(
+        &mut self,
+        filepath: String,
+        masks: &MaskedRegions,
+    ) -> Result<(), SeqDbError> {
+        let mut reader = self.get_fastx_reader(filepath)?;
+        self.load_seq_from_reader(&mut reader, Some(masks), &mut |_, _| {})
+    }
+
+    /// load the sequences from a fastx file with the lowercase bases uppercased, the lowercase
+    /// intervals are kept in `CompactSeq::soft_mask` for `reconstruct_with_mask`
+    pub fn load_seqs_from_fastx_with_soft_mask(
+        &mut self,
+        filepath: String,
+    ) -> Result<(), SeqDbError> {
+        let first_sid = self.seqs.len();
+        let reader = self.get_fastx_reader(filepath)?.with_soft_mask(true);
+        let mut soft_masks = Vec::<Option<Vec<(u32, u32)>>>::new();
+        let mut bad_rec = None;
+        let mut named_seqs = reader
+            .map_while(|rec| rec.map_err(|e| bad_rec = Some(e)).ok())
+            .map(|rec| {
+                soft_masks.push(rec.soft_mask.filter(|soft_mask| !soft_mask.is_empty()));
+                (rec.source, rec.id, rec.seq)
+            });
+        let res = self.load_named_seqs(&mut named_seqs, None, &mut |_, _| {});
+        drop(named_seqs);
+        self.seqs[first_sid..]
+            .iter_mut()
+            .zip(soft_masks)
+            .for_each(|(seq, soft_mask)| {
+                // the bases are uppercased before they are counted
+                if let (Some(composition), Some(soft_mask)) =
+                    (seq.base_composition.as_mut(), soft_mask.as_ref())
+                {
+                    composition.lowercase_count =
+                        soft_mask.iter().map(|(bgn, end)| end - bgn).sum();
+                }
+                seq.soft_mask = soft_mask;
             });
+        res?;
+        bad_rec.map_or(Ok(()), |e| Err(record_error(e)))
+    }
+
+    /// check the in-memory fragments are there to append the new sequences to, the fragment
+    /// ids of a DB backed by a memory-mapped fragment store can not be extended
+    fn check_frags_appendable(&self) -> Result<(), std::io::Error> {
+        if self.frags.is_none() && self.frag_store.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "can not add sequences to a seq db backed by a memory-mapped fragment store",
+            ));
+        }
+        Ok(())
+    }
+
+    /// check the `max_fragment_len` leaves room for the bases after the k-mer overlap of an
+    /// internal fragment
+    fn check_max_fragment_len(&self) -> Result<(), std::io::Error> {
+        match self.max_fragment_len {
+            Some(m) if m <= self.shmmr_spec.k => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "max_fragment_len {} must be larger than k = {}",
+                    m, self.shmmr_spec.k
+                ),
+            )),
+            _ => Ok(()),
+        }
     }
 
-    pub fn load_seqs_from_fastx(&mut self, filepath: String) -> Result<(), std::io::Error> {
-        match self.get_fastx_reader(filepath)? {
-            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
-            GZFastaReader::GZFile(reader) => self.load_seq_from_reader(&mut reader.into_iter()),
+    /// add the sequences from a fastx file to the DB, the existing sequences and fragments keep
+    /// their ids and the new fragments are aligned to the existing ones when the shimmer pairs match
+    pub fn add_seqs_from_file(&mut self, filepath: String) -> Result<(), SeqDbError> {
+        self.check_frags_appendable()?;
+        self.load_seqs_from_fastx(filepath)
+    }
+
+    /// add a sequence to the DB, and return the id of the sequence
+    pub fn add_seq(&mut self, name: String, seq: Vec<u8>) -> Result<u32, SeqDbError> {
+        self.check_frags_appendable()?;
+        let sid = self.seqs.len() as u32;
+        self.try_load_seqs_from_seq_vec(&vec![(sid, None, name, seq)])?;
+        Ok(sid)
+    }
 
-            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
-            GZFastaReader::RegularFile(reader) => {
-                self.load_seq_from_reader(&mut reader.into_iter())
+    /// merge the sequences of another DB of the same shimmer spec into the DB without
+    /// recomputing the shimmers, e.g. to combine the DBs built for each sample, the sequences
+    /// of `other` are appended in the order of their ids with the names resolved by the
+    /// `duplicate_name_policy` of the DB, `DuplicateNamePolicy::Error` if it is not set
+    ///
+    /// the fragment ids of `other` are shifted past the fragments of the DB, a literal
+    /// internal fragment of `other` with the same bases as one of the DB under the same
+    /// shimmer pair is stored as a `FullMatch` of it, and the fragment signatures of `other`
+    /// are added to the `frag_map`; an index-only DB can only be merged with another
+    /// index-only DB, and the DB is not changed if the merge fails
+    pub fn merge(&mut self, mut other: CompactSeqDB) -> Result<(), SeqDbError> {
+        if self.shmmr_spec != other.shmmr_spec {
+            return Err(SeqDbError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can not merge the seq dbs of different shimmer specs",
+            )));
+        }
+        self.check_frags_appendable()?;
+        let other_has_frags = other.frags.is_some() || other.frag_store.is_some();
+        // a new DB without the sequences or the fragments takes the kind of `other`
+        let has_kind = self.frags.is_some() || !self.seqs.is_empty();
+        if has_kind && self.frags.is_some() != other_has_frags {
+            return Err(SeqDbError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can not merge an index-only seq db with a seq db of fragments",
+            )));
+        }
+        if self.duplicate_name_policy.unwrap_or_default() == DuplicateNamePolicy::Error {
+            let mut names = FxHashSet::<&[u8]>::default();
+            if let Some(seq) = other.seqs.iter().find(|&seq| {
+                self.name_to_id.contains_key(seq.name_bytes()) || !names.insert(seq.name_bytes())
+            }) {
+                return Err(SeqDbError::Parse(format!(
+                    "duplicate sequence name: {}",
+                    seq.name
+                )));
             }
+        }
+        let other_frag_crcs = if self.frag_crcs.is_some() && other_has_frags {
+            other.enable_frag_crcs()?;
+            other.frag_crcs.take()
+        } else {
+            None
+        };
+
+        let other_frags = match other.frags.take() {
+            Some(frags) => frags,
+            None if other.frag_store.is_some() => {
+                let frags = other.frag_source()?;
+                (0..frags.frag_count() as u32)
+                    .map(|frag_id| frags.get_frag(frag_id).unwrap().into_owned())
+                    .collect::<Fragments>()
+            }
+            None => Fragments::new(),
+        };
+        if other_has_frags && self.frags.is_none() {
+            self.frags = Some(Fragments::new());
+        }
+        let frag_offset = self.frags.as_ref().map_or(0, |frags| frags.len() as u32);
+
+        // the fragments of `other` stored as a copy of a fragment of the DB
+        let mut copy_of = FxHashMap::<u32, u32>::default();
+        if let Some(frags) = self.frags.as_ref() {
+            other.frag_map.iter().for_each(|(shmmr_pair, sigs)| {
+                if let Some(self_sigs) = self.frag_map.get(shmmr_pair) {
+                    sigs.iter().for_each(|sig| {
+                        if let Some(bases) = other_frags[sig.0 as usize].internal_bases() {
+                            if let Some(t_sig) = self_sigs.iter().find(|t_sig| {
+                                t_sig.3 - t_sig.2 == sig.3 - sig.2
+                                    && frags[t_sig.0 as usize]
+                                        .internal_bases()
+                                        .is_some_and(|b| b == bases)
+                            }) {
+                                copy_of.insert(sig.0, t_sig.0);
+                            }
+                        }
+                    });
+                }
+            });
+        }
+        let remap = |frag_id: u32| {
+            copy_of
+                .get(&frag_id)
+                .map_or(frag_id + frag_offset, |&id| id)
         };
+        if let Some(frags) = self.frags.as_mut() {
+            other_frags
+                .into_iter()
+                .enumerate()
+                .for_each(|(frag_id, frag)| {
+                    let frag = match copy_of.get(&(frag_id as u32)) {
+                        Some(&base_frag_id) => Fragment::AlnSegments((
+                            base_frag_id,
+                            false,
+                            frag.base_len(),
+                            vec![AlnSegment::FullMatch],
+                        )),
+                        // the bases of a base fragment stored as a copy are the ones of the
+                        // copied fragment, so the alignment segments are kept
+                        None => match frag {
+                            Fragment::AlnSegments((base_frag_id, reversed, length, aln_segs)) => {
+                                Fragment::AlnSegments((
+                                    remap(base_frag_id),
+                                    reversed,
+                                    length,
+                                    aln_segs,
+                                ))
+                            }
+                            frag => frag,
+                        },
+                    };
+                    frags.push(frag);
+                });
+        }
+        if let (Some(frag_crcs), Some(other_frag_crcs)) = (self.frag_crcs.as_mut(), other_frag_crcs)
+        {
+            frag_crcs.extend(other_frag_crcs);
+        }
 
+        let seq_offset = self.seqs.len() as u32;
+        let other_frag_map = std::mem::take(&mut other.frag_map);
+        if let (Some(metrics), Some(other_metrics)) =
+            (self.build_metrics.as_mut(), other.build_metrics.as_ref())
+        {
+            metrics.merge(other_metrics);
+        }
+        for mut seq in other.seqs.into_iter() {
+            (seq.name, seq.raw_name) = self.resolve_seq_name(&seq.name, seq.raw_name.as_deref())?;
+            seq.id += seq_offset;
+            seq.seq_frag_range.0 += frag_offset;
+            self.push_seq(seq);
+        }
+        other_frag_map.into_iter().for_each(|(shmmr_pair, sigs)| {
+            let e = self.frag_map.entry(shmmr_pair).or_default();
+            sigs.into_iter().for_each(|mut sig| {
+                sig.0 += frag_offset;
+                sig.1 += seq_offset;
+                e.push(sig);
+            });
+            if let Some(filter) = self.high_freq_filter.as_mut() {
+                filter.update(&shmmr_pair, e.len());
+            }
+        });
         Ok(())
     }
 
-    fn load_index_from_reader(&mut self, reader: &mut dyn Iterator<Item = io::Result<SeqRec>>) {
+    fn load_index_from_reader(
+        &mut self,
+        reader: &mut dyn Iterator<Item = io::Result<SeqRec>>,
+    ) -> Result<(), SeqDbError> {
         let mut seqs = <Vec<(u32, Option<String>, String, Vec<u8>)>>::new();
+        let mut raw_names = Vec::<Option<Vec<u8>>>::new();
         let mut sid = 0;
         loop {
             let mut count = 0;
             let mut end_ext_loop = false;
             seqs.clear();
+            raw_names.clear();
 
             loop {
                 if let Some(rec) = reader.next() {
-                    let rec = rec.unwrap();
+                    let rec = rec.map_err(record_error)?;
                     let source = rec.source;
-                    let seqname = String::from_utf8_lossy(&rec.id).into_owned();
+                    let (seqname, raw_name) = seq_name_from_id(rec.id);
                     seqs.push((sid, source, seqname, rec.seq));
+                    raw_names.push(raw_name);
                     sid += 1;
                 } else {
                     end_ext_loop = true;
@@ -563,14 +2457,40 @@ impl CompactSeqDB {
                 }
             }
 
-            self.load_index_from_seq_vec(&seqs);
+            self.load_index_with_raw_names(&seqs, &raw_names)?;
             if end_ext_loop {
                 break;
             }
         }
+        Ok(())
     }
 
+    /// load the shimmer index of the sequences, the duplicate names are kept unless the
+    /// `duplicate_name_policy` is set, it panics on a duplicate name under
+    /// `DuplicateNamePolicy::Error` or a DB loaded from a file without its hash, see
+    /// `try_load_index_from_seq_vec`
     pub fn load_index_from_seq_vec(&mut self, seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>) {
+        self.try_load_index_from_seq_vec(seqs)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// the same as `load_index_from_seq_vec`, but it fails instead of panicking, a duplicate
+    /// name fails with `SeqDbError::Parse` under `DuplicateNamePolicy::Error`, the default if the
+    /// `duplicate_name_policy` is not set
+    pub fn try_load_index_from_seq_vec(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+    ) -> Result<(), SeqDbError> {
+        self.with_duplicate_name_error(|sdb| sdb.load_index_with_raw_names(seqs, &[]))
+    }
+
+    /// the `raw_names` are the `CompactSeq::raw_name` of the sequences, it can be empty if
+    /// all names are valid UTF-8
+    fn load_index_with_raw_names(
+        &mut self,
+        seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+        raw_names: &[Option<Vec<u8>>],
+    ) -> Result<(), SeqDbError> {
         let all_shmmrs = self.get_shmmrs_from_seqs(seqs);
         let seq_names = seqs
             .iter()
@@ -602,16 +2522,26 @@ impl CompactSeqDB {
             })
             .collect::<Vec<(u32, CompactSeq, Vec<_>)>>()
             .into_iter()
-            .for_each(|(sid, cs, internal_frags)| {
+            .enumerate()
+            .try_for_each(|(i, (sid, mut cs, internal_frags))| {
+                let raw_name = raw_names.get(i).and_then(|raw_name| raw_name.as_deref());
+                (cs.name, cs.raw_name) = self.resolve_seq_name(&cs.name, raw_name)?;
+                if self.tally_base_composition {
+                    cs.base_composition = Some(BaseComposition::of(&seqs[i].3));
+                }
                 internal_frags
                     .iter()
                     .zip(cs.seq_frag_range.0..cs.seq_frag_range.0 + cs.seq_frag_range.1)
                     .for_each(|((shmmr, bgn, end, orientation), frg_id)| {
                         let e = self.frag_map.entry(*shmmr).or_default();
                         e.push((frg_id, sid, *bgn, *end, *orientation));
+                        if let Some(filter) = self.high_freq_filter.as_mut() {
+                            filter.update(shmmr, e.len());
+                        }
                     });
-                self.seqs.push(cs);
-            });
+                self.push_seq(cs);
+                Ok(())
+            })
     }
 
     fn _write_shmmr_vec_from_reader(
@@ -643,97 +2573,1118 @@ impl CompactSeqDB {
                 }
             }
 
-            self.get_shmmrs_from_seqs(&seqs)
-                .iter()
-                .map(|(_, v)| v)
-                .for_each(|v| {
-                    v.iter().for_each(|m| {
-                        let _ = writer.write_u64::<LittleEndian>(m.x);
-                        let _ = writer.write_u64::<LittleEndian>(m.y);
-                    });
-                });
+            self.get_shmmrs_from_seqs(&seqs)
+                .iter()
+                .map(|(_, v)| v)
+                .for_each(|v| {
+                    v.iter().for_each(|m| {
+                        let _ = writer.write_u64::<LittleEndian>(m.x);
+                        let _ = writer.write_u64::<LittleEndian>(m.y);
+                    });
+                });
+
+            if end_ext_loop {
+                break;
+            }
+        }
+    }
+
+    pub fn load_index_from_fastx(&mut self, filepath: String) -> Result<(), SeqDbError> {
+        let mut reader = self.get_fastx_reader(filepath)?;
+        self.load_index_from_reader(&mut reader)
+    }
+    #[cfg(feature = "with_agc")]
+    pub fn load_index_from_agcfile(&mut self, agcfile: AGCFile) -> Result<(), SeqDbError> {
+        //let agcfile = AGCFile::new(filepath);
+
+        self.load_index_from_reader(&mut agcfile.into_iter())
+    }
+}
+
+impl CompactSeqDB {
+    /// the in-memory fragments, or the memory-mapped fragment store if the fragments are not
+    /// in memory, it fails with `Unsupported` if neither is loaded, e.g. for an index-only DB
+    pub fn frag_source(&self) -> Result<&dyn FragSource, std::io::Error> {
+        match (&self.frags, &self.frag_store) {
+            (Some(frags), _) => Ok(frags as &dyn FragSource),
+            (None, Some(frag_store)) => Ok(frag_store.as_ref() as &dyn FragSource),
+            (None, None) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the fragments of the seq db are not loaded",
+            )),
+        }
+    }
+
+    /// query a sequence and return the hit chains in the base coordinates of the query and
+    /// the target sequences, sorted by the chain score, a chain with both orientations is split
+    ///
+    /// the query path only reads the index and the fragments, so a DB wrapped in an `Arc`
+    /// can serve the queries from many threads without locking
+    ///
+    /// with `orient_to_query`, the target span of each hit is reconstructed into
+    /// `AlignmentHit::target_seq` in the orientation of the query, a chain flipping the
+    /// orientation is split into the runs of each orientation first
+    ///
+    /// it panics if the shimmers of the DB are computed by a hash not set by `set_hasher`,
+    /// see `try_query`
+    pub fn query(&self, seq: &[u8], penalty: f32, orient_to_query: bool) -> Vec<AlignmentHit> {
+        self.try_query(seq, penalty, orient_to_query)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// the same as `query`, but it fails with `InvalidInput` instead of panicking if the DB
+    /// is built with another hash than the one of the DB, e.g. a DB file built with a custom
+    /// hash and loaded without `set_hasher`
+    pub fn try_query(
+        &self,
+        seq: &[u8],
+        penalty: f32,
+        orient_to_query: bool,
+    ) -> Result<Vec<AlignmentHit>, std::io::Error> {
+        self.query_with_pair_count(
+            seq,
+            penalty,
+            orient_to_query,
+            self.pair_target_count.as_ref(),
+        )
+    }
+
+    /// query a batch of sequences, the hits of each query are the same as the ones of `query`
+    ///
+    /// the per-target shimmer pair counts are built once for the batch if they are not built
+    /// by `build_pair_target_count` yet, and the queries run in parallel
+    pub fn query_batch(
+        &self,
+        frags: &[Vec<u8>],
+        penalty: f32,
+        orient_to_query: bool,
+    ) -> Vec<Vec<AlignmentHit>> {
+        let pair_target_count = match self.pair_target_count.as_ref() {
+            Some(pair_target_count) => Cow::Borrowed(pair_target_count),
+            None => Cow::Owned(shmmr_pair_target_count(&self.frag_map)),
+        };
+        frags
+            .par_iter()
+            .map(|seq| {
+                self.query_with_pair_count(seq, penalty, orient_to_query, Some(&*pair_target_count))
+                    .unwrap_or_else(|e| panic!("{}", e))
+            })
+            .collect()
+    }
+
+    fn query_with_pair_count(
+        &self,
+        seq: &[u8],
+        penalty: f32,
+        orient_to_query: bool,
+        pair_target_count: Option<&ShmmrPairTargetCount>,
+    ) -> Result<Vec<AlignmentHit>, std::io::Error> {
+        let k = self.shmmr_spec.k;
+        let shmmrs = self.seq_shmmrs(0, seq)?;
+        let raw_query_hits = raw_query_shmmrs(
+            &self.frag_map,
+            &shmmrs,
+            self.high_freq_filter.as_ref(),
+            self.shmmr_spec.alphabet,
+        );
+        let target_hits = query_fragment_to_hps_with_pair_count(
+            raw_query_hits,
+            penalty,
+            None,
+            None,
+            None,
+            None,
+            DEFAULT_MIN_CHAIN_SCORE,
+            DEFAULT_MIN_CHAIN_LEN,
+            pair_target_count,
+        );
+        let mut out = Vec::<AlignmentHit>::new();
+        target_hits.into_iter().for_each(|(sid, chains)| {
+            let target = &self.seqs[sid as usize];
+            chains.iter().for_each(|(score, hps)| {
+                split_chain_by_orientation(hps).into_iter().for_each(|run| {
+                    let ((query_start, query_end), (target_start, target_end)) =
+                        hit_run_to_base_span(run, k);
+                    let target_end = target_end.min(target.len as u32);
+                    let strand = Strand::of(&run[0]).as_u8();
+                    let target_seq = if orient_to_query {
+                        let target_seq = self.reconstruct_range(sid, target_start, target_end);
+                        if strand == 1 {
+                            Some(reverse_complement(&target_seq))
+                        } else {
+                            Some(target_seq)
+                        }
+                    } else {
+                        None
+                    };
+                    let (query_clip_start, query_clip_end) =
+                        chain_query_clips(run, seq.len() as u32, k);
+                    out.push(AlignmentHit {
+                        name: target.name.clone(),
+                        seq_id: sid,
+                        query_start,
+                        query_end: query_end.min(seq.len() as u32),
+                        query_clip_start,
+                        query_clip_end,
+                        target_start,
+                        target_end,
+                        strand,
+                        score: *score,
+                        target_seq,
+                    });
+                });
+            });
+        });
+        out.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap()
+                .then(a.seq_id.cmp(&b.seq_id))
+        });
+        Ok(out)
+    }
+
+    /// the per-base coverage depth of the target `sid` by the hit chains to it, e.g. the
+    /// chains of the target from `query_fragment_to_hps` of many reads, the target span of
+    /// each run of the same orientation is mapped to the bases as the hits of `query`, so
+    /// the chains on both strands are counted, and a base is counted once per chain even if
+    /// the runs of a chain overlap
+    pub fn target_coverage(&self, sid: u32, chains: &[(f32, Vec<HitPair>)]) -> Vec<u32> {
+        let k = self.shmmr_spec.k;
+        let target_len = self.seqs[sid as usize].len;
+        // the coverage changes at the starts and the ends of the merged spans
+        let mut delta = vec![0_i64; target_len + 1];
+        chains.iter().for_each(|(_, hps)| {
+            let mut spans = split_chain_by_orientation(hps)
+                .into_iter()
+                .map(|run| {
+                    let (_, (target_start, target_end)) = hit_run_to_base_span(run, k);
+                    (target_start as usize, (target_end as usize).min(target_len))
+                })
+                .filter(|(bgn, end)| bgn < end)
+                .collect::<Vec<_>>();
+            spans.sort();
+            let mut merged = Vec::<(usize, usize)>::new();
+            spans
+                .into_iter()
+                .for_each(|(bgn, end)| match merged.last_mut() {
+                    Some(last) if bgn <= last.1 => last.1 = last.1.max(end),
+                    _ => merged.push((bgn, end)),
+                });
+            merged.into_iter().for_each(|(bgn, end)| {
+                delta[bgn] += 1;
+                delta[end] -= 1;
+            });
+        });
+        let mut depth = 0_i64;
+        delta[..target_len]
+            .iter()
+            .map(|d| {
+                depth += d;
+                depth as u32
+            })
+            .collect()
+    }
+
+    /// build the filter of the shimmer pairs shared by more than `min_count` fragments, the
+    /// queries skip copying the hits of those pairs, the loads, `add_seq` and `merge` keep
+    /// the filter up to date, so building it on an empty DB gets it built during the loads
+    pub fn build_high_freq_filter(&mut self, min_count: usize) {
+        self.high_freq_filter = Some(HighFreqFilter::new(&self.frag_map, min_count));
+    }
+
+    /// build the per-target shimmer pair counts once, so the queries skip counting the hits
+    /// of each shimmer pair on each target, adding sequences to the DB drops the counts
+    pub fn build_pair_target_count(&mut self) {
+        self.pair_target_count = Some(shmmr_pair_target_count(&self.frag_map));
+    }
+
+    /// remove the shimmer pairs shared by fewer than `min_multiplicity` fragments from the
+    /// `frag_map` to shrink the index, it returns the number of the shimmer pairs removed
+    ///
+    /// the pruning is query-only: the fragments and the sequences are kept, so the
+    /// reconstruction is not changed, but the queries, the methods walking the `frag_map`,
+    /// e.g. `fragment_coverage`, and the shimmer map files written afterwards miss the removed
+    /// shimmer pairs, and the sequences added later do not reuse their fragments; it can not
+    /// be undone for the in-memory index, reload the DB to get the pairs back
+    pub fn prune_index(&mut self, min_multiplicity: u32) -> usize {
+        let n_shmmr_pairs = self.frag_map.len();
+        self.frag_map
+            .retain(|_, sigs| sigs.len() >= min_multiplicity as usize);
+        self.pair_target_count = None;
+        n_shmmr_pairs - self.frag_map.len()
+    }
+
+    /// build the suffix array of the fragments for `find_substring`, it costs about 5 bytes
+    /// per base of the distinct fragments, adding sequences to the DB drops the index
+    pub fn build_substring_index(&mut self) {
+        self.substring_index = Some(SubstringIndex::new(self));
+    }
+
+    /// the `(seq_id, pos)` of the exact occurrences of a pattern in the sequences, in the
+    /// order of the sequences and the positions, e.g. to find a motif shorter than k, a
+    /// temporary index is built if it is not built by `build_substring_index`
+    ///
+    /// the index looks up the first `k + 1` bases of a longer pattern, and the occurrences
+    /// are confirmed against the reconstructed bases
+    pub fn find_substring(&self, pattern: &[u8]) -> Vec<(u32, u32)> {
+        let index = match self.substring_index.as_ref() {
+            Some(index) => Cow::Borrowed(index),
+            None => Cow::Owned(SubstringIndex::new(self)),
+        };
+        let k = index.k as usize;
+        if pattern.len() <= k + 1 {
+            return index.find(pattern);
+        }
+        index
+            .find(&pattern[..k + 1])
+            .into_iter()
+            .filter(|&(seq_id, pos)| {
+                let end = pos as usize + pattern.len();
+                end <= self.seqs[seq_id as usize].len
+                    && self.reconstruct_range(seq_id, pos, end as u32) == pattern
+            })
+            .collect()
+    }
+
+    /// index the positions of the fragments for `fragment_occurrences`, the index is
+    /// updated as the sequences are added, so the loads after it decode the new fragments
+    /// for their checksums unless `frag_crcs` is set
+    pub fn build_fragment_occurrences(&mut self) -> Result<(), FragmentError> {
+        self.frag_occurrences = Some(FragmentOccurrences::new(self)?);
+        Ok(())
+    }
+
+    /// the `(seq_id, offset)` of every fragment of the same bases as the fragment `frag_id`,
+    /// including itself, in the order of the sequences and the offsets, e.g. to find the
+    /// haplotypes carrying an exact block, the offset of an internal fragment is the start of
+    /// its leading k-mer overlap, so the fragment bases are the `[offset, offset + base_len)`
+    /// of each sequence, a temporary index is built if it is not built by
+    /// `build_fragment_occurrences`
+    ///
+    /// the copies are found by their bases, including the ones stored as the other kinds of
+    /// fragments, but not the ones in the reverse complement, it fails with `NotFound` for
+    /// a fragment not in a sequence with the fragments loaded
+    pub fn try_fragment_occurrences(
+        &self,
+        frag_id: u32,
+    ) -> Result<Vec<(u32, usize)>, FragmentError> {
+        match self.frag_occurrences.as_ref() {
+            Some(index) => index.occurrences(self, frag_id),
+            None => FragmentOccurrences::new(self)?.occurrences(self, frag_id),
+        }
+    }
+
+    /// `try_fragment_occurrences`, it panics for a fragment not found or not decoded
+    pub fn fragment_occurrences(&self, frag_id: u32) -> Vec<(u32, usize)> {
+        self.try_fragment_occurrences(frag_id).unwrap()
+    }
+
+    /// reconstruct the bases `[start, end)` of a sequence, only the fragments overlapping
+    /// the range are reconstructed, `end` is clipped to the sequence length
+    ///
+    /// it panics for a `seq_id` not in the DB, like `get_sub_seq_by_id`
+    pub fn reconstruct_range(&self, seq_id: u32, start: u32, end: u32) -> Vec<u8> {
+        let end = end.min(self.seqs[seq_id as usize].len as u32);
+        if start >= end {
+            return vec![];
+        }
+        self.get_sub_seq_by_id(seq_id, start, end)
+    }
+
+    /// reconstruct a sequence with the bases in its `soft_mask` intervals lowercased, it is the
+    /// same as `get_seq_by_id` for the sequences without a soft mask
+    pub fn reconstruct_with_mask(&self, seq_id: u32) -> Vec<u8> {
+        let mut seq = self.get_seq_by_id(seq_id);
+        if let Some(soft_mask) = &self.seqs[seq_id as usize].soft_mask {
+            apply_soft_mask(&mut seq, soft_mask);
+        }
+        seq
+    }
+
+    /// the base counts of a sequence, they are tallied at the load with
+    /// `tally_base_composition`, or counted on the bases reconstructed with the soft mask
+    /// otherwise, which panics for an index-only sequence
+    pub fn base_composition(&self, seq_id: u32) -> BaseComposition {
+        self.seqs[seq_id as usize]
+            .base_composition
+            .unwrap_or_else(|| BaseComposition::of(&self.reconstruct_with_mask(seq_id)))
+    }
+
+    /// write the bases of a sequence to `out` fragment by fragment, without building the
+    /// whole sequence in memory
+    pub fn reconstruct_to<W: Write>(&self, seq_id: u32, out: &mut W) -> Result<(), std::io::Error> {
+        let (frag_start, n_frags) = self.seqs[seq_id as usize].seq_frag_range;
+        self.write_frag_bases(seq_id, frag_start..frag_start + n_frags, out)
+    }
+
+    /// reconstruct a sequence into `out` (cleared first) without the temporary buffers of
+    /// `get_seq_by_id`, only the 2-bit packed base fragments of the aligned fragments are
+    /// decoded into `scratch`, so reusing `out` and `scratch` across the calls saves the heap
+    /// allocations once they are large enough, except for the fragments decoded by a
+    /// memory-mapped fragment store
+    ///
+    /// the aligned fragments on the reverse strand are reverse complemented in `out`, it
+    /// fails with `InvalidData` as `try_get_seq_by_id`
+    pub fn reconstruct_into(
+        &self,
+        seq_id: u32,
+        out: &mut Vec<u8>,
+        scratch: &mut Vec<u8>,
+    ) -> Result<(), std::io::Error> {
+        let k = self.shmmr_spec.k as usize;
+        let frags = self.frag_source()?;
+        let (frag_start, n_frags) = self.seqs[seq_id as usize].seq_frag_range;
+        out.clear();
+        for frag_id in frag_start..frag_start + n_frags {
+            let frag = frags.get_frag(frag_id).unwrap();
+            let bgn = out.len();
+            match frag.as_ref() {
+                Fragment::Prefix(b) | Fragment::Suffix(b) => {
+                    out.extend_from_slice(b);
+                    continue;
+                }
+                Fragment::Internal(b) => {
+                    out.extend_from_slice(&b[k..]);
+                    continue;
+                }
+                Fragment::Internal2Bit(p) => unpack_bases_into(p, out),
+                Fragment::InternalNRuns(r) => unpack_n_runs_into(r, out),
+                Fragment::AlnSegments((base_frag_id, reversed, _length, aln_segs)) => {
+                    let base_frg = frags.get_frag(*base_frag_id);
+                    let base_seq: &[u8] = match base_frg.as_deref() {
+                        Some(Fragment::Internal(b)) => b,
+                        Some(Fragment::Internal2Bit(p)) => {
+                            scratch.clear();
+                            unpack_bases_into(p, scratch);
+                            &scratch[..]
+                        }
+                        Some(Fragment::InternalNRuns(r)) => {
+                            scratch.clear();
+                            unpack_n_runs_into(r, scratch);
+                            &scratch[..]
+                        }
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "fragment {} of sequence {} refers to non-internal fragment {}",
+                                    frag_id, seq_id, base_frag_id
+                                ),
+                            ))
+                        }
+                    };
+                    try_reconstruct_seq_from_aln_segs_into(base_seq, aln_segs, out)?;
+                    if *reversed {
+                        reverse_complement_in_place(&mut out[bgn..]);
+                    }
+                }
+            }
+            // the leading k-mer overlap of the internal fragments
+            out.drain(bgn..bgn + k);
+        }
+        Ok(())
+    }
+
+    /// write a sequence as a FASTA record with the bases in one line
+    fn write_fasta_record<W: Write>(&self, seq_id: u32, out: &mut W) -> Result<(), std::io::Error> {
+        out.write_all(b">")?;
+        out.write_all(self.seqs[seq_id as usize].name_bytes())?;
+        out.write_all(b"\n")?;
+        self.reconstruct_to(seq_id, out)?;
+        out.write_all(b"\n")
+    }
+
+    /// reconstruct all sequences to the FASTA files in `dir` (created if it is not there),
+    /// the sequences are reconstructed in parallel on the current rayon pool, it returns the
+    /// paths of the files written
+    ///
+    /// with `PerFile::Sequence`, each sequence is streamed to its own file, the sequences
+    /// with the same file name, see `export_file_name`, get the sequence id appended to the
+    /// file name after the first one, with `PerFile::Combined`, the sequences of a batch
+    /// are reconstructed in parallel and written in the order of the ids
+    pub fn export_all(
+        &self,
+        dir: String,
+        per_file: PerFile,
+    ) -> Result<Vec<String>, std::io::Error> {
+        let dir = std::path::Path::new(&dir);
+        std::fs::create_dir_all(dir)?;
+        match per_file {
+            PerFile::Sequence => {
+                let mut used_names = FxHashSet::<String>::default();
+                let files = self
+                    .seqs
+                    .iter()
+                    .map(|seq| {
+                        let mut file_name = export_file_name(seq.name_bytes());
+                        if !used_names.insert(file_name.clone()) {
+                            let stem = file_name.strip_suffix(".fa").unwrap();
+                            file_name = format!("{}_{}.fa", stem, seq.id);
+                            used_names.insert(file_name.clone());
+                        }
+                        (seq.id, dir.join(file_name).to_string_lossy().to_string())
+                    })
+                    .collect::<Vec<_>>();
+                files.par_iter().try_for_each(|(seq_id, path)| {
+                    let mut out = BufWriter::new(File::create(path)?);
+                    self.write_fasta_record(*seq_id, &mut out)?;
+                    out.flush()
+                })?;
+                Ok(files.into_iter().map(|(_, path)| path).collect())
+            }
+            PerFile::Combined => {
+                let path = dir.join("seqs.fa").to_string_lossy().to_string();
+                let mut out = BufWriter::new(File::create(&path)?);
+                let batch_size = rayon::current_num_threads() * 4;
+                for batch in self.seqs.chunks(batch_size) {
+                    let records = batch
+                        .par_iter()
+                        .map(|seq| {
+                            let mut record = Vec::<u8>::with_capacity(seq.len + 1);
+                            self.write_fasta_record(seq.id, &mut record)?;
+                            Ok(record)
+                        })
+                        .collect::<Result<Vec<_>, std::io::Error>>()?;
+                    records
+                        .iter()
+                        .try_for_each(|record| out.write_all(record))?;
+                }
+                out.flush()?;
+                Ok(vec![path])
+            }
+        }
+    }
+
+    /// write the fragment graph in GFA 1.0, e.g. to view it in Bandage
+    ///
+    /// the fragments of the same bases in either orientation are one segment named by the id
+    /// of the first of them, with the bases in the smaller orientation, each sequence is a
+    /// path through the segments, the consecutive fragments of the paths are linked with the
+    /// k-mer overlap of an internal fragment with the fragment before it, the suffix fragment
+    /// does not overlap the fragment before it
+    pub fn write_gfa<W: Write>(&self, out: &mut W) -> Result<(), std::io::Error> {
+        let k = self.shmmr_spec.k;
+        let orientation = |reversed: bool| if reversed { '-' } else { '+' };
+        writeln!(out, "H\tVN:Z:1.0")?;
+
+        let mut segments = FxHashMap::<Vec<u8>, u32>::default();
+        // the (segment, reversed, the overlap with the fragment before it) along each sequence
+        let mut paths = Vec::<Vec<(u32, bool, u32)>>::with_capacity(self.seqs.len());
+        for seq in self.seqs.iter() {
+            let (frag_start, n_frags) = seq.seq_frag_range;
+            let mut path = Vec::<(u32, bool, u32)>::with_capacity(n_frags as usize);
+            for (i, frag_id) in (frag_start..frag_start + n_frags).enumerate() {
+                let bases = self.fragment_bases(frag_id)?;
+                let rc_bases = reverse_complement(&bases);
+                let reversed = rc_bases < bases;
+                let bases = if reversed { rc_bases } else { bases };
+                let segment = match segments.get(&bases) {
+                    Some(segment) => *segment,
+                    None => {
+                        let seg_bases = if bases.is_empty() {
+                            "*".into()
+                        } else {
+                            String::from_utf8_lossy(&bases)
+                        };
+                        writeln!(out, "S\t{}\t{}", frag_id, seg_bases)?;
+                        segments.insert(bases, frag_id);
+                        frag_id
+                    }
+                };
+                // the fragments are the prefix, the internal ones and then the suffix
+                let overlap = if i + 1 < n_frags as usize { k } else { 0 };
+                path.push((segment, reversed, overlap));
+            }
+            paths.push(path);
+        }
+        drop(segments);
+
+        // a link and the one of the segments swapped and flipped are the same
+        let mut links = FxHashSet::<(u32, bool, u32, bool, u32)>::default();
+        for path in paths.iter() {
+            for w in path.windows(2) {
+                let ((s0, r0, _), (s1, r1, overlap)) = (w[0], w[1]);
+                let link = (s0, r0, s1, r1, overlap).min((s1, !r1, s0, !r0, overlap));
+                if links.insert(link) {
+                    let (s0, r0, s1, r1, overlap) = link;
+                    writeln!(
+                        out,
+                        "L\t{}\t{}\t{}\t{}\t{}M",
+                        s0,
+                        orientation(r0),
+                        s1,
+                        orientation(r1),
+                        overlap
+                    )?;
+                }
+            }
+        }
+
+        for (seq, path) in self.seqs.iter().zip(paths) {
+            let segment_names = path
+                .iter()
+                .map(|(segment, reversed, _)| format!("{}{}", segment, orientation(*reversed)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let overlaps = if path.len() > 1 {
+                path[1..]
+                    .iter()
+                    .map(|(_, _, overlap)| format!("{}M", overlap))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            } else {
+                "*".to_string()
+            };
+            // the raw bytes of the name, e.g. a name that is not valid UTF-8, see `name_bytes`
+            out.write_all(b"P\t")?;
+            out.write_all(seq.name_bytes())?;
+            writeln!(out, "\t{}\t{}", segment_names, overlaps)?;
+        }
+        Ok(())
+    }
+
+    /// the number of the other sequences sharing the shimmer pair of each fragment of a
+    /// sequence, in the order of the fragments along the sequence, the prefix and the suffix
+    /// fragments are 0
+    pub fn fragment_coverage(&self, seq_id: u32) -> Vec<u32> {
+        let (frag_start, n_frags) = self.seqs[seq_id as usize].seq_frag_range;
+        let mut coverage = vec![0_u32; n_frags as usize];
+        self.frag_map.values().for_each(|sigs| {
+            if !sigs.iter().any(|sig| sig.1 == seq_id) {
+                return;
+            }
+            let n_other_seqs = sigs
+                .iter()
+                .filter(|sig| sig.1 != seq_id)
+                .map(|sig| sig.1)
+                .collect::<FxHashSet<u32>>()
+                .len() as u32;
+            sigs.iter()
+                .filter(|sig| sig.1 == seq_id)
+                .for_each(|sig| coverage[(sig.0 - frag_start) as usize] = n_other_seqs);
+        });
+        coverage
+    }
+
+    /// the ids of the fragments of `seq_a` sharing the shimmer pair with a fragment of
+    /// `seq_b`, in the ascending order, the fragments are stored per sequence so a shared
+    /// fragment has a different id in each sequence
+    pub fn shared_fragments(&self, seq_a: u32, seq_b: u32) -> Vec<u32> {
+        let mut frag_ids = self
+            .frag_map
+            .values()
+            .filter(|sigs| sigs.iter().any(|sig| sig.1 == seq_b))
+            .flat_map(|sigs| sigs.iter().filter(|sig| sig.1 == seq_a).map(|sig| sig.0))
+            .collect::<Vec<u32>>();
+        frag_ids.sort_unstable();
+        frag_ids.dedup();
+        frag_ids
+    }
+
+    /// the positions `(pos_a, pos_b, strand)` of the shimmers of `seq_a` matching the ones of
+    /// `seq_b` through the shimmer pairs the two sequences share, e.g. for a dotplot, sorted
+    /// and without the duplicates
+    ///
+    /// the positions are the ends of the k-mers as in the `HitPair`s, the strand is 0 for a
+    /// shimmer pair in the same orientation on both sequences and 1 otherwise
+    pub fn anchors_between(&self, seq_a: u32, seq_b: u32) -> Vec<(u32, u32, u8)> {
+        let mut anchors = Vec::<(u32, u32, u8)>::new();
+        self.frag_map.values().for_each(|sigs| {
+            let sigs_b = sigs.iter().filter(|sig| sig.1 == seq_b).collect::<Vec<_>>();
+            if sigs_b.is_empty() {
+                return;
+            }
+            sigs.iter().filter(|sig| sig.1 == seq_a).for_each(|sig_a| {
+                sigs_b.iter().for_each(|sig_b| {
+                    if sig_a.4 == sig_b.4 {
+                        anchors.push((sig_a.2, sig_b.2, 0));
+                        anchors.push((sig_a.3, sig_b.3, 0));
+                    } else {
+                        anchors.push((sig_a.2, sig_b.3, 1));
+                        anchors.push((sig_a.3, sig_b.2, 1));
+                    }
+                })
+            });
+        });
+        anchors.sort_unstable();
+        anchors.dedup();
+        anchors
+    }
+
+    /// the Jaccard index of the shimmer pairs of every two sequences, without aligning the
+    /// sequences, the entry is 0 if neither of the two sequences has a shimmer pair
+    pub fn pairwise_similarity_matrix(&self) -> Vec<Vec<f32>> {
+        let n_seqs = self.seqs.len();
+        // the number of the distinct shimmer pairs shared by each two sequences, the diagonal
+        // is the number of the distinct shimmer pairs of the sequence
+        let mut n_shared = vec![vec![0_u32; n_seqs]; n_seqs];
+        self.frag_map.values().for_each(|sigs| {
+            let mut sids = sigs.iter().map(|sig| sig.1).collect::<Vec<u32>>();
+            sids.sort_unstable();
+            sids.dedup();
+            sids.iter().enumerate().for_each(|(i, &sid_a)| {
+                sids[i..].iter().for_each(|&sid_b| {
+                    n_shared[sid_a as usize][sid_b as usize] += 1;
+                })
+            });
+        });
+        (0..n_seqs)
+            .map(|a| {
+                (0..n_seqs)
+                    .map(|b| {
+                        let shared = n_shared[a.min(b)][a.max(b)];
+                        let union = n_shared[a][a] + n_shared[b][b] - shared;
+                        if union == 0 {
+                            0.0
+                        } else {
+                            shared as f32 / union as f32
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// the MinHash sketch of a sequence for `jaccard_estimate`, the `size` smallest distinct
+    /// hashes of its shimmers in the ascending order, e.g. to pre-cluster the sequences
+    /// before chaining them, the shimmers are computed on the reconstructed bases with the
+    /// spec and the hash of the DB
+    ///
+    /// it fails with `Unsupported` if the fragments are not loaded, e.g. for an index-only
+    /// DB, and as `try_get_seq_by_id` and `seq_shmmrs` otherwise
+    pub fn try_sketch(&self, seq_id: u32, size: usize) -> Result<Vec<u64>, std::io::Error> {
+        if self.frags.is_none() && self.frag_store.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("the fragments of sequence {} are not loaded", seq_id),
+            ));
+        }
+        let seq = self.try_get_seq_by_id(seq_id)?;
+        let mut hashes = self
+            .seq_shmmrs(seq_id, &seq)?
+            .iter()
+            .map(|m| m.hash())
+            .collect::<Vec<u64>>();
+        hashes.sort_unstable();
+        hashes.dedup();
+        hashes.truncate(size);
+        Ok(hashes)
+    }
+
+    /// `try_sketch`, it panics for an index-only sequence or a DB loaded with another hash
+    pub fn sketch(&self, seq_id: u32, size: usize) -> Vec<u64> {
+        self.try_sketch(seq_id, size).unwrap()
+    }
+
+    /// diff two sequences at the fragment level without a base-level alignment, the
+    /// fragments are matched by their shimmer pairs, orientations and lengths, so the diff
+    /// misses the substitutions keeping the length of a fragment, and it is only meaningful
+    /// for two sequences in the same orientation
+    ///
+    /// the runs of the shared fragments, the deletions (in `a` only) and the insertions
+    /// (in `b` only) are reported along the sequences, a substitution is a deletion followed
+    /// by an insertion at the same position, the cost grows with the square of the number of
+    /// the differing fragments, so it is for the closely related sequences
+    pub fn fragment_diff(&self, seq_id_a: u32, seq_id_b: u32) -> Vec<DiffOp> {
+        let frags_a = self.frag_keys(seq_id_a);
+        let frags_b = self.frag_keys(seq_id_b);
+        let keys_a = frags_a.iter().map(|f| &f.2).collect::<Vec<_>>();
+        let keys_b = frags_b.iter().map(|f| &f.2).collect::<Vec<_>>();
+        let matches = diff_matches(&keys_a, &keys_b);
+
+        let len_a = self.seqs[seq_id_a as usize].len as u32;
+        let len_b = self.seqs[seq_id_b as usize].len as u32;
+        let run = |frags: &[(u32, (u32, u32), FragKey)]| {
+            let ids = frags.iter().map(|f| f.0).collect::<Vec<_>>();
+            (ids, (frags[0].1 .0, frags[frags.len() - 1].1 .1))
+        };
+        let mut ops = Vec::<DiffOp>::new();
+        let (mut i, mut j) = (0_usize, 0_usize);
+        // a sentinel match at the ends to flush the last differences
+        matches
+            .iter()
+            .copied()
+            .chain([(frags_a.len(), frags_b.len())])
+            .for_each(|(mi, mj)| {
+                let mut pos_a = frags_a.get(i).map_or(len_a, |f| f.1 .0);
+                let pos_b = frags_b.get(j).map_or(len_b, |f| f.1 .0);
+                if i < mi {
+                    let (frags_a, span_a) = run(&frags_a[i..mi]);
+                    // the insertion of a substitution is after the deleted fragments
+                    pos_a = span_a.1;
+                    ops.push(DiffOp::Deletion {
+                        frags_a,
+                        span_a,
+                        pos_b,
+                    });
+                }
+                if j < mj {
+                    let (frags_b, span_b) = run(&frags_b[j..mj]);
+                    ops.push(DiffOp::Insertion {
+                        frags_b,
+                        span_b,
+                        pos_a,
+                    });
+                }
+                if mi == frags_a.len() {
+                    return;
+                }
+                let (fa, fb) = (&frags_a[mi], &frags_b[mj]);
+                match ops.last_mut() {
+                    Some(DiffOp::Shared {
+                        frags_a,
+                        span_a,
+                        frags_b,
+                        span_b,
+                    }) if i == mi && j == mj => {
+                        frags_a.push(fa.0);
+                        span_a.1 = fa.1 .1;
+                        frags_b.push(fb.0);
+                        span_b.1 = fb.1 .1;
+                    }
+                    _ => ops.push(DiffOp::Shared {
+                        frags_a: vec![fa.0],
+                        span_a: fa.1,
+                        frags_b: vec![fb.0],
+                        span_b: fb.1,
+                    }),
+                }
+                i = mi + 1;
+                j = mj + 1;
+            });
+        ops
+    }
+
+    /// the fragment ids, the base spans and the keys of the fragments of a sequence
+    fn frag_keys(&self, seq_id: u32) -> Vec<(u32, (u32, u32), FragKey)> {
+        let (frag_start, n_frags) = self.seqs[seq_id as usize].seq_frag_range;
+        let mut shmmr_keys = vec![None; n_frags as usize];
+        self.frag_map.iter().for_each(|(sp, sigs)| {
+            sigs.iter().filter(|sig| sig.1 == seq_id).for_each(|sig| {
+                shmmr_keys[(sig.0 - frag_start) as usize] = Some(ShmmrGraphNode(sp.0, sp.1, sig.4))
+            })
+        });
+        let frags = self.frag_source().unwrap_or_else(|e| panic!("{}", e));
+        let mut base_offset = 0_u32;
+        (frag_start..frag_start + n_frags)
+            .zip(shmmr_keys)
+            .map(|(frag_id, shmmr_key)| {
+                let f = frags.get_frag(frag_id).unwrap();
+                let frag_len = f.len(self.shmmr_spec.k) as u32;
+                let key = match shmmr_key {
+                    Some(node) => FragKey::Shmmr(node, frag_len),
+                    None => FragKey::Bases(
+                        self.reconstruct_seq_from_frags(seq_id, [frag_id].into_iter())
+                            .unwrap(),
+                    ),
+                };
+                let span = (base_offset, base_offset + frag_len);
+                base_offset += frag_len;
+                (frag_id, span, key)
+            })
+            .collect()
+    }
+
+    /// walk the `frag_map`, each shimmer pair with the signatures of the fragments between
+    /// the shimmers, in no particular order, e.g. to build an auxiliary index
+    pub fn iter_index(&self) -> impl Iterator<Item = (ShmmrPair, &Vec<FragmentSignature>)> + '_ {
+        self.frag_map
+            .iter()
+            .map(|(shmmr_pair, sigs)| (*shmmr_pair, sigs))
+    }
 
-            if end_ext_loop {
-                break;
+    /// the histogram of the shimmer pair multiplicities in the `frag_map`, mapping the number
+    /// of the fragments sharing a shimmer pair to the number of the distinct shimmer pairs
+    /// with that many fragments, the long tail shows the repeats to cut off in the queries
+    pub fn minimizer_histogram(&self) -> BTreeMap<u32, u64> {
+        let mut histogram = BTreeMap::<u32, u64>::new();
+        self.iter_index().for_each(|(_, sigs)| {
+            *histogram.entry(sigs.len() as u32).or_default() += 1;
+        });
+        histogram
+    }
+
+    pub fn stats(&self) -> DbStats {
+        let mut stats = DbStats {
+            n_seqs: self.seqs.len(),
+            total_bases: self.seqs.iter().map(|s| s.len).sum(),
+            n_shmmr_pairs: self.frag_map.len(),
+            n_frag_refs: self.frag_map.values().map(|v| v.len()).sum(),
+            ..Default::default()
+        };
+        let frags = self.frag_source().unwrap_or_else(|e| panic!("{}", e));
+        stats.n_frags = frags.frag_count();
+        (0..stats.n_frags as u32).for_each(|frag_id| {
+            match frags.get_frag(frag_id).unwrap().as_ref() {
+                Fragment::Prefix(b) | Fragment::Suffix(b) => {
+                    stats.n_prefix_suffix_frags += 1;
+                    stats.stored_bytes += b.len();
+                }
+                Fragment::Internal(b) => {
+                    stats.n_internal_frags += 1;
+                    stats.stored_bytes += b.len();
+                }
+                Fragment::Internal2Bit(p) => {
+                    stats.n_internal_frags += 1;
+                    stats.stored_bytes += p.1.len() + std::mem::size_of::<u32>() * p.2.len();
+                }
+                Fragment::InternalNRuns(r) => {
+                    stats.n_internal_frags += 1;
+                    stats.stored_bytes += r.2.len() + std::mem::size_of::<(u32, u32)>() * r.1.len();
+                }
+                Fragment::AlnSegments((_, _, _, a)) => {
+                    stats.n_aln_segment_frags += 1;
+                    stats.stored_bytes += std::mem::size_of::<AlnSegment>() * a.len();
+                }
             }
+        });
+        if stats.stored_bytes > 0 {
+            stats.compression_ratio = stats.total_bases as f64 / stats.stored_bytes as f64;
         }
+        stats
     }
 
-    pub fn load_index_from_fastx(&mut self, filepath: String) -> Result<(), std::io::Error> {
-        match self.get_fastx_reader(filepath)? {
-            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
-            GZFastaReader::GZFile(reader) => self.load_index_from_reader(&mut reader.into_iter()),
-
-            #[allow(clippy::useless_conversion)] // the into_iter() is necessary for dyn patching
-            GZFastaReader::RegularFile(reader) => {
-                self.load_index_from_reader(&mut reader.into_iter())
-            }
-        };
+    pub fn write_frags_to_mmap_store(&self, filepath: String) -> Result<(), std::io::Error> {
+        MmapFragStore::write_frag_store(self.frags.as_ref().unwrap(), filepath)
+    }
 
+    /// back the fragment lookups by a memory-mapped fragment store, the in-memory
+    /// fragments are released
+    pub fn load_frags_from_mmap_store(&mut self, filepath: String) -> Result<(), SeqDbError> {
+        let frag_store = MmapFragStore::new(filepath)?;
+        self.check_frag_ranges(frag_store.frag_count())?;
+        self.frag_store = Some(Arc::new(frag_store));
+        self.frags = None;
         Ok(())
     }
-    #[cfg(feature = "with_agc")]
-    pub fn load_index_from_agcfile(&mut self, agcfile: AGCFile) -> Result<(), std::io::Error> {
-        //let agcfile = AGCFile::new(filepath);
 
-        self.load_index_from_reader(&mut agcfile.into_iter());
-        Ok(())
+    /// check the fragments of the sequences and the fragment checksums are within the
+    /// `n_frags` fragments loaded for the DB
+    fn check_frag_ranges(&self, n_frags: usize) -> Result<(), SeqDbError> {
+        if let Some(seq) = self
+            .seqs
+            .iter()
+            .find(|s| s.seq_frag_range.0 as usize + s.seq_frag_range.1 as usize > n_frags)
+        {
+            let (frag_start, n_seq_frags) = seq.seq_frag_range;
+            return Err(SeqDbError::Integrity(format!(
+                "the fragments {}..{} of sequence {} are out of the {} fragments",
+                frag_start,
+                frag_start + n_seq_frags,
+                seq.id,
+                n_frags
+            )));
+        }
+        match &self.frag_crcs {
+            Some(frag_crcs) if frag_crcs.len() != n_frags => Err(SeqDbError::Integrity(format!(
+                "{} fragment checksums for {} fragments",
+                frag_crcs.len(),
+                n_frags
+            ))),
+            _ => Ok(()),
+        }
     }
-}
 
-impl CompactSeqDB {
-    fn reconstruct_seq_from_frags<I: Iterator<Item = u32>>(&self, frag_ids: I) -> Vec<u8> {
+    fn reconstruct_seq_from_frags<I: Iterator<Item = u32>>(
+        &self,
+        seq_id: u32,
+        frag_ids: I,
+    ) -> Result<Vec<u8>, std::io::Error> {
         let mut reconstructed_seq = <Vec<u8>>::new();
-        let frags: &Vec<Fragment> = self.frags.as_ref().unwrap();
+        self.write_frag_bases(seq_id, frag_ids, &mut reconstructed_seq)?;
+        Ok(reconstructed_seq)
+    }
+
+    /// write the bases of the fragments of the sequence `seq_id` to `out` one fragment at a
+    /// time with the leading k-mer overlaps of the internal fragments trimmed, it fails with
+    /// `InvalidData` if an aligned fragment does not refer to an internal fragment
+    fn write_frag_bases<I: Iterator<Item = u32>, W: Write>(
+        &self,
+        seq_id: u32,
+        frag_ids: I,
+        out: &mut W,
+    ) -> Result<(), std::io::Error> {
+        let frags = self.frag_source()?;
         // let mut _p = 0;
-        frag_ids.for_each(|frag_id| {
+        for frag_id in frag_ids {
             //println!("{}:{}", frg_id, sdb.frags[*frg_id as usize]);
-            match frags.get(frag_id as usize).unwrap() {
+            match frags.get_frag(frag_id).unwrap().as_ref() {
                 Fragment::Prefix(b) => {
-                    reconstructed_seq.extend_from_slice(&b[..]);
+                    out.write_all(&b[..])?;
                     //println!("P p: {} {} {}", frag_id, _p, _p + b.len());
                     //_p += b.len();
                 }
                 Fragment::Suffix(b) => {
-                    reconstructed_seq.extend_from_slice(&b[..]);
+                    out.write_all(&b[..])?;
                     //println!("S p: {} {} {}", frag_id, _p, _p + b.len());
                     //_p += b.len();
                 }
                 Fragment::Internal(b) => {
-                    reconstructed_seq.extend_from_slice(&b[self.shmmr_spec.k as usize..]);
+                    out.write_all(&b[self.shmmr_spec.k as usize..])?;
                     //println!("I p: {} {} {}", frag_id, _p, _p + b.len()-self.shmmr_spec.k as usize);
                     //_p += b.len()-self.shmmr_spec.k as usize;
                 }
+                Fragment::Internal2Bit(p) => {
+                    out.write_all(&unpack_bases(p)[self.shmmr_spec.k as usize..])?;
+                }
+                Fragment::InternalNRuns(r) => {
+                    out.write_all(&unpack_n_runs(r)[self.shmmr_spec.k as usize..])?;
+                }
                 Fragment::AlnSegments((frg_id, reversed, _length, a)) => {
-                    if let Fragment::Internal(base_seq) = frags.get(*frg_id as usize).unwrap() {
-                        let mut seq = reconstruct_seq_from_aln_segs(base_seq, a);
-                        /*  // for debugging
-                        if *_length as usize != seq.len() {
-                            println!("DBG X: {:?} {:?}", String::from_utf8_lossy(base_seq), a);
-                        }
-                        */
+                    let base_frg = frags.get_frag(*frg_id);
+                    let base_seq = base_frg
+                        .as_ref()
+                        .and_then(|f| f.internal_bases())
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "fragment {} of sequence {} refers to non-internal fragment {}",
+                                    frag_id, seq_id, frg_id
+                                ),
+                            )
+                        })?;
+                    let mut seq = reconstruct_seq_from_aln_segs(&base_seq, a);
+                    /*  // for debugging
+                    if *_length as usize != seq.len() {
+                        println!("DBG X: {:?} {:?}", String::from_utf8_lossy(base_seq), a);
+                    }
+                    */
 
-                        assert_eq!(*_length as usize, seq.len());
-                        if *reversed {
-                            seq = reverse_complement(&seq);
-                        }
-                        reconstructed_seq.extend_from_slice(&seq[self.shmmr_spec.k as usize..]);
-                        // println!("A p: {} {} {}", frag_id, _p, _p + seq.len()-self.shmmr_spec.k as usize);
-                        // _p += seq.len()-self.shmmr_spec.k as usize;
+                    assert_eq!(*_length as usize, seq.len());
+                    if *reversed {
+                        seq = reverse_complement(&seq);
                     }
+                    out.write_all(&seq[self.shmmr_spec.k as usize..])?;
+                    // println!("A p: {} {} {}", frag_id, _p, _p + seq.len()-self.shmmr_spec.k as usize);
+                    // _p += seq.len()-self.shmmr_spec.k as usize;
                 }
             }
-        });
+        }
+
+        Ok(())
+    }
+
+    /// decode the bases of a fragment in the sequence orientation, including the leading k-mer
+    /// overlap of the internal fragments
+    fn fragment_bases(&self, frag_id: u32) -> Result<Vec<u8>, FragmentError> {
+        let frags = self
+            .frag_source()
+            .map_err(|_| FragmentError::NotFound(frag_id))?;
+        let frag = frags
+            .get_frag(frag_id)
+            .ok_or(FragmentError::NotFound(frag_id))?;
+        let bases = match frag.as_ref() {
+            Fragment::Prefix(b) | Fragment::Suffix(b) => b.clone(),
+            Fragment::AlnSegments((base_frag_id, reversed, length, aln_segs)) => {
+                let base_frg = frags.get_frag(*base_frag_id);
+                let base_seq = base_frg.as_ref().and_then(|f| f.internal_bases()).ok_or(
+                    FragmentError::BadBase {
+                        frag_id,
+                        base_frag_id: *base_frag_id,
+                    },
+                )?;
+                if aln_segs.iter().any(|s| match s {
+                    AlnSegment::Match(x1, x2) => x1 > x2 || *x2 as usize > base_seq.len(),
+                    _ => false,
+                }) {
+                    return Err(FragmentError::BadSegments(frag_id));
+                }
+                let seq = reconstruct_seq_from_aln_segs(&base_seq, aln_segs);
+                if seq.len() != *length as usize {
+                    return Err(FragmentError::LengthMismatch {
+                        frag_id,
+                        expected: *length,
+                        got: seq.len() as u32,
+                    });
+                }
+                if *reversed {
+                    reverse_complement(&seq)
+                } else {
+                    seq
+                }
+            }
+            f => f.internal_bases().unwrap().into_owned(),
+        };
+        Ok(bases)
+    }
+
+    /// record the CRC32 of the bases of each fragment in `frag_crcs`, the existing fragments
+    /// are decoded for their checksums and the fragments added later are checksummed from
+    /// the input sequences
+    pub fn enable_frag_crcs(&mut self) -> Result<(), FragmentError> {
+        if self.frag_crcs.is_some() {
+            return Ok(());
+        }
+        let n_frags = self
+            .frag_source()
+            .map_or(0, |frags| frags.frag_count() as u32);
+        let frag_crcs = (0..n_frags)
+            .into_par_iter()
+            .map(|frag_id| self.fragment_bases(frag_id).map(|b| bases_crc(&b)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.frag_crcs = Some(frag_crcs);
+        Ok(())
+    }
 
-        reconstructed_seq
+    /// check a fragment decodes to the bases of its recorded length, and to the bases of the
+    /// recorded checksum if `frag_crcs` is set, e.g. to catch a corrupted DB file
+    pub fn verify_fragment(&self, frag_id: u32) -> Result<(), FragmentError> {
+        let bases = self.fragment_bases(frag_id)?;
+        if let Some(&expected) = self
+            .frag_crcs
+            .as_ref()
+            .and_then(|crcs| crcs.get(frag_id as usize))
+        {
+            let got = bases_crc(&bases);
+            if got != expected {
+                return Err(FragmentError::ChecksumMismatch {
+                    frag_id,
+                    expected,
+                    got,
+                });
+            }
+        }
+        Ok(())
     }
 
     pub fn get_seq(&self, seq: &CompactSeq) -> Vec<u8> {
         self.reconstruct_seq_from_frags(
-            seq.seq_frag_range.0..seq.seq_frag_range.0 + seq.seq_frag_range.1
+            seq.id,
+            seq.seq_frag_range.0..seq.seq_frag_range.0 + seq.seq_frag_range.1,
+        )
+        .unwrap()
+    }
+
+    /// reconstruct a sequence, the fragments referring to the missing or non-internal base
+    /// fragments of a corrupted DB are reported as an `InvalidData` error
+    pub fn try_get_seq_by_id(&self, sid: u32) -> Result<Vec<u8>, std::io::Error> {
+        let seq = self.seqs.get(sid as usize).unwrap();
+        self.reconstruct_seq_from_frags(
+            sid,
+            seq.seq_frag_range.0..seq.seq_frag_range.0 + seq.seq_frag_range.1,
         )
     }
 
+    /// reconstruct each sequence and compare it with the checksum recorded at build time, the
+    /// result is `(seq_id, ok)` in the order of the sequences, a sequence failing to
+    /// reconstruct or without a checksum, see `CompactSeq::checksum`, is not ok
+    pub fn verify_all(&self) -> Vec<(u32, bool)> {
+        self.seqs
+            .par_iter()
+            .map(|seq| {
+                let ok = match seq.checksum {
+                    Some(checksum) => self
+                        .try_get_seq_by_id(seq.id)
+                        .is_ok_and(|bases| bases_crc(&bases) == checksum),
+                    None => false,
+                };
+                (seq.id, ok)
+            })
+            .collect()
+    }
+
     /* TODO */
     /*
     pub fn get_sub_seq(&self, seq: &CompactSeq, b: usize, e:usize) -> Vec<u8> {
@@ -744,10 +3695,7 @@ impl CompactSeqDB {
 
 impl GetSeq for CompactSeqDB {
     fn get_seq_by_id(&self, sid: u32) -> Vec<u8> {
-        let seq = self.seqs.get(sid as usize).unwrap();
-        self.reconstruct_seq_from_frags(
-            seq.seq_frag_range.0..seq.seq_frag_range.0 + seq.seq_frag_range.1
-        )
+        self.try_get_seq_by_id(sid).unwrap()
     }
 
     fn get_sub_seq_by_id(&self, sid: u32, bgn: u32, end: u32) -> Vec<u8> {
@@ -757,15 +3705,10 @@ impl GetSeq for CompactSeqDB {
         let mut _p = 0;
         let mut base_offset = 0_u32;
         let mut sub_seq_frag = vec![];
-        let frags: &Vec<Fragment> = self.frags.as_ref().unwrap();
+        let frags = self.frag_source().unwrap_or_else(|e| panic!("{}", e));
         for frag_id in frag_range.0..frag_range.0 + frag_range.1 {
-            let f = &frags[frag_id as usize];
-            let frag_len = match f {
-                Fragment::AlnSegments(d) => d.2 - self.shmmr_spec.k,
-                Fragment::Prefix(b) => b.len() as u32,
-                Fragment::Internal(b) => b.len() as u32 - self.shmmr_spec.k,
-                Fragment::Suffix(b) => b.len() as u32,
-            };
+            let f = frags.get_frag(frag_id).unwrap();
+            let frag_len = f.len(self.shmmr_spec.k) as u32;
             if (base_offset <= bgn && bgn < base_offset + frag_len)
                 || (base_offset <= end && end < base_offset + frag_len)
                 || (bgn <= base_offset && base_offset + frag_len <= end)
@@ -777,7 +3720,9 @@ impl GetSeq for CompactSeqDB {
         }
 
         // println!("DBG0: {} {}" , sid,  sub_seq_frag.len() );
-        let reconstructed_seq = self.reconstruct_seq_from_frags(sub_seq_frag.iter().map(|v| v.0));
+        let reconstructed_seq = self
+            .reconstruct_seq_from_frags(sid, sub_seq_frag.iter().map(|v| v.0))
+            .unwrap();
 
         // println!("DBG: {} {} {} {} {}", sid, frag_range.1, sub_seq_frag.len(), end, reconstructed_seq.len() );
 
@@ -810,13 +3755,182 @@ impl CompactSeqDB {
     }
 }
 
+/// read the version string at the start of a seq db (index) file, a shorter file is not a
+/// seq db file
+fn read_version_string<R: Read>(
+    in_file: &mut R,
+    version_string: &mut [u8],
+    filepath: &str,
+) -> Result<(), SeqDbError> {
+    in_file.read_exact(version_string).map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            SeqDbError::Format(format!("{} is not a seq db file", filepath))
+        } else {
+            SeqDbError::Io(e)
+        }
+    })
+}
+
 impl CompactSeqDB {
+    /// the shimmer map as a vector sorted by the keys, so the same DB always serializes
+    /// to the same bytes
+    fn sorted_frag_map(&self) -> Vec<(ShmmrPair, Vec<FragmentSignature>)> {
+        let mut frag_map = self
+            .frag_map
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect::<Vec<(ShmmrPair, Vec<FragmentSignature>)>>();
+        frag_map.sort_by_key(|e| e.0);
+        frag_map
+    }
+
+    pub fn write_to_file(&self, filepath: String) -> Result<(), std::io::Error> {
+        let mut out_file = BufWriter::new(File::create(filepath)?);
+        out_file.write_all(SEQ_DB_FILE_VERSION.as_bytes())?;
+        let config = config::standard();
+        bincode::encode_into_std_write(
+            (
+                &self.shmmr_spec,
+                &self.seqs,
+                self.sorted_frag_map(),
+                &self.frags,
+                &self.frag_crcs,
+            ),
+            &mut out_file,
+            config,
+        )
+        .map_err(|e| io::Error::other(format!("seq db file writing error: {}", e)))?;
+        out_file.flush()?;
+        Ok(())
+    }
+
+    /// load a DB written by `write_to_file`, the shimmers of a DB built with a custom hash are
+    /// only computed after the hash is set by `set_hasher`
+    ///
+    /// a file of another format fails with `SeqDbError::Format` and the fragments not covering
+    /// the sequences with `SeqDbError::Integrity`
+    pub fn load_from_file(filepath: String) -> Result<Self, SeqDbError> {
+        let mut in_file = io::BufReader::new(File::open(&filepath)?);
+        let mut version_string = [0_u8; 7];
+        read_version_string(&mut in_file, &mut version_string, &filepath)?;
+        if version_string != SEQ_DB_FILE_VERSION.as_bytes() {
+            return Err(SeqDbError::Format(format!(
+                "{} is not a seq db file or the format version is not supported (got {}, expect {})",
+                filepath,
+                String::from_utf8_lossy(&version_string),
+                SEQ_DB_FILE_VERSION
+            )));
+        }
+        let config = config::standard();
+        let decode_error = |e: bincode::error::DecodeError| {
+            SeqDbError::Format(format!("seq db file reading error: {}", e))
+        };
+        let shmmr_spec =
+            bincode::decode_from_std_read(&mut in_file, config).map_err(decode_error)?;
+        let seqs =
+            decode_compact_seqs(&mut in_file, CompactSeqLayout::Current).map_err(decode_error)?;
+        #[allow(clippy::type_complexity)]
+        let (frag_map, frags): (
+            Vec<(ShmmrPair, Vec<FragmentSignature>)>,
+            Option<Fragments>,
+        ) = bincode::decode_from_std_read(&mut in_file, config).map_err(decode_error)?;
+        let frag_crcs: Option<Vec<u32>> =
+            bincode::decode_from_std_read(&mut in_file, config).map_err(decode_error)?;
+        let frag_map = frag_map.into_iter().collect::<ShmmrToFrags>();
+        let sdb = CompactSeqDB {
+            shmmr_spec,
+            duplicate_name_policy: None,
+            compress_with_alignment: true,
+            max_fragment_len: None,
+            low_complexity_mask: None,
+            tally_base_composition: false,
+            name_to_id: build_name_to_id(&seqs),
+            seqs,
+            frag_map,
+            frags,
+            frag_store: None,
+            high_freq_filter: None,
+            pair_target_count: None,
+            substring_index: None,
+            frag_occurrences: None,
+            build_metrics: None,
+            frag_crcs,
+            hasher: Arc::new(DefaultShmmrHasher),
+        };
+        if let Some(frags) = sdb.frags.as_ref() {
+            sdb.check_frag_ranges(frags.len())?;
+        }
+        Ok(sdb)
+    }
+
+    /// write the shimmer spec, the sequence metadata and the shimmer map without the fragments,
+    /// the DB loaded by `load_index` answers the queries but it can not reconstruct sequences
+    /// until the fragments are attached, e.g. with `load_frags_from_mmap_store`
+    pub fn write_index(&self, filepath: String) -> Result<(), std::io::Error> {
+        let mut out_file = BufWriter::new(File::create(filepath)?);
+        out_file.write_all(SEQ_DB_INDEX_FILE_VERSION.as_bytes())?;
+        let config = config::standard();
+        bincode::encode_into_std_write(
+            (&self.shmmr_spec, &self.seqs, self.sorted_frag_map()),
+            &mut out_file,
+            config,
+        )
+        .map_err(|e| io::Error::other(format!("seq db index file writing error: {}", e)))?;
+        out_file.flush()?;
+        Ok(())
+    }
+
+    /// load an index written by `write_index`, a file of another format fails with
+    /// `SeqDbError::Format`
+    pub fn load_index(filepath: String) -> Result<Self, SeqDbError> {
+        let mut in_file = io::BufReader::new(File::open(&filepath)?);
+        let mut version_string = [0_u8; 7];
+        read_version_string(&mut in_file, &mut version_string, &filepath)?;
+        if version_string != SEQ_DB_INDEX_FILE_VERSION.as_bytes() {
+            return Err(SeqDbError::Format(format!(
+                "{} is not a seq db index file or the format version is not supported (got {}, expect {})",
+                filepath,
+                String::from_utf8_lossy(&version_string),
+                SEQ_DB_INDEX_FILE_VERSION
+            )));
+        }
+        let config = config::standard();
+        let decode_error = |e: bincode::error::DecodeError| {
+            SeqDbError::Format(format!("seq db index file reading error: {}", e))
+        };
+        let shmmr_spec = bincode::decode_from_std_read(&mut in_file, config).map_err(decode_error)?;
+        let seqs = decode_compact_seqs(&mut in_file, CompactSeqLayout::Current)
+            .map_err(decode_error)?;
+        let frag_map: Vec<(ShmmrPair, Vec<FragmentSignature>)> =
+            bincode::decode_from_std_read(&mut in_file, config).map_err(decode_error)?;
+        Ok(CompactSeqDB {
+            shmmr_spec,
+            duplicate_name_policy: None,
+            compress_with_alignment: true,
+            max_fragment_len: None,
+            low_complexity_mask: None,
+            tally_base_composition: false,
+            name_to_id: build_name_to_id(&seqs),
+            seqs,
+            frag_map: frag_map.into_iter().collect::<ShmmrToFrags>(),
+            frags: None,
+            frag_store: None,
+            high_freq_filter: None,
+            pair_target_count: None,
+            substring_index: None,
+            frag_occurrences: None,
+            build_metrics: None,
+            frag_crcs: None,
+            hasher: Arc::new(DefaultShmmrHasher),
+        })
+    }
+
     pub fn write_to_frag_files(&self, file_prefix: String, chunk_size: Option<usize>) {
         let mut sdx_file = BufWriter::new(
             File::create(file_prefix.clone() + ".sdx").expect("sdx file creating fail\n"),
         );
         sdx_file
-            .write_all("SDX:0.5".as_bytes())
+            .write_all(SDX_FILE_VERSION.as_bytes())
             .expect("sdx file writing error");
         let mut frg_file =
             BufWriter::new(File::create(file_prefix + ".frg").expect("frg file creating fail\n"));
@@ -837,12 +3951,7 @@ impl CompactSeqDB {
             .map(|&frags| {
                 let mut total_frag_len = 0_u32;
                 frags.iter().for_each(|f| {
-                    total_frag_len += match f {
-                        Fragment::AlnSegments(d) => d.2 - self.shmmr_spec.k,
-                        Fragment::Prefix(b) => b.len() as u32,
-                        Fragment::Internal(b) => b.len() as u32 - self.shmmr_spec.k,
-                        Fragment::Suffix(b) => b.len() as u32,
-                    };
+                    total_frag_len += f.len(self.shmmr_spec.k) as u32;
                 });
 
                 let w = bincode::encode_to_vec(frags.to_vec(), config).unwrap();
@@ -873,6 +3982,94 @@ impl CompactSeqDB {
     }
 }
 
+/// a hit of `MultiDb::query`, the `seq_id` of the hit is the id of the target in the shard
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardHit {
+    pub shard_id: u32,
+    pub hit: AlignmentHit,
+}
+
+/// several DBs or indexes, e.g. one per chromosome, queried as one DB, a sequence is identified
+/// by `(shard_id, seq_id)` with `seq_id` local to the shard
+///
+/// the hit scores are only comparable across the shards built with the same shimmer spec
+#[derive(Debug, Clone, Default)]
+pub struct MultiDb {
+    pub shards: Vec<CompactSeqDB>,
+}
+
+impl MultiDb {
+    pub fn new(shards: Vec<CompactSeqDB>) -> Self {
+        MultiDb { shards }
+    }
+
+    /// load the shards written by `CompactSeqDB::write_to_file`, in the order of the files
+    pub fn load_from_files(filepaths: &[String]) -> Result<Self, SeqDbError> {
+        let shards = filepaths
+            .iter()
+            .map(|filepath| CompactSeqDB::load_from_file(filepath.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(MultiDb { shards })
+    }
+
+    /// load the shard indexes written by `CompactSeqDB::write_index`, in the order of the
+    /// files, the indexes have no bases so the queries can not ask for the target bases
+    pub fn load_indexes(filepaths: &[String]) -> Result<Self, SeqDbError> {
+        let shards = filepaths
+            .iter()
+            .map(|filepath| CompactSeqDB::load_index(filepath.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(MultiDb { shards })
+    }
+
+    /// the `(shard_id, seq_id)` of the (first) sequence with the name in the first shard
+    /// having it
+    pub fn id_of(&self, name: &str) -> Option<(u32, u32)> {
+        self.shards
+            .iter()
+            .enumerate()
+            .find_map(|(shard_id, sdb)| sdb.id_of(name).map(|sid| (shard_id as u32, sid)))
+    }
+
+    /// the name of a sequence
+    pub fn name_of(&self, shard_id: u32, seq_id: u32) -> Option<&str> {
+        self.shards
+            .get(shard_id as usize)
+            .and_then(|sdb| sdb.name_of(seq_id))
+    }
+
+    pub fn get_seq_by_id(&self, shard_id: u32, seq_id: u32) -> Vec<u8> {
+        self.shards[shard_id as usize].get_seq_by_id(seq_id)
+    }
+
+    /// query all shards, the hits of each shard are the ones of `CompactSeqDB::query`, merged
+    /// and sorted by the score, the ties are broken by `(shard_id, seq_id)`
+    pub fn query(&self, seq: &[u8], penalty: f32, orient_to_query: bool) -> Vec<ShardHit> {
+        let mut out = self
+            .shards
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(shard_id, sdb)| {
+                sdb.query(seq, penalty, orient_to_query)
+                    .into_iter()
+                    .map(move |hit| ShardHit {
+                        shard_id: shard_id as u32,
+                        hit,
+                    })
+            })
+            .collect::<Vec<_>>();
+        out.sort_by(|a, b| {
+            b.hit
+                .score
+                .partial_cmp(&a.hit.score)
+                .unwrap()
+                .then(a.shard_id.cmp(&b.shard_id))
+                .then(a.hit.seq_id.cmp(&b.hit.seq_id))
+        });
+        out
+    }
+}
+
 pub fn frag_map_to_adj_list(
     frag_map: &ShmmrToFrags,
     min_count: usize,
@@ -1201,9 +4398,49 @@ pub fn raw_query_fragment(
     frag_map: &ShmmrToFrags,
     query_frag: &Vec<u8>,
     shmmr_spec: &ShmmrSpec,
+) -> Vec<FragmentHit> {
+    raw_query_fragment_with_filter(frag_map, query_frag, shmmr_spec, None)
+}
+
+/// the same as `raw_query_fragment`, but the shimmer pairs in the `high_freq_filter` are
+/// reported with empty fragment lists without copying their fragment signatures
+pub fn raw_query_fragment_with_filter(
+    frag_map: &ShmmrToFrags,
+    query_frag: &Vec<u8>,
+    shmmr_spec: &ShmmrSpec,
+    high_freq_filter: Option<&HighFreqFilter>,
 ) -> Vec<FragmentHit> {
     let shmmrs = sequence_to_shmmrs(0, query_frag, shmmr_spec, false);
-    let query_results = pair_shmmrs(&shmmrs)
+    raw_query_shmmrs(frag_map, &shmmrs, high_freq_filter, shmmr_spec.alphabet)
+}
+
+/// the same as `raw_query_fragment_with_filter`, but the shimmers of the query are computed
+/// by `hasher`, it fails with `InvalidInput` if the hasher is not the one of `shmmr_spec`
+pub fn raw_query_fragment_with_hasher(
+    frag_map: &ShmmrToFrags,
+    query_frag: &[u8],
+    shmmr_spec: &ShmmrSpec,
+    high_freq_filter: Option<&HighFreqFilter>,
+    hasher: &dyn ShmmrHasher,
+) -> Result<Vec<FragmentHit>, io::Error> {
+    let shmmrs = sequence_to_shmmrs_with_hasher(0, query_frag, shmmr_spec, false, hasher)?;
+    Ok(raw_query_shmmrs(
+        frag_map,
+        &shmmrs,
+        high_freq_filter,
+        shmmr_spec.alphabet,
+    ))
+}
+
+/// the hits of the shimmer pairs of a query, the protein hits are only the fragments with the
+/// orientation of the query pair, so the proteins only have the forward-strand hits
+fn raw_query_shmmrs(
+    frag_map: &ShmmrToFrags,
+    shmmrs: &Vec<MM128>,
+    high_freq_filter: Option<&HighFreqFilter>,
+    alphabet: Alphabet,
+) -> Vec<FragmentHit> {
+    let query_results = pair_shmmrs(shmmrs)
         .par_iter()
         .map(|(s0, s1)| {
             let p0 = s0.pos() + 1;
@@ -1217,8 +4454,15 @@ pub fn raw_query_fragment(
             }
         })
         .map(|(s0, s1, p0, p1, orientation)| {
+            if high_freq_filter.is_some_and(|f| f.is_high_freq(&(s0, s1))) {
+                return ((s0, s1), (p0, p1, orientation), vec![]);
+            }
             if let Some(m) = frag_map.get(&(s0, s1)) {
-                ((s0, s1), (p0, p1, orientation), m.clone())
+                let m = match alphabet {
+                    Alphabet::Nucleotide => m.clone(),
+                    Alphabet::Protein => m.iter().filter(|f| f.4 == orientation).copied().collect(),
+                };
+                ((s0, s1), (p0, p1, orientation), m)
             } else {
                 ((s0, s1), (p0, p1, orientation), vec![])
             }
@@ -1227,6 +4471,87 @@ pub fn raw_query_fragment(
     query_results
 }
 
+/// a shimmer pair index with the spec and the hash of the shimmers it is built with, so the
+/// queries always compute the shimmers of the query the same way as the index, e.g. for the
+/// index of a `CompactSeqDB` loaded by `load_index`
+#[derive(Debug, Clone)]
+pub struct SeqIndex {
+    shmmr_spec: ShmmrSpec,
+    frag_map: ShmmrToFrags,
+    hasher: Arc<dyn ShmmrHasher>,
+}
+
+impl SeqIndex {
+    /// the index of the shimmers computed by `DefaultShmmrHasher`, it fails with
+    /// `InvalidInput` if the `hash_id` of the spec is another one, see `new_with_hasher`
+    pub fn new(frag_map: ShmmrToFrags, shmmr_spec: ShmmrSpec) -> Result<Self, io::Error> {
+        Self::new_with_hasher(frag_map, shmmr_spec, Arc::new(DefaultShmmrHasher))
+    }
+
+    /// the index of the shimmers computed by `hasher`, it fails with `InvalidInput` if the
+    /// hasher is not the one recorded in the `hash_id` of the spec
+    pub fn new_with_hasher(
+        frag_map: ShmmrToFrags,
+        shmmr_spec: ShmmrSpec,
+        hasher: Arc<dyn ShmmrHasher>,
+    ) -> Result<Self, io::Error> {
+        shmmr_spec.check_hasher(hasher.as_ref())?;
+        Ok(SeqIndex {
+            shmmr_spec,
+            frag_map,
+            hasher,
+        })
+    }
+
+    pub fn shmmr_spec(&self) -> &ShmmrSpec {
+        &self.shmmr_spec
+    }
+
+    pub fn frag_map(&self) -> &ShmmrToFrags {
+        &self.frag_map
+    }
+
+    /// the hits of the shimmer pairs of `frag`, see `raw_query_fragment`, it fails with
+    /// `InvalidInput` if the index is taken from a DB loaded without its hash, see
+    /// `CompactSeqDB::set_hasher`
+    pub fn query(&self, frag: &[u8]) -> Result<Vec<FragmentHit>, io::Error> {
+        raw_query_fragment_with_hasher(
+            &self.frag_map,
+            frag,
+            &self.shmmr_spec,
+            None,
+            self.hasher.as_ref(),
+        )
+    }
+
+    /// the hits of `frag` chained to each target with the default limits, see
+    /// `aln::query_fragment_to_hps`, it fails like `query`
+    pub fn query_to_hps(&self, frag: &[u8], penalty: f32) -> Result<TargetHitPairLists, io::Error> {
+        Ok(query_fragment_to_hps_with_pair_count(
+            self.query(frag)?,
+            penalty,
+            None,
+            None,
+            None,
+            None,
+            DEFAULT_MIN_CHAIN_SCORE,
+            DEFAULT_MIN_CHAIN_LEN,
+            None,
+        ))
+    }
+}
+
+/// take the index of a DB, the fragments and the sequences are dropped
+impl From<CompactSeqDB> for SeqIndex {
+    fn from(sdb: CompactSeqDB) -> Self {
+        SeqIndex {
+            shmmr_spec: sdb.shmmr_spec,
+            frag_map: sdb.frag_map,
+            hasher: sdb.hasher,
+        }
+    }
+}
+
 pub fn raw_query_fragment_from_mmap_midx(
     frag_map_location: &ShmmrToIndexFileLocation,
     frag_map_mmap_file: &Mmap,
@@ -1248,8 +4573,11 @@ pub fn raw_query_fragment_from_mmap_midx(
             }
         })
         .map(|(s0, s1, p0, p1, orientation)| {
-            let m =
+            let mut m =
                 get_shmmr_matches_from_mmap_file(frag_map_location, (s0, s1), frag_map_mmap_file);
+            if shmmr_spec.alphabet == Alphabet::Protein {
+                m.retain(|f| f.4 == orientation);
+            }
             ((s0, s1), (p0, p1, orientation), m)
         })
         .collect::<Vec<_>>();
@@ -1268,6 +4596,164 @@ pub fn get_shmmr_matches_from_mmap_file(
     }
 }
 
+/// an overlap between two sequences, the coordinates are 0-based and half-open
+#[derive(Debug, Clone)]
+pub struct Overlap {
+    pub query_id: u32,
+    pub query_len: u32,
+    pub query_start: u32,
+    pub query_end: u32,
+    pub target_id: u32,
+    pub target_len: u32,
+    pub target_start: u32,
+    pub target_end: u32,
+    /// 0 for the same orientation, 1 for the query overlapping the reverse strand of the target
+    pub strand: u8,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct OverlapParams {
+    pub penalty: f32,
+    pub max_count: Option<u32>,
+    pub max_aln_span: Option<u32>,
+    /// the overlaps with the chain score less than `min_score` are dropped
+    pub min_score: f32,
+    /// the number of the queries whose overlaps can wait in the channel to the callback of
+    /// `all_vs_all_overlaps_with_callback` before the chaining threads block
+    pub channel_capacity: usize,
+}
+
+impl Default for OverlapParams {
+    fn default() -> Self {
+        OverlapParams {
+            penalty: 0.5,
+            max_count: None,
+            max_aln_span: None,
+            min_score: 0.0,
+            channel_capacity: 256,
+        }
+    }
+}
+
+/// index all sequences and chain each sequence against the index, each pair of sequences is
+/// reported once with `query_id < target_id`, the self hits are excluded, the overlaps are
+/// sorted by the query id, the target id and then the descending score
+pub fn all_vs_all_overlaps(
+    seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+    shmmr_spec: &ShmmrSpec,
+    params: &OverlapParams,
+) -> Vec<Overlap> {
+    let mut overlaps = Vec::<Overlap>::new();
+    all_vs_all_overlaps_with_callback(seqs, shmmr_spec, params, |overlap| overlaps.push(overlap));
+    overlaps.sort_by(|a, b| {
+        (a.query_id, a.target_id)
+            .cmp(&(b.query_id, b.target_id))
+            .then(b.score.partial_cmp(&a.score).unwrap())
+    });
+    overlaps
+}
+
+/// the same as `all_vs_all_overlaps`, but the overlaps are passed to `on_overlap` on the
+/// calling thread as the queries are chained instead of being collected, e.g. to write
+/// them out incrementally
+///
+/// the queries are chained in parallel by rayon against the shared index, the overlaps of
+/// each query go through a channel bounded by `params.channel_capacity`, so the memory stays
+/// flat if `on_overlap` keeps up, the overlaps of a query come together, but the queries
+/// come in the order they are done
+pub fn all_vs_all_overlaps_with_callback(
+    seqs: &Vec<(u32, Option<String>, String, Vec<u8>)>,
+    shmmr_spec: &ShmmrSpec,
+    params: &OverlapParams,
+    mut on_overlap: impl FnMut(Overlap),
+) {
+    let mut sdb = CompactSeqDB::new(shmmr_spec.clone());
+    // the overlaps only use the sequence ids, e.g. the reads may share the names
+    sdb.duplicate_name_policy = Some(DuplicateNamePolicy::Keep);
+    sdb.load_index_from_seq_vec(seqs);
+    let seq_lens = seqs
+        .iter()
+        .map(|(sid, _, _, seq)| (*sid, seq.len() as u32))
+        .collect::<FxHashMap<u32, u32>>();
+
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<Vec<Overlap>>(params.channel_capacity);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            seqs.par_iter()
+                .for_each_with(sender, |sender, (query_id, _, _, seq)| {
+                    let overlaps = query_overlaps(
+                        &sdb.frag_map,
+                        &seq_lens,
+                        *query_id,
+                        seq,
+                        shmmr_spec,
+                        params,
+                    );
+                    if !overlaps.is_empty() {
+                        let _ = sender.send(overlaps);
+                    }
+                });
+        });
+        // the receiver is dropped if `on_overlap` panics, so the senders do not block
+        receiver
+            .into_iter()
+            .for_each(|overlaps| overlaps.into_iter().for_each(&mut on_overlap));
+    });
+}
+
+/// chain a query against the index, the overlaps with the targets of the ids not larger than
+/// `query_id` are skipped
+fn query_overlaps(
+    frag_map: &ShmmrToFrags,
+    seq_lens: &FxHashMap<u32, u32>,
+    query_id: u32,
+    seq: &Vec<u8>,
+    shmmr_spec: &ShmmrSpec,
+    params: &OverlapParams,
+) -> Vec<Overlap> {
+    let k = shmmr_spec.k;
+    let raw_query_hits = raw_query_fragment(frag_map, seq, shmmr_spec);
+    let query_len = seq.len() as u32;
+    let mut out = Vec::<Overlap>::new();
+    query_fragment_to_hps(
+        raw_query_hits,
+        seq,
+        shmmr_spec,
+        params.penalty,
+        params.max_count,
+        params.max_count,
+        params.max_count,
+        params.max_aln_span,
+        params.min_score,
+        DEFAULT_MIN_CHAIN_LEN,
+    )
+    .into_iter()
+    .filter(|(target_id, _)| *target_id > query_id)
+    .for_each(|(target_id, chains)| {
+        let target_len = *seq_lens.get(&target_id).unwrap();
+        chains.iter().for_each(|(score, hps)| {
+            split_chain_by_orientation(hps).into_iter().for_each(|run| {
+                let ((query_start, query_end), (target_start, target_end)) =
+                    hit_run_to_base_span(run, k);
+                out.push(Overlap {
+                    query_id,
+                    query_len,
+                    query_start,
+                    query_end: query_end.min(query_len),
+                    target_id,
+                    target_len,
+                    target_start,
+                    target_end: target_end.min(target_len),
+                    strand: Strand::of(&run[0]).as_u8(),
+                    score: *score,
+                });
+            });
+        });
+    });
+    out
+}
+
 pub fn get_match_positions_with_fragment(
     shmmr_map: &ShmmrToFrags,
     frag: &Vec<u8>,
@@ -1280,7 +4766,7 @@ pub fn get_match_positions_with_fragment(
             let q_direction = v.1 .2;
             v.2.into_iter().for_each(|w| {
                 let (_, sid, p0, p1, direction) = w;
-                let direction = if direction == q_direction { 0 } else { 1 };
+                let direction = Strand::from_orientations(q_direction, direction).as_u8();
                 res.entry(sid).or_default().push((p0, p1, direction));
             });
         });
@@ -1303,11 +4789,40 @@ pub fn write_shmmr_map_file(
     buf.write_u32::<LittleEndian>(shmmr_spec.k)?;
     buf.write_u32::<LittleEndian>(shmmr_spec.r)?;
     buf.write_u32::<LittleEndian>(shmmr_spec.min_span)?;
-    buf.write_u32::<LittleEndian>(shmmr_spec.sketch as u32)?;
+    // bit 0: sketch, bit 1: forward-only hashing, so the files without the bit 1 are canonical,
+    // bit 2: the density follows the flag as a u32, bit 3: the protein alphabet, bit 4: the
+    // reduction levels follow (the density) as a u32, so the files without it are reduced by
+    // `DEFAULT_REDUCTION_LEVELS`, bits 8 to 31: the hash id, so the files without it are
+    // hashed by `DefaultShmmrHasher`
+    if shmmr_spec.hash_id >= 1 << 24 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the hash id {} does not fit in the .mdb file",
+                shmmr_spec.hash_id
+            ),
+        ));
+    }
+    let flag = (shmmr_spec.sketch as u32)
+        | ((!shmmr_spec.canonical as u32) << 1)
+        | ((shmmr_spec.density.is_some() as u32) << 2)
+        | (((shmmr_spec.alphabet == Alphabet::Protein) as u32) << 3)
+        | (((shmmr_spec.levels != DEFAULT_REDUCTION_LEVELS) as u32) << 4)
+        | (shmmr_spec.hash_id << 8);
+    buf.write_u32::<LittleEndian>(flag)?;
+    if let Some(density) = shmmr_spec.density {
+        buf.write_u32::<LittleEndian>(density)?;
+    }
+    if shmmr_spec.levels != DEFAULT_REDUCTION_LEVELS {
+        buf.write_u32::<LittleEndian>(shmmr_spec.levels)?;
+    }
 
     buf.write_u64::<LittleEndian>(shmmr_map.len() as u64)?;
-    shmmr_map
-        .iter()
+    // write the shimmer pairs in the sorted order for reproducible files
+    let mut shmmr_pairs = shmmr_map.iter().collect::<Vec<_>>();
+    shmmr_pairs.sort_unstable_by_key(|(k, _)| **k);
+    shmmr_pairs
+        .into_iter()
         .try_for_each(|(k, v)| -> Result<(), std::io::Error> {
             buf.write_u64::<LittleEndian>(k.0)?;
             buf.write_u64::<LittleEndian>(k.1)?;
@@ -1325,6 +4840,29 @@ pub fn write_shmmr_map_file(
     Ok(())
 }
 
+/// decode the flag of the shimmer spec in the .mdb header to `(sketch, canonical, with_density,
+/// alphabet, with_levels, hash_id)`
+fn mdb_spec_flag(flag: u32) -> Result<(bool, bool, bool, Alphabet, bool, u32), io::Error> {
+    if flag & 0xE0 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported shimmer spec flag {:#x} in the .mdb file", flag),
+        ));
+    }
+    Ok((
+        flag & 0b001 == 0b001,
+        flag & 0b010 == 0,
+        flag & 0b100 == 0b100,
+        if flag & 0b1000 == 0b1000 {
+            Alphabet::Protein
+        } else {
+            Alphabet::Nucleotide
+        },
+        flag & 0b10000 == 0b10000,
+        flag >> 8,
+    ))
+}
+
 pub fn read_mdb_file(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::Error> {
     let mut in_file =
         File::open(filepath).expect("Error while opening the SHIMMER map file (.mdb) file");
@@ -1347,7 +4885,21 @@ pub fn read_mdb_file(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::
     cursor += 4;
     let flag = LittleEndian::read_u32(&buf[cursor..cursor + 4]);
     cursor += 4;
-    let sketch = (flag & 0b01) == 0b01;
+    let (sketch, canonical, with_density, alphabet, with_levels, hash_id) = mdb_spec_flag(flag)?;
+    let density = if with_density {
+        let density = LittleEndian::read_u32(&buf[cursor..cursor + 4]);
+        cursor += 4;
+        Some(density)
+    } else {
+        None
+    };
+    let levels = if with_levels {
+        let levels = LittleEndian::read_u32(&buf[cursor..cursor + 4]);
+        cursor += 4;
+        levels
+    } else {
+        DEFAULT_REDUCTION_LEVELS
+    };
 
     let shmmr_spec = ShmmrSpec {
         w,
@@ -1355,6 +4907,11 @@ pub fn read_mdb_file(filepath: String) -> Result<(ShmmrSpec, ShmmrToFrags), io::
         r,
         min_span,
         sketch,
+        canonical,
+        hash_id,
+        density,
+        alphabet,
+        levels,
     };
     u64bytes.clone_from_slice(&buf[cursor..cursor + 8]);
     let shmmr_key_len = usize::from_le_bytes(u64bytes);
@@ -1435,16 +4992,36 @@ pub fn read_mdb_file_to_frag_locations(
 
     in_file.read_exact(&mut u32bytes)?;
     let flag = LittleEndian::read_u32(&u32bytes);
-    let sketch = (flag & 0b01) == 0b01;
+    let (sketch, canonical, with_density, alphabet, with_levels, hash_id) = mdb_spec_flag(flag)?;
 
     cursor += 4 * 5;
 
+    let density = if with_density {
+        in_file.read_exact(&mut u32bytes)?;
+        cursor += 4;
+        Some(LittleEndian::read_u32(&u32bytes))
+    } else {
+        None
+    };
+    let levels = if with_levels {
+        in_file.read_exact(&mut u32bytes)?;
+        cursor += 4;
+        LittleEndian::read_u32(&u32bytes)
+    } else {
+        DEFAULT_REDUCTION_LEVELS
+    };
+
     let shmmr_spec = ShmmrSpec {
         w,
         k,
         r,
         min_span,
         sketch,
+        canonical,
+        hash_id,
+        density,
+        alphabet,
+        levels,
     };
 
     in_file.read_exact(&mut u64bytes)?;
@@ -17,19 +17,387 @@ pub struct OvlpMatch {
     pub deltas: Option<Vec<DeltaPoint>>,
 }
 
-#[derive(Clone, Debug, Decode, Encode)]
+impl OvlpMatch {
+    /// the number of the insertion/deletion delta points inside the aligned region,
+    /// a substitution is encoded as one insertion and one deletion by `match_reads`,
+    /// it falls back to `dist` if the deltas are not kept
+    pub fn indel_count(&self) -> u32 {
+        match &self.deltas {
+            Some(deltas) => deltas
+                .iter()
+                .filter(|dpt| dpt.x > self.bgn0 && dpt.x < self.end0)
+                .count() as u32,
+            None => self.dist,
+        }
+    }
+
+    /// the fraction of the matched bases over the aligned span, every delta point
+    /// consumes one base of one of the sequences, so the matched bases are
+    /// `(span0 + span1 - indel_count) / 2`
+    pub fn identity(&self) -> f32 {
+        let span0 = self.end0 - self.bgn0;
+        let span1 = self.end1 - self.bgn1;
+        let aln_len = span0.max(span1);
+        if aln_len == 0 {
+            return 0.0;
+        }
+        let n_match = (span0 + span1).saturating_sub(self.indel_count()) >> 1;
+        n_match as f32 / aln_len as f32
+    }
+
+    /// trim the ends of the alignment back to the outermost exact matches of at least
+    /// `min_anchor_len` bases, the coordinates, `deltas`, `dist`, `m_size` and the end of the
+    /// longest match are those of the trimmed alignment
+    ///
+    /// the delta points at or outside the new ends are dropped, it is `None` if there is no
+    /// such exact match or the deltas are not kept
+    pub fn trim_to_anchors(&self, min_anchor_len: u32) -> Option<OvlpMatch> {
+        let deltas = self.deltas.as_ref()?;
+        // the exact matches `(bgn0, bgn1, end0, end1)` backward from the end, the i-th one
+        // starts at the i-th delta point and the last one ends at the first delta point
+        let mut runs = Vec::<(u32, u32, u32, u32)>::with_capacity(deltas.len() + 1);
+        let (mut x, mut y) = (self.end0, self.end1);
+        deltas.iter().for_each(|d| {
+            runs.push((d.x, d.y, x, y));
+            if d.dk > 0 {
+                x = d.x - d.dk as u32;
+                y = d.y;
+            } else {
+                x = d.x;
+                y = d.y - (-d.dk) as u32;
+            }
+        });
+        let l = x.saturating_sub(self.bgn0).min(y.saturating_sub(self.bgn1));
+        runs.push((x - l, y - l, x, y));
+
+        let is_anchor = |r: &(u32, u32, u32, u32)| r.2 - r.0 >= min_anchor_len;
+        let last = runs.iter().position(is_anchor)?;
+        let first = runs.iter().rposition(is_anchor).unwrap();
+        let (bgn0, bgn1, _, _) = runs[first];
+        let (_, _, end0, end1) = runs[last];
+        let (_, _, m_end0, m_end1) = *runs[last..=first].iter().max_by_key(|r| r.2 - r.0).unwrap();
+        let deltas = deltas[last..first].to_vec();
+        let dist = deltas
+            .iter()
+            .filter(|dpt| dpt.x > bgn0 && dpt.x < end0)
+            .count() as u32;
+        Some(OvlpMatch {
+            m_size: (end0 - bgn0 + end1 - bgn1 + 2 * dist) >> 1,
+            dist,
+            bgn0,
+            end0,
+            bgn1,
+            end1,
+            m_end0,
+            m_end1,
+            deltas: Some(deltas),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Decode, Encode)]
 pub struct ShmmrSpec {
     pub w: u32,
     pub k: u32,
     pub r: u32,
     pub min_span: u32,
     pub sketch: bool,
+    /// hash the lesser of a k-mer and its reverse complement so a sequence and its reverse
+    /// complement have the same shimmers, only the forward k-mers are hashed if false
+    pub canonical: bool,
+    /// the `ShmmrHasher::hash_id` of the hash computing the shimmers, the shimmers of the
+    /// different hashes never match, so a DB is only queried with the hash it is built with
+    pub hash_id: u32,
+    /// the target number of the shimmers per kb, the shimmers are then the k-mers with a hash
+    /// below the threshold giving the density after the shimmers closer than `min_span` are
+    /// dropped, see `sketch_threshold`, and `w`, `r` and `sketch` are not used
+    pub density: Option<u32>,
+    /// the alphabet of the sequences, the protein k-mers have no reverse complement, so
+    /// `canonical` is not used and all shimmers are on the forward strand
+    pub alphabet: Alphabet,
+    /// the number of the reductions of the window minimizers, each keeps the minimizers of
+    /// the windows of `r` consecutive shimmers of the level below, so the shimmers paired in
+    /// the `frag_map` get sparser with more levels, it is not used if `r` is 1 or for the
+    /// sketch shimmers, the shimmers closer than `min_span` are dropped after the reductions,
+    /// so few levels leave few shimmers with a `min_span` longer than the window
+    pub levels: u32,
+}
+
+/// the alphabet of the sequences the shimmers are computed on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Decode, Encode)]
+pub enum Alphabet {
+    /// the bases `ACGT` of either case, the k-mers with the other bytes are skipped
+    #[default]
+    Nucleotide,
+    /// the 20 standard amino acids of either case, the k-mers with the other bytes, e.g. `X`
+    /// or `*`, are skipped, a k-mer of up to 25 residues is hashed as the 5-bit codes of
+    /// the residues
+    Protein,
+}
+
+/// the 5-bit codes of the 20 amino acids of either case, 20 for the other bytes
+const PROTEIN_CODES: [u8; 256] = protein_codes();
+
+const fn protein_codes() -> [u8; 256] {
+    let mut codes = [20_u8; 256];
+    let residues = b"ACDEFGHIKLMNPQRSTVWY";
+    let mut i = 0;
+    while i < residues.len() {
+        codes[residues[i] as usize] = i as u8;
+        codes[residues[i].to_ascii_lowercase() as usize] = i as u8;
+        i += 1;
+    }
+    codes
+}
+
+/// shift the code of the residue `b` into the protein k-mer `aa_mer` of the `mask` bits, it
+/// returns false if `b` is not one of the 20 amino acids
+#[inline(always)]
+fn push_residue(aa_mer: &mut u128, b: u8, mask: u128) -> bool {
+    let c = PROTEIN_CODES[b as usize];
+    if c < 20 {
+        *aa_mer = (*aa_mer << 5 | c as u128) & mask;
+        true
+    } else {
+        false
+    }
+}
+
+/// the hash id of `DefaultShmmrHasher`
+pub const DEFAULT_SHMMR_HASH_ID: u32 = 0;
+
+/// the number of the reductions of the window minimizers of the specs and the files without
+/// `ShmmrSpec::levels`
+pub const DEFAULT_REDUCTION_LEVELS: u32 = 2;
+
+/// the most reductions of the window minimizers `ShmmrSpec::validate` accepts
+pub const MAX_REDUCTION_LEVELS: u32 = 8;
+
+/// the hash of the k-mers giving the shimmer `x`, a k-mer is passed as its two bit planes,
+/// the low bits and the high bits of the 2-bit encoded bases (A: 0, C: 1, G: 2, T: 3), and a
+/// protein k-mer as the lower and the upper 64 bits of the 5-bit codes of its residues
+///
+/// the hash should be uniform over the 64 bits, the sketch shimmers keep the hashes below a
+/// threshold, and only the lower 56 bits are kept in `MM128::x`
+pub trait ShmmrHasher: fmt::Debug + Send + Sync {
+    /// the id recorded in `ShmmrSpec::hash_id`, `DEFAULT_SHMMR_HASH_ID` is reserved for
+    /// `DefaultShmmrHasher`
+    fn hash_id(&self) -> u32;
+    fn hash_mmer(&self, mmer: (u64, u64)) -> u64;
+}
+
+/// the hash the shimmers have always been computed with
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultShmmrHasher;
+
+impl ShmmrHasher for DefaultShmmrHasher {
+    fn hash_id(&self) -> u32 {
+        DEFAULT_SHMMR_HASH_ID
+    }
+
+    #[inline(always)]
+    fn hash_mmer(&self, mmer: (u64, u64)) -> u64 {
+        u64hash(mmer.0) ^ u64hash(mmer.1 ^ 0xAD12CF59)
+    }
+}
+
+/// the spec of `ShmmrSpec::assembly`, e.g. to fill the fields not set in a struct literal with
+/// `..ShmmrSpec::default()`
+impl Default for ShmmrSpec {
+    fn default() -> Self {
+        ShmmrSpec::assembly()
+    }
+}
+
+impl ShmmrSpec {
+    /// the spec for the assemblies and the other long accurate sequences, the same as
+    /// `seq_db::SHMMRSPEC`
+    pub fn assembly() -> Self {
+        ShmmrSpec {
+            w: 80,
+            k: 56,
+            r: 4,
+            min_span: 64,
+            sketch: true,
+            canonical: true,
+            hash_id: DEFAULT_SHMMR_HASH_ID,
+            density: None,
+            alphabet: Alphabet::Nucleotide,
+            levels: DEFAULT_REDUCTION_LEVELS,
+        }
+    }
+
+    /// the spec for the noisy long reads, the shorter k-mers are more likely to be free of
+    /// the errors, and an odd `k` has no palindromic k-mers
+    pub fn long_read() -> Self {
+        ShmmrSpec {
+            w: 33,
+            k: 33,
+            r: 1,
+            min_span: 0,
+            sketch: false,
+            ..Self::assembly()
+        }
+    }
+
+    /// the spec for the short reads, dense enough to get a few shimmer pairs from a 150 bp read
+    pub fn short_read() -> Self {
+        ShmmrSpec {
+            w: 24,
+            k: 21,
+            r: 1,
+            min_span: 0,
+            sketch: false,
+            ..Self::assembly()
+        }
+    }
+
+    /// the spec for the protein sequences, the 8-residue k-mers are specific enough with the
+    /// 20 amino acids
+    pub fn protein() -> Self {
+        ShmmrSpec {
+            w: 24,
+            k: 8,
+            r: 1,
+            min_span: 0,
+            sketch: false,
+            canonical: false,
+            alphabet: Alphabet::Protein,
+            ..Self::assembly()
+        }
+    }
+
+    /// the same spec with `levels` reductions of the window minimizers
+    pub fn with_levels(self, levels: u32) -> Self {
+        ShmmrSpec { levels, ..self }
+    }
+
+    /// the same spec with the shimmers selected for `density` shimmers per kb instead of by
+    /// `w` and `r`
+    pub fn with_density(self, density: u32) -> Self {
+        ShmmrSpec {
+            density: Some(density),
+            ..self
+        }
+    }
+
+    /// check the parameters are in the ranges the shimmer generation supports, `k` up to 56
+    /// (25 for the proteins), `r` from 1 to 12 and, for the window minimizers, `w` (the number
+    /// of the k-mers in a window, so it is not bounded by `k`) from 1 to 128 and `levels` up
+    /// to `MAX_REDUCTION_LEVELS`, and a `density` from 1 to the highest density the shimmers
+    /// spaced by `min_span` reach, it fails with `InvalidInput`
+    pub fn validate(&self) -> Result<(), std::io::Error> {
+        let invalid = |msg: String| {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid shimmer spec {:?}: {}", self, msg),
+            ))
+        };
+        let max_k = match self.alphabet {
+            Alphabet::Nucleotide => 56,
+            Alphabet::Protein => 25,
+        };
+        if self.k == 0 || self.k > max_k {
+            return invalid(format!("k = {} is not in [1, {}]", self.k, max_k));
+        }
+        if self.r == 0 || self.r > 12 {
+            return invalid(format!("r = {} is not in [1, 12]", self.r));
+        }
+        if let Some(density) = self.density {
+            let max_density = max_density(self.min_span);
+            if density == 0 || density as f64 > max_density {
+                return invalid(format!(
+                    "density = {} is not in [1, {:.1}] for min_span = {}",
+                    density, max_density, self.min_span
+                ));
+            }
+        } else if !self.sketch {
+            if self.w == 0 || self.w > 128 {
+                return invalid(format!("w = {} is not in [1, 128]", self.w));
+            }
+            if self.levels > MAX_REDUCTION_LEVELS {
+                return invalid(format!(
+                    "levels = {} is not in [0, {}]",
+                    self.levels, MAX_REDUCTION_LEVELS
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// the hash threshold of the sketch shimmers, the k-mers with a hash below it are sampled,
+    /// it is `u64::MAX >> 4 >> r` without a `density` and it panics if such `r` is not in
+    /// [1, 12]
+    ///
+    /// a sampled k-mer is kept if no other sampled k-mer is within `min_span` on either side,
+    /// so a sampling rate `p` of the k-mers gives `p * (1 - p)^(2 * min_span)` shimmers per
+    /// base of a random sequence, and the threshold of a `density` is the one of the rate
+    /// giving the density, it only depends on the spec so all sequences have the same
+    /// shimmers for the same k-mers
+    pub fn sketch_threshold(&self) -> u64 {
+        match self.density {
+            Some(density) => {
+                let target = (density as f64 / 1000.0).min(max_density(self.min_span) / 1000.0);
+                let rate = |p: f64| p * (1.0 - p).powi(2 * self.min_span as i32);
+                // the rate is increasing up to the maximum at 1 / (2 * min_span + 1)
+                let (mut lo, mut hi) = (0.0_f64, 1.0 / (2 * self.min_span + 1) as f64);
+                (0..64).for_each(|_| {
+                    let mid = 0.5 * (lo + hi);
+                    if rate(mid) < target {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                });
+                // the cast saturates at `u64::MAX` for the rate 1
+                (hi * u64::MAX as f64) as u64
+            }
+            None => {
+                assert!(self.r > 0 && self.r < 13);
+                u64::MAX >> 4 >> self.r
+            }
+        }
+    }
+
+    /// check the hasher is the one recorded in `hash_id`, it fails with `InvalidInput`
+    /// otherwise
+    pub fn check_hasher(&self, hasher: &dyn ShmmrHasher) -> Result<(), std::io::Error> {
+        if hasher.hash_id() != self.hash_id {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "the shimmers of the spec are computed by the hash {}, not by the hash {} of {:?}",
+                    self.hash_id,
+                    hasher.hash_id(),
+                    hasher
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// the maximum density (shimmers per kb) of the sketch shimmers of a random sequence with
+/// `min_span`, it is reached by sampling 1 in `2 * min_span + 1` k-mers
+fn max_density(min_span: u32) -> f64 {
+    let p = 1.0 / (2 * min_span + 1) as f64;
+    1000.0 * p * (1.0 - p).powi(2 * min_span as i32)
 }
 
+/// an edit of the alignment of `seq1` to `seq0` by `match_reads`, the delta points of an
+/// `OvlpMatch` are in the order from the end of the alignment to the start
+///
+/// see `seq_db::deltas_to_aln_segs` for turning them to the `AlnSegment` edits of `seq1`
+/// relative to `seq0`
 #[derive(Copy, Clone, Debug)]
 pub struct DeltaPoint {
+    /// the position in `seq0` right after the edit
     pub x: u32,
+    /// the position in `seq1` right after the edit
     pub y: u32,
+    /// the change of the diagonal `x - y` by the edit, `dk > 0` for the `dk` bases of `seq0`
+    /// deleted from `seq1`, `dk < 0` for the `-dk` bases `seq1[y + dk..y]` inserted, a
+    /// substitution is a deletion and an insertion at the same point
     pub dk: i32,
 }
 
@@ -54,6 +422,42 @@ fn track_delta_point(
     dpts
 }
 
+/// the parameters of `match_reads_with_params`, the fields map to the arguments of `match_reads`
+#[derive(Clone, Copy, Debug)]
+pub struct MatchReadsParams {
+    /// `get_delta`: keep the delta points of the alignment
+    pub get_delta: bool,
+    /// `tol`: the expected fraction of the differing bases, the max number of deltas is
+    /// `32 + 2 * tolerance * min(len0, len1)`, and the band is widened to
+    /// `32 + 300 * tolerance^2 * log2(min(len0, len1))` if that is wider than `band_width`
+    pub tolerance: f64,
+    /// overriding the max number of deltas derived from `tolerance` if it is set
+    pub max_deltas: Option<u32>,
+    /// `min_match_len`: the alignments shorter than it are dropped
+    pub min_aln_len: u32,
+    /// `min_match_start`: the length of the first exact match starting the alignment
+    pub min_match_start: u32,
+    /// `bandwidth`: the min band width of the diagonals kept in the dynamic programming
+    pub band_width: u32,
+    /// trim the alignment with `OvlpMatch::trim_to_anchors` if it is set and `get_delta` is
+    /// true, the alignments without an exact match of this length are dropped
+    pub trim_anchor_len: Option<u32>,
+}
+
+impl Default for MatchReadsParams {
+    fn default() -> Self {
+        MatchReadsParams {
+            get_delta: true,
+            tolerance: 0.1,
+            max_deltas: None,
+            min_aln_len: 0,
+            min_match_start: 0,
+            band_width: 32,
+            trim_anchor_len: None,
+        }
+    }
+}
+
 pub fn match_reads<'a>(
     seq0: &'a Vec<u8>,
     seq1: &'a Vec<u8>,
@@ -62,25 +466,49 @@ pub fn match_reads<'a>(
     min_match_len: u32,
     min_match_start: u32,
     bandwidth: u32,
+) -> Option<OvlpMatch> {
+    let params = MatchReadsParams {
+        get_delta,
+        tolerance: tol,
+        max_deltas: None,
+        min_aln_len: min_match_len,
+        min_match_start,
+        band_width: bandwidth,
+        trim_anchor_len: None,
+    };
+    match_reads_with_params(seq0, seq1, &params)
+}
+
+pub fn match_reads_with_params(
+    seq0: &[u8],
+    seq1: &[u8],
+    params: &MatchReadsParams,
 ) -> Option<OvlpMatch> {
     //
     // A variation of the O(nD) algorithm for read alignments
     //
+    let MatchReadsParams {
+        get_delta,
+        tolerance: tol,
+        max_deltas,
+        min_aln_len: min_match_len,
+        min_match_start,
+        band_width: bandwidth,
+        trim_anchor_len,
+    } = *params;
 
     // let min_match_len = 1200;
     let len0 = seq0.len();
     let len1 = seq1.len();
     //println!("S {} {}", len0, len1);
     //let d_max = 64 + (0.01 * if len0 < len1 {len0 as f32} else {len1 as f32}) as u32;
-    let d_max = 32
-        + (tol
-            * if len0 < len1 {
-                len0 as f64
-            } else {
-                len1 as f64
-            }) as u32;
-    let max_band_width = bandwidth;
-    let band_tolerance = bandwidth;
+    // a substitution takes two deltas (an insertion and a deletion), and the diagonals of
+    // the noisier alignments drift further apart, so both limits grow with the tolerance
+    let expected_len = len0.min(len1) as f64;
+    let d_max = max_deltas.unwrap_or(32 + (2.0 * tol * expected_len) as u32);
+    let band = bandwidth.max(32 + (300.0 * tol * tol * expected_len.max(2.0).log2()) as u32);
+    let max_band_width = band;
+    let band_tolerance = band;
     let mut k_min = 0_i32;
     let mut k_max = 0_i32;
     let mut uv_map = FxHashMap::<i32, (u32, u32)>::default();
@@ -217,11 +645,65 @@ pub fn match_reads<'a>(
     }
     if !matched {
         None
+    } else if let (Some(len), true) = (trim_anchor_len, get_delta) {
+        rtn.trim_to_anchors(len)
     } else {
         Some(rtn)
     }
 }
 
+/// append a CIGAR operation, it is merged to the last operation if they are the same
+pub fn push_cigar_op(cigar: &mut Vec<(char, u32)>, op: char, len: u32) {
+    if len == 0 {
+        return;
+    }
+    match cigar.last_mut() {
+        Some(last) if last.0 == op => last.1 += len,
+        _ => cigar.push((op, len)),
+    }
+}
+
+/// convert the delta points from `match_reads` to the CIGAR operations (`M`, `I`, `D`) of
+/// the alignment of `seq1` (the query) to `seq0` (the reference) from the start to `(end0, end1)`
+///
+/// `match_reads` only tracks the insertions and the deletions, a substitution shows up as an
+/// adjacent pair of `I` and `D`
+pub fn deltas_to_cigar(deltas: &[DeltaPoint], end0: u32, end1: u32) -> Vec<(char, u32)> {
+    // the delta points are stored backward from the end of the alignment
+    let mut rev_cigar = Vec::<(char, u32)>::new();
+    let (mut x, mut y) = (end0, end1);
+    deltas.iter().for_each(|d| {
+        push_cigar_op(&mut rev_cigar, 'M', x - d.x);
+        if d.dk > 0 {
+            push_cigar_op(&mut rev_cigar, 'D', d.dk as u32);
+            x = d.x - d.dk as u32;
+            y = d.y;
+        } else {
+            push_cigar_op(&mut rev_cigar, 'I', (-d.dk) as u32);
+            x = d.x;
+            y = d.y - (-d.dk) as u32;
+        }
+    });
+    let l = x.min(y);
+    push_cigar_op(&mut rev_cigar, 'M', l);
+    push_cigar_op(&mut rev_cigar, 'D', x - l);
+    push_cigar_op(&mut rev_cigar, 'I', y - l);
+
+    let mut cigar = Vec::<(char, u32)>::new();
+    rev_cigar
+        .into_iter()
+        .rev()
+        .for_each(|(op, len)| push_cigar_op(&mut cigar, op, len));
+    cigar
+}
+
+pub fn cigar_to_string(cigar: &[(char, u32)]) -> String {
+    cigar
+        .iter()
+        .map(|(op, len)| format!("{}{}", len, op))
+        .collect::<String>()
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct MM128 {
     pub x: u64,
@@ -416,12 +898,41 @@ pub fn reduce_shmmr(mers: Vec<MM128>, r: u32, padding: bool) -> Vec<MM128> {
 
 pub fn sequence_to_shmmrs1(
     rid: u32,
-    seq: &Vec<u8>,
+    seq: &[u8],
     w: u32,
     k: u32,
     r: u32,
     min_span: u32,
     padding: bool,
+) -> Vec<MM128> {
+    window_shmmrs(
+        rid,
+        seq,
+        w,
+        k,
+        r,
+        DEFAULT_REDUCTION_LEVELS,
+        min_span,
+        padding,
+        true,
+        Alphabet::Nucleotide,
+        &DefaultShmmrHasher,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn window_shmmrs<H: ShmmrHasher + ?Sized>(
+    rid: u32,
+    seq: &[u8],
+    w: u32,
+    k: u32,
+    r: u32,
+    levels: u32,
+    min_span: u32,
+    padding: bool,
+    canonical: bool,
+    alphabet: Alphabet,
+    hasher: &H,
 ) -> Vec<MM128> {
     let base2bits: [u64; 256] = [
         0, 1, 2, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
@@ -445,7 +956,12 @@ pub fn sequence_to_shmmrs1(
     assert!(r > 0 && r < 13);
     let mut fmmer = (0_u64, 0_u64);
     let mut rmmer = (0_u64, 0_u64);
+    let mut n_valid = 0_u32;
     let mask = u64::MAX >> (64 - k);
+    assert!(alphabet == Alphabet::Nucleotide || k <= 25);
+    let canonical = canonical && alphabet == Alphabet::Nucleotide;
+    let mut aa_mer = 0_u128;
+    let aa_mask = u128::MAX >> (128 - 5 * k.min(25));
     let mut rbuf = RingBuffer::new(w as usize);
     let mut min_mer = MM128 {
         x: u64::MAX,
@@ -456,25 +972,42 @@ pub fn sequence_to_shmmrs1(
             break;
         }
 
-        let c = base2bits[seq[pos] as usize];
-        // println!("C {} {} {}", seq[pos], pos, c);
-        if c < 4 {
-            fmmer.0 <<= 1;
-            fmmer.0 |= c & 0b01;
-            fmmer.0 &= mask;
-            fmmer.1 <<= 1;
-            fmmer.1 |= (c & 0b10) >> 1;
-            fmmer.1 &= mask;
-
-            let rc = 0x3 ^ c;
-            rmmer.0 >>= 1;
-            rmmer.0 |= (rc & 0b01) << shift;
-            rmmer.0 &= mask;
-            rmmer.1 >>= 1;
-            rmmer.1 |= ((rc & 0b10) >> 1) << shift;
-            rmmer.1 &= mask;
-        }
-        if fmmer == rmmer {
+        if alphabet == Alphabet::Protein {
+            if push_residue(&mut aa_mer, seq[pos], aa_mask) {
+                fmmer = (aa_mer as u64, (aa_mer >> 64) as u64);
+                n_valid += 1;
+            } else {
+                n_valid = 0;
+            }
+        } else {
+            let c = base2bits[seq[pos] as usize];
+            // println!("C {} {} {}", seq[pos], pos, c);
+            if c < 4 {
+                fmmer.0 <<= 1;
+                fmmer.0 |= c & 0b01;
+                fmmer.0 &= mask;
+                fmmer.1 <<= 1;
+                fmmer.1 |= (c & 0b10) >> 1;
+                fmmer.1 &= mask;
+
+                let rc = 0x3 ^ c;
+                rmmer.0 >>= 1;
+                rmmer.0 |= (rc & 0b01) << shift;
+                rmmer.0 &= mask;
+                rmmer.1 >>= 1;
+                rmmer.1 |= ((rc & 0b10) >> 1) << shift;
+                rmmer.1 &= mask;
+                n_valid += 1;
+            } else {
+                n_valid = 0;
+            }
+        }
+        // no shimmers from the k-mers with `N` or the other non-ACGT bases, e.g. assembly gaps
+        if n_valid < k {
+            pos += 1;
+            continue;
+        }
+        if canonical && fmmer == rmmer {
             pos += 1;
             continue;
         }
@@ -483,13 +1016,13 @@ pub fn sequence_to_shmmrs1(
             continue;
         }
         let mut forward = true;
-        if rmmer.0 < fmmer.0 {
+        if canonical && rmmer.0 < fmmer.0 {
             forward = false;
         }
 
         let mmer_hash = match forward {
-            true => u64hash(fmmer.0) ^ u64hash(fmmer.1 ^ 0xAD12CF59),
-            false => u64hash(rmmer.0) ^ u64hash(rmmer.1 ^ 0xAD12CF59),
+            true => hasher.hash_mmer(fmmer),
+            false => hasher.hash_mmer(rmmer),
             //true => u64hash(fmmer.0) ^ u64hash(fmmer.1) ^ 0x0,
             //false => u64hash(rmmer.0) ^ u64hash(rmmer.1) ^ 0x0,
         };
@@ -531,7 +1064,9 @@ pub fn sequence_to_shmmrs1(
 
     //let mut shmmrs = shmmrs;
     if r > 1 {
-        shmmrs = reduce_shmmr(reduce_shmmr(shmmrs, r, padding), r, padding);
+        for _ in 0..levels {
+            shmmrs = reduce_shmmr(shmmrs, r, padding);
+        }
     };
     let mut shmmrs2 = Vec::<MM128>::new();
     shmmrs
@@ -555,7 +1090,32 @@ pub fn sequence_to_shmmrs1(
     shmmrs2
 }
 
-pub fn sequence_to_shmmrs2(rid: u32, seq: &Vec<u8>, k: u32, r: u32, min_span: u32) -> Vec<MM128> {
+pub fn sequence_to_shmmrs2(rid: u32, seq: &[u8], k: u32, r: u32, min_span: u32) -> Vec<MM128> {
+    assert!(r > 0 && r < 13);
+    let threshold = u64::MAX >> 4 >> r;
+    sketch_shmmrs(
+        rid,
+        seq,
+        k,
+        threshold,
+        min_span,
+        true,
+        Alphabet::Nucleotide,
+        &DefaultShmmrHasher,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sketch_shmmrs<H: ShmmrHasher + ?Sized>(
+    rid: u32,
+    seq: &[u8],
+    k: u32,
+    threshold: u64,
+    min_span: u32,
+    canonical: bool,
+    alphabet: Alphabet,
+    hasher: &H,
+) -> Vec<MM128> {
     let base2bits: [u64; 256] = [
         0, 1, 2, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
         4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
@@ -573,34 +1133,55 @@ pub fn sequence_to_shmmrs2(rid: u32, seq: &Vec<u8>, k: u32, r: u32, min_span: u3
     let mut pos = 0;
     let shift = k - 1;
     assert!(k <= 56);
-    assert!(r > 0 && r < 13);
     let mut fmmer = (0_u64, 0_u64);
     let mut rmmer = (0_u64, 0_u64);
+    let mut n_valid = 0_u32;
     let mask = u64::MAX >> (64 - k);
+    assert!(alphabet == Alphabet::Nucleotide || k <= 25);
+    let canonical = canonical && alphabet == Alphabet::Nucleotide;
+    let mut aa_mer = 0_u128;
+    let aa_mask = u128::MAX >> (128 - 5 * k.min(25));
     loop {
         if pos >= seq.len() {
             break;
         }
 
-        let c = base2bits[seq[pos] as usize];
-        // println!("C {} {} {}", seq[pos], pos, c);
-        if c < 4 {
-            fmmer.0 <<= 1;
-            fmmer.0 |= c & 0b01;
-            fmmer.0 &= mask;
-            fmmer.1 <<= 1;
-            fmmer.1 |= (c & 0b10) >> 1;
-            fmmer.1 &= mask;
-
-            let rc = 0x3 ^ c;
-            rmmer.0 >>= 1;
-            rmmer.0 |= (rc & 0b01) << shift;
-            rmmer.0 &= mask;
-            rmmer.1 >>= 1;
-            rmmer.1 |= ((rc & 0b10) >> 1) << shift;
-            rmmer.1 &= mask;
-        }
-        if fmmer == rmmer {
+        if alphabet == Alphabet::Protein {
+            if push_residue(&mut aa_mer, seq[pos], aa_mask) {
+                fmmer = (aa_mer as u64, (aa_mer >> 64) as u64);
+                n_valid += 1;
+            } else {
+                n_valid = 0;
+            }
+        } else {
+            let c = base2bits[seq[pos] as usize];
+            // println!("C {} {} {}", seq[pos], pos, c);
+            if c < 4 {
+                fmmer.0 <<= 1;
+                fmmer.0 |= c & 0b01;
+                fmmer.0 &= mask;
+                fmmer.1 <<= 1;
+                fmmer.1 |= (c & 0b10) >> 1;
+                fmmer.1 &= mask;
+
+                let rc = 0x3 ^ c;
+                rmmer.0 >>= 1;
+                rmmer.0 |= (rc & 0b01) << shift;
+                rmmer.0 &= mask;
+                rmmer.1 >>= 1;
+                rmmer.1 |= ((rc & 0b10) >> 1) << shift;
+                rmmer.1 &= mask;
+                n_valid += 1;
+            } else {
+                n_valid = 0;
+            }
+        }
+        // no shimmers from the k-mers with `N` or the other non-ACGT bases, e.g. assembly gaps
+        if n_valid < k {
+            pos += 1;
+            continue;
+        }
+        if canonical && fmmer == rmmer {
             pos += 1;
             continue;
         }
@@ -609,16 +1190,16 @@ pub fn sequence_to_shmmrs2(rid: u32, seq: &Vec<u8>, k: u32, r: u32, min_span: u3
             continue;
         }
         let mut forward = true;
-        if rmmer.0 < fmmer.0 {
+        if canonical && rmmer.0 < fmmer.0 {
             forward = false;
         }
 
         let mmer_hash = match forward {
-            true => u64hash(fmmer.0) ^ u64hash(fmmer.1 ^ 0xAD12CF59),
-            false => u64hash(rmmer.0) ^ u64hash(rmmer.1 ^ 0xAD12CF59),
+            true => hasher.hash_mmer(fmmer),
+            false => hasher.hash_mmer(rmmer),
         };
 
-        if mmer_hash < u64::MAX >> 4 >> r {
+        if mmer_hash < threshold {
             let strand: u64 = if forward { 0 } else { 1 };
             let m = MM128 {
                 x: mmer_hash << 8 | k as u64,
@@ -654,16 +1235,80 @@ pub fn sequence_to_shmmrs2(rid: u32, seq: &Vec<u8>, k: u32, r: u32, min_span: u3
     shmmrs2
 }
 
+/// compute the shimmers with `DefaultShmmrHasher`, it panics if the spec records another hash,
+/// see `sequence_to_shmmrs_with_hasher` for the other hashes
+///
+/// no shimmers are computed from the k-mers with `N` or the other non-ACGT bases, the .mdb
+/// and .frg files written before have the shimmers of those k-mers, so the shimmers next to
+/// the non-ACGT bases differ from the ones of these files
 pub fn sequence_to_shmmrs(
     rid: u32,
     seq: &Vec<u8>,
     shmmrspec: &ShmmrSpec,
     padding: bool,
+) -> Vec<MM128> {
+    shmmrspec
+        .check_hasher(&DefaultShmmrHasher)
+        .unwrap_or_else(|e| panic!("{}", e));
+    spec_shmmrs(rid, seq, shmmrspec, padding, &DefaultShmmrHasher)
+}
+
+/// the same as `sequence_to_shmmrs`, but the k-mers are hashed by `hasher`, it fails with
+/// `InvalidInput` if the hasher is not the one recorded in `ShmmrSpec::hash_id`
+pub fn sequence_to_shmmrs_with_hasher(
+    rid: u32,
+    seq: &[u8],
+    shmmrspec: &ShmmrSpec,
+    padding: bool,
+    hasher: &dyn ShmmrHasher,
+) -> Result<Vec<MM128>, std::io::Error> {
+    shmmrspec.check_hasher(hasher)?;
+    Ok(spec_shmmrs(rid, seq, shmmrspec, padding, hasher))
+}
+
+fn spec_shmmrs<H: ShmmrHasher + ?Sized>(
+    rid: u32,
+    seq: &[u8],
+    shmmrspec: &ShmmrSpec,
+    padding: bool,
+    hasher: &H,
 ) -> Vec<MM128> {
     let (w, k, r, min_span) = (shmmrspec.w, shmmrspec.k, shmmrspec.r, shmmrspec.min_span);
-    if !shmmrspec.sketch {
-        sequence_to_shmmrs1(rid, seq, w, k, r, min_span, padding)
+    if !shmmrspec.sketch && shmmrspec.density.is_none() {
+        window_shmmrs(
+            rid,
+            seq,
+            w,
+            k,
+            r,
+            shmmrspec.levels,
+            min_span,
+            padding,
+            shmmrspec.canonical,
+            shmmrspec.alphabet,
+            hasher,
+        )
     } else {
-        sequence_to_shmmrs2(rid, seq, k, r, min_span)
+        let threshold = shmmrspec.sketch_threshold();
+        sketch_shmmrs(
+            rid,
+            seq,
+            k,
+            threshold,
+            min_span,
+            shmmrspec.canonical,
+            shmmrspec.alphabet,
+            hasher,
+        )
     }
 }
+
+/// compute the shimmers of a sequence without a sequence database, the shimmers
+/// carry the same packed hash `x` and position `y` (with the sequence id 0) as the
+/// ones computed when sequences are loaded into a `CompactSeqDB`
+pub fn shmmrs_from_seq(seq: &[u8], shmmrspec: &ShmmrSpec) -> Vec<MM128> {
+    shmmrspec
+        .check_hasher(&DefaultShmmrHasher)
+        .unwrap_or_else(|e| panic!("{}", e));
+    spec_shmmrs(0, seq, shmmrspec, false, &DefaultShmmrHasher)
+}
@@ -1,6 +1,6 @@
 // src/lib.rs
 pub const VERSION_STRING: &str = env!("VERSION_STRING");
-use pgr_db::aln::{self, HitPair};
+use pgr_db::aln::{self, HitPair, Strand};
 use pgr_db::graph_utils::{AdjList, ShmmrGraphNode};
 use pgr_db::seq_db;
 //use pgr_db::seqs2variants;
@@ -152,7 +152,8 @@ impl SeqIndexDB {
             "Only DB created with load_from_fastx() can add data from another fastx file"
         );
         let sdb = self.db_internal.seq_db.as_mut().unwrap();
-        sdb.load_seqs_from_fastx(filepath)?;
+        sdb.load_seqs_from_fastx(filepath)
+            .map_err(std::io::Error::from)?;
         Ok(())
     }
 
@@ -472,6 +473,8 @@ impl SeqIndexDB {
                 max_count_query,
                 max_count_target,
                 max_aln_span,
+                aln::DEFAULT_MIN_CHAIN_SCORE,
+                aln::DEFAULT_MIN_CHAIN_LEN,
             )
         };
 
@@ -519,11 +522,7 @@ impl SeqIndexDB {
                         .unwrap()
                         .get(&seq_id)
                         .unwrap(); //TODO, check if seq_info is None
-                    let same_orientation = if left_match.0 .2 == left_match.1 .2 {
-                        true
-                    } else {
-                        false
-                    };
+                    let same_orientation = Strand::of(left_match) == Strand::Forward;
 
                     let qb = left_match.0 .0;
                     let qe = right_match.0 .1;
@@ -1522,14 +1521,27 @@ impl AGCFile {
 ///     chunk alignment ignoring the gaps. Typically, a number between 0.1 to 0.5 should
 ///     be used.
 ///
-#[pyfunction(signature = (sp_hits, max_span, penalty))]
+/// max_hits : int
+///     The max number of the hits chained. If there are more hits, e.g., for a very
+///     repetitive query, the hits of the less repetitive query spans are kept first and
+///     the rest are subsampled evenly along the query. The hits dropped are not in the
+///     output. ``None`` (the default) chains all hits.
+///
+#[pyfunction(signature = (sp_hits, max_span, penalty, max_hits = None))]
 pub fn sparse_aln(
     sp_hits: Vec<HitPair>,
     max_span: u32,
     penalty: f32,
+    max_hits: Option<usize>,
 ) -> PyResult<Vec<(f32, Vec<HitPair>)>> {
     let mut hp = sp_hits.clone();
-    Ok(aln::sparse_aln(&mut hp, max_span, penalty))
+    let mut chain_params = aln::ChainParams::linear(penalty);
+    chain_params.max_hits = max_hits;
+    Ok(aln::sparse_aln_with_params(
+        &mut hp,
+        max_span,
+        &chain_params,
+    ))
 }
 
 /// Generate a list of shimmer pair from a sequence
@@ -1577,6 +1589,7 @@ fn get_shmmr_pairs_from_seq(
         r,
         min_span,
         sketch: false,
+        ..ShmmrSpec::default()
     };
     let shmmrs = sequence_to_shmmrs(0, &seq, &shmmr_spec, padding);
     let res = seq_db::pair_shmmrs(&shmmrs)
@@ -1649,6 +1662,7 @@ fn get_shmmr_dots(
         r,
         min_span,
         sketch: false,
+        ..ShmmrSpec::default()
     };
 
     let shmmr0 = sequence_to_shmmrs(0, &seq0, &shmmr_spec, false);
@@ -1937,6 +1951,7 @@ pub fn shmmr_dbg_consensus(
         r,
         min_span,
         sketch: false,
+        ..ShmmrSpec::default()
     };
     let consensus = pgr_db::ec::shmmr_dbg_consensus(seqs, &Some(spec));
     match consensus {
@@ -1980,6 +1995,7 @@ pub fn guided_shmmr_dbg_consensus(
         r,
         min_span,
         sketch: false,
+        ..ShmmrSpec::default()
     };
     let consensus = pgr_db::ec::guided_shmmr_dbg_consensus(seqs, &Some(spec), min_cov);
     match consensus {
@@ -2020,6 +2036,7 @@ pub fn shmmr_sparse_aln_consensus(
         r,
         min_span,
         sketch: false,
+        ..ShmmrSpec::default()
     };
     let consensus = pgr_db::ec::shmmr_sparse_aln_consensus(seqs, &Some(spec), min_cov);
     match consensus {